@@ -0,0 +1,236 @@
+//! Contains [`AdaptiveLimit`], a semaphore whose number of permits is raised and lowered
+//! within bounds depending on how requests are going (see `concurrency.adaptive`).
+//!
+//! This works like TCP's congestion control (additive increase, multiplicative decrease):
+//!
+//! - after as many fast successes as there are permits, another permit is added
+//! - throttling (429 or 503) and failures (error statuses, timeouts) halve the permits
+//! - latencies well above the usual remove a permit, since the server is likely struggling
+//!
+//! Decreases happen at most once per [`COOLDOWN`], so that a burst of errors from
+//! requests that were all sent at once only counts once.
+
+use std::{
+    ops::RangeInclusive,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::{AcquireError, OwnedSemaphorePermit, Semaphore};
+
+/// The least time between two decreases of the same limit.
+pub const COOLDOWN: Duration = Duration::from_secs(2);
+
+/// How many times the usual latency a request can take before permits are removed.
+const SLOW_FACTOR: u32 = 3;
+
+/// How a request went, as reported to [`AdaptiveLimit::record`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The request succeeded, taking this long.
+    Success(Duration),
+    /// The server asked us to slow down (429 or 503).
+    Throttled,
+    /// The request failed with an error status or timed out.
+    Failed,
+}
+
+#[derive(Debug)]
+struct State {
+    limit: usize,
+    /// Permits to forget as they're released, since they were in use when the limit was lowered.
+    excess: usize,
+    /// Successes since the limit last changed.
+    successes: usize,
+    /// Exponentially weighted moving average of latencies.
+    latency: Option<Duration>,
+    /// The usual latency, which follows [`Self::latency`] down quickly and up slowly.
+    baseline: Option<Duration>,
+    last_decrease: Option<Instant>,
+}
+
+/// A semaphore that adapts its permits, see the [module docs](`self`).
+///
+/// This is shared (behind an [`Arc`]) by everything that sends the limited requests.
+#[derive(Debug)]
+pub struct AdaptiveLimit {
+    /// What's being limited, for logging, e.g. `"image"`.
+    name: &'static str,
+    bounds: RangeInclusive<usize>,
+    semaphore: Arc<Semaphore>,
+    state: Mutex<State>,
+}
+
+impl AdaptiveLimit {
+    /// Constructs a new [`AdaptiveLimit`] starting at `initial` permits (clamped to `bounds`).
+    ///
+    /// ## Panics
+    ///
+    /// If `bounds` is empty or starts at zero.
+    #[must_use]
+    pub fn new(name: &'static str, initial: usize, bounds: RangeInclusive<usize>) -> Self {
+        assert!(
+            *bounds.start() > 0 && bounds.start() <= bounds.end(),
+            "adaptive bounds must be above zero and not empty"
+        );
+
+        let limit = initial.clamp(*bounds.start(), *bounds.end());
+
+        Self {
+            name,
+            bounds,
+            semaphore: Arc::new(Semaphore::new(limit)),
+            state: Mutex::new(State {
+                limit,
+                excess: 0,
+                successes: 0,
+                latency: None,
+                baseline: None,
+                last_decrease: None,
+            }),
+        }
+    }
+
+    /// Constructs a new [`AdaptiveLimit`] that always has `permits` permits.
+    ///
+    /// ## Panics
+    ///
+    /// If `permits` is zero.
+    #[must_use]
+    pub fn fixed(name: &'static str, permits: usize) -> Self {
+        Self::new(name, permits, permits..=permits)
+    }
+
+    /// Returns the current number of permits.
+    ///
+    /// ## Panics
+    ///
+    /// If the lock is poisoned.
+    #[must_use]
+    pub fn limit(&self) -> usize {
+        self.state
+            .lock()
+            .expect("adaptive limit lock poisoned")
+            .limit
+    }
+
+    /// Waits for a permit, which is released when dropped.
+    ///
+    /// ## Errors
+    ///
+    /// If the semaphore has been closed, which never happens.
+    pub async fn acquire(self: &Arc<Self>) -> Result<AdaptivePermit, AcquireError> {
+        let permit = self.semaphore.clone().acquire_owned().await?;
+
+        Ok(AdaptivePermit {
+            permit: Some(permit),
+            limit: self.clone(),
+        })
+    }
+
+    /// Adjusts the number of permits depending on `outcome`.
+    ///
+    /// ## Panics
+    ///
+    /// If the lock is poisoned.
+    pub fn record(&self, outcome: Outcome) {
+        if self.bounds.start() == self.bounds.end() {
+            return;
+        }
+
+        let mut state = self.state.lock().expect("adaptive limit lock poisoned");
+
+        match outcome {
+            Outcome::Success(latency) => {
+                let average = state.latency.map_or(latency, |avg| (avg * 7 + latency) / 8);
+                let baseline = match state.baseline {
+                    Some(b) if b < average => b + average.saturating_sub(b) / 32,
+                    _ => average,
+                };
+                state.latency = Some(average);
+                state.baseline = Some(baseline);
+
+                if average > baseline * SLOW_FACTOR {
+                    let limit = state.limit - 1;
+                    self.decrease(&mut state, limit, "latency rose");
+                    return;
+                }
+
+                state.successes += 1;
+
+                if state.successes >= state.limit && state.limit < *self.bounds.end() {
+                    let limit = state.limit + 1;
+                    self.set_limit(&mut state, limit);
+                    debug!("Raised {} permits to {limit}", self.name);
+                }
+            }
+            Outcome::Throttled => {
+                let limit = state.limit / 2;
+                self.decrease(&mut state, limit, "throttled");
+            }
+            Outcome::Failed => {
+                let limit = state.limit / 2;
+                self.decrease(&mut state, limit, "server errors");
+            }
+        }
+    }
+
+    /// Lowers the limit to `limit` (within bounds), unless it was lowered within [`COOLDOWN`].
+    fn decrease(&self, state: &mut State, limit: usize, reason: &str) {
+        let limit = limit.max(*self.bounds.start());
+
+        if limit >= state.limit || state.last_decrease.is_some_and(|t| t.elapsed() < COOLDOWN) {
+            return;
+        }
+
+        state.last_decrease = Some(Instant::now());
+        self.set_limit(state, limit);
+        info!("Lowered {} permits to {limit} ({reason})", self.name);
+    }
+
+    /// Adds or removes permits so that there are `limit` in total.
+    fn set_limit(&self, state: &mut State, limit: usize) {
+        if limit > state.limit {
+            let added = limit - state.limit;
+            // permits still in use that were going to be forgotten can be kept instead
+            let kept = added.min(state.excess);
+            state.excess -= kept;
+            self.semaphore.add_permits(added - kept);
+        } else {
+            let removed = state.limit - limit;
+            let forgotten = self.semaphore.forget_permits(removed);
+            state.excess += removed - forgotten;
+        }
+
+        state.limit = limit;
+        state.successes = 0;
+    }
+
+    /// Returns `permit` to the semaphore, or forgets it if there are too many.
+    fn release(&self, permit: OwnedSemaphorePermit) {
+        let mut state = self
+            .state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+        if state.excess > 0 {
+            state.excess -= 1;
+            permit.forget();
+        }
+    }
+}
+
+/// A permit from an [`AdaptiveLimit`], released when dropped.
+#[derive(Debug)]
+pub struct AdaptivePermit {
+    permit: Option<OwnedSemaphorePermit>,
+    limit: Arc<AdaptiveLimit>,
+}
+
+impl Drop for AdaptivePermit {
+    fn drop(&mut self) {
+        if let Some(permit) = self.permit.take() {
+            self.limit.release(permit);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+//! Contains [`AuthSession`], which logs in to Manga-Dex with a personal API client
+//! (see the `[auth]` config section), and [`BearerAuth`], the middleware that sends
+//! its access token with every request of an [`ApiClient`](`crate::api::client::ApiClient`).
+//!
+//! Access tokens only last 15 minutes, so they're refreshed shortly before they expire,
+//! logging in again if the refresh token has expired too.
+//!
+//! ## References
+//!
+//! - <https://api.mangadex.org/docs/02-authentication/personal-clients/>
+
+use crate::{api::middleware::Middleware, config};
+
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures::future::BoxFuture;
+use miette::{IntoDiagnostic, Result, bail, miette};
+use reqwest::{
+    Url,
+    header::{AUTHORIZATION, CONTENT_TYPE, HeaderValue},
+};
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+/// Where tokens are requested from.
+pub const TOKEN_URL: &str =
+    "https://auth.mangadex.org/realms/mangadex/protocol/openid-connect/token";
+
+/// How long before an access token expires that it's refreshed.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+/// What's needed to log in with a personal API client.
+#[derive(Clone)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+// leave the secrets out, so that they don't end up in logs
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("client_id", &self.client_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Credentials {
+    /// Returns the credentials in the `[auth]` config section, or
+    /// the dotted key of the first option that isn't set.
+    ///
+    /// ## Errors
+    ///
+    /// If any of the options aren't set.
+    pub fn from_config(cfg: &config::Auth) -> std::result::Result<Self, &'static str> {
+        Ok(Self {
+            username: cfg.username.clone().ok_or("auth.username")?,
+            password: cfg.password.clone().ok_or("auth.password")?,
+            client_id: cfg.client_id.clone().ok_or("auth.client_id")?,
+            client_secret: cfg.client_secret.clone().ok_or("auth.client_secret")?,
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    /// In seconds.
+    expires_in: u64,
+}
+
+#[derive(Deserialize, Debug)]
+struct TokenError {
+    error: String,
+    error_description: Option<String>,
+}
+
+#[derive(Debug)]
+struct Tokens {
+    access: String,
+    refresh: Option<String>,
+    expires_at: Instant,
+}
+
+/// A logged in session, which hands out access tokens (see the [module docs](`self`)).
+#[derive(Debug)]
+pub struct AuthSession {
+    client: reqwest::Client,
+    token_url: Url,
+    credentials: Credentials,
+    /// `None` until the first token is requested, since logging in may not be needed.
+    tokens: Mutex<Option<Tokens>>,
+}
+
+impl AuthSession {
+    /// Constructs a new [`AuthSession`], which logs in when a token is first needed.
+    ///
+    /// ## Errors
+    ///
+    /// If the HTTP client can't be constructed.
+    pub fn new(credentials: Credentials, user_agent: &str) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .user_agent(user_agent)
+            .build()
+            .into_diagnostic()?;
+
+        Ok(Self {
+            client,
+            token_url: Url::parse(TOKEN_URL).into_diagnostic()?,
+            credentials,
+            tokens: Mutex::new(None),
+        })
+    }
+
+    /// Returns an access token, logging in or refreshing the current one if needed.
+    ///
+    /// ## Errors
+    ///
+    /// If logging in fails, e.g. if the credentials are wrong.
+    pub async fn access_token(&self) -> Result<String> {
+        let mut tokens = self.tokens.lock().await;
+
+        if let Some(t) = tokens.as_ref()
+            && t.expires_at > Instant::now() + EXPIRY_MARGIN
+        {
+            return Ok(t.access.clone());
+        }
+
+        let refreshed = match tokens.as_ref().and_then(|t| t.refresh.clone()) {
+            Some(refresh) => self
+                .request_tokens(&[("grant_type", "refresh_token"), ("refresh_token", &refresh)])
+                .await
+                .inspect_err(|e| debug!("Failed to refresh the access token, logging in: {e}"))
+                .ok(),
+            None => None,
+        };
+
+        let new = if let Some(v) = refreshed {
+            v
+        } else {
+            let c = &self.credentials;
+            let new = self
+                .request_tokens(&[
+                    ("grant_type", "password"),
+                    ("username", &c.username),
+                    ("password", &c.password),
+                ])
+                .await?;
+            info!("Logged in to Manga-Dex as {:?}", c.username);
+            new
+        };
+
+        let access = new.access.clone();
+        *tokens = Some(new);
+
+        Ok(access)
+    }
+
+    /// Requests tokens with `grant` (plus the client's id and secret).
+    async fn request_tokens(&self, grant: &[(&str, &str)]) -> Result<Tokens> {
+        let c = &self.credentials;
+        let mut form = vec![
+            ("client_id", c.client_id.as_str()),
+            ("client_secret", c.client_secret.as_str()),
+        ];
+        form.extend_from_slice(grant);
+
+        let sent_at = Instant::now();
+        let r = self
+            .client
+            .post(self.token_url.clone())
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(serde_urlencoded::to_string(&form).into_diagnostic()?)
+            .send()
+            .await
+            .into_diagnostic()?;
+
+        let status = r.status();
+        let body = r.bytes().await.into_diagnostic()?;
+
+        if !status.is_success() {
+            let reason = match serde_json::from_slice::<TokenError>(&body) {
+                Ok(e) => e.error_description.unwrap_or(e.error),
+                Err(_) => status.to_string(),
+            };
+            bail!("failed to log in to Manga-Dex: {reason}");
+        }
+
+        let r: TokenResponse = serde_json::from_slice(&body).into_diagnostic()?;
+
+        Ok(Tokens {
+            access: r.access_token,
+            refresh: r.refresh_token,
+            expires_at: sent_at + Duration::from_secs(r.expires_in),
+        })
+    }
+}
+
+/// Sends the access token of an [`AuthSession`] with every request.
+#[derive(Debug, Clone)]
+pub struct BearerAuth(pub Arc<AuthSession>);
+
+impl Middleware for BearerAuth {
+    fn before<'a>(&'a self, request: &'a mut reqwest::Request) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async {
+            let token = self.0.access_token().await?;
+            let value = HeaderValue::from_str(&format!("Bearer {token}"))
+                .map_err(|e| miette!("access token isn't a valid header: {e}"))?;
+            request.headers_mut().insert(AUTHORIZATION, value);
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,213 @@
+//! Contains [`SearchCache`], a short-lived cache of search results used by
+//! [`SearchClient`](`crate::api::search::SearchClient`), so that searching for the
+//! same thing again (e.g. after backing out of a manga) doesn't hit the API.
+//!
+//! Results are kept in memory, and optionally on disk (in [`search_cache_dir`])
+//! so they're also reused across runs. Those are also read back by [`cached_manga`]
+//! in [offline mode](`crate::offline`), however old they are, so they're only removed
+//! once they're found to have expired while searching for the same thing again.
+
+use crate::{
+    api::{
+        models::{Manga, MangaStatistics},
+        search::SearchResults,
+    },
+    manifest::sha256_hex,
+    paths::search_cache_dir,
+};
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use miette::{IntoDiagnostic, Result};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A search response as stored on disk.
+#[derive(Serialize, Deserialize)]
+struct CachedResponse {
+    fetched_at: DateTime<Utc>,
+    /// The response as sent by Manga-Dex, since [`SearchResults`] can't be serialized.
+    response: serde_json::Value,
+    statistics: HashMap<Uuid, MangaStatistics>,
+}
+
+/// A cache of [`SearchResults`] by request, which expire after a while.
+///
+/// Clones share the same cache.
+#[derive(Debug, Clone)]
+pub struct SearchCache {
+    entries: Arc<Mutex<HashMap<String, (Instant, SearchResults)>>>,
+    ttl: Duration,
+    on_disk: bool,
+}
+
+impl SearchCache {
+    /// Creates an empty cache whose results expire after `ttl`, which
+    /// are also saved to disk if `on_disk`.
+    #[must_use]
+    pub fn new(ttl: Duration, on_disk: bool) -> Self {
+        Self {
+            entries: Arc::default(),
+            ttl,
+            on_disk,
+        }
+    }
+
+    /// Returns the cache key of a request with `params`.
+    #[must_use]
+    pub fn key(params: &[(String, String)]) -> String {
+        serde_urlencoded::to_string(params).unwrap_or_default()
+    }
+
+    fn path(key: &str) -> Result<PathBuf> {
+        Ok(search_cache_dir()?.join(format!("{}.json", sha256_hex(key.as_bytes()))))
+    }
+
+    /// Returns the results cached for `key`, if they haven't expired.
+    ///
+    /// ## Panics
+    ///
+    /// If another thread panicked while using the cache.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<SearchResults> {
+        let entries = self.entries.lock().expect("search cache lock poisoned");
+
+        if let Some((fetched_at, results)) = entries.get(key)
+            && fetched_at.elapsed() < self.ttl
+        {
+            return Some(results.clone());
+        }
+
+        drop(entries);
+
+        if !self.on_disk {
+            return None;
+        }
+
+        let (age, results) = self.read(key)?;
+        // keep the original fetch time, so results don't outlive the ttl by being read back
+        let fetched_at = Instant::now().checked_sub(age).unwrap_or_else(Instant::now);
+        self.entries
+            .lock()
+            .expect("search cache lock poisoned")
+            .insert(key.to_string(), (fetched_at, results.clone()));
+
+        Some(results)
+    }
+
+    /// Reads the results cached on disk for `key` (and how old they are),
+    /// if they haven't expired. Expired ones are removed.
+    fn read(&self, key: &str) -> Option<(Duration, SearchResults)> {
+        let path = Self::path(key).ok()?;
+        let raw = fs::read(&path).ok()?;
+        let cached: CachedResponse = serde_json::from_slice(&raw)
+            .inspect_err(|e| debug!("Ignoring unreadable search cache entry: {e}"))
+            .ok()?;
+
+        let age = (Utc::now() - cached.fetched_at).to_std().ok()?;
+
+        if age >= self.ttl {
+            if let Err(e) = fs::remove_file(&path) {
+                debug!(
+                    "Failed to remove expired search cache entry {}: {e}",
+                    path.display()
+                );
+            }
+
+            return None;
+        }
+
+        let mut results: SearchResults = serde_json::from_value(cached.response).ok()?;
+        results.statistics = cached.statistics;
+        Some((age, results))
+    }
+
+    /// Caches `results` for `key`, along with `response` (what they were parsed from)
+    /// if the cache is on disk. Expired results are evicted from memory first.
+    ///
+    /// ## Panics
+    ///
+    /// If another thread panicked while using the cache.
+    pub fn insert(&self, key: &str, response: serde_json::Value, results: &SearchResults) {
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        let mut entries = self.entries.lock().expect("search cache lock poisoned");
+        entries.retain(|_, (fetched_at, _)| fetched_at.elapsed() < self.ttl);
+        entries.insert(key.to_string(), (Instant::now(), results.clone()));
+        drop(entries);
+
+        if !self.on_disk {
+            return;
+        }
+
+        let cached = CachedResponse {
+            fetched_at: Utc::now(),
+            response,
+            statistics: results.statistics.clone(),
+        };
+
+        if let Err(e) = Self::write(key, &cached) {
+            warn!("Failed to save search results to the cache: {e}");
+        }
+    }
+
+    fn write(key: &str, cached: &CachedResponse) -> Result<()> {
+        let path = Self::path(key)?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).into_diagnostic()?;
+        }
+
+        fs::write(path, serde_json::to_vec(cached).into_diagnostic()?).into_diagnostic()
+    }
+}
+
+/// Returns every manga in the search results cached on disk (however old they are),
+/// keeping the most recently fetched copy of each.
+///
+/// Unreadable entries are skipped, and nothing is returned if the cache was never on disk.
+#[must_use]
+pub fn cached_manga() -> HashMap<Uuid, Manga> {
+    let mut manga: HashMap<Uuid, (DateTime<Utc>, Manga)> = HashMap::new();
+    let Ok(entries) = search_cache_dir().and_then(|dir| fs::read_dir(dir).into_diagnostic()) else {
+        return HashMap::new();
+    };
+
+    for path in entries.filter_map(|e| Some(e.ok()?.path())) {
+        let Some(cached) = fs::read(&path)
+            .ok()
+            .and_then(|raw| serde_json::from_slice::<CachedResponse>(&raw).ok())
+        else {
+            debug!("Ignoring unreadable search cache entry {}", path.display());
+            continue;
+        };
+
+        let Ok(results) = serde_json::from_value::<SearchResults>(cached.response) else {
+            continue;
+        };
+
+        for data in results.data {
+            let fetched = Manga { data };
+            let uuid = fetched.uuid();
+
+            if manga
+                .get(&uuid)
+                .is_none_or(|(at, _)| *at < cached.fetched_at)
+            {
+                manga.insert(uuid, (cached.fetched_at, fetched));
+            }
+        }
+    }
+
+    debug!("Read {} manga from the search cache", manga.len());
+    manga.into_iter().map(|(uuid, (_, m))| (uuid, m)).collect()
+}
@@ -1,53 +1,210 @@
 //! Contains [`ApiClient`] struct for interacting with Manga-Dex's API.
 
+use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use crate::{api::endpoints::Endpoint, config};
+use crate::{
+    api::{
+        deprecation::WatchDeprecations,
+        endpoints::Endpoint,
+        middleware::{EnforceBudget, Exchange, Middleware, RecordMetrics, RecordRequests},
+        request_id::RequestId,
+        response::{ApiResponse, ResponseHeaders},
+    },
+    config,
+    deserializers::from_slice_with_path,
+    metrics::record_retry,
+    offline::{RefuseRequests, is_offline},
+};
 
 use crate::errors::ApiError;
+use chrono::Utc;
 use miette::{IntoDiagnostic, Result, bail};
-use reqwest::header::HeaderMap;
-use reqwest::{self, StatusCode};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{self, Method, StatusCode};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use serde_json;
 
 // prevent threads spamming ratelimit logs
 static RATELIMIT_LOGGED: AtomicBool = AtomicBool::new(false);
 
+/// The default for [`ApiClientBuilder::base_url`], Manga-Dex's API.
+pub const DEFAULT_BASE_URL: &str = "https://api.mangadex.org";
+
+/// The default for [`ApiClientBuilder::user_agent`].
+///
+/// Manga-Dex requires a user agent that identifies the client (its name, version and
+/// somewhere to reach its authors), and blocks ones that pretend to be a browser.
+pub const DEFAULT_USER_AGENT: &str = concat!(
+    "rust_mdex_dl/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/hachispin/learning-projects)"
+);
+
+/// How requests are retried, see [`ApiClientBuilder::retry_policy`].
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// How many times a request is sent before giving up when ratelimited.
+    pub max_retries: u32,
+}
+
+impl RetryPolicy {
+    /// Creates a [`RetryPolicy`] which sends a request up to `max_retries` times.
+    #[must_use]
+    pub const fn new(max_retries: u32) -> Self {
+        Self { max_retries }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
 #[derive(Debug, Clone)]
 /// A wrapper over [`reqwest::Client`] for Manga-Dex interactions.
 pub struct ApiClient {
     client: reqwest::Client,
     base_url: reqwest::Url,
-    max_retries: u32,
+    retry_policy: RetryPolicy,
+    /// The max size of a response body (in bytes) that'll be parsed as JSON.
+    max_body_size: usize,
+    /// Run around every request, see [`Self::with_middleware`].
+    middleware: Vec<Arc<dyn Middleware>>,
 }
 
-impl ApiClient {
-    /// Creates a new [`ApiClient`] with [`reqwest::Client::builder()`]
+/// Builds an [`ApiClient`] without a [`config::Client`], see [`ApiClient::builder`].
+#[derive(Debug, Clone)]
+pub struct ApiClientBuilder {
+    base_url: reqwest::Url,
+    user_agent: String,
+    retry_policy: RetryPolicy,
+    max_response_mib: usize,
+    middleware: Vec<Arc<dyn Middleware>>,
+}
+
+impl Default for ApiClientBuilder {
+    fn default() -> Self {
+        let mut middleware: Vec<Arc<dyn Middleware>> = vec![
+            Arc::new(RecordRequests),
+            Arc::new(RecordMetrics),
+            Arc::new(WatchDeprecations),
+            Arc::new(EnforceBudget),
+        ];
+
+        if is_offline() {
+            middleware.insert(0, Arc::new(RefuseRequests));
+        }
+
+        Self {
+            base_url: reqwest::Url::parse(DEFAULT_BASE_URL).expect("default base url is valid"),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            retry_policy: RetryPolicy::default(),
+            max_response_mib: config::default_max_response_mib(),
+            middleware,
+        }
+    }
+}
+
+impl ApiClientBuilder {
+    /// Sets the url every [`Endpoint`] is joined onto, [`DEFAULT_BASE_URL`] by default.
+    #[must_use]
+    pub fn base_url(mut self, base_url: reqwest::Url) -> Self {
+        self.base_url = base_url;
+        self
+    }
+
+    /// Sets the user agent sent with every request, [`DEFAULT_USER_AGENT`] by default.
+    #[must_use]
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Sets how requests are retried, [`RetryPolicy::default`] by default.
+    #[must_use]
+    pub const fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sets the max size of a response body (in MiB) that'll be parsed as JSON.
+    #[must_use]
+    pub const fn max_response_mib(mut self, max_response_mib: usize) -> Self {
+        self.max_response_mib = max_response_mib;
+        self
+    }
+
+    /// Adds `middleware` to run around every request, see [`ApiClient::with_middleware`].
+    #[must_use]
+    pub fn middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Builds the [`ApiClient`] with [`reqwest::Client::builder()`].
     ///
     /// ## Errors
     ///
     /// An error can occur if [`reqwest::ClientBuilder`] fails.
-    pub fn new(client_cfg: &config::Client) -> Result<Self> {
-        let base_url = client_cfg.base_url.clone();
-        let max_retries = client_cfg.max_retries;
-
+    pub fn build(self) -> Result<ApiClient> {
         let client = reqwest::Client::builder()
-            .user_agent(client_cfg.user_agent.clone())
+            .user_agent(self.user_agent)
             .build()
             .into_diagnostic()?;
 
-        Ok(Self {
+        Ok(ApiClient {
             client,
-            base_url,
-            max_retries,
+            base_url: self.base_url,
+            retry_policy: self.retry_policy,
+            max_body_size: self.max_response_mib * 1_048_576,
+            middleware: self.middleware,
         })
     }
+}
+
+impl ApiClient {
+    /// Creates a new [`ApiClient`] from the `[client]` section of the config.
+    ///
+    /// Requests are recorded for trace bundles with [`RecordRequests`], and
+    /// in the run's metrics with [`RecordMetrics`]. API changes that Manga-Dex
+    /// signals are logged with [`WatchDeprecations`]. Every request fails
+    /// with [`RefuseRequests`] in [offline mode](`crate::offline`).
+    ///
+    /// ## Errors
+    ///
+    /// An error can occur if [`reqwest::ClientBuilder`] fails.
+    pub fn new(client_cfg: &config::Client) -> Result<Self> {
+        Self::builder()
+            .base_url(client_cfg.base_url.clone())
+            .user_agent(client_cfg.user_agent.clone())
+            .retry_policy(RetryPolicy::new(client_cfg.max_retries))
+            .max_response_mib(client_cfg.max_response_mib)
+            .build()
+    }
+
+    /// Returns an [`ApiClientBuilder`], for creating a client without a config.
+    /// Its defaults are the same as the default config's.
+    #[must_use]
+    pub fn builder() -> ApiClientBuilder {
+        ApiClientBuilder::default()
+    }
+
+    /// Adds `middleware` to run around every request, after the ones already added.
+    ///
+    /// Clones of this client made afterwards share it.
+    #[must_use]
+    pub fn with_middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
 
     /// Sends a GET request to the `endpoint` prefixed with
     /// the [`Self::base_url`] and returns the response.
     ///
-    /// Use [`Self::get_ok_json()`] if this response is intended to parsed as JSON.
+    /// Use [`Self::get_ok()`] if this response is intended to parsed as JSON.
     ///
     /// ## Panics
     ///
@@ -55,33 +212,94 @@ impl ApiClient {
     ///
     /// ## Errors
     ///
-    /// If [`reqwest::ClientBuilder`] fails or an
-    /// error is propagated from [`Self::handle_ratelimit`].
+    /// An `Err()` value is returned if:
+    ///
+    /// * every attempt was ratelimited ([`ApiError::RateLimited`])
+    /// * the request couldn't be built or sent ([`ApiError::Network`])
+    /// * a 429 response didn't say when to retry (no `Retry-After` header)
+    /// * a [middleware](`Middleware`) refused the request, e.g. once the run's
+    ///   budget is used up or while offline
+    ///
+    /// The status isn't checked otherwise, so 5xx responses are returned as they are,
+    /// whereas [`Self::get_ok()`] turns them into [`ApiError::ServerError`].
     pub async fn get(&self, endpoint: Endpoint) -> Result<reqwest::Response> {
+        self.send(RequestId::next(), Method::GET, &endpoint, None)
+            .await
+    }
+
+    /// Sends a `method` request with an optional JSON `body` to the `endpoint`,
+    /// retrying when ratelimited, and returns the response.
+    ///
+    /// The body is serialized beforehand so that it can be resent on retries.
+    /// Each attempt is passed through [`Self::middleware`].
+    ///
+    /// Log lines about the request are prefixed with its `id`.
+    async fn send(
+        &self,
+        id: RequestId,
+        method: Method,
+        endpoint: &Endpoint,
+        body: Option<Vec<u8>>,
+    ) -> Result<reqwest::Response> {
         let uri = endpoint.as_string();
         let url = self.base_url.join(&uri).into_diagnostic()?;
 
-        trace!("Sending GET request, url={url}");
+        trace!("[{id}] Sending {method} request, url={url}");
         let mut current_attempt = 0;
+        let mut retry_after = None;
 
         let r = loop {
-            if current_attempt >= self.max_retries {
-                bail!(
-                    "`ApiClient::get()`: exhausted all retry attempts (max_retries={})",
-                    self.max_retries
+            if current_attempt >= self.retry_policy.max_retries {
+                warn!(
+                    "[{id}] Exhausted all retry attempts for {method} request (max_retries={})",
+                    self.retry_policy.max_retries
                 );
+                bail!(ApiError::RateLimited {
+                    id,
+                    endpoint: endpoint.clone(),
+                    retry_after,
+                });
+            }
+
+            let mut request = self.client.request(method.clone(), url.clone());
+
+            if let Some(body) = &body {
+                request = request
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(body.clone());
+            }
+
+            let mut request = request
+                .build()
+                .map_err(|source| ApiError::Network { id, source })?;
+
+            for middleware in &self.middleware {
+                middleware.before(&mut request).await?;
+            }
+
+            let sent_at = Utc::now();
+            let start = Instant::now();
+            let r = self.client.execute(request).await;
+
+            let exchange = Exchange {
+                request_id: id,
+                method: method.clone(),
+                url: url.clone(),
+                sent_at,
+                elapsed: start.elapsed(),
+            };
+
+            for middleware in self.middleware.iter().rev() {
+                middleware.after(&exchange, r.as_ref().ok()).await?;
             }
 
-            let r = self
-                .client
-                .get(url.clone())
-                .send()
-                .await
-                .into_diagnostic()?;
+            let r = r.map_err(|source| ApiError::Network { id, source })?;
 
             if r.status() == StatusCode::TOO_MANY_REQUESTS {
                 current_attempt += 1;
-                Self::handle_ratelimit(r.headers(), current_attempt).await?;
+                let headers = ResponseHeaders::from_headers(r.headers());
+                retry_after = Some(Self::handle_ratelimit(id, &headers, current_attempt).await?);
+                record_retry();
                 continue;
             }
 
@@ -91,74 +309,248 @@ impl ApiClient {
         Ok(r)
     }
 
-    /// Fetches from the `endpoint` and parses the response as JSON.
+    /// Fetches from the `endpoint` and parses the response as `T`, which can be
+    /// [`serde_json::Value`] for responses without a type of their own.
     ///
-    /// The `Ok()` value contains this JSON response.
+    /// The `Ok()` value contains the parsed body, along with the response's status
+    /// and [headers](`ResponseHeaders`) (e.g. how much of the ratelimit is left).
     ///
     /// ## Errors
     ///
     /// An `Err()` value is returned if it's either:
     ///
-    /// * not parsable as JSON
+    /// * not parsable as `T`
+    /// * larger than [`Self::max_body_size`]
     /// * bubbling up an `Err()` from [`Self::get()`]
     /// * invalid due to the `r_json["result"]` field
     ///     - expects `"result": "ok"` but may be `"result": "error"`
     ///
     /// This should be preferred over using [`Self::get()`]
     /// if the response is intended to be parsed as JSON.
-    pub async fn get_ok_json(&self, endpoint: Endpoint) -> Result<serde_json::Value> {
-        let r = self.get(endpoint.clone()).await?;
+    pub async fn get_ok<T: DeserializeOwned>(&self, endpoint: Endpoint) -> Result<ApiResponse<T>> {
+        let id = RequestId::next();
+        let r = self.send(id, Method::GET, &endpoint, None).await?;
+        self.parse_ok(id, &endpoint, r).await
+    }
+
+    /// Like [`Self::get_ok()`], but only returns the parsed body.
+    ///
+    /// ## Errors
+    ///
+    /// The same as [`Self::get_ok()`].
+    pub async fn get_ok_parsed<T: DeserializeOwned>(&self, endpoint: Endpoint) -> Result<T> {
+        self.get_ok(endpoint).await.map(ApiResponse::into_body)
+    }
+
+    /// Sends a POST request with `body` as JSON to the `endpoint`, then
+    /// validates and parses the response like [`Self::get_ok()`].
+    ///
+    /// ## Errors
+    ///
+    /// If `body` can't be serialized, or the same as [`Self::get_ok()`].
+    pub async fn post_json<B: Serialize, T: DeserializeOwned>(
+        &self,
+        endpoint: Endpoint,
+        body: &B,
+    ) -> Result<T> {
+        self.send_json(Method::POST, endpoint, body).await
+    }
+
+    /// Sends a PUT request with `body` as JSON to the `endpoint`, then
+    /// validates and parses the response like [`Self::get_ok()`].
+    ///
+    /// ## Errors
+    ///
+    /// If `body` can't be serialized, or the same as [`Self::get_ok()`].
+    pub async fn put_json<B: Serialize, T: DeserializeOwned>(
+        &self,
+        endpoint: Endpoint,
+        body: &B,
+    ) -> Result<T> {
+        self.send_json(Method::PUT, endpoint, body).await
+    }
+
+    /// Sends a DELETE request to the `endpoint` and returns the
+    /// response as JSON, validated like [`Self::get_ok()`].
+    ///
+    /// ## Errors
+    ///
+    /// The same as [`Self::get_ok()`].
+    pub async fn delete(&self, endpoint: Endpoint) -> Result<serde_json::Value> {
+        let id = RequestId::next();
+        let r = self.send(id, Method::DELETE, &endpoint, None).await?;
+        self.parse_ok(id, &endpoint, r)
+            .await
+            .map(ApiResponse::into_body)
+    }
+
+    async fn send_json<B: Serialize, T: DeserializeOwned>(
+        &self,
+        method: Method,
+        endpoint: Endpoint,
+        body: &B,
+    ) -> Result<T> {
+        let body = serde_json::to_vec(body).into_diagnostic()?;
+        let id = RequestId::next();
+        let r = self.send(id, method, &endpoint, Some(body)).await?;
+        self.parse_ok(id, &endpoint, r)
+            .await
+            .map(ApiResponse::into_body)
+    }
+
+    /// Reads `r` (the response of `endpoint`, sent as request `id`), checking
+    /// its `result` field and status code before parsing it as `T`.
+    async fn parse_ok<T: DeserializeOwned>(
+        &self,
+        id: RequestId,
+        endpoint: &Endpoint,
+        r: reqwest::Response,
+    ) -> Result<ApiResponse<T>> {
+        /// Only the `result` field, so that it can be checked without parsing everything.
+        #[derive(Deserialize)]
+        struct ResultField<'a> {
+            #[serde(borrow)]
+            result: Option<&'a str>,
+        }
+
         let status_code = r.status();
         let success = r.status().is_success();
-        let r_text = r.text().await.into_diagnostic()?;
+        let headers = ResponseHeaders::from_headers(r.headers());
+        let r_bytes = self.read_body_limited(id, endpoint, r).await?;
+
+        trace!("[{id}] r_text={:?}", String::from_utf8_lossy(&r_bytes));
+
+        if !success {
+            // only the error details are needed, so parse the body once, straight into a `Value`
+            let r_json = serde_json::from_slice(&r_bytes).unwrap_or_else(|e| {
+                error!("[{id}] Error parsing JSON: {e:#?}");
+                error!(
+                    "[{id}] Raw response body as text: {:#?}",
+                    String::from_utf8_lossy(&r_bytes)
+                );
+                serde_json::Value::Null
+            });
+
+            bail!(ApiError::from_response(
+                id,
+                endpoint,
+                &r_json,
+                status_code,
+                &headers
+            ));
+        }
+
+        let result_field: ResultField = match serde_json::from_slice(&r_bytes) {
+            Ok(result_field) => result_field,
+            Err(e) => {
+                error!("[{id}] Error parsing JSON: {e:#?}");
+                error!(
+                    "[{id}] Raw response body as text: {:#?}",
+                    String::from_utf8_lossy(&r_bytes)
+                );
+
+                bail!(ApiError::DeserializeFailed {
+                    id,
+                    endpoint: endpoint.clone(),
+                    path: None,
+                    value: None,
+                    source: e,
+                });
+            }
+        };
+
+        if result_field.result.is_none_or(|result| result == "error") {
+            let r_json = serde_json::from_slice(&r_bytes).unwrap_or_default();
+            bail!(ApiError::from_response(
+                id,
+                endpoint,
+                &r_json,
+                status_code,
+                &headers
+            ));
+        }
 
-        trace!("r_text={r_text:?}");
+        if headers.ratelimit_remaining == Some(0) {
+            debug!(
+                "[{id}] Used up the ratelimit of endpoint {endpoint:?} (limit={:?}, retry_after={:?})",
+                headers.ratelimit_limit, headers.retry_after
+            );
+        }
 
-        let r_json: serde_json::Value = serde_json::from_str(&r_text).map_err(|e| {
-            error!("Error parsing JSON: {e:#?}");
-            error!("Raw response body as text: {r_text:#?}");
-            ApiError::blank(&endpoint, status_code)
+        let body = from_slice_with_path(&r_bytes).map_err(|e| {
+            error!("[{id}] Error parsing response of endpoint {endpoint:?}: {e}");
+            ApiError::deserialize_failed(id, endpoint, e)
         })?;
 
-        let result = r_json
-            .get("result")
-            .and_then(|r| r.as_str())
-            .unwrap_or("error");
+        Ok(ApiResponse {
+            body,
+            status: status_code,
+            headers,
+        })
+    }
+
+    /// Reads the body of `r` chunk-by-chunk, bailing as soon as it exceeds [`Self::max_body_size`].
+    ///
+    /// This avoids buffering pathologically large responses in full before rejecting them.
+    async fn read_body_limited(
+        &self,
+        id: RequestId,
+        endpoint: &Endpoint,
+        mut r: reqwest::Response,
+    ) -> Result<Vec<u8>> {
+        let limit = self.max_body_size;
+        let content_length = r.content_length();
 
-        if result == "error" || !success {
-            bail!(ApiError::new(&endpoint, &r_json, status_code));
+        if content_length.is_some_and(|len| len > limit as u64) {
+            bail!(ApiError::BodyTooLarge {
+                id,
+                endpoint: endpoint.clone(),
+                limit,
+                size: content_length,
+            });
         }
 
-        Ok(r_json)
+        #[allow(clippy::cast_possible_truncation)]
+        let mut body = Vec::with_capacity(content_length.unwrap_or(0) as usize);
+
+        while let Some(chunk) = r
+            .chunk()
+            .await
+            .map_err(|source| ApiError::Network { id, source })?
+        {
+            if body.len() + chunk.len() > limit {
+                bail!(ApiError::BodyTooLarge {
+                    id,
+                    endpoint: endpoint.clone(),
+                    limit,
+                    size: content_length,
+                });
+            }
+
+            body.extend_from_slice(&chunk);
+        }
+
+        Ok(body)
     }
 
-    /// Sleeps and logs ratelimit based off of provided `headers`.
-    async fn handle_ratelimit(headers: &HeaderMap, retry_count: u32) -> Result<()> {
-        let retry_after = Self::get_retry_after(headers)?;
-        let sleep_duration = Duration::from_secs(u64::from(retry_after));
+    /// Sleeps and logs ratelimit based off of provided `headers`, returning how long it slept.
+    async fn handle_ratelimit(
+        id: RequestId,
+        headers: &ResponseHeaders,
+        retry_count: u32,
+    ) -> Result<Duration> {
+        let sleep_duration = headers
+            .retry_after
+            .ok_or_else(|| miette::miette!("couldn't find `retry-after` header"))?;
 
         if !RATELIMIT_LOGGED.swap(true, Ordering::SeqCst) {
-            warn!("Ratelimited (received 429: Too Many Requests), attempt {retry_count}");
-            warn!("Sleeping for {}s...", sleep_duration.as_secs());
+            warn!("[{id}] Ratelimited (received 429: Too Many Requests), attempt {retry_count}");
+            warn!("[{id}] Sleeping for {}s...", sleep_duration.as_secs());
         }
 
         tokio::time::sleep(sleep_duration).await;
         RATELIMIT_LOGGED.store(false, Ordering::SeqCst);
 
-        Ok(())
-    }
-
-    /// Attempts to parse a response's headers for `Retry-After` headers or equivalent.
-    fn get_retry_after(headers: &HeaderMap) -> Result<u32> {
-        let retry_in = headers
-            .get("retry-after")
-            .or_else(|| headers.get("x-ratelimit-retry-in"))
-            .ok_or_else(|| miette::miette!("couldn't find `retry-after` header"))?
-            .to_str()
-            .into_diagnostic()?;
-
-        retry_in
-            .parse()
-            .map_err(|e| miette::miette!("failed to parse retry_in={retry_in}: {e}"))
+        Ok(sleep_duration)
     }
 }
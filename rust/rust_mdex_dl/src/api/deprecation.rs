@@ -0,0 +1,181 @@
+//! Contains [`ServerNotice`], the headers Manga-Dex attaches to a response about the request
+//! (its own `X-Request-ID`) or about the API changing (`Deprecation`, `Sunset`, `Warning` and
+//! `Link`s to what replaces it), and [`WatchDeprecations`], the middleware that logs them.
+//!
+//! The first breaking change signalled in a run is also printed as a warning, since it means
+//! this may stop working once the endpoint is removed. See [`deprecations`] for every one seen.
+//!
+//! ## References
+//!
+//! - [RFC 9745](https://www.rfc-editor.org/rfc/rfc9745) (`Deprecation`)
+//! - [RFC 8594](https://www.rfc-editor.org/rfc/rfc8594) (`Sunset`)
+
+use crate::{
+    api::middleware::{Exchange, Middleware},
+    progress::multi_progress,
+};
+
+use std::{
+    collections::{BTreeMap, btree_map::Entry},
+    fmt,
+    sync::{LazyLock, Mutex},
+};
+
+use chrono::DateTime;
+use console::style;
+use futures::future::BoxFuture;
+use miette::Result;
+use reqwest::header::{HeaderMap, LINK, WARNING};
+
+/// The breaking changes signalled so far this run, by the path of the endpoint.
+static DEPRECATIONS: LazyLock<Mutex<BTreeMap<String, ServerNotice>>> =
+    LazyLock::new(|| Mutex::new(BTreeMap::new()));
+
+/// What Manga-Dex said about a request in its response's headers, see the [module docs](`self`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServerNotice {
+    /// Manga-Dex's own id for the request, which it asks for in bug reports.
+    pub request_id: Option<String>,
+    /// When the endpoint was (or will be) deprecated, as sent (e.g. `@1688169599`).
+    pub deprecation: Option<String>,
+    /// When the endpoint will stop working, as an HTTP date.
+    pub sunset: Option<String>,
+    /// The targets of `Link`s with `rel="deprecation"` or `rel="sunset"`, explaining the change.
+    pub links: Vec<String>,
+    /// Every `Warning` header, e.g. `299 - "Deprecated API"`.
+    pub warnings: Vec<String>,
+}
+
+impl ServerNotice {
+    /// Reads the notice from a response's `headers`, ignoring headers that aren't valid text.
+    #[must_use]
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let text = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.trim().to_string())
+        };
+
+        let links = headers
+            .get_all(LINK)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .filter(|link| {
+                let link = link.replace([' ', '"'], "").to_lowercase();
+                link.contains("rel=deprecation") || link.contains("rel=sunset")
+            })
+            .filter_map(|link| {
+                let (_, rest) = link.split_once('<')?;
+                Some(rest.split_once('>')?.0.to_string())
+            })
+            .collect();
+
+        Self {
+            request_id: text("x-request-id"),
+            deprecation: text("deprecation"),
+            sunset: text("sunset"),
+            links,
+            warnings: headers
+                .get_all(WARNING)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+
+    /// Whether this signals that the endpoint is changing, i.e. it's deprecated, will be
+    /// removed, or came with a persistent (`299`) warning. Other warnings, such as caches'
+    /// `110` (response is stale), say nothing about the API.
+    #[must_use]
+    pub fn is_breaking(&self) -> bool {
+        self.deprecation.is_some()
+            || self.sunset.is_some()
+            || self
+                .warnings
+                .iter()
+                .any(|w| w.trim_start().starts_with("299"))
+    }
+}
+
+/// Describes the breaking changes of the notice, e.g.
+/// `deprecated since 2023-06-30, removed after Sat, 01 Mar 2025 00:00:00 GMT`.
+impl fmt::Display for ServerNotice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+
+        if let Some(deprecation) = &self.deprecation {
+            // RFC 9745 dates are `@` followed by a unix timestamp
+            let since = deprecation
+                .strip_prefix('@')
+                .and_then(|t| t.parse().ok())
+                .and_then(|t| DateTime::from_timestamp(t, 0))
+                .map(|t| format!(" since {}", t.format("%Y-%m-%d")));
+
+            parts.push(format!("deprecated{}", since.unwrap_or_default()));
+        }
+
+        if let Some(sunset) = &self.sunset {
+            parts.push(format!("removed after {sunset}"));
+        }
+
+        parts.extend(self.warnings.iter().map(|w| format!("warning: {w}")));
+        parts.extend(self.links.iter().map(|l| format!("see {l}")));
+
+        f.write_str(&parts.join(", "))
+    }
+}
+
+/// Returns every breaking change signalled so far this run, by the path of the endpoint.
+#[must_use]
+pub fn deprecations() -> BTreeMap<String, ServerNotice> {
+    DEPRECATIONS.lock().map(|d| d.clone()).unwrap_or_default()
+}
+
+/// Logs Manga-Dex's id for every request, and records (and warns about) every endpoint
+/// it signals a breaking change for, see the [module docs](`self`).
+#[derive(Debug, Clone, Copy)]
+pub struct WatchDeprecations;
+
+impl Middleware for WatchDeprecations {
+    fn after<'a>(
+        &'a self,
+        exchange: &'a Exchange,
+        response: Option<&'a reqwest::Response>,
+    ) -> BoxFuture<'a, Result<()>> {
+        let id = exchange.request_id;
+        let notice = response.map(|r| ServerNotice::from_headers(r.headers()));
+
+        if let Some(server_id) = notice.as_ref().and_then(|n| n.request_id.as_ref()) {
+            trace!("[{id}] Manga-Dex request id: {server_id}");
+        }
+
+        if let Some(notice) = notice.filter(ServerNotice::is_breaking)
+            && let Ok(mut seen) = DEPRECATIONS.lock()
+        {
+            let first = seen.is_empty();
+
+            if let Entry::Vacant(entry) = seen.entry(exchange.url.path().to_string()) {
+                let path = entry.key();
+                warn!("[{id}] Manga-Dex signalled a breaking change for {path}: {notice}");
+
+                if first {
+                    let message = style(format!(
+                        "Warning: Manga-Dex says part of its API used here is changing \
+                        ({path}: {notice}), so this may need updating"
+                    ))
+                    .yellow();
+
+                    // drawn above any progress bars, rather than through them
+                    multi_progress().suspend(|| eprintln!("{message}"));
+                }
+
+                entry.insert(notice);
+            }
+        }
+
+        Box::pin(async { Ok(()) })
+    }
+}
@@ -2,31 +2,84 @@
 
 use crate::{
     api::{
-        client::ApiClient,
+        adaptive::{AdaptiveLimit, AdaptivePermit, Outcome},
+        client::{ApiClient, DEFAULT_USER_AGENT},
         endpoints::Endpoint,
-        models::{Chapter, Manga},
+        models::{Aggregate, Chapter, ChapterStatistics, MAX_STATISTICS_IDS, Manga},
+        preflight::preflight,
+        ratelimit::RateLimiter,
+        request_id::RequestId,
     },
-    config::{Config, ImageQuality, Images},
+    budget::{check_budget, record_request},
+    config::{
+        Config, DownloadOrder, ImageQuality, Images, Naming, Notifications, NotifyEvent, Preflight,
+        Progress, Storage, default_manga_permits, default_package_permits,
+    },
+    disk::{average_page_size, check_space},
+    images::{ImageFormat, looks_like_markup, postprocess, verify_image},
+    library::{ChapterEntry, LibraryIndex},
+    manifest::{ChapterManifest, ManifestPage, sha256_hex},
+    metadata::ChapterMetadata,
+    metrics::{record_chapter, record_image, record_retry},
+    naming::{MIN_NUM_WIDTH, chapter_values, manga_values, num_width, series_num_width},
+    notify::{Notification, Notifier},
+    order::sort_chapters,
     paths::manga_save_dir,
+    progress::{JobProgress, ProgressEvent, emit, multi_progress, print_line},
+    queue::DownloadQueue,
+    repackage::{ChapterPackaging, package_chapter},
+    store::{add_to_store, link_stored},
 };
 
 use std::{
-    path::PathBuf,
+    collections::{BTreeMap, HashMap},
+    num::NonZeroUsize,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
     sync::{
         Arc,
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
     },
+    time::Duration,
 };
 
 use bytes::Bytes;
+use chrono::Utc;
+use console::style;
+use futures::Stream;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use isolang::Language;
-use miette::{ErrReport, IntoDiagnostic, Result};
-use reqwest::{self, Client, Url};
-use sanitise_file_name::sanitise;
+use miette::{Context, Diagnostic, ErrReport, IntoDiagnostic, Result, bail, miette};
+use reqwest::{
+    self, Client, StatusCode, Url,
+    header::{CONTENT_LENGTH, CONTENT_TYPE},
+};
 use serde::Deserialize;
-use serde_json;
-use tokio::{sync::Semaphore, time::Instant};
+use thiserror::Error;
+use tokio::{
+    sync::{
+        Semaphore,
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+    },
+    task::{AbortHandle, JoinSet},
+    time::Instant,
+};
+use tracing::Instrument;
+use uuid::Uuid;
+
+/// Whether downloads are only estimated, set by [`set_dry_run`].
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// Makes [`DownloadClient::download_chapters`] only print an estimate of what would be
+/// downloaded (`--dry-run`), without writing anything.
+pub fn set_dry_run(dry_run: bool) {
+    DRY_RUN.store(dry_run, Ordering::Relaxed);
+}
+
+/// Returns true if downloads are only estimated, see [`set_dry_run`].
+pub fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
 
 /// Stores the response structure of the `GetChapterCdn`
 /// endpoint for deserializing.
@@ -44,6 +97,18 @@ struct ChapterCdnData {
 struct ChapterCdn {
     base_url: Url,
     chapter: ChapterCdnData,
+    /// When this was fetched, since its urls expire, see [`Self::is_stale`].
+    #[serde(skip, default = "Instant::now")]
+    fetched_at: Instant,
+}
+
+/// An image request refused with 403 or 410, which usually means
+/// that the [`ChapterCdn`] it came from expired.
+#[derive(Debug, Error, Diagnostic)]
+#[error("image request {id} was refused with {status}, its cdn url may have expired")]
+struct CdnRefused {
+    id: RequestId,
+    status: StatusCode,
 }
 
 impl ChapterCdn {
@@ -54,21 +119,33 @@ impl ChapterCdn {
     /// - <https://api.mangadex.org/docs/2-limitations/#endpoint-specific-rate-limits>
     const RATELIMIT: u32 = 40;
 
-    /// Constructs a new [`ChapterCdn`] for the given [`Chapter`]
-    pub async fn new(api: &ApiClient, chapter: &Chapter) -> Result<Self> {
+    /// How long a cdn is trusted for before being fetched again when a chapter starts.
+    /// Manga-Dex@Home urls are only valid for a while (about 15 minutes).
+    const MAX_AGE: Duration = Duration::from_mins(10);
+
+    /// Returns true if this was fetched more than [`Self::MAX_AGE`] ago.
+    fn is_stale(&self) -> bool {
+        self.fetched_at.elapsed() > Self::MAX_AGE
+    }
+
+    /// Constructs a new [`ChapterCdn`] for the given [`Chapter`], waiting for `limiter`
+    /// (which is shared by every cdn request) first.
+    pub async fn new(api: &ApiClient, chapter: &Chapter, limiter: &RateLimiter) -> Result<Self> {
         debug!("Fetching CDN for chapter_uuid={}", chapter.uuid());
         let endpoint = Endpoint::GetChapterCdn(chapter.uuid());
 
-        let r_json = api.get_ok_json(endpoint).await.map_err(|e| {
+        limiter.acquire().await;
+        let response = api.get_ok(endpoint).await.map_err(|e| {
             error!(
                 "Failed to fetch cdn for chapter {}: {e}",
-                chapter.formatted_title()
+                chapter.formatted_title(MIN_NUM_WIDTH)
             );
             error!("Chapter info: {chapter:?}");
-            miette::miette!("failed to fetch {}", chapter.uuid())
+            e.wrap_err(format!("failed to fetch {}", chapter.uuid()))
         })?;
+        limiter.observe(&response.headers);
 
-        let cdn = serde_json::from_value::<Self>(r_json).into_diagnostic()?;
+        let cdn: Self = response.body;
         let num_lossless = cdn.chapter.data.len();
         let num_lossy = cdn.chapter.data_saver.len();
 
@@ -102,6 +179,36 @@ impl ChapterCdn {
         Ok(cdn)
     }
 
+    /// Returns the quality to download in, which is `preferred` unless it has no images.
+    ///
+    /// In that case, the other quality is used (and a warning is logged) instead.
+    ///
+    /// ## Errors
+    ///
+    /// If neither quality has any images, since there's nothing to download.
+    fn resolve_quality(&self, preferred: &ImageQuality, chapter: &Chapter) -> Result<ImageQuality> {
+        let has_images = |quality: &ImageQuality| match quality {
+            ImageQuality::Lossless => !self.chapter.data.is_empty(),
+            ImageQuality::Lossy => !self.chapter.data_saver.is_empty(),
+        };
+
+        if has_images(preferred) {
+            return Ok(preferred.clone());
+        }
+
+        let fallback = preferred.other();
+
+        if has_images(&fallback) {
+            warn!(
+                "Chapter {} has no {preferred:?} images, falling back to {fallback:?}",
+                chapter.uuid()
+            );
+            return Ok(fallback);
+        }
+
+        bail!("chapter {} has no images in either quality", chapter.uuid());
+    }
+
     /// Constructs the image urls in the format:
     ///
     /// `$.baseUrl / $QUALITY / $.chapter.hash / $.chapter.$QUALITY[*]`
@@ -137,7 +244,7 @@ impl ChapterCdn {
             images.push(url_prefix.join(name).into_diagnostic()?);
         }
 
-        debug!("first_image_url={:?}", images.first().map(Url::as_str),);
+        debug!("first_image_url={:?}", images.first().map(Url::as_str));
 
         trace!(
             "all_image_urls={:?}",
@@ -151,9 +258,19 @@ impl ChapterCdn {
 /// Stores info needed for downloading a chapter; used in [`DownloadClient::download_chapter`]
 #[derive(Debug)]
 struct ChapterDownloadInfo {
+    /// For fetching the cdn again when it's [stale](`ChapterCdn::is_stale`).
+    api: ApiClient,
     chapter: Chapter,
     cdn: ChapterCdn,
     pb: ProgressBar,
+    /// Where [`DownloadEvent`]s are sent, if anywhere.
+    events: EventSink,
+    /// Whether pages already in the chapter's dir are reused instead of downloaded again.
+    resume: bool,
+    /// How many digits the chapter's number is padded to, see [`num_width`].
+    num_width: usize,
+    /// The chapter's forum thread for `chapter.json`, see [`DownloadClient::chapter_thread_urls`].
+    thread_url: Option<String>,
 }
 
 impl ChapterDownloadInfo {
@@ -171,348 +288,2237 @@ impl ChapterDownloadInfo {
         pb
     }
 
-    /// Using a chapter, fetches its cdn and gives it a progress bar.
-    async fn new(api: &ApiClient, chapter: Chapter) -> Result<Self> {
-        let cdn = ChapterCdn::new(api, &chapter).await?;
+    /// Using a chapter, fetches its cdn (waiting for `cdn_limiter`) and gives it a progress bar.
+    async fn new(api: &ApiClient, chapter: Chapter, cdn_limiter: &RateLimiter) -> Result<Self> {
+        let cdn = ChapterCdn::new(api, &chapter, cdn_limiter).await?;
         let num_images = cdn.chapter.data.len();
         let pb = Self::get_progress_bar(num_images as u64);
 
-        Ok(Self { chapter, cdn, pb })
+        Ok(Self {
+            api: api.clone(),
+            chapter,
+            cdn,
+            pb,
+            events: EventSink::default(),
+            resume: false,
+            num_width: MIN_NUM_WIDTH,
+            thread_url: None,
+        })
     }
 }
 
-/// Handles fetching of cdns and downloading of chapters.
+/// An event from [`DownloadClient::download_chapter_stream`].
 #[derive(Debug, Clone)]
-pub struct DownloadClient {
-    client: Client,
-    language: Language,
-    image_semaphore: Arc<Semaphore>,
-    chapter_semaphore: Arc<Semaphore>,
+pub enum DownloadEvent {
+    /// A page started downloading (or being reused, if it was already saved).
+    PageStarted { page: String },
+    /// A page was saved as `bytes` bytes, possibly by an earlier attempt (if `resumed`).
+    PageFinished {
+        page: String,
+        bytes: usize,
+        resumed: bool,
+    },
+    /// Every page was saved, and the chapter's manifest was written.
+    ChapterFinished { entry: ChapterEntry },
+    /// The chapter couldn't be downloaded. This is always the last event.
+    Failed { error: String },
 }
 
-impl DownloadClient {
-    /// Constructs a new [`DownloadClient`].
-    ///
-    /// ## Errors
-    ///
-    /// An error can occur if [`Client::builder`] fails.
-    pub fn new(cfg: &Config) -> Result<Self> {
-        let user_agent = cfg.client.user_agent.clone();
-        let chapter_permits = cfg.concurrency.chapter_permits;
-        let image_permits = cfg.concurrency.image_permits;
+/// Sends [`DownloadEvent`]s to a [stream](`DownloadClient::download_chapter_stream`),
+/// or nowhere if there isn't one.
+#[derive(Debug, Clone, Default)]
+struct EventSink(Option<UnboundedSender<DownloadEvent>>);
 
-        let client = Client::builder()
-            .user_agent(user_agent)
-            .build()
-            .into_diagnostic()?;
+impl EventSink {
+    /// Sends `event`, ignoring it if the stream was dropped.
+    fn send(&self, event: DownloadEvent) {
+        if let Some(sender) = &self.0 {
+            let _ = sender.send(event);
+        }
+    }
+}
 
-        let image_semaphore = Arc::from(Semaphore::new(image_permits));
-        let language = cfg.client.language;
-        let chapter_semaphore = Arc::from(Semaphore::new(chapter_permits));
+/// What the pages of a chapter share while being downloaded, see [`DownloadClient::download_page`].
+struct PageContext {
+    api: ApiClient,
+    chapter: Chapter,
+    quality: ImageQuality,
+    /// The url of each page, replaced by [`DownloadClient::refresh_urls`] if they expire.
+    urls: tokio::sync::Mutex<Vec<Url>>,
+    chapter_dir: PathBuf,
+    images_cfg: Images,
+    /// Pages already saved by an earlier attempt, see [`DownloadClient::saved_pages`].
+    saved_pages: HashMap<String, String>,
+    events: EventSink,
+    /// The total size of the pages saved so far.
+    chapter_size: AtomicUsize,
+    pb: ProgressBar,
+    start: Instant,
+}
 
-        Ok(Self {
-            client,
-            language,
-            image_semaphore,
-            chapter_semaphore,
-        })
+/// Aborts a task when dropped, so that dropping a stream stops its download.
+struct AbortOnDrop(AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
     }
+}
 
-    /* Helpers for `download_chapter()` */
+/// The outcome of [`DownloadClient::download_chapters`].
+#[derive(Debug, Clone, Default)]
+pub struct DownloadSummary {
+    /// Chapters which were fully downloaded and saved.
+    pub downloaded: Vec<Chapter>,
+    /// Chapters which couldn't be downloaded, along with the reason why.
+    pub failed: Vec<(Chapter, String)>,
+    /// Chapters hosted off Manga-Dex, which were skipped (see [`Chapter::external_url`]).
+    pub external: Vec<Chapter>,
+    /// Chapters marked as unavailable, which were skipped (see [`Chapter::is_unavailable`]).
+    pub unavailable: Vec<Chapter>,
+    /// The total size of all saved images in bytes.
+    pub total_bytes: usize,
+    /// Where downloaded chapters were packaged, if `[package]` is set.
+    pub packaged: Vec<PathBuf>,
+    /// Chapters which were downloaded but couldn't be packaged, along with the reason why.
+    pub unpackaged: Vec<(Chapter, String)>,
+}
 
-    /// Returns a tuple, `(Bytes, String)` on success.
-    ///
-    /// `Bytes` is self explanatory, while `String` contains the filename
-    /// extension **without the leading dot**. (e.g, "png", not ".png")
-    ///
-    /// Note that the extension can only be "JPEG", "PNG", or "GIF" according to ref.
-    ///
-    /// Reference: <https://api.mangadex.org/docs/04-chapter/upload/#requirements-and-limitations>
-    async fn download_image(&self, image_url: &Url) -> Result<(Bytes, String)> {
-        let ext = image_url.as_str().split('.').next_back().unwrap_or("png");
+/// A chapter that would be downloaded, see [`DownloadClient::estimate_chapters`].
+#[derive(Debug, Clone)]
+pub struct ChapterEstimate {
+    pub chapter: Chapter,
+    /// The number of pages, or `None` if the chapter's cdn couldn't be fetched.
+    pub pages: Option<usize>,
+}
 
-        if !["png", "jpg", "jpeg", "gif"].contains(&ext) {
-            warn!(
-                "Unexpected image url extension {:?} for image url {}",
-                ext,
-                &image_url.as_str()
+/// Lists `chapters` (which are unavailable) greyed out, one per line.
+fn print_unavailable(chapters: &[Chapter]) {
+    for chapter in chapters {
+        print_line(
+            style(format!(
+                "    {} (unavailable)",
+                Notification::chapter_label(chapter)
+            ))
+            .dim(),
+        );
+    }
+}
+
+impl DownloadSummary {
+    /// Prints how many chapters were downloaded, and why any failed.
+    pub fn print(&self) {
+        print_line(
+            style(format!(
+                "Downloaded {} chapters ({:.1} MiB)",
+                self.downloaded.len(),
+                DownloadClient::to_mib(self.total_bytes)
+            ))
+            .green(),
+        );
+
+        for (chapter, reason) in &self.failed {
+            print_line(
+                style(format!(
+                    "Failed to download chapter {}: {reason}",
+                    Notification::chapter_label(chapter)
+                ))
+                .red(),
             );
         }
 
-        let data = self
-            .client
-            .get(image_url.as_ref())
-            .send()
-            .await
-            .into_diagnostic()?
-            .bytes()
-            .await
-            .into_diagnostic()?;
+        if !self.packaged.is_empty() {
+            print_line(style(format!("Packaged {} chapters", self.packaged.len())).green());
+        }
 
-        trace!("Downloaded image {:?}", image_url.as_str());
-        Ok((data, ext.to_string()))
+        for (chapter, reason) in &self.unpackaged {
+            print_line(
+                style(format!(
+                    "Failed to package chapter {}: {reason}",
+                    Notification::chapter_label(chapter)
+                ))
+                .red(),
+            );
+        }
+
+        if !self.unavailable.is_empty() {
+            let message = format!(
+                "Skipped {} unavailable chapters (set `chapters.attempt_unavailable` to try them):",
+                self.unavailable.len()
+            );
+            print_line(style(message).yellow());
+            print_unavailable(&self.unavailable);
+        }
+
+        if !self.external.is_empty() {
+            print_line(
+                style(format!(
+                    "Skipped {} chapters hosted outside of Manga-Dex:",
+                    self.external.len()
+                ))
+                .yellow(),
+            );
+
+            for chapter in &self.external {
+                if let Some(url) = chapter.external_url() {
+                    print_line(format!(
+                        "    {} {}",
+                        Notification::chapter_label(chapter),
+                        style(url).dim()
+                    ));
+                }
+            }
+        }
     }
+}
 
-    /// Saves the image bytes into `chapter_dir` using `page`, which should be zero-padded.
-    ///
-    /// The tuple, `image_info` comes from [`Self::download_image`],
-    /// formatted as `(image_bytes, image_file_format)` accordingly.
-    ///
-    /// `chapter_dir` should follow the format: `project_root/parent_manga/chapter`
-    /// and be created beforehand.
-    async fn save_image(
-        &self,
-        image_info: (Bytes, String),
-        chapter_dir: PathBuf,
-        page: &str,
-    ) -> Result<()> {
-        let filename = format!("{}.{}", page, image_info.1);
-        let save = chapter_dir.join(filename);
+/// Handles fetching of cdns and downloading of chapters.
+#[derive(Debug, Clone)]
+pub struct DownloadClient {
+    client: Client,
+    language: Language,
+    /// Bounds how many images are downloaded at once, adapting to how the cdn responds
+    /// if `concurrency.adaptive` is enabled.
+    image_limit: Arc<AdaptiveLimit>,
+    /// Like [`Self::image_limit`], but for chapters, adapting to how whole chapters go.
+    chapter_limit: Arc<AdaptiveLimit>,
+    /// Bounds CPU-bound post-processing (e.g, conversion) to the number of cores.
+    cpu_semaphore: Arc<Semaphore>,
+    /// Bounds how many manga [`Self::download_many`] downloads at once.
+    manga_semaphore: Arc<Semaphore>,
+    /// Bounds how many chapters are packaged at once, separately from downloading.
+    package_semaphore: Arc<Semaphore>,
+    /// Keeps [`Endpoint::GetChapterCdn`] requests under [`ChapterCdn::RATELIMIT`],
+    /// even when several manga are downloaded at once.
+    cdn_limiter: Arc<RateLimiter>,
+    /// Shared by every manga being downloaded, so that their progress bars don't overlap.
+    pb_multi: MultiProgress,
+    /// The overall progress bar, shown above the per-chapter ones.
+    progress: Arc<JobProgress>,
+    naming: Naming,
+    storage: Storage,
+    /// Whether to download chapters marked as unavailable, see [`Chapter::is_unavailable`].
+    attempt_unavailable: bool,
+    /// The order chapters are downloaded in, see [`sort_chapters`].
+    order: DownloadOrder,
+    notifier: Notifier,
+    preflight: Preflight,
+    /// Whether the [preflight] check already passed, so that it's only done once.
+    preflight_passed: Arc<AtomicBool>,
+    /// How chapters are packaged after downloading, if they are.
+    packaging: Option<ChapterPackaging>,
+}
 
-        tokio::fs::write(&save, image_info.0)
-            .await
-            .into_diagnostic()?;
+/// Builds a [`DownloadClient`] without a [`Config`], see [`DownloadClient::builder`].
+#[derive(Debug, Clone)]
+pub struct DownloadClientBuilder {
+    user_agent: String,
+    language: Language,
+    image_permits: usize,
+    chapter_permits: usize,
+    manga_permits: usize,
+    /// The bounds of image and chapter permits, if they're adaptive.
+    adaptive: Option<(RangeInclusive<usize>, RangeInclusive<usize>)>,
+    naming: Naming,
+    storage: Storage,
+    attempt_unavailable: bool,
+    order: DownloadOrder,
+    notifications: Notifications,
+    progress_template: String,
+    preflight: Preflight,
+    packaging: Option<ChapterPackaging>,
+    package_permits: usize,
+}
 
-        trace!("Saved page {} to {:?}", page, &save.to_str());
-        Ok(())
+impl Default for DownloadClientBuilder {
+    fn default() -> Self {
+        Self {
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            language: Language::Eng,
+            image_permits: 10,
+            chapter_permits: 3,
+            manga_permits: default_manga_permits(),
+            adaptive: None,
+            naming: Naming::default(),
+            storage: Storage::default(),
+            attempt_unavailable: false,
+            order: DownloadOrder::default(),
+            notifications: Notifications::default(),
+            progress_template: Progress::default().template,
+            preflight: Preflight::default(),
+            packaging: None,
+            package_permits: default_package_permits(),
+        }
     }
+}
 
-    /// Helper function for converting bytes to MiB.
-    #[allow(clippy::cast_precision_loss)]
-    #[inline]
-    fn to_mib(num_bytes: usize) -> f64 {
-        num_bytes as f64 / 1_048_576.0
+impl DownloadClientBuilder {
+    /// Sets the user agent sent with every image request, [`DEFAULT_USER_AGENT`] by default.
+    #[must_use]
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Sets the language that titles are named in, English by default.
+    #[must_use]
+    pub const fn language(mut self, language: Language) -> Self {
+        self.language = language;
+        self
     }
 
-    /// Downloads and saves a chapter's images concurrently and returns the total size in bytes.
+    /// Sets how many images, chapters and manga are downloaded at once, like the
+    /// `[concurrency]` section of the config (10, 3 and 2 by default).
     ///
-    /// This also creates the dirs needed to store these images.
-    async fn download_chapter(
-        &self,
-        download_info: ChapterDownloadInfo,
-        parent_manga_title: &str,
-        images_cfg: &Images,
-    ) -> Result<usize> {
-        let images = download_info
-            .cdn
-            .construct_image_urls(&images_cfg.quality)?;
+    /// ## Panics
+    ///
+    /// If any of these are zero, since nothing could ever be downloaded.
+    #[must_use]
+    pub fn permits(
+        mut self,
+        image_permits: usize,
+        chapter_permits: usize,
+        manga_permits: usize,
+    ) -> Self {
+        assert!(
+            image_permits > 0 && chapter_permits > 0 && manga_permits > 0,
+            "permits must be above zero"
+        );
 
-        let zero_pad = format!("{}", images.len()).len();
+        self.image_permits = image_permits;
+        self.chapter_permits = chapter_permits;
+        self.manga_permits = manga_permits;
+        self
+    }
 
-        let chapter_uuid_suffix = download_info.chapter.uuid().to_string()[..8].to_string();
-        let chapter_size = Arc::new(AtomicUsize::new(0));
-        let chapter_title = &download_info.chapter.formatted_title();
+    /// Makes image and chapter permits adapt to how the cdn responds, staying within
+    /// these bounds (see [`AdaptiveLimit`]). They're fixed by default.
+    ///
+    /// ## Panics
+    ///
+    /// If either range is empty or starts at zero.
+    #[must_use]
+    pub fn adaptive_permits(
+        mut self,
+        image_bounds: RangeInclusive<usize>,
+        chapter_bounds: RangeInclusive<usize>,
+    ) -> Self {
+        assert!(
+            [&image_bounds, &chapter_bounds]
+                .iter()
+                .all(|b| *b.start() > 0 && b.start() <= b.end()),
+            "permit bounds must be above zero and not empty"
+        );
 
-        let parent_manga_title_safe = sanitise(parent_manga_title);
-        let chapter_title_safe = sanitise(chapter_title);
+        self.adaptive = Some((image_bounds, chapter_bounds));
+        self
+    }
 
-        let chapter_dir = manga_save_dir()?
-            .join(parent_manga_title_safe)
-            .join(chapter_title_safe);
+    /// Sets how the library's dirs and pages are named, [`Naming::default`] by default.
+    #[must_use]
+    pub fn naming(mut self, naming: Naming) -> Self {
+        self.naming = naming;
+        self
+    }
 
-        tokio::fs::create_dir_all(&chapter_dir)
-            .await
-            .into_diagnostic()?;
+    /// Sets where and how the library is stored, [`Storage::default`] by default.
+    #[must_use]
+    pub fn storage(mut self, storage: Storage) -> Self {
+        self.storage = storage;
+        self
+    }
 
-        let chapter_dir = chapter_dir.canonicalize().into_diagnostic()?;
-        let mut handles = Vec::with_capacity(images.len());
-        let handle_client = Arc::new(self.clone());
+    /// Sets whether to download chapters marked as unavailable, `false` by default.
+    #[must_use]
+    pub const fn attempt_unavailable(mut self, attempt_unavailable: bool) -> Self {
+        self.attempt_unavailable = attempt_unavailable;
+        self
+    }
 
-        info!(
-            "Downloading {} images from chapter {:?} of manga {:?}",
-            images.len(),
-            download_info.chapter.data.attributes.chapter_number,
-            parent_manga_title,
-        );
+    /// Sets the order chapters are downloaded in, [`DownloadOrder::Feed`] by default.
+    #[must_use]
+    pub const fn order(mut self, order: DownloadOrder) -> Self {
+        self.order = order;
+        self
+    }
 
-        let pb = Arc::new(download_info.pb);
-        let start = Instant::now();
+    /// Sets how finished downloads are announced, [`Notifications::default`] by default.
+    #[must_use]
+    pub fn notifications(mut self, notifications: Notifications) -> Self {
+        self.notifications = notifications;
+        self
+    }
 
-        for (i, url) in images.into_iter().enumerate() {
-            let chapter_uuid_suffix = chapter_uuid_suffix.clone();
-            let chapter_dir = chapter_dir.clone();
+    /// Sets the template of the overall progress bar, see [`crate::progress`].
+    #[must_use]
+    pub fn progress_template(mut self, template: impl Into<String>) -> Self {
+        self.progress_template = template.into();
+        self
+    }
 
-            // `Arc<T>` clones
-            let semaphore = self.image_semaphore.clone();
-            let pb = pb.clone();
-            let chapter_size = chapter_size.clone();
-            let h = handle_client.clone();
+    /// Sets when Manga-Dex is checked before downloading, see [`preflight`].
+    #[must_use]
+    pub fn preflight(mut self, preflight: Preflight) -> Self {
+        self.preflight = preflight;
+        self
+    }
 
-            handles.push(tokio::spawn(async move {
-                let _permit = semaphore.acquire().await.into_diagnostic()?;
-                let page = format!("{i:0>zero_pad$}");
-                let data = h.download_image(&url).await?;
+    /// Packages each chapter with `packaging` after downloading it, `permits` at once
+    /// (see [`package_chapter`]). Nothing is packaged by default.
+    ///
+    /// ## Panics
+    ///
+    /// If `permits` is zero, since nothing could ever be packaged.
+    #[must_use]
+    pub fn packaging(mut self, packaging: ChapterPackaging, permits: usize) -> Self {
+        assert!(permits > 0, "permits must be above zero");
+
+        self.packaging = Some(packaging);
+        self.package_permits = permits;
+        self
+    }
 
-                let size_bytes = data.0.len();
+    /// Builds the [`DownloadClient`].
+    ///
+    /// ## Errors
+    ///
+    /// An error can occur if [`Client::builder`] fails.
+    pub fn build(self) -> Result<DownloadClient> {
+        let client = Client::builder()
+            .user_agent(&self.user_agent)
+            .build()
+            .into_diagnostic()?;
 
-                debug!(
-                    "chapter_uuid_suffix={} page={} dl_time_ms={} size_mib={:.3}",
-                    chapter_uuid_suffix,
-                    page,
-                    (Instant::now() - start).as_millis(),
-                    Self::to_mib(size_bytes),
-                );
+        let notifier = Notifier::new(&self.notifications, &self.user_agent)?;
+        let (image_limit, chapter_limit) = match self.adaptive {
+            Some((image_bounds, chapter_bounds)) => (
+                AdaptiveLimit::new("image", self.image_permits, image_bounds),
+                AdaptiveLimit::new("chapter", self.chapter_permits, chapter_bounds),
+            ),
+            None => (
+                AdaptiveLimit::fixed("image", self.image_permits),
+                AdaptiveLimit::fixed("chapter", self.chapter_permits),
+            ),
+        };
+        let cpu_permits = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        let cpu_semaphore = Arc::from(Semaphore::new(cpu_permits));
+        let manga_semaphore = Arc::from(Semaphore::new(self.manga_permits));
+        let package_semaphore = Arc::from(Semaphore::new(self.package_permits));
+        let cdn_limiter = Arc::new(RateLimiter::new(
+            ChapterCdn::RATELIMIT as usize,
+            Duration::from_mins(1),
+        ));
+
+        Ok(DownloadClient {
+            client,
+            language: self.language,
+            image_limit: Arc::new(image_limit),
+            chapter_limit: Arc::new(chapter_limit),
+            cpu_semaphore,
+            manga_semaphore,
+            package_semaphore,
+            cdn_limiter,
+            pb_multi: multi_progress(),
+            progress: Arc::new(JobProgress::new(&self.progress_template)),
+            naming: self.naming,
+            storage: self.storage,
+            attempt_unavailable: self.attempt_unavailable,
+            order: self.order,
+            notifier,
+            preflight: self.preflight,
+            preflight_passed: Arc::default(),
+            packaging: self.packaging,
+        })
+    }
+}
 
-                chapter_size.fetch_add(size_bytes, Ordering::Relaxed);
-                h.save_image(data, chapter_dir, &page).await?;
+impl DownloadClient {
+    /// Constructs a new [`DownloadClient`] from the config.
+    ///
+    /// ## Errors
+    ///
+    /// An error can occur if [`Client::builder`] fails.
+    pub fn new(cfg: &Config) -> Result<Self> {
+        let concurrency = &cfg.concurrency;
+
+        let mut builder = Self::builder()
+            .user_agent(cfg.client.user_agent.clone())
+            .language(cfg.client.language)
+            .permits(
+                concurrency.image_permits,
+                concurrency.chapter_permits,
+                concurrency.manga_permits,
+            );
 
-                pb.inc(1);
-                Ok::<(), ErrReport>(())
-            }));
+        if concurrency.adaptive {
+            builder = builder.adaptive_permits(
+                concurrency.min_image_permits..=concurrency.max_image_permits,
+                concurrency.min_chapter_permits..=concurrency.max_chapter_permits,
+            );
         }
 
-        futures::future::try_join_all(handles)
-            .await
-            .into_diagnostic()?;
+        if let (Some(format), Some(dest)) = (cfg.package.format, &cfg.package.dest) {
+            let packaging = ChapterPackaging::new(cfg, format, dest.clone());
+            builder = builder.packaging(packaging, concurrency.package_permits);
+        }
 
-        let chapter_size = chapter_size.load(Ordering::Relaxed);
+        builder
+            .naming(cfg.naming.clone())
+            .storage(cfg.storage.clone())
+            .attempt_unavailable(cfg.chapters.attempt_unavailable)
+            .order(cfg.download.order)
+            .notifications(cfg.notifications.clone())
+            .progress_template(cfg.progress.template.clone())
+            .preflight(cfg.preflight.clone())
+            .build()
+    }
 
-        info!(
-            "({}) Completed downloads in {}ms, total size is {:.3} MiB",
-            chapter_uuid_suffix,
-            (Instant::now() - start).as_millis(),
-            Self::to_mib(chapter_size),
-        );
+    /// Returns a [`DownloadClientBuilder`], for creating a client without a config.
+    /// Its defaults are the same as the default config's.
+    #[must_use]
+    pub fn builder() -> DownloadClientBuilder {
+        DownloadClientBuilder::default()
+    }
 
-        pb.finish_and_clear();
-        Ok(chapter_size)
+    /* Helpers for `download_chapter()` */
+
+    /// Reports how an image request went to [`Self::image_limit`] and to the run's
+    /// [budget](`crate::budget`). [`Self::chapter_limit`] is told how whole chapters
+    /// went instead, by [`Self::spawn_chapter`].
+    fn record_outcome(&self, outcome: Outcome, status: Option<StatusCode>) {
+        record_request(status);
+        self.image_limit.record(outcome);
     }
 
-    /// Helper for [`Self::download_chapters`].
-    async fn download_batch(
-        &self,
-        batch: Vec<ChapterDownloadInfo>,
-        parent_manga: Arc<Manga>,
-        pb_multi: &MultiProgress,
-        images_cfg: &Images,
-    ) -> Result<usize> {
-        let start = Instant::now();
-        let batch_size = Arc::new(AtomicUsize::new(0));
-        let batch_len = batch.len();
-        let parent_uuid = parent_manga.uuid();
-        let parent_manga_title = parent_manga.title(self.language);
-        let mut handles = Vec::with_capacity(batch.len());
+    /// Returns a tuple, `(Bytes, String)` on success.
+    ///
+    /// `Bytes` is self explanatory, while `String` contains the filename
+    /// extension **without the leading dot**. (e.g, "png", not ".png")
+    ///
+    /// The extension is determined from the image's magic bytes, falling back to the
+    /// `Content-Type` header and then the url. A warning is logged if these disagree.
+    ///
+    /// ## Errors
+    ///
+    /// If the request fails, or the response is empty or an HTML error page.
+    async fn download_image(&self, image_url: &Url) -> Result<(Bytes, String)> {
+        let id = RequestId::next();
+        let url_ext = image_url.path().rsplit('.').next().unwrap_or_default();
 
-        for info in batch {
-            if info.chapter.parent_uuid() != parent_uuid {
-                warn!(
-                    "Expected chapter {} to have parent manga {}, instead got {}",
-                    info.chapter.uuid(),
-                    parent_uuid,
-                    info.chapter.parent_uuid()
-                );
-                warn!("This may lead to chapters being saved to the wrong locations!");
+        check_budget()?;
+        trace!("[{id}] Downloading image {:?}", image_url.as_str());
+        let start = Instant::now();
+        let url_format = ImageFormat::from_extension(url_ext);
+
+        let r = match self.client.get(image_url.as_ref()).send().await {
+            Ok(r) => r,
+            Err(e) => {
+                self.record_outcome(Outcome::Failed, None);
+                return Err(e)
+                    .into_diagnostic()
+                    .wrap_err_with(|| format!("image request {id} failed"));
             }
+        };
 
-            pb_multi.add(info.pb.clone());
+        let outcome = match r.status() {
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => Outcome::Throttled,
+            s if s.is_success() => Outcome::Success(start.elapsed()),
+            _ => Outcome::Failed,
+        };
+        self.record_outcome(outcome, Some(r.status()));
 
-            let h = self.clone();
-            let images_cfg = images_cfg.clone();
-            let parent_manga_title = parent_manga_title.clone();
+        if matches!(r.status(), StatusCode::FORBIDDEN | StatusCode::GONE) {
+            return Err(CdnRefused {
+                id,
+                status: r.status(),
+            }
+            .into());
+        }
 
-            // arc clones
-            let semaphore = self.chapter_semaphore.clone();
-            let batch_size = batch_size.clone();
+        let r = r
+            .error_for_status()
+            .into_diagnostic()
+            .wrap_err_with(|| format!("image request {id} failed"))?;
 
-            handles.push(tokio::spawn(async move {
-                let _permit = semaphore.acquire().await.into_diagnostic()?;
+        let content_type = r
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
 
-                let chapter_size = h
-                    .download_chapter(info, &parent_manga_title, &images_cfg)
-                    .await?;
+        let data = r
+            .bytes()
+            .await
+            .into_diagnostic()
+            .wrap_err_with(|| format!("image request {id} failed"))?;
+
+        if data.is_empty() {
+            bail!(
+                "received an empty body for image {} (request {id})",
+                image_url.as_str()
+            );
+        }
 
-                batch_size.fetch_add(chapter_size, Ordering::Relaxed);
+        let is_html = content_type
+            .as_deref()
+            .is_some_and(|ct| ct.starts_with("text/"))
+            || looks_like_markup(&data);
 
-                Ok::<(), ErrReport>(())
-            }));
+        if is_html {
+            bail!(
+                "received a non-image (content-type={:?}) body for image {} (request {id})",
+                content_type.as_deref().unwrap_or("none"),
+                image_url.as_str()
+            );
         }
 
-        futures::future::try_join_all(handles)
-            .await
-            .into_diagnostic()?;
+        let magic_format = ImageFormat::from_magic(&data);
+        let mime_format = content_type.as_deref().and_then(ImageFormat::from_mime);
+
+        for (source, format) in [("content-type", mime_format), ("url extension", url_format)] {
+            if let (Some(real), Some(claimed)) = (magic_format, format)
+                && real != claimed
+            {
+                warn!(
+                    "[{id}] Image {} has a mismatched {source}: claimed {claimed:?}, actually {real:?}",
+                    image_url.as_str()
+                );
+            }
+        }
 
-        let batch_size = batch_size.load(Ordering::Relaxed);
+        let format = magic_format.or(mime_format).or(url_format);
 
-        info!(
-            "Batch download ({} chapters) completed in {}ms, total size is {:.3} MiB",
-            batch_len,
-            (Instant::now() - start).as_millis(),
-            Self::to_mib(batch_size),
+        let ext = format.map_or_else(
+            || {
+                warn!(
+                    "[{id}] Couldn't determine the format of image {:?}, saving as \"png\"",
+                    image_url.as_str()
+                );
+                "png"
+            },
+            ImageFormat::extension,
         );
 
-        Ok(batch_size)
+        record_image(data.len(), start.elapsed());
+        trace!("[{id}] Downloaded image {:?}", image_url.as_str());
+        Ok((data, ext.to_string()))
     }
 
-    /// Downloads all chapters given.
-    ///
-    /// Chapters are also downloaded concurrently, using
-    /// [`Self::chapter_semaphore`] for the number of permits.
+    /// Applies post-processing from `images_cfg` to a downloaded image (see [`postprocess`]).
     ///
-    /// NOTE: **All of these chapters should come from the same parent manga.**
-    /// A warning is logged otherwise.
-    ///
-    /// ## Errors
+    /// Returns the resulting images in the same `(Bytes, String)` format as
+    /// [`Self::download_image`] (of which there may be two, if a spread was
+    /// split), along with whether the image is a double-page spread.
     ///
-    /// The only errors that can occur here are the
-    /// ones propagated from [`Self::download_batch`].
-    pub async fn download_chapters(
+    /// Processing is done on the blocking thread pool, bounded by [`Self::cpu_semaphore`].
+    async fn postprocess_image(
         &self,
-        api: &ApiClient,
-        chapters: Vec<Chapter>,
-        parent_manga: Manga,
+        image_info: (Bytes, String),
         images_cfg: &Images,
-    ) -> Result<()> {
-        let start = Instant::now();
-        let pb_multi = MultiProgress::new();
-        let parent_manga = Arc::new(parent_manga);
-        let manga_size = Arc::new(AtomicUsize::new(0));
+    ) -> Result<(Vec<(Bytes, String)>, bool)> {
+        let Some(format) = ImageFormat::from_extension(&image_info.1) else {
+            return Ok((vec![image_info], false));
+        };
 
-        info!(
-            "Downloading {} chapters of manga {:?}, manga_uuid={}",
-            chapters.len(),
-            parent_manga.title(self.language),
-            parent_manga.uuid()
-        );
+        let _permit = self.cpu_semaphore.acquire().await.into_diagnostic()?;
+        let data = image_info.0.clone();
+        let images_cfg = images_cfg.clone();
 
-        let mut iter = chapters.into_iter();
-        let batch_size = ChapterCdn::RATELIMIT as usize;
+        let processed =
+            tokio::task::spawn_blocking(move || postprocess(&data, format, &images_cfg))
+                .await
+                .into_diagnostic()??;
 
-        loop {
-            let batch: Vec<_> = iter
-                .by_ref()
-                .take(batch_size)
-                .map(|c| async move { ChapterDownloadInfo::new(api, c).await })
-                .collect();
+        let Some(images) = processed.images else {
+            return Ok((vec![image_info], processed.is_spread));
+        };
 
-            if batch.is_empty() {
+        trace!(
+            "Post-processed image ({} bytes) into {} images ({} bytes)",
+            image_info.0.len(),
+            images.len(),
+            images.iter().map(|(d, _)| d.len()).sum::<usize>()
+        );
+
+        let images = images
+            .into_iter()
+            .map(|(data, format)| (Bytes::from(data), format.extension().to_string()))
+            .collect();
+
+        Ok((images, processed.is_spread))
+    }
+
+    /// Names the images resulting from post-processing `page`.
+    ///
+    /// A single image keeps the page's name, while split spreads are suffixed
+    /// with letters (e.g, "05a", "05b") so that they still sort correctly.
+    fn page_names(page: &str, count: usize) -> Vec<String> {
+        if count == 1 {
+            return vec![page.to_string()];
+        }
+
+        (b'a'..=b'z')
+            .take(count)
+            .map(|c| format!("{page}{}", char::from(c)))
+            .collect()
+    }
+
+    /// Post-processes and saves a downloaded image as one or more pages.
+    async fn process_and_save(
+        &self,
+        image_info: (Bytes, String),
+        chapter_dir: &Path,
+        page: &str,
+        source_url: &Url,
+        images_cfg: &Images,
+    ) -> Result<Vec<ManifestPage>> {
+        let (images, is_spread) = self.postprocess_image(image_info, images_cfg).await?;
+        let names = Self::page_names(page, images.len());
+        let mut saved = Vec::with_capacity(images.len());
+
+        for (name, image) in names.iter().zip(images) {
+            let mut manifest_page = self
+                .save_image(image, chapter_dir, name, source_url)
+                .await?;
+
+            manifest_page.spread = is_spread;
+            saved.push(manifest_page);
+        }
+
+        Ok(saved)
+    }
+
+    /// Saves the image bytes into `chapter_dir` using `page`, which should be zero-padded.
+    ///
+    /// The tuple, `image_info` comes from [`Self::download_image`],
+    /// formatted as `(image_bytes, image_file_format)` accordingly.
+    ///
+    /// `chapter_dir` should follow the format: `project_root/parent_manga/chapter`
+    /// and be created beforehand.
+    ///
+    /// Saving is idempotent, so that retried pages never leave duplicates behind:
+    ///
+    /// * if the page is already saved with identical contents, nothing is written
+    /// * the image is written to `{page}.partial` first, then renamed into place
+    /// * copies of the same page with a different extension (from an earlier,
+    ///   different format guess or quality) are removed
+    ///
+    /// Returns the saved page as a [`ManifestPage`], with `source_url` as its url.
+    async fn save_image(
+        &self,
+        image_info: (Bytes, String),
+        chapter_dir: &Path,
+        page: &str,
+        source_url: &Url,
+    ) -> Result<ManifestPage> {
+        let (data, ext) = image_info;
+        let filename = format!("{page}.{ext}");
+        let save = chapter_dir.join(&filename);
+        let manifest_page = ManifestPage {
+            page: page.to_string(),
+            file: filename,
+            size: data.len() as u64,
+            sha256: sha256_hex(&data),
+            source_url: source_url.clone(),
+            spread: false,
+        };
+
+        let unchanged = match tokio::fs::metadata(&save).await {
+            Ok(m) if m.len() == data.len() as u64 => {
+                tokio::fs::read(&save).await.into_diagnostic()? == data
+            }
+            _ => false,
+        };
+        let partial = chapter_dir.join(format!("{page}.partial"));
+        let dedupe = self.storage.dedupe;
+
+        if unchanged {
+            trace!(
+                "Page {page} is already saved at {:?}, skipping",
+                &save.to_str()
+            );
+        } else if dedupe && link_stored(&manifest_page.sha256, &ext, &save, &partial).await? {
+            trace!("Saved page {page} as a link to an identical page");
+        } else {
+            tokio::fs::write(&partial, data).await.into_diagnostic()?;
+            tokio::fs::rename(&partial, &save).await.into_diagnostic()?;
+
+            trace!("Saved page {} to {:?}", page, &save.to_str());
+        }
+
+        if dedupe {
+            add_to_store(&save, &manifest_page.sha256, &ext).await;
+        }
+
+        let stale = ImageFormat::ALL
+            .iter()
+            .map(|f| f.extension())
+            .filter(|e| *e != ext)
+            .map(|e| chapter_dir.join(format!("{page}.{e}")));
+
+        for path in stale {
+            match tokio::fs::remove_file(&path).await {
+                // the format mismatch itself is already warned about when downloading
+                Ok(()) => debug!("Removed stale copy of page {page} at {:?}", path.to_str()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e).into_diagnostic(),
+            }
+        }
+
+        Ok(manifest_page)
+    }
+
+    /// Decodes every saved page with [`verify_image`], re-downloading
+    /// corrupt pages up to [`Self::VERIFY_ATTEMPTS`] times.
+    ///
+    /// Re-downloaded pages are replaced in `pages`, and
+    /// `chapter_size` is adjusted for them accordingly.
+    ///
+    /// Returns (and logs) the pages which are still corrupt after all attempts.
+    async fn verify_pages(
+        &self,
+        pages: &mut [ManifestPage],
+        chapter_dir: &Path,
+        chapter_size: &AtomicUsize,
+        images_cfg: &Images,
+    ) -> Result<Vec<String>> {
+        let mut pending: Vec<usize> = (0..pages.len()).collect();
+
+        for attempt in 0..=Self::VERIFY_ATTEMPTS {
+            let checks = pending.iter().map(|&i| {
+                let path = chapter_dir.join(&pages[i].file);
+                tokio::task::spawn_blocking(move || verify_image(&path))
+            });
+
+            let results = futures::future::try_join_all(checks)
+                .await
+                .into_diagnostic()?;
+
+            pending = pending
+                .into_iter()
+                .zip(results)
+                .filter_map(|(i, result)| {
+                    result
+                        .inspect_err(|e| warn!("Corrupt page {}: {e}", pages[i].page))
+                        .err()
+                        .map(|_| i)
+                })
+                .collect();
+
+            if pending.is_empty() || attempt == Self::VERIFY_ATTEMPTS {
                 break;
             }
 
-            let batch = futures::future::try_join_all(batch).await;
+            info!(
+                "Re-downloading {} corrupt pages (attempt {}/{})",
+                pending.len(),
+                attempt + 1,
+                Self::VERIFY_ATTEMPTS
+            );
 
-            let batch = match batch {
-                Ok(v) => v,
-                Err(e) => {
-                    error!("Encountered error {e} while using fetched cdns in `dl_info_results`!");
+            for &i in &pending {
+                let _permit = self.image_limit.acquire().await.into_diagnostic()?;
+                let old = &pages[i];
+
+                record_retry();
+                let data = self.download_image(&old.source_url).await?;
+
+                // a split spread yields both halves, so only keep the matching one.
+                // `save_image()` replaces (or removes) the corrupt copy
+                let Some(new) = self
+                    .process_and_save(data, chapter_dir, &old.page, &old.source_url, images_cfg)
+                    .await?
+                    .into_iter()
+                    .find(|p| p.page == old.page)
+                else {
+                    warn!("Re-downloaded page {} no longer matches its name", old.page);
                     continue;
+                };
+
+                #[allow(clippy::cast_possible_truncation)]
+                {
+                    chapter_size.fetch_sub(old.size as usize, Ordering::Relaxed);
+                    chapter_size.fetch_add(new.size as usize, Ordering::Relaxed);
                 }
+
+                pages[i] = new;
+            }
+        }
+
+        let corrupt: Vec<String> = pending.into_iter().map(|i| pages[i].page.clone()).collect();
+
+        if !corrupt.is_empty() {
+            error!(
+                "{} pages in {} are still corrupt after re-downloading: {:?}",
+                corrupt.len(),
+                chapter_dir.display(),
+                corrupt
+            );
+        }
+
+        Ok(corrupt)
+    }
+
+    /// How many times corrupt pages are re-downloaded in [`Self::verify_pages`].
+    const VERIFY_ATTEMPTS: u32 = 2;
+
+    /// Helper function for converting bytes to MiB.
+    #[allow(clippy::cast_precision_loss)]
+    #[inline]
+    fn to_mib(num_bytes: usize) -> f64 {
+        num_bytes as f64 / 1_048_576.0
+    }
+
+    /// Returns a chapter's name, rendered using [`Naming::chapter`] (with its number padded
+    /// to `num_width` digits), before [`Naming::policy`] is applied.
+    ///
+    /// Falls back to its uuid if the template renders as an empty name.
+    fn chapter_name(&self, chapter: &Chapter, num_width: usize) -> String {
+        let name = self
+            .naming
+            .chapter
+            .render(&chapter_values(chapter, num_width));
+
+        if name.is_empty() {
+            warn!(
+                "Naming template {:?} is empty for chapter {}, using its uuid instead",
+                self.naming.chapter.to_string(),
+                chapter.uuid()
+            );
+            return chapter.uuid().to_string();
+        }
+
+        name
+    }
+
+    /// Returns the name of a chapter's dir, i.e. its [name](`Self::chapter_name`)
+    /// made into a dir name with [`Naming::policy`].
+    fn chapter_dir_name(&self, chapter: &Chapter, num_width: usize) -> String {
+        let name = self.chapter_name(chapter, num_width);
+        self.naming.policy.dir_name(&name, Some(chapter.uuid()))
+    }
+
+    /// Creates (if needed) and returns the canonical dir that a chapter's pages are saved in,
+    /// named with [`Self::chapter_dir_name`].
+    async fn create_chapter_dir(
+        &self,
+        manga_dir_name: &str,
+        chapter: &Chapter,
+        num_width: usize,
+    ) -> Result<PathBuf> {
+        let chapter_dir_name = self.chapter_dir_name(chapter, num_width);
+        let chapter_dir = manga_save_dir()?
+            .join(manga_dir_name)
+            .join(chapter_dir_name);
+
+        tokio::fs::create_dir_all(&chapter_dir)
+            .await
+            .into_diagnostic()?;
+
+        chapter_dir.canonicalize().into_diagnostic()
+    }
+
+    /// Logs and [emits](`emit`) that `chapter` (with `pages` pages) is starting to download.
+    fn announce_chapter(chapter: &Chapter, pages: usize, manga_dir_name: &str) {
+        let chapter_number = &chapter.data.attributes.chapter_number;
+
+        info!(
+            "Downloading {pages} images from chapter {chapter_number:?} of manga {manga_dir_name:?}"
+        );
+
+        emit(&ProgressEvent::ChapterStarted {
+            manga_uuid: chapter.parent_uuid(),
+            chapter_uuid: chapter.uuid(),
+            chapter_number: chapter_number.clone(),
+            pages,
+        });
+    }
+
+    /// Returns the quality to download `info`'s chapter in, and the url of each of its images.
+    ///
+    /// If its cdn is [stale](`ChapterCdn::is_stale`) (e.g. from waiting in a long queue),
+    /// it's fetched again first.
+    async fn image_urls(
+        &self,
+        info: &mut ChapterDownloadInfo,
+        images_cfg: &Images,
+    ) -> Result<(ImageQuality, Vec<Url>)> {
+        if info.cdn.is_stale() {
+            debug!("Cdn of chapter is stale, fetching it again");
+            info.cdn = self.fetch_cdn(&info.api, &info.chapter).await?;
+        }
+
+        let quality = info
+            .cdn
+            .resolve_quality(&images_cfg.quality, &info.chapter)?;
+        let urls = info.cdn.construct_image_urls(&quality)?;
+
+        Ok((quality, urls))
+    }
+
+    /// Downloads and saves a chapter's images concurrently, returning it as a [`ChapterEntry`].
+    ///
+    /// This also creates the dirs needed to store these images, inside `manga_dir_name`.
+    async fn download_chapter(
+        &self,
+        mut download_info: ChapterDownloadInfo,
+        manga_dir_name: &str,
+        images_cfg: &Images,
+    ) -> Result<ChapterEntry> {
+        let (quality, images) = self.image_urls(&mut download_info, images_cfg).await?;
+        download_info.pb.set_length(images.len() as u64);
+
+        let chapter_dir = self
+            .create_chapter_dir(
+                manga_dir_name,
+                &download_info.chapter,
+                download_info.num_width,
+            )
+            .await?;
+        let page_names = self.chapter_page_names(&download_info, images.len());
+        let page_count = images.len();
+        let mut handles = Vec::with_capacity(page_count);
+        let handle_client = Arc::new(self.clone());
+
+        let mut manifest =
+            Self::pending_manifest(&download_info, &quality, images_cfg, &images, &page_names);
+        let saved_pages =
+            Self::prepare_pages(&manifest, &chapter_dir, download_info.resume).await?;
+
+        Self::announce_chapter(&download_info.chapter, page_count, manga_dir_name);
+
+        let ctx = Arc::new(PageContext {
+            api: download_info.api.clone(),
+            chapter: download_info.chapter.clone(),
+            quality,
+            urls: tokio::sync::Mutex::new(images),
+            saved_pages,
+            chapter_dir,
+            images_cfg: images_cfg.clone(),
+            events: download_info.events.clone(),
+            chapter_size: AtomicUsize::new(0),
+            pb: download_info.pb,
+            start: Instant::now(),
+        });
+
+        for (i, page) in page_names.into_iter().enumerate() {
+            // `Arc<T>` clones
+            let limit = self.image_limit.clone();
+            let h = handle_client.clone();
+            let ctx = ctx.clone();
+            let span = info_span!("page", page = i);
+
+            handles.push(tokio::spawn(
+                async move {
+                    let _permit = limit.acquire().await.into_diagnostic()?;
+                    h.download_page(&ctx, i, page).await
+                }
+                .instrument(span),
+            ));
+        }
+
+        let mut pages: Vec<ManifestPage> = futures::future::try_join_all(handles)
+            .await
+            .into_diagnostic()?
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        let (chapter_dir, chapter_size, start) = (&ctx.chapter_dir, &ctx.chapter_size, ctx.start);
+
+        if images_cfg.verify {
+            self.verify_pages(&mut pages, chapter_dir, chapter_size, images_cfg)
+                .await?;
+        }
+
+        manifest.downloaded_at = Utc::now();
+        manifest.pages = pages;
+        manifest.write(chapter_dir).await?;
+
+        if self.storage.chapter_json {
+            let thread_url = download_info.thread_url.clone();
+
+            ChapterMetadata::new(&download_info.chapter, &manifest.cdn_hash, thread_url)
+                .write(chapter_dir)
+                .await?;
+        }
+
+        let chapter_size = chapter_size.load(Ordering::Relaxed);
+
+        info!(
+            "Completed downloads in {}ms, total size is {:.3} MiB",
+            (Instant::now() - start).as_millis(),
+            Self::to_mib(chapter_size),
+        );
+
+        ctx.pb.finish_and_clear();
+        record_chapter(start.elapsed());
+
+        emit(&ProgressEvent::ChapterFinished {
+            chapter_uuid: ctx.chapter.uuid(),
+            pages: manifest.pages.len(),
+            bytes: manifest.pages.iter().map(|p| p.size).sum(),
+        });
+
+        let chapter_dir_name = chapter_dir.file_name().map(PathBuf::from);
+        let entry = ChapterEntry::new(
+            &download_info.chapter,
+            &manifest,
+            chapter_dir_name.unwrap_or_default(),
+        );
+
+        download_info.events.send(DownloadEvent::ChapterFinished {
+            entry: entry.clone(),
+        });
+
+        Ok(entry)
+    }
+
+    /// Returns how many digits chapter numbers of `manga` are padded to, from its whole
+    /// series (see [`series_num_width`]) rather than only `chapters`.
+    ///
+    /// If the series' chapters can't be fetched, this falls back to [`num_width`] (with
+    /// a warning), since that's only a problem if the series ever gets more digits.
+    async fn series_num_width(api: &ApiClient, manga: &Manga, chapters: &[Chapter]) -> usize {
+        let aggregate: Result<Aggregate> = api
+            .get_ok_parsed(Endpoint::GetMangaAggregate(manga.uuid(), Vec::new()))
+            .await;
+
+        match aggregate {
+            Ok(aggregate) => series_num_width(manga, chapters, &aggregate),
+            Err(e) => {
+                warn!(
+                    "Failed to fetch every chapter number, padding them by the chapters downloaded instead: {e}"
+                );
+                num_width(manga, chapters)
+            }
+        }
+    }
+
+    /// Returns the urls of the forum threads of `chapters` (which have one) by uuid,
+    /// fetching the statistics of up to [`MAX_STATISTICS_IDS`] chapters at once.
+    ///
+    /// These are only for `chapter.json`, so nothing is fetched unless [`Storage::chapter_json`]
+    /// is set, and failing to fetch them is logged and ignored.
+    async fn chapter_thread_urls(
+        &self,
+        api: &ApiClient,
+        chapters: &[Chapter],
+    ) -> HashMap<Uuid, String> {
+        let mut thread_urls = HashMap::new();
+
+        if !self.storage.chapter_json {
+            return thread_urls;
+        }
+
+        let uuids: Vec<Uuid> = chapters.iter().map(Chapter::uuid).collect();
+
+        for batch in uuids.chunks(MAX_STATISTICS_IDS) {
+            match ChapterStatistics::fetch(api, batch.to_vec()).await {
+                Ok(statistics) => thread_urls.extend(
+                    statistics
+                        .into_iter()
+                        .filter_map(|(uuid, s)| Some((uuid, s.comments?.thread_url()))),
+                ),
+                Err(e) => debug!(
+                    "Failed to fetch the statistics of {} chapters: {e}",
+                    batch.len()
+                ),
+            }
+        }
+
+        thread_urls
+    }
+
+    /// Returns the names of a chapter's `page_count` pages, rendered
+    /// using [`Naming::page`] and then [`Naming::policy`].
+    ///
+    /// Pages are numbered in the order the CDN lists them, zero-padded to the same width.
+    fn chapter_page_names(
+        &self,
+        download_info: &ChapterDownloadInfo,
+        page_count: usize,
+    ) -> Vec<String> {
+        let zero_pad = format!("{page_count}").len();
+        let page_values = chapter_values(&download_info.chapter, download_info.num_width);
+
+        (0..page_count)
+            .map(|i| {
+                let mut page_values = page_values.clone();
+                page_values.insert("page", format!("{i:0>zero_pad$}"));
+                let name = self.naming.page.render(&page_values);
+                self.naming.policy.file_stem(&name, None)
+            })
+            .collect()
+    }
+
+    /// Returns the manifest of a chapter that's about to be downloaded (in `quality`, from
+    /// `images` saved as `page_names`), which has no pages yet, see [`Self::prepare_pages`].
+    fn pending_manifest(
+        download_info: &ChapterDownloadInfo,
+        quality: &ImageQuality,
+        images_cfg: &Images,
+        images: &[Url],
+        page_names: &[String],
+    ) -> ChapterManifest {
+        ChapterManifest {
+            chapter_uuid: download_info.chapter.uuid(),
+            manga_uuid: download_info.chapter.parent_uuid(),
+            cdn_hash: download_info.cdn.chapter.hash.clone(),
+            requested_quality: (*quality != images_cfg.quality).then(|| images_cfg.quality.clone()),
+            quality: quality.clone(),
+            downloaded_at: Utc::now(),
+            pages: Vec::new(),
+            cdn_pages: images
+                .iter()
+                .map(Self::cdn_filename)
+                .zip(page_names.iter().cloned())
+                .collect(),
+        }
+    }
+
+    /// Returns the filename of an image's `url` on the CDN, e.g. `"x1-abc.png"`.
+    fn cdn_filename(url: &Url) -> String {
+        url.path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// Records which page each of a chapter's images is saved as in a [pending
+    /// manifest](`ChapterManifest::write_pending`), before any page is saved. Returns the
+    /// pages already saved in `chapter_dir` which can be reused, if `resume` is set.
+    ///
+    /// If an earlier attempt numbered the images differently (because the CDN changed since),
+    /// its pages are [renumbered](`Self::reconcile_pages`) rather than overwritten.
+    async fn prepare_pages(
+        pending: &ChapterManifest,
+        chapter_dir: &Path,
+        resume: bool,
+    ) -> Result<HashMap<String, String>> {
+        let previous = ChapterManifest::read_latest(chapter_dir).unwrap_or_else(|e| {
+            warn!("Ignoring the chapter's previous manifest: {e}");
+            None
+        });
+        pending.write_pending(chapter_dir).await?;
+
+        let mut saved = Self::saved_pages(chapter_dir).await?;
+
+        // the CDN names images differently in each quality, so pages downloaded in another
+        // quality are replaced (see `crate::upgrade`) rather than renumbered
+        if let Some(previous) = &previous
+            && previous.quality != pending.quality
+        {
+            info!(
+                "Replacing the chapter's {:?} pages with {:?} ones",
+                previous.quality, pending.quality
+            );
+            return Ok(HashMap::new());
+        }
+
+        if let Some(previous) = previous
+            && !previous.cdn_pages.is_empty()
+            && previous.cdn_pages != pending.cdn_pages
+        {
+            warn!("The chapter's images changed on the CDN since it was last downloaded");
+            saved =
+                Self::reconcile_pages(chapter_dir, &previous.cdn_pages, &pending.cdn_pages, saved)
+                    .await?;
+        }
+
+        Ok(if resume { saved } else { HashMap::new() })
+    }
+
+    /// Renames the `saved` pages whose images were numbered differently in `previous` to
+    /// their numbers in `current`, and removes those whose images are no longer on the CDN.
+    /// Returns the pages saved afterwards, like [`Self::saved_pages`].
+    async fn reconcile_pages(
+        chapter_dir: &Path,
+        previous: &BTreeMap<String, String>,
+        current: &BTreeMap<String, String>,
+        mut saved: HashMap<String, String>,
+    ) -> Result<HashMap<String, String>> {
+        let mut renamed = Vec::new();
+
+        for (cdn_file, old_page) in previous {
+            let new_page = current.get(cdn_file);
+
+            if new_page == Some(old_page) {
+                continue;
+            }
+
+            let Some(file) = saved.remove(old_page) else {
+                continue;
+            };
+
+            if let Some(new_page) = new_page {
+                // moved aside first, since its new name may still be taken by another page
+                let aside = format!("{file}.partial");
+                let ext = file.rsplit_once('.').map_or("", |(_, ext)| ext);
+                let new_file = format!("{new_page}.{ext}");
+
+                debug!("Renumbering page {file:?} to {new_file:?}");
+                tokio::fs::rename(chapter_dir.join(&file), chapter_dir.join(&aside))
+                    .await
+                    .into_diagnostic()?;
+                renamed.push((aside, new_page.clone(), new_file));
+            } else {
+                warn!(
+                    "Removing page {file:?}, since its image {cdn_file:?} is no longer on the CDN"
+                );
+                tokio::fs::remove_file(chapter_dir.join(&file))
+                    .await
+                    .into_diagnostic()?;
+            }
+        }
+
+        for (aside, page, file) in renamed {
+            tokio::fs::rename(chapter_dir.join(aside), chapter_dir.join(&file))
+                .await
+                .into_diagnostic()?;
+            saved.insert(page, file);
+        }
+
+        Ok(saved)
+    }
+
+    /// Downloads (or [reuses](`Self::reuse_page`)) and saves the `i`th page of a chapter.
+    ///
+    /// If the image is [refused](`CdnRefused`), the chapter's cdn is fetched again
+    /// and the page is retried once with its new url.
+    async fn download_page(
+        &self,
+        ctx: &PageContext,
+        i: usize,
+        page: String,
+    ) -> Result<Vec<ManifestPage>> {
+        ctx.events
+            .send(DownloadEvent::PageStarted { page: page.clone() });
+
+        let mut url = ctx.urls.lock().await[i].clone();
+        let resumed = ctx.saved_pages.get(&page);
+        let saved = if let Some(file) = resumed {
+            vec![Self::reuse_page(&ctx.chapter_dir, &page, file, &url).await?]
+        } else {
+            let data = match self.download_image(&url).await {
+                Err(e) if e.downcast_ref::<CdnRefused>().is_some() => {
+                    warn!("{e}, fetching the chapter's cdn again");
+                    url = self.refresh_urls(ctx, i, &url).await?;
+                    record_retry();
+                    self.download_image(&url).await?
+                }
+                data => data?,
             };
 
-            let batch_size = self
-                .download_batch(batch, parent_manga.clone(), &pb_multi, images_cfg)
+            self.process_and_save(data, &ctx.chapter_dir, &page, &url, &ctx.images_cfg)
+                .await?
+        };
+
+        #[allow(clippy::cast_possible_truncation)]
+        let size_bytes = saved.iter().map(|p| p.size as usize).sum();
+
+        debug!(
+            "Saved {page} after {}ms, size is {:.3} MiB",
+            (Instant::now() - ctx.start).as_millis(),
+            Self::to_mib(size_bytes),
+        );
+
+        ctx.chapter_size.fetch_add(size_bytes, Ordering::Relaxed);
+        self.progress.add_bytes(size_bytes);
+        ctx.events.send(DownloadEvent::PageFinished {
+            page: page.clone(),
+            bytes: size_bytes,
+            resumed: resumed.is_some(),
+        });
+        emit(&ProgressEvent::PageDownloaded {
+            chapter_uuid: ctx.chapter.uuid(),
+            page,
+            bytes: size_bytes,
+        });
+
+        ctx.pb.inc(1);
+        Ok(saved)
+    }
+
+    /// Fetches `chapter`'s cdn again (waiting for [`Self::cdn_limiter`]), since its urls expire.
+    async fn fetch_cdn(&self, api: &ApiClient, chapter: &Chapter) -> Result<ChapterCdn> {
+        ChapterCdn::new(api, chapter, &self.cdn_limiter).await
+    }
+
+    /// Replaces `ctx`'s urls with ones from a newly fetched cdn (unless another page already
+    /// did so after `expired` was refused), returning the new url of the `i`th page.
+    ///
+    /// ## Errors
+    ///
+    /// If the cdn can't be fetched, or no longer has an `i`th page.
+    async fn refresh_urls(&self, ctx: &PageContext, i: usize, expired: &Url) -> Result<Url> {
+        let mut urls = ctx.urls.lock().await;
+
+        if urls.get(i) == Some(expired) {
+            let cdn = self.fetch_cdn(&ctx.api, &ctx.chapter).await?;
+            *urls = cdn.construct_image_urls(&ctx.quality)?;
+        }
+
+        urls.get(i)
+            .cloned()
+            .ok_or_else(|| miette!("the chapter's new cdn has no page {i}"))
+    }
+
+    /// Returns the pages already saved in `chapter_dir`, mapping each page's name
+    /// (e.g. "05") to its filename (e.g. "05.png"). Unfinished (`.partial`) files are left out.
+    async fn saved_pages(chapter_dir: &Path) -> Result<HashMap<String, String>> {
+        let mut saved = HashMap::new();
+        let mut entries = tokio::fs::read_dir(chapter_dir).await.into_diagnostic()?;
+
+        while let Some(entry) = entries.next_entry().await.into_diagnostic()? {
+            let file = entry.file_name().to_string_lossy().to_string();
+
+            if let Some((page, ext)) = file.rsplit_once('.')
+                && ext != "partial"
+                && ext != "json"
+            {
+                saved.insert(page.to_string(), file.clone());
+            }
+        }
+
+        Ok(saved)
+    }
+
+    /// Returns `page`, which is already saved in `chapter_dir` as `file`,
+    /// as a [`ManifestPage`] (with `source_url` as its url).
+    async fn reuse_page(
+        chapter_dir: &Path,
+        page: &str,
+        file: &str,
+        source_url: &Url,
+    ) -> Result<ManifestPage> {
+        let data = tokio::fs::read(chapter_dir.join(file))
+            .await
+            .into_diagnostic()?;
+
+        trace!("Reusing page {page}, already saved as {file:?}");
+        Ok(ManifestPage {
+            page: page.to_string(),
+            file: file.to_string(),
+            size: data.len() as u64,
+            sha256: sha256_hex(&data),
+            source_url: source_url.clone(),
+            spread: false,
+        })
+    }
+
+    /// Downloads `chapter` of `manga` on its own, returning a stream of [`DownloadEvent`]s
+    /// instead of drawing progress bars, so that frontends can show progress themselves.
+    ///
+    /// Pages already saved by an earlier attempt are reused, so a chapter can be resumed
+    /// by calling this again. Dropping the stream stops the download.
+    ///
+    /// Unlike [`Self::download_chapters`], the chapter isn't added to the library index
+    /// or download history. Use [`DownloadEvent::ChapterFinished`]'s entry for that.
+    pub fn download_chapter_stream(
+        &self,
+        api: &ApiClient,
+        chapter: Chapter,
+        manga: &Manga,
+        images_cfg: &Images,
+    ) -> impl Stream<Item = DownloadEvent> + use<> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let events = EventSink(Some(sender));
+        let manga_dir_name = self.manga_dir_name(manga);
+        let (h, api, images_cfg) = (self.clone(), api.clone(), images_cfg.clone());
+        let manga = manga.clone();
+        let span = info_span!("chapter", uuid = %chapter.uuid());
+
+        let task = tokio::spawn(
+            async move {
+                let download = async {
+                    let chapter_num_width =
+                        Self::series_num_width(&api, &manga, std::slice::from_ref(&chapter)).await;
+                    let mut info = ChapterDownloadInfo::new(&api, chapter, &h.cdn_limiter).await?;
+                    info.pb = ProgressBar::hidden();
+                    info.events = events.clone();
+                    info.resume = true;
+                    info.num_width = chapter_num_width;
+                    info.thread_url = h
+                        .chapter_thread_urls(&api, std::slice::from_ref(&info.chapter))
+                        .await
+                        .remove(&info.chapter.uuid());
+
+                    h.download_chapter(info, &manga_dir_name, &images_cfg).await
+                };
+
+                if let Err(e) = download.await {
+                    error!("Failed to download chapter: {e:?}");
+                    events.send(DownloadEvent::Failed {
+                        error: e.to_string(),
+                    });
+                }
+            }
+            .instrument(span),
+        );
+
+        // the stream ends once the task finishes, dropping the last sender
+        let task = AbortOnDrop(task.abort_handle());
+        futures::stream::unfold((receiver, task), |(mut receiver, task)| async move {
+            let event = receiver.recv().await?;
+            Some((event, (receiver, task)))
+        })
+    }
+
+    /// Returns where `manga` is saved in the library, see [`Self::manga_dir_name`].
+    ///
+    /// ## Errors
+    ///
+    /// If propagated from [`manga_save_dir`].
+    pub fn manga_dir(&self, manga: &Manga) -> Result<PathBuf> {
+        Ok(manga_save_dir()?.join(self.manga_dir_name(manga)))
+    }
+
+    /// Returns the name of `manga`'s dir in the library, using [`Naming::manga`]
+    /// and then [`Naming::policy`].
+    ///
+    /// Falls back to its uuid if the template renders as an empty name.
+    fn manga_dir_name(&self, manga: &Manga) -> String {
+        let name = self
+            .naming
+            .manga
+            .render(&manga_values(manga, self.language));
+
+        if name.is_empty() {
+            warn!(
+                "Naming template {:?} is empty for manga {}, using its uuid instead",
+                self.naming.manga.to_string(),
+                manga.uuid()
+            );
+            return manga.uuid().to_string();
+        }
+
+        self.naming.policy.dir_name(&name, Some(manga.uuid()))
+    }
+
+    /// How many chapters' cdns are fetched ahead of a chapter permit being free, see
+    /// [`Self::download_all`]. Kept low, since cdn urls expire after a while.
+    const CDN_PREFETCH: usize = 1;
+
+    /// Helper for [`Self::download_all`], which downloads `info`'s chapter in a new task
+    /// (holding `permit` until it's done) and sends the result to `done`.
+    ///
+    /// Chapters are downloaded independently, so one failing doesn't stop the others
+    /// (whose pages stay on disk).
+    fn spawn_chapter(
+        &self,
+        info: ChapterDownloadInfo,
+        permit: AdaptivePermit,
+        parent_uuid: Uuid,
+        images_cfg: &Images,
+        manga_dir_name: &str,
+        done: UnboundedSender<(Chapter, Result<ChapterEntry>)>,
+    ) {
+        if info.chapter.parent_uuid() != parent_uuid {
+            warn!(
+                "Expected chapter {} to have parent manga {}, instead got {}",
+                info.chapter.uuid(),
+                parent_uuid,
+                info.chapter.parent_uuid()
+            );
+            warn!("This may lead to chapters being saved to the wrong locations!");
+        }
+
+        self.pb_multi.add(info.pb.clone());
+
+        let chapter = info.chapter.clone();
+        let pb = info.pb.clone();
+        let h = self.clone();
+        let images_cfg = images_cfg.clone();
+        let manga_dir_name = manga_dir_name.to_string();
+        let page_size = average_page_size(&images_cfg.quality);
+        let span = info_span!("chapter", uuid = %chapter.uuid());
+
+        tokio::spawn(
+            async move {
+                let _permit = permit;
+                let start = Instant::now();
+                let pages = chapter.data.attributes.pages.max(1);
+
+                let result = async {
+                    // re-checked per chapter, since other downloads may be filling the disk too
+                    let needed = chapter.data.attributes.pages as u64 * page_size;
+                    check_space(&h.storage, &manga_save_dir()?, needed, false)?;
+
+                    h.download_chapter(info, &manga_dir_name, &images_cfg).await
+                }
+                .await;
+
+                // the time per page, so that long chapters don't look like a struggling server
+                h.chapter_limit.record(match &result {
+                    Ok(_) => {
+                        Outcome::Success(start.elapsed() / u32::try_from(pages).unwrap_or(u32::MAX))
+                    }
+                    Err(_) => Outcome::Failed,
+                });
+
+                match &result {
+                    Ok(_) => h.progress.chapter_done(),
+                    Err(_) => pb.finish_and_clear(),
+                }
+
+                let _ = done.send((chapter, result));
+            }
+            .instrument(span),
+        );
+    }
+
+    /// Records a downloaded chapter in the library index, marks it done in the queue,
+    /// adds it to `summary` and sends a [chapter notification](`NotifyEvent::Chapter`).
+    ///
+    /// ## Errors
+    ///
+    /// If propagated from [`LibraryIndex::update`] or [`DownloadQueue::update`].
+    async fn record_downloaded(
+        &self,
+        parent_manga: &Manga,
+        manga_title: &str,
+        manga_dir_name: &str,
+        (chapter, entry): (Chapter, ChapterEntry),
+        summary: &mut DownloadSummary,
+    ) -> Result<()> {
+        LibraryIndex::update(|index| {
+            index.record_chapter(parent_manga, manga_title, manga_dir_name, entry.clone());
+        })?;
+
+        DownloadQueue::update(|queue| queue.mark_done(parent_manga.uuid(), &[chapter.uuid()]))?;
+
+        self.notifier
+            .send(&Notification::finished(
+                NotifyEvent::Chapter,
+                manga_title,
+                vec![Notification::chapter_label(&chapter)],
+                entry.size,
+            ))
+            .await;
+
+        #[allow(clippy::cast_possible_truncation)]
+        let size = entry.size as usize;
+        summary.total_bytes += size;
+        summary.downloaded.push(chapter);
+        Ok(())
+    }
+
+    /// Records `failed` chapters (with why they failed) in `summary`, uncounting them
+    /// from the overall progress and sending a [chapter notification](`NotifyEvent::Chapter`)
+    /// for each.
+    async fn record_failures(
+        &self,
+        manga_title: &str,
+        failed: Vec<(Chapter, String)>,
+        summary: &mut DownloadSummary,
+    ) {
+        self.progress.chapters_skipped(failed.len());
+
+        for (chapter, reason) in &failed {
+            emit(&ProgressEvent::ChapterFailed {
+                chapter_uuid: chapter.uuid(),
+                error: reason.clone(),
+            });
+
+            self.notifier
+                .send(&Notification::failed(
+                    NotifyEvent::Chapter,
+                    manga_title,
+                    vec![Notification::chapter_label(chapter)],
+                    reason.clone(),
+                ))
+                .await;
+        }
+
+        summary.failed.extend(failed);
+    }
+
+    /// Downloads all chapters given.
+    ///
+    /// Chapters are also downloaded concurrently, using
+    /// [`Self::chapter_limit`] for the number of permits.
+    ///
+    /// NOTE: **All of these chapters should come from the same parent manga.**
+    /// A warning is logged otherwise.
+    ///
+    /// The chapters are added to the [`DownloadQueue`] first, and marked done as
+    /// each one is saved.
+    ///
+    /// Returns a [`DownloadSummary`] of which chapters were (or weren't) downloaded. A chapter
+    /// failing doesn't stop the others, and is recorded in [`DownloadSummary::failed`]
+    /// (staying in the queue, so that `queue resume` can retry it).
+    ///
+    /// Chapters hosted outside of Manga-Dex are skipped (and given a `.url` shortcut,
+    /// if `storage.external_shortcuts` is set), see [`DownloadSummary::external`]. So are
+    /// unavailable chapters, unless `chapters.attempt_unavailable` is set. The rest are
+    /// downloaded in [`Self::order`].
+    ///
+    /// A [manga notification](`NotifyEvent::Manga`) is sent once every chapter is done,
+    /// and a [chapter notification](`NotifyEvent::Chapter`) as each one is.
+    ///
+    /// ## Errors
+    ///
+    /// If the library index or download queue can't be saved.
+    #[instrument(name = "manga", skip_all, fields(uuid = %parent_manga.uuid()))]
+    pub async fn download_chapters(
+        &self,
+        api: &ApiClient,
+        chapters: Vec<Chapter>,
+        parent_manga: Manga,
+        images_cfg: &Images,
+    ) -> Result<DownloadSummary> {
+        let manga_title = parent_manga.title(self.language);
+        // from the whole series (not just these chapters), so that names don't change later
+        let chapter_num_width = Self::series_num_width(api, &parent_manga, &chapters).await;
+        let (mut chapters, external, unavailable) = self.skip_chapters(chapters, &manga_title);
+        sort_chapters(&mut chapters, self.order);
+
+        if is_dry_run() {
+            let (estimates, page_size) = self.estimate_chapters(api, chapters, images_cfg).await;
+            Self::print_estimate(&manga_title, &estimates, page_size);
+            Self::print_skipped(&external, &unavailable);
+            return Ok(DownloadSummary::default());
+        }
+
+        if self.storage.external_shortcuts {
+            self.write_shortcuts(&parent_manga, &external, chapter_num_width)
                 .await?;
+        }
+
+        if chapters.is_empty() {
+            return Ok(DownloadSummary {
+                external,
+                unavailable,
+                ..DownloadSummary::default()
+            });
+        }
 
-            manga_size.fetch_add(batch_size, Ordering::Relaxed);
+        self.check_preflight(api, chapters.len()).await?;
+        let labels: Vec<String> = chapters.iter().map(Notification::chapter_label).collect();
+
+        self.progress.start(&self.pb_multi, chapters.len());
+        emit(&ProgressEvent::MangaStarted {
+            manga_uuid: parent_manga.uuid(),
+            title: manga_title.clone(),
+            chapters: chapters.len(),
+        });
+        let manga_uuid = parent_manga.uuid();
+
+        // boxed, since running every chapter's download (and packaging) makes for a large future
+        let result =
+            Box::pin(self.download_all(api, chapters, parent_manga, images_cfg, chapter_num_width))
+                .await
+                .map(|summary| DownloadSummary {
+                    external,
+                    unavailable,
+                    ..summary
+                });
+
+        self.progress.end();
+
+        if let Ok(summary) = &result {
+            emit(&ProgressEvent::MangaFinished {
+                manga_uuid,
+                downloaded: summary.downloaded.len(),
+                failed: summary.failed.len(),
+                bytes: summary.total_bytes,
+            });
         }
 
-        let manga_size = manga_size.load(Ordering::Relaxed);
+        let notification = match &result {
+            Ok(summary) if summary.failed.is_empty() => Notification::finished(
+                NotifyEvent::Manga,
+                &manga_title,
+                labels,
+                summary.total_bytes as u64,
+            ),
+            Ok(summary) => Notification::failed(
+                NotifyEvent::Manga,
+                &manga_title,
+                summary
+                    .failed
+                    .iter()
+                    .map(|(c, _)| Notification::chapter_label(c))
+                    .collect(),
+                format!(
+                    "{} of {} chapters failed",
+                    summary.failed.len(),
+                    summary.failed.len() + summary.downloaded.len()
+                ),
+            ),
+            Err(e) => Notification::failed(NotifyEvent::Manga, &manga_title, labels, e.to_string()),
+        };
+
+        self.notifier.send(&notification).await;
+        result
+    }
+
+    /// Splits the chapters that can't be downloaded out of `chapters`, returning
+    /// `(chapters, external, unavailable)`.
+    ///
+    /// Unavailable chapters are only split out if `chapters.attempt_unavailable` isn't set.
+    fn skip_chapters(
+        &self,
+        chapters: Vec<Chapter>,
+        manga_title: &str,
+    ) -> (Vec<Chapter>, Vec<Chapter>, Vec<Chapter>) {
+        // external chapters have no pages on Manga-Dex, so there's no cdn to fetch
+        let (external, chapters): (Vec<Chapter>, Vec<Chapter>) =
+            chapters.into_iter().partition(Chapter::is_external);
+
+        // these usually 404 on the cdn, unless they've been made available again since
+        let (unavailable, chapters): (Vec<Chapter>, Vec<Chapter>) = if self.attempt_unavailable {
+            (Vec::new(), chapters)
+        } else {
+            chapters.into_iter().partition(Chapter::is_unavailable)
+        };
+
+        if !external.is_empty() {
+            info!(
+                "Skipping {} chapters of {manga_title:?} hosted outside of Manga-Dex",
+                external.len()
+            );
+        }
+
+        if !unavailable.is_empty() {
+            info!(
+                "Skipping {} unavailable chapters of {manga_title:?}",
+                unavailable.len()
+            );
+        }
+
+        (chapters, external, unavailable)
+    }
+
+    /// Prints the chapters that would be skipped in a dry run.
+    fn print_skipped(external: &[Chapter], unavailable: &[Chapter]) {
+        if !external.is_empty() {
+            print_line(
+                style(format!(
+                    "{} chapters are hosted outside of Manga-Dex and would be skipped",
+                    external.len()
+                ))
+                .yellow(),
+            );
+        }
+
+        if !unavailable.is_empty() {
+            print_line(
+                style(format!(
+                    "{} chapters are unavailable and would be skipped:",
+                    unavailable.len()
+                ))
+                .yellow(),
+            );
+            print_unavailable(unavailable);
+        }
+    }
+
+    /// Writes a `.url` shortcut (named like its chapter dir) to each of the `external`
+    /// chapters of `manga` in its dir, so they can still be opened from the library.
+    async fn write_shortcuts(
+        &self,
+        manga: &Manga,
+        external: &[Chapter],
+        num_width: usize,
+    ) -> Result<()> {
+        if external.is_empty() {
+            return Ok(());
+        }
+
+        let manga_dir = self.manga_dir(manga)?;
+        tokio::fs::create_dir_all(&manga_dir)
+            .await
+            .into_diagnostic()?;
+
+        for chapter in external {
+            let Some(url) = chapter.external_url() else {
+                continue;
+            };
+
+            // named like the chapter's dir would be, leaving room for the extension
+            let name = self.chapter_name(chapter, num_width);
+            let stem = self.naming.policy.file_stem(&name, Some(chapter.uuid()));
+            let path = manga_dir.join(format!("{stem}.url"));
+            tokio::fs::write(&path, format!("[InternetShortcut]\r\nURL={url}\r\n"))
+                .await
+                .into_diagnostic()?;
+
+            debug!("Wrote shortcut to {url} at {}", path.display());
+        }
+
+        Ok(())
+    }
+
+    /// Helper for [`Self::download_all`], which fetches the cdn of each of `chapters` (in
+    /// order) and sends it to `sender`, waiting whenever the channel is full.
+    async fn fetch_cdns(
+        &self,
+        api: &ApiClient,
+        chapters: Vec<Chapter>,
+        num_width: usize,
+        sender: mpsc::Sender<(Chapter, Result<ChapterDownloadInfo>)>,
+    ) {
+        let mut thread_urls = self.chapter_thread_urls(api, &chapters).await;
+
+        for chapter in chapters {
+            let thread_url = thread_urls.remove(&chapter.uuid());
+            let info = ChapterDownloadInfo::new(api, chapter.clone(), &self.cdn_limiter)
+                .await
+                .map(|info| ChapterDownloadInfo {
+                    num_width,
+                    thread_url,
+                    ..info
+                });
+
+            if sender.send((chapter, info)).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Helper for [`Self::download_chapters`], which does the actual downloading.
+    async fn download_all(
+        &self,
+        api: &ApiClient,
+        chapters: Vec<Chapter>,
+        parent_manga: Manga,
+        images_cfg: &Images,
+        num_width: usize,
+    ) -> Result<DownloadSummary> {
+        let start = Instant::now();
+        let parent_manga = Arc::new(parent_manga);
+        let mut summary = DownloadSummary::default();
+        let manga_dir_name = self.manga_dir_name(&parent_manga);
+        let manga_title = parent_manga.title(self.language);
+
+        info!(
+            "Downloading {} chapters of manga {:?}, manga_uuid={}",
+            chapters.len(),
+            manga_title,
+            parent_manga.uuid()
+        );
+
+        // queued first, so that an interrupted download can be resumed with `queue resume`
+        DownloadQueue::update(|queue| queue.enqueue(&parent_manga, &manga_title, &chapters))?;
+
+        let pages: usize = chapters.iter().map(|c| c.data.attributes.pages).sum();
+        let needed = pages as u64 * average_page_size(&images_cfg.quality);
+        debug!("Estimated {needed} bytes for {pages} pages");
+        check_space(&self.storage, &manga_save_dir()?, needed, true)?;
+
+        // cdns are fetched just before they're needed (the channel only holds a few), since
+        // they expire, while the chapters before them download
+        let (cdn_sender, mut cdn_receiver) = mpsc::channel(Self::CDN_PREFETCH);
+        let (done_sender, mut done_receiver) = mpsc::unbounded_channel();
+        let (package_sender, mut package_receiver) = mpsc::unbounded_channel();
+        let parent_uuid = parent_manga.uuid();
+        let dir_name = manga_dir_name.as_str();
+        let fetch_cdns = self.fetch_cdns(api, chapters, num_width, cdn_sender);
+
+        let start_downloads = async move {
+            while let Some((chapter, info)) = cdn_receiver.recv().await {
+                let info = match info {
+                    Ok(info) => info,
+                    Err(e) => {
+                        let e = e.wrap_err("failed to fetch the chapter's cdn");
+                        let _ = done_sender.send((chapter, Err(e)));
+                        continue;
+                    }
+                };
+
+                // waiting for a permit here also stops more cdns being fetched
+                let Ok(permit) = self.chapter_limit.acquire().await else {
+                    break;
+                };
+
+                let done = done_sender.clone();
+                self.spawn_chapter(info, permit, parent_uuid, images_cfg, dir_name, done);
+            }
+        };
+
+        // the summary, index and queue are updated as each chapter finishes, so
+        // that they stay up to date if the run is interrupted
+        let record_results = async {
+            while let Some((chapter, result)) = done_receiver.recv().await {
+                match result {
+                    Ok(entry) => {
+                        if self.packaging.is_some() {
+                            let _ = package_sender.send((chapter.clone(), entry.clone()));
+                        }
+
+                        self.record_downloaded(
+                            &parent_manga,
+                            &manga_title,
+                            dir_name,
+                            (chapter, entry),
+                            &mut summary,
+                        )
+                        .await?;
+                    }
+                    Err(e) => {
+                        error!("Failed to download chapter {}: {e:?}", chapter.uuid());
+                        let failed = vec![(chapter, e.to_string())];
+                        self.record_failures(&manga_title, failed, &mut summary)
+                            .await;
+                    }
+                }
+            }
+
+            // lets the packaging workers finish once they've caught up
+            drop(package_sender);
+            Ok::<(), ErrReport>(())
+        };
+
+        let package_chapters = async {
+            let Some(packaging) = &self.packaging else {
+                return (Vec::new(), Vec::new());
+            };
+
+            self.package_all(packaging, &mut package_receiver, &manga_title, dir_name)
+                .await
+        };
+
+        let ((), (), recorded, (packaged, unpackaged)) = tokio::join!(
+            fetch_cdns,
+            start_downloads,
+            record_results,
+            package_chapters
+        );
+        recorded?;
+        summary.packaged = packaged;
+        summary.unpackaged = unpackaged;
 
         info!(
             "All downloads completed in {}ms, total size is {:.3} MiB",
             (Instant::now() - start).as_millis(),
-            Self::to_mib(manga_size),
+            Self::to_mib(summary.total_bytes),
         );
 
+        Ok(summary)
+    }
+
+    /// Helper for [`Self::download_all`], which packages each chapter received from
+    /// `chapters` (as they're downloaded) with `packaging`, until the channel closes.
+    ///
+    /// Packaging is done on the blocking thread pool, bounded by [`Self::package_semaphore`]
+    /// rather than the download permits, so that compressing a large chapter overlaps with
+    /// downloading the next ones.
+    ///
+    /// Returns where chapters were packaged, and the chapters which couldn't be (with why).
+    async fn package_all(
+        &self,
+        packaging: &ChapterPackaging,
+        chapters: &mut UnboundedReceiver<(Chapter, ChapterEntry)>,
+        manga_title: &str,
+        manga_dir_name: &str,
+    ) -> (Vec<PathBuf>, Vec<(Chapter, String)>) {
+        let mut workers = JoinSet::new();
+        // kept outside the workers, so that chapters whose worker panicked are still reported
+        let mut packaging_chapters = HashMap::new();
+        let mut packaged = Vec::new();
+        let mut unpackaged = Vec::new();
+
+        while let Some((chapter, entry)) = chapters.recv().await {
+            let Ok(permit) = Arc::clone(&self.package_semaphore).acquire_owned().await else {
+                break;
+            };
+
+            let packaging = packaging.clone();
+            let manga_title = manga_title.to_string();
+            let manga_dir = PathBuf::from(manga_dir_name);
+
+            let worker = workers.spawn_blocking(move || {
+                let _permit = permit;
+                package_chapter(&packaging, &manga_title, &manga_dir, &entry)
+            });
+            packaging_chapters.insert(worker.id(), chapter);
+        }
+
+        while let Some(joined) = workers.join_next_with_id().await {
+            let (id, result) = match joined {
+                Ok((id, result)) => (id, result),
+                Err(e) => (e.id(), Err(miette!("packaging panicked: {e}"))),
+            };
+
+            let Some(chapter) = packaging_chapters.remove(&id) else {
+                continue;
+            };
+
+            match result {
+                Ok(path) => {
+                    debug!("Packaged chapter to {}", path.display());
+                    packaged.push(path);
+                }
+                Err(e) => {
+                    error!("Failed to package chapter {}: {e:?}", chapter.uuid());
+                    unpackaged.push((chapter, e.to_string()));
+                }
+            }
+        }
+
+        (packaged, unpackaged)
+    }
+
+    /// How many pages [`Self::estimate_chapters`] samples the size of.
+    const SIZE_SAMPLES: usize = 5;
+
+    /// Fetches the cdns of `chapters` to count their pages, and samples the size of up to
+    /// [`Self::SIZE_SAMPLES`] pages (spread across the chapters) without downloading them.
+    ///
+    /// Returns the estimates, along with the average sampled page size in bytes (if any could
+    /// be sampled). Chapters whose cdns can't be fetched are logged and given no page count.
+    pub async fn estimate_chapters(
+        &self,
+        api: &ApiClient,
+        chapters: Vec<Chapter>,
+        images_cfg: &Images,
+    ) -> (Vec<ChapterEstimate>, Option<u64>) {
+        let step = (chapters.len() / Self::SIZE_SAMPLES).max(1);
+
+        let urls = futures::future::join_all(chapters.iter().map(|chapter| async move {
+            let cdn = ChapterCdn::new(api, chapter, &self.cdn_limiter).await?;
+            let quality = cdn.resolve_quality(&images_cfg.quality, chapter)?;
+            cdn.construct_image_urls(&quality)
+        }))
+        .await;
+
+        let mut estimates = Vec::with_capacity(chapters.len());
+        let mut samples = Vec::new();
+
+        for (i, (chapter, urls)) in chapters.into_iter().zip(urls).enumerate() {
+            let pages = match urls {
+                Ok(urls) => {
+                    if i % step == 0
+                        && samples.len() < Self::SIZE_SAMPLES
+                        && let Some(url) = urls.first()
+                    {
+                        samples.push(url.clone());
+                    }
+
+                    Some(urls.len())
+                }
+                Err(e) => {
+                    warn!("Failed to fetch cdn of chapter {}: {e}", chapter.uuid());
+                    None
+                }
+            };
+
+            estimates.push(ChapterEstimate { chapter, pages });
+        }
+
+        let mut sizes = Vec::with_capacity(samples.len());
+
+        for url in samples {
+            // `HEAD` responses have no body, so read the header directly
+            let size = self
+                .client
+                .head(url.clone())
+                .send()
+                .await
+                .ok()
+                .and_then(|r| r.headers().get(CONTENT_LENGTH)?.to_str().ok()?.parse().ok());
+
+            if let Some(size) = size {
+                sizes.push(size);
+            } else {
+                debug!("Couldn't get the size of sampled page {url}");
+            }
+        }
+
+        let page_size = (!sizes.is_empty()).then(|| sizes.iter().sum::<u64>() / sizes.len() as u64);
+        (estimates, page_size)
+    }
+
+    /// Prints a table of `estimates`, with sizes estimated from `page_size` (in bytes).
+    fn print_estimate(manga_title: &str, estimates: &[ChapterEstimate], page_size: Option<u64>) {
+        #[allow(clippy::cast_possible_truncation)]
+        let size = |pages: usize| match page_size {
+            Some(page_size) => format!("{:.1} MiB", Self::to_mib(pages * page_size as usize)),
+            None => "?".to_string(),
+        };
+
+        print_line(style(manga_title).bold());
+        print_line(
+            style(format!(
+                "{:<12} {:>6} {:>12}",
+                "Chapter", "Pages", "Est. size"
+            ))
+            .dim(),
+        );
+
+        for estimate in estimates {
+            let label = Notification::chapter_label(&estimate.chapter);
+
+            match estimate.pages {
+                Some(pages) => print_line(format!("{label:<12} {pages:>6} {:>12}", size(pages))),
+                None => print_line(format!("{label:<12} {:>6} {:>12}", "?", "?")),
+            }
+        }
+
+        let pages: usize = estimates.iter().filter_map(|e| e.pages).sum();
+
+        print_line(
+            style(format!(
+                "Would download {} chapters, {pages} pages, ~{} (dry run, nothing was written)",
+                estimates.len(),
+                size(pages)
+            ))
+            .green(),
+        );
+    }
+
+    /// Downloads the chapters of several manga, with up to
+    /// [`Self::manga_semaphore`] of them being downloaded at once.
+    ///
+    /// Every manga shares the same image and chapter permits and ratelimit, so this
+    /// doesn't download anything faster than allowed, it just avoids waiting for
+    /// one manga's last few chapters before starting the next manga.
+    ///
+    /// Returns the outcome of [`Self::download_chapters`] for each manga, in the same order.
+    ///
+    /// ## Errors
+    ///
+    /// If the [preflight] check for every chapter (of every manga) fails,
+    /// in which case nothing is downloaded.
+    pub async fn download_many(
+        &self,
+        api: &ApiClient,
+        jobs: Vec<(Manga, Vec<Chapter>)>,
+        images_cfg: &Images,
+    ) -> Result<Vec<Result<DownloadSummary>>> {
+        let chapters = jobs.iter().map(|(_, chapters)| chapters.len()).sum();
+        self.check_preflight(api, chapters).await?;
+
+        let jobs = jobs.into_iter().map(|(manga, chapters)| async move {
+            let _permit = self.manga_semaphore.acquire().await.into_diagnostic()?;
+            self.download_chapters(api, chapters, manga, images_cfg)
+                .await
+        });
+
+        Ok(futures::future::join_all(jobs).await)
+    }
+
+    /// Runs the [preflight] check before downloading `chapters` chapters,
+    /// unless it already passed for this client.
+    ///
+    /// ## Errors
+    ///
+    /// If propagated from [`preflight`].
+    async fn check_preflight(&self, api: &ApiClient, chapters: usize) -> Result<()> {
+        if self.preflight_passed.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        preflight(api, &self.client, &self.preflight, chapters).await?;
+
+        if self.preflight.enabled && chapters >= self.preflight.min_chapters {
+            self.preflight_passed.store(true, Ordering::Relaxed);
+        }
+
         Ok(())
     }
 }
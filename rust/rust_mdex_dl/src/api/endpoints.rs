@@ -16,6 +16,13 @@ pub enum Endpoint {
     /// - [Redoc](https://api.mangadex.org/docs/redoc.html#tag/Chapter/operation/get-chapter-id)
     /// - [Swagger](https://api.mangadex.org/docs/swagger.html#/Chapter/get-chapter-id)
     GetChapter(Uuid),
+    /// Takes search parameters (e.g. `manga`, `volume[]` and `groups[]`) and returns chapters.
+    ///
+    /// ## References
+    ///
+    /// - [Redoc](https://api.mangadex.org/docs/redoc.html#tag/Chapter/operation/get-chapter)
+    /// - [Swagger](https://api.mangadex.org/docs/swagger.html#/Chapter/get-chapter)
+    GetChapters(Vec<(String, String)>),
     /// Takes a chapter's UUID and returns its download (CDN) info.
     ///
     /// ## References
@@ -23,7 +30,14 @@ pub enum Endpoint {
     /// - [Redoc](https://api.mangadex.org/docs/redoc.html#tag/AtHome/operation/get-at-home-server-chapterId)
     /// - [Swagger](https://api.mangadex.org/docs/swagger.html#/AtHome/get-at-home-server-chapterId)
     GetChapterCdn(Uuid),
-    /// Takes a manga's UUID and returns its info.
+    /// Takes search parameters (e.g. `manga[]` and `locales[]`) and returns cover art.
+    ///
+    /// ## References
+    ///
+    /// - [Redoc](https://api.mangadex.org/docs/redoc.html#tag/Cover/operation/get-cover)
+    /// - [Swagger](https://api.mangadex.org/docs/swagger.html#/Cover/get-cover)
+    GetCovers(Vec<(String, String)>),
+    /// Takes a manga's UUID and returns its info, including its authors, artists and cover.
     ///
     /// ## References
     ///
@@ -37,6 +51,50 @@ pub enum Endpoint {
     /// - [Redoc](https://api.mangadex.org/docs/redoc.html#tag/Manga/operation/get-manga-id-feed)
     /// - [Swagger](https://api.mangadex.org/docs/swagger.html#/Manga/get-manga-id-feed)
     GetMangaChapters(Uuid, Vec<(String, String)>),
+    /// Takes a manga's UUID and returns its volumes and chapter numbers (without
+    /// the chapters' info), filtered by the given parameters.
+    ///
+    /// ## References
+    ///
+    /// - [Redoc](https://api.mangadex.org/docs/redoc.html#tag/Manga/operation/get-manga-aggregate)
+    /// - [Swagger](https://api.mangadex.org/docs/swagger.html#/Manga/get-manga-aggregate)
+    GetMangaAggregate(Uuid, Vec<(String, String)>),
+    /// Takes the UUIDs of (up to 100) manga and returns their ratings and follows.
+    ///
+    /// ## References
+    ///
+    /// - [Redoc](https://api.mangadex.org/docs/redoc.html#tag/Statistics/operation/get-statistics-manga)
+    /// - [Swagger](https://api.mangadex.org/docs/swagger.html#/Statistics/get-statistics-manga)
+    GetMangaStatistics(Vec<Uuid>),
+    /// Takes the UUIDs of (up to 100) chapters and returns their comment threads.
+    ///
+    /// ## References
+    ///
+    /// - [Redoc](https://api.mangadex.org/docs/redoc.html#tag/Statistics/operation/get-statistics-chapters-uuids)
+    /// - [Swagger](https://api.mangadex.org/docs/swagger.html#/Statistics/get-statistics-chapters-uuids)
+    GetChapterStatistics(Vec<Uuid>),
+    /// Takes legacy numeric ids (in a POST body) and returns their uuids.
+    ///
+    /// ## References
+    ///
+    /// - [Redoc](https://api.mangadex.org/docs/redoc.html#tag/Legacy/operation/post-legacy-mapping)
+    /// - [Swagger](https://api.mangadex.org/docs/swagger.html#/Legacy/post-legacy-mapping)
+    LegacyMapping,
+    /// Takes a manga's UUID and returns (GET) or sets (POST) its reading status
+    /// in the logged in user's library, see [`AuthSession`](`crate::api::auth::AuthSession`).
+    ///
+    /// ## References
+    ///
+    /// - [Redoc](https://api.mangadex.org/docs/redoc.html#tag/Manga/operation/get-manga-id-status)
+    /// - [Swagger](https://api.mangadex.org/docs/swagger.html#/Manga/get-manga-id-status)
+    MangaReadingStatus(Uuid),
+    /// Returns `pong` (as plain text, not JSON) if the API is up, see [`crate::api::preflight`].
+    ///
+    /// ## References
+    ///
+    /// - [Redoc](https://api.mangadex.org/docs/redoc.html#tag/Infrastructure/operation/get-ping)
+    /// - [Swagger](https://api.mangadex.org/docs/swagger.html#/Infrastructure/get-ping)
+    Ping,
     /// Takes search parameters (with query string) and returns a list of manga.
     ///
     /// ## References
@@ -55,9 +113,23 @@ impl Endpoint {
     #[must_use]
     pub fn as_string(&self) -> String {
         match self {
-            Self::GetChapter(uuid) => format!("/chapter/{uuid}"),
+            Self::GetChapter(uuid) => {
+                format!("/chapter/{uuid}?includes[]=scanlation_group&includes[]=user")
+            }
+            Self::GetChapters(params) => format!(
+                "/chapter?{}",
+                serde_urlencoded::to_string(params)
+                    .expect("failed to build `GetChapters` query string")
+            ),
             Self::GetChapterCdn(uuid) => format!("/at-home/server/{uuid}"),
-            Self::GetManga(uuid) => format!("/manga/{uuid}"),
+            Self::GetCovers(params) => format!(
+                "/cover?{}",
+                serde_urlencoded::to_string(params)
+                    .expect("failed to build `GetCovers` query string")
+            ),
+            Self::GetManga(uuid) => {
+                format!("/manga/{uuid}?includes[]=author&includes[]=artist&includes[]=cover_art")
+            }
 
             Self::GetMangaChapters(uuid, params) => format!(
                 "/manga/{uuid}/feed?{}",
@@ -65,6 +137,38 @@ impl Endpoint {
                     .expect("failed to build `GetMangaChapters` query string")
             ),
 
+            Self::GetMangaAggregate(uuid, params) => format!(
+                "/manga/{uuid}/aggregate?{}",
+                serde_urlencoded::to_string(params)
+                    .expect("failed to build `GetMangaAggregate` query string")
+            ),
+
+            Self::GetMangaStatistics(uuids) => format!(
+                "/statistics/manga?{}",
+                serde_urlencoded::to_string(
+                    uuids
+                        .iter()
+                        .map(|uuid| ("manga[]", uuid.to_string()))
+                        .collect::<Vec<_>>()
+                )
+                .expect("failed to build `GetMangaStatistics` query string")
+            ),
+
+            Self::GetChapterStatistics(uuids) => format!(
+                "/statistics/chapter?{}",
+                serde_urlencoded::to_string(
+                    uuids
+                        .iter()
+                        .map(|uuid| ("chapter[]", uuid.to_string()))
+                        .collect::<Vec<_>>()
+                )
+                .expect("failed to build `GetChapterStatistics` query string")
+            ),
+
+            Self::LegacyMapping => "/legacy/mapping".to_string(),
+            Self::MangaReadingStatus(uuid) => format!("/manga/{uuid}/status"),
+            Self::Ping => "/ping".to_string(),
+
             Self::SearchManga(params) => {
                 format!(
                     "/manga?{}",
@@ -0,0 +1,146 @@
+//! Contains [`find_gaps`], which finds runs of chapter numbers missing from a
+//! manga's chapters, and [`report_gaps`], which warns about them.
+//!
+//! Only whole numbers are considered, so a missing `"10.5"` isn't a gap, but
+//! a missing `"10"` is (unless there's a `"10.5"`, which counts as having it).
+
+use crate::{
+    api::{
+        language::MdLanguage,
+        models::{Chapter, ChapterNumber, Manga},
+        search::{ChapterFilter, SearchClient},
+    },
+    progress::print_line,
+};
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use console::style;
+use isolang::Language;
+use miette::Result;
+
+/// A run of whole chapter numbers (`start..=end`) with no chapter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChapterGap {
+    pub start: u32,
+    pub end: u32,
+    /// Other languages which have every chapter in the gap, if they were looked up.
+    pub available_in: Vec<MdLanguage>,
+}
+
+impl fmt::Display for ChapterGap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "Chapter {}", self.start)
+        } else {
+            write!(f, "Chapters {}–{}", self.start, self.end)
+        }
+    }
+}
+
+/// Returns every gap between the lowest and highest numbered of `chapters`, in order.
+///
+/// Chapters without a numeric number (e.g. oneshots) are ignored.
+#[must_use]
+pub fn find_gaps(chapters: &[Chapter]) -> Vec<ChapterGap> {
+    let numbers: BTreeSet<u32> = chapters
+        .iter()
+        .filter_map(|c| c.number().map(ChapterNumber::whole))
+        .collect();
+
+    let mut gaps = Vec::new();
+
+    for (prev, next) in numbers.iter().zip(numbers.iter().skip(1)) {
+        if next - prev > 1 {
+            gaps.push(ChapterGap {
+                start: prev + 1,
+                end: next - 1,
+                available_in: Vec::new(),
+            });
+        }
+    }
+
+    gaps
+}
+
+/// Fills in [`ChapterGap::available_in`] for each of `gaps`, using `all_chapters`
+/// (the manga's chapters in every language).
+fn find_available(gaps: &mut [ChapterGap], all_chapters: &[Chapter]) {
+    let mut numbers: BTreeMap<MdLanguage, BTreeSet<u32>> = BTreeMap::new();
+
+    for chapter in all_chapters {
+        if let Some(number) = chapter.number() {
+            numbers
+                .entry(chapter.data.attributes.translated_language)
+                .or_default()
+                .insert(number.whole());
+        }
+    }
+
+    for gap in gaps {
+        gap.available_in = numbers
+            .iter()
+            .filter(|(_, n)| (gap.start..=gap.end).all(|i| n.contains(&i)))
+            .map(|(language, _)| *language)
+            .collect();
+    }
+}
+
+/// Warns about the gaps in `chapters` (the chapters of `manga` in `language`).
+///
+/// If `suggest_languages`, every chapter of `manga` is fetched to find which other
+/// languages have the missing chapters. Returns the gaps found.
+///
+/// ## Errors
+///
+/// If `suggest_languages` and the chapters can't be fetched, see
+/// [`SearchClient::fetch_chapters`].
+pub async fn report_gaps(
+    searcher: &SearchClient,
+    manga: &Manga,
+    chapters: &[Chapter],
+    language: Language,
+    suggest_languages: bool,
+) -> Result<Vec<ChapterGap>> {
+    let mut gaps = find_gaps(chapters);
+
+    if gaps.is_empty() {
+        return Ok(gaps);
+    }
+
+    if suggest_languages {
+        let all_chapters = searcher
+            .fetch_chapters(manga, &[], &ChapterFilter::default())
+            .await?;
+        find_available(&mut gaps, &all_chapters);
+    }
+
+    let code = language.to_639_1().unwrap_or_else(|| language.to_639_3());
+
+    for gap in &gaps {
+        let verb = if gap.start == gap.end { "has" } else { "have" };
+        warn!(
+            "{gap} of {:?} {verb} no {code} translation",
+            manga.title(language)
+        );
+
+        let mut line = format!("{gap} {verb} no {code} translation");
+        let others: Vec<String> = gap
+            .available_in
+            .iter()
+            .filter(|l| **l != MdLanguage::new(language))
+            .map(MdLanguage::to_string)
+            .collect();
+
+        if !others.is_empty() {
+            line = format!("{line} (available in: {})", others.join(", "));
+        }
+
+        print_line(style(line).yellow());
+    }
+
+    Ok(gaps)
+}
@@ -0,0 +1,245 @@
+//! Contains [`apply_group_preferences`], which filters and dedupes
+//! chapters using the `[groups]` config section.
+
+use crate::{
+    api::{language::MdLanguage, models::Chapter},
+    config::Groups,
+};
+
+use std::collections::HashMap;
+
+/// Returns true if `entry` (a group's name or uuid, from the config)
+/// refers to the scanlation group with `uuid` and `name`.
+fn matches_group(entry: &str, uuid: &str, name: Option<&str>) -> bool {
+    let entry = entry.trim();
+
+    entry.eq_ignore_ascii_case(uuid) || name.is_some_and(|n| entry.eq_ignore_ascii_case(n.trim()))
+}
+
+/// Returns the position of the most preferred of `chapter`'s groups in `preferred`, if any.
+fn preferred_rank(chapter: &Chapter, preferred: &[String]) -> Option<usize> {
+    chapter
+        .groups()
+        .filter_map(|(uuid, name)| {
+            preferred
+                .iter()
+                .position(|p| matches_group(p, &uuid.to_string(), name))
+        })
+        .min()
+}
+
+/// Returns the position of the most preferred of the groups named `names` in `preferred`,
+/// for chapters in the library (which only know their groups by name).
+#[must_use]
+pub fn preferred_name_rank(names: &[String], preferred: &[String]) -> Option<usize> {
+    names
+        .iter()
+        .filter_map(|name| {
+            preferred
+                .iter()
+                .position(|p| matches_group(p, "", Some(name)))
+        })
+        .min()
+}
+
+/// Returns true if any of `chapter`'s groups are in `blocked`.
+fn is_blocked(chapter: &Chapter, blocked: &[String]) -> bool {
+    chapter.groups().any(|(uuid, name)| {
+        blocked
+            .iter()
+            .any(|b| matches_group(b, &uuid.to_string(), name))
+    })
+}
+
+/// Identifies the versions of the same chapter: its volume, number and language.
+type ChapterKey<'a> = (Option<&'a str>, &'a str, MdLanguage);
+
+/// Returns `chapter`'s [`ChapterKey`], or `None` if it has no number.
+fn chapter_key(chapter: &Chapter) -> Option<ChapterKey<'_>> {
+    let attrs = &chapter.data.attributes;
+
+    Some((
+        attrs.volume.as_deref(),
+        attrs.chapter_number.as_deref()?,
+        attrs.translated_language,
+    ))
+}
+
+/// Removes chapters from [blocked](`Groups::blocked`) groups, then dedupes
+/// chapter versions using [preferred](`Groups::preferred`) groups.
+///
+/// If a chapter (i.e. a volume, number and language) has a version from a preferred group,
+/// only the version from the most preferred group is kept. Other chapters are left as
+/// they are, as are chapters without a number. The order of `chapters` is otherwise kept.
+#[must_use]
+pub fn apply_group_preferences(chapters: Vec<Chapter>, groups: &Groups) -> Vec<Chapter> {
+    let total = chapters.len();
+    let chapters: Vec<Chapter> = chapters
+        .into_iter()
+        .filter(|c| !is_blocked(c, &groups.blocked))
+        .collect();
+
+    if chapters.len() != total {
+        info!(
+            "Skipped {} chapters from blocked groups",
+            total - chapters.len()
+        );
+    }
+
+    if groups.preferred.is_empty() {
+        return chapters;
+    }
+
+    // (volume, number, language) => (rank, index) of the best version so far
+    let mut best: HashMap<ChapterKey, (usize, usize)> = HashMap::new();
+
+    for (i, chapter) in chapters.iter().enumerate() {
+        let (Some(key), Some(rank)) = (
+            chapter_key(chapter),
+            preferred_rank(chapter, &groups.preferred),
+        ) else {
+            continue;
+        };
+
+        best.entry(key)
+            .and_modify(|b| {
+                if rank < b.0 {
+                    *b = (rank, i);
+                }
+            })
+            .or_insert((rank, i));
+    }
+
+    let keep: Vec<bool> = chapters
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            chapter_key(c)
+                .and_then(|key| best.get(&key))
+                .is_none_or(|&(_, best_i)| best_i == i)
+        })
+        .collect();
+
+    let before = chapters.len();
+    let chapters: Vec<Chapter> = chapters
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(c, keep)| keep.then_some(c))
+        .collect();
+
+    if chapters.len() != before {
+        info!(
+            "Skipped {} duplicate chapter versions in favour of preferred groups",
+            before - chapters.len()
+        );
+    }
+
+    chapters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    const ALPHA: &str = "00000000-0000-0000-0000-00000000000a";
+    const BETA: &str = "00000000-0000-0000-0000-00000000000b";
+
+    fn chapter(
+        id: u128,
+        volume: Option<&str>,
+        number: &str,
+        language: &str,
+        group: &str,
+    ) -> Chapter {
+        let name = if group == ALPHA { "Alpha" } else { "Beta" };
+        let time = "2024-01-01T00:00:00+00:00";
+
+        serde_json::from_value(json!({
+            "data": {
+                "id": uuid::Uuid::from_u128(id).to_string(),
+                "type": "chapter",
+                "attributes": {
+                    "volume": volume,
+                    "chapter": number,
+                    "title": null,
+                    "translatedLanguage": language,
+                    "externalUrl": null,
+                    "isUnavailable": false,
+                    "publishAt": time,
+                    "readableAt": time,
+                    "createdAt": time,
+                    "updatedAt": time,
+                    "pages": 20,
+                    "version": 1,
+                },
+                "relationships": [{
+                    "id": group,
+                    "type": "scanlation_group",
+                    "attributes": { "name": name },
+                }],
+            }
+        }))
+        .unwrap()
+    }
+
+    fn ids(chapters: &[Chapter]) -> Vec<u128> {
+        chapters.iter().map(|c| c.uuid().as_u128()).collect()
+    }
+
+    fn groups(preferred: &[&str], blocked: &[&str]) -> Groups {
+        Groups {
+            preferred: preferred.iter().map(ToString::to_string).collect(),
+            blocked: blocked.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    #[test]
+    fn keeps_the_preferred_version() {
+        let chapters = vec![
+            chapter(1, Some("1"), "1", "en", ALPHA),
+            chapter(2, Some("1"), "1", "en", BETA),
+            chapter(3, Some("1"), "2", "en", ALPHA),
+        ];
+        let kept = apply_group_preferences(chapters, &groups(&["beta", "alpha"], &[]));
+
+        assert_eq!(ids(&kept), [2, 3]);
+    }
+
+    #[test]
+    fn keeps_versions_in_other_volumes_and_languages() {
+        let chapters = vec![
+            chapter(1, Some("1"), "1", "en", ALPHA),
+            chapter(2, Some("2"), "1", "en", BETA),
+            chapter(3, Some("1"), "1", "pt-br", BETA),
+            chapter(4, None, "1", "en", BETA),
+        ];
+        let kept = apply_group_preferences(chapters, &groups(&[ALPHA, BETA], &[]));
+
+        assert_eq!(ids(&kept), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn skips_blocked_groups() {
+        let chapters = vec![
+            chapter(1, Some("1"), "1", "en", ALPHA),
+            chapter(2, Some("1"), "1", "en", BETA),
+        ];
+        let kept = apply_group_preferences(chapters, &groups(&[], &[" Alpha "]));
+
+        assert_eq!(ids(&kept), [2]);
+    }
+
+    #[test]
+    fn matches_library_chapters_by_name() {
+        let preferred = ["beta".to_string(), "alpha".to_string()];
+        let names = ["Alpha".to_string(), "Beta".to_string()];
+
+        assert_eq!(preferred_name_rank(&names, &preferred), Some(0));
+        assert_eq!(
+            preferred_name_rank(&["Gamma".to_string()], &preferred),
+            None
+        );
+    }
+}
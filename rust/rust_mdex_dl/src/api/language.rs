@@ -0,0 +1,180 @@
+//! Contains [`MdLanguage`], the language codes used by Manga-Dex.
+//!
+//! These are ISO 639-1 codes, some of which have a variant that [`Language`] can't
+//! represent, e.g. `ja-ro` (romanized Japanese) or `pt-br` (Brazilian Portuguese).
+//!
+//! ref: <https://api.mangadex.org/docs/3-enumerations/#language-codes--localization>
+
+use std::fmt;
+
+use isolang::Language;
+use serde::{Deserialize, Serialize};
+
+/// A variant of an [`MdLanguage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Variant {
+    /// Written in latin characters, e.g. `ja-ro`.
+    Romanized,
+    /// A regional variant, e.g. `br` in `pt-br`, as lowercase ASCII.
+    Region([u8; 2]),
+}
+
+/// A language code used by Manga-Dex, see the [module docs](`self`).
+///
+/// Unknown (or null) codes, which Manga-Dex does return sometimes, are [`Language::Und`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct MdLanguage {
+    pub language: Language,
+    pub variant: Option<Variant>,
+}
+
+impl MdLanguage {
+    /// Constructs a new [`MdLanguage`] without a variant.
+    #[must_use]
+    pub const fn new(language: Language) -> Self {
+        Self {
+            language,
+            variant: None,
+        }
+    }
+
+    /// Constructs a new romanized [`MdLanguage`], e.g. `ja-ro`.
+    #[must_use]
+    pub const fn romanized(language: Language) -> Self {
+        Self {
+            language,
+            variant: Some(Variant::Romanized),
+        }
+    }
+
+    /// Parses a code such as `ja`, `ja-ro` or `pt-br`, returning `None` if it isn't valid.
+    #[must_use]
+    pub fn parse(code: &str) -> Option<Self> {
+        // check <https://api.mangadex.org/manga/0c936660-cb06-491b-8b61-15dacad1bfb4> json for why
+        if code.is_empty() || code.eq_ignore_ascii_case("null") {
+            return Some(Self::new(Language::Und));
+        }
+
+        let code = code.to_ascii_lowercase();
+        let (base, variant) = match code.split_once('-') {
+            Some((base, "ro")) => (base, Some(Variant::Romanized)),
+            Some((base, region))
+                if region.len() == 2 && region.bytes().all(|b| b.is_ascii_alphabetic()) =>
+            {
+                let region = region.as_bytes();
+                (base, Some(Variant::Region([region[0], region[1]])))
+            }
+            Some(_) => return None,
+            None => (code.as_str(), None),
+        };
+
+        Some(Self {
+            language: Language::from_639_1(base)?,
+            variant,
+        })
+    }
+
+    /// Returns true if this is a romanized variant, e.g. `ja-ro`.
+    #[must_use]
+    pub const fn is_romanized(&self) -> bool {
+        matches!(self.variant, Some(Variant::Romanized))
+    }
+
+    /// Returns the code used by Manga-Dex, e.g. `"ja-ro"`, or `None` if the language is unknown.
+    #[must_use]
+    pub fn code(&self) -> Option<String> {
+        let base = self.language.to_639_1()?;
+
+        Some(match self.variant {
+            None => base.to_string(),
+            Some(Variant::Romanized) => format!("{base}-ro"),
+            Some(Variant::Region(region)) => {
+                format!("{base}-{}", String::from_utf8_lossy(&region))
+            }
+        })
+    }
+}
+
+impl From<MdLanguage> for Language {
+    /// Drops the variant, e.g. `ja-ro` becomes Japanese.
+    fn from(language: MdLanguage) -> Self {
+        language.language
+    }
+}
+
+impl TryFrom<String> for MdLanguage {
+    type Error = String;
+
+    fn try_from(code: String) -> Result<Self, Self::Error> {
+        Self::parse(&code).ok_or_else(|| format!("invalid language code {code:?}"))
+    }
+}
+
+impl From<MdLanguage> for String {
+    fn from(language: MdLanguage) -> Self {
+        language.code().unwrap_or_default()
+    }
+}
+
+impl fmt::Display for MdLanguage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code().as_deref().unwrap_or("??"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_plain_codes() {
+        assert_eq!(
+            MdLanguage::parse("ja"),
+            Some(MdLanguage::new(Language::Jpn))
+        );
+        assert_eq!(
+            MdLanguage::parse("EN"),
+            Some(MdLanguage::new(Language::Eng))
+        );
+    }
+
+    #[test]
+    fn parses_variants() {
+        assert_eq!(
+            MdLanguage::parse("ja-ro"),
+            Some(MdLanguage::romanized(Language::Jpn))
+        );
+        assert_eq!(
+            MdLanguage::parse("pt-BR"),
+            Some(MdLanguage {
+                language: Language::Por,
+                variant: Some(Variant::Region(*b"br")),
+            })
+        );
+    }
+
+    #[test]
+    fn treats_missing_codes_as_unknown() {
+        let unknown = Some(MdLanguage::new(Language::Und));
+        assert_eq!(MdLanguage::parse(""), unknown);
+        assert_eq!(MdLanguage::parse("null"), unknown);
+        assert_eq!(MdLanguage::parse("NULL"), unknown);
+    }
+
+    #[test]
+    fn rejects_invalid_codes() {
+        assert_eq!(MdLanguage::parse("xx"), None);
+        assert_eq!(MdLanguage::parse("ja-"), None);
+        assert_eq!(MdLanguage::parse("es-419"), None);
+        assert_eq!(MdLanguage::parse("pt-b1"), None);
+    }
+
+    #[test]
+    fn round_trips_codes() {
+        for code in ["ja", "ja-ro", "pt-br", "zh-hk"] {
+            assert_eq!(MdLanguage::parse(code).unwrap().to_string(), code);
+        }
+        assert_eq!(MdLanguage::new(Language::Und).to_string(), "??");
+    }
+}
@@ -0,0 +1,224 @@
+//! Contains [`MdLink`] and [`LegacyIds`], which turn links to Manga-Dex (or paths from
+//! backups) into uuids, including the legacy numeric ids that older links still use,
+//! e.g. `https://mangadex.org/title/7139/one-punch-man`.
+//!
+//! Legacy ids are mapped to uuids with [`Endpoint::LegacyMapping`].
+
+use crate::api::{client::ApiClient, endpoints::Endpoint, models::Chapter};
+
+use std::collections::HashMap;
+
+use miette::{Context, Result, miette};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// The most legacy ids mapped in a single request.
+pub const MAX_LEGACY_IDS: usize = 100;
+
+/// What a legacy id is the id of.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LegacyType {
+    Manga,
+    Chapter,
+}
+
+#[derive(Serialize, Debug)]
+struct MappingRequest<'a> {
+    #[serde(rename = "type")]
+    kind: LegacyType,
+    ids: &'a [u64],
+}
+
+#[derive(Deserialize, Debug)]
+struct MappingResponse {
+    data: Vec<Mapping>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Mapping {
+    attributes: MappingAttributes,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct MappingAttributes {
+    legacy_id: u64,
+    new_id: Uuid,
+}
+
+/// Maps the legacy `ids` of `kind` to uuids, in batches of [`MAX_LEGACY_IDS`].
+///
+/// Ids that Manga-Dex has no mapping for are left out.
+///
+/// ## Errors
+///
+/// If propagated from [`ApiClient::post_json`].
+pub async fn resolve_legacy_ids(
+    api: &ApiClient,
+    kind: LegacyType,
+    ids: &[u64],
+) -> Result<HashMap<u64, Uuid>> {
+    let mut mapped = HashMap::with_capacity(ids.len());
+
+    for batch in ids.chunks(MAX_LEGACY_IDS) {
+        let request = MappingRequest { kind, ids: batch };
+        let r: MappingResponse = api
+            .post_json(Endpoint::LegacyMapping, &request)
+            .await
+            .wrap_err_with(|| format!("failed to map legacy {kind:?} ids"))?;
+
+        mapped.extend(
+            r.data
+                .into_iter()
+                .map(|m| (m.attributes.legacy_id, m.attributes.new_id)),
+        );
+    }
+
+    if mapped.len() < ids.len() {
+        debug!(
+            "{} legacy {kind:?} ids have no mapping",
+            ids.len() - mapped.len()
+        );
+    }
+
+    Ok(mapped)
+}
+
+/// A manga or chapter that a link refers to, see [`Self::parse`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MdLink {
+    Manga(Uuid),
+    Chapter(Uuid),
+    LegacyManga(u64),
+    LegacyChapter(u64),
+}
+
+impl MdLink {
+    /// Parses a link to a manga or chapter, such as:
+    ///
+    /// - `https://mangadex.org/title/{uuid}/{slug}`
+    /// - `https://mangadex.org/chapter/{uuid}`
+    /// - `mangadex.org/title/7139/one-punch-man` (a legacy id)
+    /// - `/manga/7139` (a path, as stored in backups)
+    /// - a bare uuid, which is taken as a manga's
+    ///
+    /// Returns `None` if `link` isn't any of these.
+    #[must_use]
+    pub fn parse(link: &str) -> Option<Self> {
+        let link = link.trim();
+
+        if let Ok(uuid) = Uuid::parse_str(link) {
+            return Some(Self::Manga(uuid));
+        }
+
+        let path = match link.split_once("mangadex.org") {
+            Some((_, path)) => path,
+            None if link.starts_with('/') => link,
+            None => return None,
+        };
+
+        let mut segments = path.split(['/', '?', '#']).filter(|s| !s.is_empty());
+        let is_chapter = match segments.next()? {
+            "title" | "manga" => false,
+            "chapter" => true,
+            _ => return None,
+        };
+        let id = segments.next()?;
+
+        if let Ok(uuid) = Uuid::parse_str(id) {
+            return Some(if is_chapter {
+                Self::Chapter(uuid)
+            } else {
+                Self::Manga(uuid)
+            });
+        }
+
+        let legacy_id = id.parse().ok()?;
+
+        Some(if is_chapter {
+            Self::LegacyChapter(legacy_id)
+        } else {
+            Self::LegacyManga(legacy_id)
+        })
+    }
+
+    /// Returns the uuid of the manga this refers to, mapping legacy ids
+    /// and fetching the chapter (for its manga) if needed.
+    ///
+    /// ## Errors
+    ///
+    /// If a request fails, or a legacy id has no mapping.
+    pub async fn manga_uuid(self, api: &ApiClient) -> Result<Uuid> {
+        let chapter_uuid = match self {
+            Self::Manga(uuid) => return Ok(uuid),
+            Self::LegacyManga(id) => return resolve_one(api, LegacyType::Manga, id).await,
+            Self::Chapter(uuid) => uuid,
+            Self::LegacyChapter(id) => resolve_one(api, LegacyType::Chapter, id).await?,
+        };
+
+        Ok(Chapter::new(api, chapter_uuid).await?.parent_uuid())
+    }
+}
+
+/// Maps a single legacy id of `kind` to its uuid.
+async fn resolve_one(api: &ApiClient, kind: LegacyType, id: u64) -> Result<Uuid> {
+    resolve_legacy_ids(api, kind, &[id])
+        .await?
+        .get(&id)
+        .copied()
+        .ok_or_else(|| miette!("no {kind:?} with the legacy id {id} was found"))
+}
+
+/// Legacy ids mapped to uuids, for resolving many links at once (see [`Self::resolve`]).
+#[derive(Debug, Clone, Default)]
+pub struct LegacyIds {
+    pub manga: HashMap<u64, Uuid>,
+    pub chapters: HashMap<u64, Uuid>,
+}
+
+impl LegacyIds {
+    /// Maps the legacy ids of every link in `links` (see [`MdLink::parse`]),
+    /// using as few requests as possible.
+    ///
+    /// ## Errors
+    ///
+    /// If propagated from [`resolve_legacy_ids`].
+    pub async fn resolve<'a>(
+        api: &ApiClient,
+        links: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Self> {
+        let (mut manga, mut chapters) = (Vec::new(), Vec::new());
+
+        for link in links {
+            match MdLink::parse(link) {
+                Some(MdLink::LegacyManga(id)) => manga.push(id),
+                Some(MdLink::LegacyChapter(id)) => chapters.push(id),
+                _ => {}
+            }
+        }
+
+        let mut ids = Self::default();
+
+        if !manga.is_empty() {
+            ids.manga = resolve_legacy_ids(api, LegacyType::Manga, &manga).await?;
+        }
+
+        if !chapters.is_empty() {
+            ids.chapters = resolve_legacy_ids(api, LegacyType::Chapter, &chapters).await?;
+        }
+
+        Ok(ids)
+    }
+
+    /// Returns the uuid that `link` refers to, if it's a legacy id
+    /// that was mapped by [`Self::resolve`].
+    #[must_use]
+    pub fn uuid(&self, link: &str) -> Option<Uuid> {
+        match MdLink::parse(link)? {
+            MdLink::LegacyManga(id) => self.manga.get(&id).copied(),
+            MdLink::LegacyChapter(id) => self.chapters.get(&id).copied(),
+            MdLink::Manga(_) | MdLink::Chapter(_) => None,
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Contains [`Middleware`], hooks run by [`ApiClient`](`crate::api::client::ApiClient`)
+//! around every request it sends, and some middleware which are built on it.
+//!
+//! Middleware run in the order they're added before a request is sent, and in the
+//! reverse order after its response is received, so the first one added wraps the rest.
+//! The hooks run once per attempt, so a request retried after being ratelimited goes
+//! through them again.
+
+use crate::{
+    api::{ratelimit::RateLimiter, request_id::RequestId, response::ResponseHeaders},
+    budget::{check_budget, record_request as record_budget_request},
+    metrics::{record_api_call, record_ratelimited},
+    redact::redact,
+    trace_bundle::{RequestRecord, record_request},
+};
+
+use std::{fmt, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use miette::Result;
+
+/// What a [`Middleware`] is told about a request after it's sent.
+#[derive(Debug, Clone)]
+pub struct Exchange {
+    /// Shared by every attempt at sending the same request.
+    pub request_id: RequestId,
+    pub method: reqwest::Method,
+    pub url: reqwest::Url,
+    pub sent_at: DateTime<Utc>,
+    /// How long it took to receive the response (or fail to).
+    pub elapsed: Duration,
+}
+
+/// A hook run before and after every request sent by an
+/// [`ApiClient`](`crate::api::client::ApiClient`).
+///
+/// Both hooks do nothing by default, so only the needed one has to be implemented.
+pub trait Middleware: fmt::Debug + Send + Sync {
+    /// Called before `request` is sent, which can be modified (e.g. to add headers).
+    ///
+    /// ## Errors
+    ///
+    /// Returning an error stops the request from being sent.
+    fn before<'a>(&'a self, request: &'a mut reqwest::Request) -> BoxFuture<'a, Result<()>> {
+        let _ = request;
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Called after `exchange` is sent, with its `response` if one was received.
+    ///
+    /// ## Errors
+    ///
+    /// Returning an error fails the request, even if its response was fine.
+    fn after<'a>(
+        &'a self,
+        exchange: &'a Exchange,
+        response: Option<&'a reqwest::Response>,
+    ) -> BoxFuture<'a, Result<()>> {
+        let _ = (exchange, response);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Records every request for trace bundles, see [`record_request`].
+#[derive(Debug, Clone, Copy)]
+pub struct RecordRequests;
+
+impl Middleware for RecordRequests {
+    fn after<'a>(
+        &'a self,
+        exchange: &'a Exchange,
+        response: Option<&'a reqwest::Response>,
+    ) -> BoxFuture<'a, Result<()>> {
+        let headers = response.map(|r| ResponseHeaders::from_headers(r.headers()));
+
+        record_request(RequestRecord {
+            request_id: exchange.request_id.to_string(),
+            timestamp: exchange.sent_at,
+            method: exchange.method.to_string(),
+            url: redact(exchange.url.as_str()).into_owned(),
+            status: response.map(|r| r.status().as_u16()),
+            server_request_id: headers
+                .as_ref()
+                .and_then(|h| h.request_id())
+                .map(str::to_string),
+            ratelimit_remaining: headers.and_then(|h| h.ratelimit_remaining),
+            elapsed_ms: exchange.elapsed.as_millis(),
+        });
+
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Records every request in the run's [metrics](`crate::metrics`).
+#[derive(Debug, Clone, Copy)]
+pub struct RecordMetrics;
+
+impl Middleware for RecordMetrics {
+    fn after<'a>(
+        &'a self,
+        exchange: &'a Exchange,
+        response: Option<&'a reqwest::Response>,
+    ) -> BoxFuture<'a, Result<()>> {
+        record_api_call(exchange.elapsed, response.is_some());
+
+        if response.is_some_and(|r| r.status() == reqwest::StatusCode::TOO_MANY_REQUESTS) {
+            record_ratelimited();
+        }
+
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Spends the run's [budget](`crate::budget`) on every request, refusing to send any
+/// more once it's exhausted.
+#[derive(Debug, Clone, Copy)]
+pub struct EnforceBudget;
+
+impl Middleware for EnforceBudget {
+    fn before<'a>(&'a self, _request: &'a mut reqwest::Request) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async { check_budget() })
+    }
+
+    fn after<'a>(
+        &'a self,
+        _exchange: &'a Exchange,
+        response: Option<&'a reqwest::Response>,
+    ) -> BoxFuture<'a, Result<()>> {
+        record_budget_request(response.map(reqwest::Response::status));
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Waits for a [`RateLimiter`] before every request, and tells it about every
+/// response's ratelimit headers (see [`RateLimiter::observe`]).
+///
+/// Clones of the same limiter can be shared with other clients (or other
+/// middleware chains) so that they're limited together.
+#[derive(Debug, Clone)]
+pub struct RateLimit(pub Arc<RateLimiter>);
+
+impl Middleware for RateLimit {
+    fn before<'a>(&'a self, _request: &'a mut reqwest::Request) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async {
+            self.0.acquire().await;
+            Ok(())
+        })
+    }
+
+    fn after<'a>(
+        &'a self,
+        _exchange: &'a Exchange,
+        response: Option<&'a reqwest::Response>,
+    ) -> BoxFuture<'a, Result<()>> {
+        if let Some(r) = response {
+            self.0.observe(&ResponseHeaders::from_headers(r.headers()));
+        }
+
+        Box::pin(async { Ok(()) })
+    }
+}
@@ -1,7 +1,21 @@
 //! Contains modules that interact with Manga-Dex's API.
 
+pub mod adaptive;
+pub mod auth;
+pub mod cache;
 pub mod client;
+pub mod deprecation;
 pub mod download;
 pub mod endpoints;
+pub mod gaps;
+pub mod groups;
+pub mod language;
+pub mod legacy;
+pub mod middleware;
 pub mod models;
+pub mod preflight;
+pub mod ratelimit;
+pub mod request_id;
+pub mod rerank;
+pub mod response;
 pub mod search;
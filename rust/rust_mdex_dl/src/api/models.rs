@@ -1,25 +1,28 @@
 //! Contains the [`Manga`] and [`Chapter`] structs
 //! which model the corresponding API responses.
 
-use std::collections::HashMap;
+use std::{cmp::Ordering, collections::HashMap, fmt, sync::OnceLock};
 
 use crate::{
-    api::{client::ApiClient, endpoints::Endpoint},
+    api::{client::ApiClient, endpoints::Endpoint, language::MdLanguage},
     deserializers::{
         // "don't use wildcard import" they said...
-        deserialize_langcode,
+        LenientEnum,
         deserialize_langcode_map,
-        deserialize_langcode_map_vec,
+        deserialize_lenient,
+        deserialize_map_or_empty,
+        deserialize_md_language_vec,
         deserialize_utc_datetime,
         deserialize_uuid,
     },
+    naming::pad_chapter_number,
 };
 
 use chrono::{DateTime, Utc};
 use isolang::Language;
-use miette::Result;
+use miette::{Context, Result};
 use reqwest::Url;
-use serde::{self, Deserialize};
+use serde::{self, Deserialize, Serialize};
 use uuid::Uuid;
 
 /// For storing the [`MangaAttributes::content_rating`] field.
@@ -27,7 +30,7 @@ use uuid::Uuid;
 /// ## References
 ///
 /// - [MangaDex docs](https://api.mangadex.org/docs/3-enumerations/#manga-content-rating)
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "lowercase")]
 #[allow(missing_docs)]
 pub enum ContentRating {
@@ -35,6 +38,9 @@ pub enum ContentRating {
     Suggestive,
     Erotica,
     Pornographic,
+    /// A rating added to Manga-Dex after this was written, see [`LenientEnum`].
+    #[serde(untagged)]
+    Unknown(String),
 }
 
 /// For storing the [`MangaAttributes::status`] field.
@@ -42,7 +48,7 @@ pub enum ContentRating {
 /// ## References
 ///
 /// - [MangaDex docs](https://api.mangadex.org/docs/3-enumerations/#manga-status)
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "lowercase")]
 #[allow(missing_docs)]
 pub enum Status {
@@ -50,6 +56,137 @@ pub enum Status {
     Completed,
     Hiatus,
     Cancelled,
+    /// A status added to Manga-Dex after this was written, see [`LenientEnum`].
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+impl ContentRating {
+    /// Returns the rating as Manga-Dex writes it, e.g. `"safe"`.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Safe => "safe",
+            Self::Suggestive => "suggestive",
+            Self::Erotica => "erotica",
+            Self::Pornographic => "pornographic",
+            Self::Unknown(raw) => raw,
+        }
+    }
+}
+
+impl LenientEnum for ContentRating {
+    const NAME: &'static str = "content rating";
+
+    fn known(raw: &str) -> Option<Self> {
+        match raw {
+            "safe" => Some(Self::Safe),
+            "suggestive" => Some(Self::Suggestive),
+            "erotica" => Some(Self::Erotica),
+            "pornographic" => Some(Self::Pornographic),
+            _ => None,
+        }
+    }
+
+    fn unknown(raw: String) -> Self {
+        Self::Unknown(raw)
+    }
+}
+
+impl<'de> Deserialize<'de> for ContentRating {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_lenient(deserializer)
+    }
+}
+
+impl fmt::Display for ContentRating {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl LenientEnum for Status {
+    const NAME: &'static str = "status";
+
+    fn known(raw: &str) -> Option<Self> {
+        match raw {
+            "ongoing" => Some(Self::Ongoing),
+            "completed" => Some(Self::Completed),
+            "hiatus" => Some(Self::Hiatus),
+            "cancelled" => Some(Self::Cancelled),
+            _ => None,
+        }
+    }
+
+    fn unknown(raw: String) -> Self {
+        Self::Unknown(raw)
+    }
+}
+
+impl<'de> Deserialize<'de> for Status {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_lenient(deserializer)
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Ongoing => "ongoing",
+            Self::Completed => "completed",
+            Self::Hiatus => "hiatus",
+            Self::Cancelled => "cancelled",
+            Self::Unknown(raw) => raw,
+        })
+    }
+}
+
+/// A manga's reading status in a user's Manga-Dex library, see [`crate::tracking`].
+///
+/// ## References
+///
+/// - [Redoc](https://api.mangadex.org/docs/redoc.html#tag/Manga/operation/post-manga-status)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[allow(missing_docs)]
+pub enum ReadingStatus {
+    Reading,
+    OnHold,
+    PlanToRead,
+    Dropped,
+    ReReading,
+    Completed,
+}
+
+impl ReadingStatus {
+    /// Every reading status, in the order Manga-Dex lists them.
+    pub const ALL: [Self; 6] = [
+        Self::Reading,
+        Self::OnHold,
+        Self::PlanToRead,
+        Self::Dropped,
+        Self::ReReading,
+        Self::Completed,
+    ];
+
+    /// Returns the status as Manga-Dex writes it, e.g. `"on_hold"`.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Reading => "reading",
+            Self::OnHold => "on_hold",
+            Self::PlanToRead => "plan_to_read",
+            Self::Dropped => "dropped",
+            Self::ReReading => "re_reading",
+            Self::Completed => "completed",
+        }
+    }
+}
+
+impl fmt::Display for ReadingStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 /// For storing the [`MangaAttributes::state`] field.
@@ -57,14 +194,39 @@ pub enum Status {
 /// ## References
 ///
 /// - [MangaDex docs](https://api.mangadex.org/docs/redoc.html#tag/Manga/operation/get-manga-id)
-#[derive(Debug, Clone, Deserialize)]
-#[serde(rename_all = "lowercase")]
+#[derive(Debug, Clone)]
 #[allow(missing_docs)]
 pub enum State {
     Draft,
     Submitted,
     Published,
     Rejected,
+    /// A state added to Manga-Dex after this was written, see [`LenientEnum`].
+    Unknown(String),
+}
+
+impl LenientEnum for State {
+    const NAME: &'static str = "state";
+
+    fn known(raw: &str) -> Option<Self> {
+        match raw {
+            "draft" => Some(Self::Draft),
+            "submitted" => Some(Self::Submitted),
+            "published" => Some(Self::Published),
+            "rejected" => Some(Self::Rejected),
+            _ => None,
+        }
+    }
+
+    fn unknown(raw: String) -> Self {
+        Self::Unknown(raw)
+    }
+}
+
+impl<'de> Deserialize<'de> for State {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_lenient(deserializer)
+    }
 }
 
 /// For storing the [`MangaAttributes::publication_demographic`] field.
@@ -72,7 +234,7 @@ pub enum State {
 /// ## References
 ///
 /// - [MangaDex docs](https://api.mangadex.org/docs/3-enumerations/#manga-publication-demographic)
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "lowercase")]
 #[allow(missing_docs)]
 pub enum PublicationDemographic {
@@ -80,6 +242,150 @@ pub enum PublicationDemographic {
     Shoujo,
     Josei,
     Seinen,
+    /// A demographic added to Manga-Dex after this was written, see [`LenientEnum`].
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+impl LenientEnum for PublicationDemographic {
+    const NAME: &'static str = "publication demographic";
+
+    fn known(raw: &str) -> Option<Self> {
+        match raw {
+            "shounen" => Some(Self::Shounen),
+            "shoujo" => Some(Self::Shoujo),
+            "josei" => Some(Self::Josei),
+            "seinen" => Some(Self::Seinen),
+            _ => None,
+        }
+    }
+
+    fn unknown(raw: String) -> Self {
+        Self::Unknown(raw)
+    }
+}
+
+impl<'de> Deserialize<'de> for PublicationDemographic {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_lenient(deserializer)
+    }
+}
+
+/// For storing the [`Relationship::related`] field, i.e. how a manga relates to another.
+///
+/// ## References
+///
+/// - [MangaDex docs](https://api.mangadex.org/docs/3-enumerations/#manga-related-enum)
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum MangaRelation {
+    Monochrome,
+    MainStory,
+    AdaptedFrom,
+    BasedOn,
+    Prequel,
+    SideStory,
+    Doujinshi,
+    SameFranchise,
+    SharedUniverse,
+    Sequel,
+    SpinOff,
+    AlternateStory,
+    AlternateVersion,
+    Preserialization,
+    Colored,
+    Serialization,
+    /// A relation added to Manga-Dex after this was written, see [`LenientEnum`].
+    Unknown(String),
+}
+
+impl MangaRelation {
+    /// Returns the relation as Manga-Dex writes it, e.g. `"spin_off"`.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Monochrome => "monochrome",
+            Self::MainStory => "main_story",
+            Self::AdaptedFrom => "adapted_from",
+            Self::BasedOn => "based_on",
+            Self::Prequel => "prequel",
+            Self::SideStory => "side_story",
+            Self::Doujinshi => "doujinshi",
+            Self::SameFranchise => "same_franchise",
+            Self::SharedUniverse => "shared_universe",
+            Self::Sequel => "sequel",
+            Self::SpinOff => "spin_off",
+            Self::AlternateStory => "alternate_story",
+            Self::AlternateVersion => "alternate_version",
+            Self::Preserialization => "preserialization",
+            Self::Colored => "colored",
+            Self::Serialization => "serialization",
+            Self::Unknown(raw) => raw,
+        }
+    }
+
+    /// Whether this continues (or precedes) the story, i.e. is a sequel or prequel.
+    #[must_use]
+    pub const fn is_continuation(&self) -> bool {
+        matches!(self, Self::Sequel | Self::Prequel)
+    }
+}
+
+impl LenientEnum for MangaRelation {
+    const NAME: &'static str = "manga relation";
+
+    fn known(raw: &str) -> Option<Self> {
+        match raw {
+            "monochrome" => Some(Self::Monochrome),
+            "main_story" => Some(Self::MainStory),
+            "adapted_from" => Some(Self::AdaptedFrom),
+            "based_on" => Some(Self::BasedOn),
+            "prequel" => Some(Self::Prequel),
+            "side_story" => Some(Self::SideStory),
+            "doujinshi" => Some(Self::Doujinshi),
+            "same_franchise" => Some(Self::SameFranchise),
+            "shared_universe" => Some(Self::SharedUniverse),
+            "sequel" => Some(Self::Sequel),
+            "spin_off" => Some(Self::SpinOff),
+            "alternate_story" => Some(Self::AlternateStory),
+            "alternate_version" => Some(Self::AlternateVersion),
+            "preserialization" => Some(Self::Preserialization),
+            "colored" => Some(Self::Colored),
+            "serialization" => Some(Self::Serialization),
+            _ => None,
+        }
+    }
+
+    fn unknown(raw: String) -> Self {
+        Self::Unknown(raw)
+    }
+}
+
+impl<'de> Deserialize<'de> for MangaRelation {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_lenient(deserializer)
+    }
+}
+
+impl fmt::Display for MangaRelation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.as_str().replace('_', " "))
+    }
+}
+
+/// The attributes of a [`Relationship`], which are only
+/// present if it was requested using `includes[]`.
+///
+/// Only the fields shared by the included types used here are modelled.
+#[derive(Deserialize, Debug, Clone)]
+pub struct RelationshipAttributes {
+    /// The name of a scanlation group (or author).
+    pub name: Option<String>,
+    /// The username of a user, e.g. a chapter's uploader.
+    pub username: Option<String>,
+    /// The filename of a cover, see [`Manga::cover_url`].
+    #[serde(rename = "fileName")]
+    pub file_name: Option<String>,
 }
 
 /// Contains [`Self::id`] and [`Self::entity_type`], indicating
@@ -96,6 +402,15 @@ pub struct Relationship {
     /// - <https://api.mangadex.org/docs/3-enumerations/#relationship-types>
     #[serde(rename = "type")]
     pub entity_type: String,
+
+    /// See [`RelationshipAttributes`].
+    #[serde(default)]
+    pub attributes: Option<RelationshipAttributes>,
+
+    /// How the related manga relates to this one, only present on manga relationships
+    /// of a manga (e.g. [`MangaRelation::Sequel`] if it's a sequel of this one).
+    #[serde(default)]
+    pub related: Option<MangaRelation>,
 }
 
 impl Relationship {
@@ -107,7 +422,7 @@ impl Relationship {
 }
 
 /// Models a chapters attributes.
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ChapterAttributes {
     /// Stores the current manga volume of the chapter.
@@ -124,8 +439,7 @@ pub struct ChapterAttributes {
     /// The **translated** language of the chapter.
     ///
     /// This is unrelated to the manga's (original) language.
-    #[serde(deserialize_with = "deserialize_langcode")]
-    pub translated_language: Language,
+    pub translated_language: MdLanguage,
     /// An external URL related to the chapter.
     ///
     /// While this is usually where you can read
@@ -156,12 +470,72 @@ pub struct ChapterAttributes {
     #[serde(deserialize_with = "deserialize_utc_datetime")]
     /// The instant when this chapter was originally created.
     pub created_at: DateTime<Utc>,
+    #[serde(deserialize_with = "deserialize_utc_datetime")]
+    /// The instant when this chapter was last edited.
+    pub updated_at: DateTime<Utc>,
     /// The number of readable pages. Can be zero.
     pub pages: usize,
-    /// The version. Probably useless.
+    /// The version, which goes up whenever the chapter is edited (e.g. when the group
+    /// replaces or fixes its pages), see [`changed_chapters`](`crate::update::changed_chapters`).
     pub version: u32,
 }
 
+/// A numeric chapter number, parsed from [`ChapterAttributes::chapter_number`].
+///
+/// Chapter numbers are free text on Manga-Dex, so anything that isn't a finite,
+/// non-negative number (e.g. `"Extra"`) doesn't parse. These order numerically,
+/// so `"9"` comes before `"10"` and `"10.5"`.
+#[derive(Debug, Clone, Copy)]
+pub struct ChapterNumber(f64);
+
+impl ChapterNumber {
+    /// Parses a chapter number such as `"10.5"`, returning `None` if it isn't numeric.
+    #[must_use]
+    pub fn parse(number: &str) -> Option<Self> {
+        let number: f64 = number.trim().parse().ok()?;
+        (number.is_finite() && number >= 0.0).then_some(Self(number))
+    }
+
+    #[must_use]
+    pub const fn value(self) -> f64 {
+        self.0
+    }
+
+    /// Returns the whole part of the number, e.g. `10` for `"10.5"`.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn whole(self) -> u32 {
+        // saturates for absurdly large numbers, which isn't worth handling
+        self.0.trunc() as u32
+    }
+}
+
+impl PartialEq for ChapterNumber {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ChapterNumber {}
+
+impl PartialOrd for ChapterNumber {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ChapterNumber {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl fmt::Display for ChapterNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct ChapterData {
     #[serde(deserialize_with = "deserialize_uuid")]
@@ -172,6 +546,58 @@ pub struct ChapterData {
     pub relationships: Vec<Relationship>,
 }
 
+/// A chapter number in an [`AggregateVolume`].
+#[derive(Deserialize, Debug, Clone)]
+pub struct AggregateChapter {
+    /// The chapter number, or `"none"`.
+    pub chapter: String,
+    /// The uuid of one of the chapter's versions.
+    #[serde(deserialize_with = "deserialize_uuid")]
+    pub id: Uuid,
+    /// The uuids of the chapter's other versions (e.g. from other groups).
+    #[serde(default)]
+    pub others: Vec<Uuid>,
+}
+
+/// A volume in an [`Aggregate`].
+#[derive(Deserialize, Debug, Clone)]
+pub struct AggregateVolume {
+    /// The volume number, or `"none"` for chapters without one.
+    pub volume: String,
+    /// The volume's chapters, by chapter number.
+    #[serde(deserialize_with = "deserialize_map_or_empty")]
+    pub chapters: HashMap<String, AggregateChapter>,
+}
+
+/// Models the JSON response of [`Endpoint::GetMangaAggregate`], which
+/// groups a manga's chapters by volume.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Aggregate {
+    /// The manga's volumes, by volume number.
+    #[serde(deserialize_with = "deserialize_map_or_empty")]
+    pub volumes: HashMap<String, AggregateVolume>,
+}
+
+impl Aggregate {
+    /// Returns the volume of every chapter version, by chapter uuid.
+    ///
+    /// Chapters without a volume are left out.
+    #[must_use]
+    pub fn volumes_by_chapter(&self) -> HashMap<Uuid, String> {
+        self.volumes
+            .values()
+            .filter(|v| v.volume != "none")
+            .flat_map(|v| {
+                v.chapters.values().flat_map(move |c| {
+                    std::iter::once(c.id)
+                        .chain(c.others.iter().copied())
+                        .map(|uuid| (uuid, v.volume.clone()))
+                })
+            })
+            .collect()
+    }
+}
+
 /// Models the entire JSON response of [`Endpoint::GetChapter`] as a struct.
 ///
 /// This also allows easy usage of [`serde::Deserialize`] for [`Self::new`].
@@ -187,42 +613,37 @@ impl Chapter {
     ///
     /// ## Errors
     ///
-    /// If propagated from [`ApiClient::get_ok_json`].
+    /// If propagated from [`ApiClient::get_ok_parsed`].
     pub async fn new(client: &ApiClient, chapter_uuid: Uuid) -> Result<Self> {
-        let r_json = client
-            .get_ok_json(Endpoint::GetChapter(chapter_uuid))
-            .await?;
-
-        let chapter = serde_json::from_value::<Self>(r_json).map_err(|e| {
-            miette::miette!("Failed to parse chapter with chapter_uuid={chapter_uuid}: {e}")
-        })?;
-
-        Ok(chapter)
+        client
+            .get_ok_parsed(Endpoint::GetChapter(chapter_uuid))
+            .await
+            .wrap_err_with(|| format!("failed to fetch chapter with chapter_uuid={chapter_uuid}"))
     }
 
     /// Returns a formatted chapter title such as:
     ///
     /// `[011] I broke through`
     ///
-    /// Zero-padding is fixed to three characters because getting
-    /// the highest chapter number is a little tricky from here.
+    /// The chapter number is zero-padded to `num_width` digits, which should come from
+    /// [`num_width`](`crate::naming::num_width`) so that the whole series sorts correctly.
     #[must_use]
-    pub fn formatted_title(&self) -> String {
+    pub fn formatted_title(&self, num_width: usize) -> String {
         let attrs = &self.data.attributes;
 
         let title = attrs.title.clone().unwrap_or_default();
-        let num = attrs
-            .chapter_number
-            .clone()
-            .unwrap_or_else(|| "---".to_string());
+        let num = attrs.chapter_number.as_deref().map_or_else(
+            || "---".to_string(),
+            |num| pad_chapter_number(num, num_width),
+        );
 
         // prevent naming conflicts
         let suffix = &self.data.id.to_string()[..8];
 
         if title.is_empty() {
-            format!("[{num:0>3}] ({suffix})")
+            format!("[{num}] ({suffix})")
         } else {
-            format!("[{num:0>3}] {title} ({suffix})")
+            format!("[{num}] {title} ({suffix})")
         }
     }
 
@@ -243,11 +664,73 @@ impl Chapter {
             .uuid()
     }
 
+    /// Iterates over the chapter's scanlation groups as `(uuid, name)`.
+    ///
+    /// Names are only present if they were included, see [`RelationshipAttributes`].
+    pub fn groups(&self) -> impl Iterator<Item = (Uuid, Option<&str>)> {
+        self.data
+            .relationships
+            .iter()
+            .filter(|r| r.entity_type == "scanlation_group")
+            .map(|r| {
+                let name = r.attributes.as_ref().and_then(|a| a.name.as_deref());
+                (r.uuid(), name)
+            })
+    }
+
+    /// Returns the names of the chapter's scanlation groups, if they were included.
+    ///
+    /// See [`RelationshipAttributes`].
+    #[must_use]
+    pub fn group_names(&self) -> Vec<String> {
+        self.groups()
+            .filter_map(|(_, name)| name.map(str::to_string))
+            .collect()
+    }
+
+    /// Returns the username of whoever uploaded the chapter, if it was included.
+    ///
+    /// See [`RelationshipAttributes`].
+    #[must_use]
+    pub fn uploader(&self) -> Option<&str> {
+        self.data
+            .relationships
+            .iter()
+            .find(|r| r.entity_type == "user")
+            .and_then(|r| r.attributes.as_ref()?.username.as_deref())
+    }
+
     /// UUID getter
     #[must_use]
     pub const fn uuid(&self) -> Uuid {
         self.data.id
     }
+
+    /// Returns the chapter's number, if it has a numeric one.
+    #[must_use]
+    pub fn number(&self) -> Option<ChapterNumber> {
+        ChapterNumber::parse(self.data.attributes.chapter_number.as_deref()?)
+    }
+
+    /// Returns the url the chapter is hosted at, if it's hosted outside of Manga-Dex.
+    #[must_use]
+    pub const fn external_url(&self) -> Option<&Url> {
+        self.data.attributes.external_url.as_ref()
+    }
+
+    /// Returns true if the chapter is hosted outside of Manga-Dex, in which case
+    /// it has no pages to download.
+    #[must_use]
+    pub const fn is_external(&self) -> bool {
+        self.data.attributes.external_url.is_some() && self.data.attributes.pages == 0
+    }
+
+    /// Returns true if the chapter has been made unreadable on Manga-Dex,
+    /// see [`ChapterAttributes::is_unavailable`].
+    #[must_use]
+    pub const fn is_unavailable(&self) -> bool {
+        self.data.attributes.is_unavailable
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -274,13 +757,65 @@ pub struct Tag {
     pub attributes: TagAttributes,
 }
 
+/// An entry of the `client.title_preference` option, see [`Manga::title`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum TitlePreference {
+    /// The language passed to [`Manga::title`], which is usually `client.language`.
+    Language,
+    /// The manga's original language, romanized (e.g. `ja-ro` for a Japanese manga).
+    Romanized,
+    /// The manga's original language.
+    Original,
+    /// A specific language, e.g. `en` or `ko-ro`.
+    Code(MdLanguage),
+}
+
+impl TryFrom<String> for TitlePreference {
+    type Error = String;
+
+    fn try_from(raw: String) -> std::result::Result<Self, Self::Error> {
+        match raw.as_str() {
+            "language" => Ok(Self::Language),
+            "romanized" => Ok(Self::Romanized),
+            "original" => Ok(Self::Original),
+            code => MdLanguage::parse(code).map(Self::Code).ok_or_else(|| {
+                format!(
+                    "invalid title preference {raw:?}, expected \"language\", \"romanized\", \"original\" or a language code"
+                )
+            }),
+        }
+    }
+}
+
+/// The title preference used by [`Manga::title`], set by [`set_title_preference`].
+static TITLE_PREFERENCE: OnceLock<Vec<TitlePreference>> = OnceLock::new();
+
+/// The title preference used if [`set_title_preference`] isn't called.
+pub const DEFAULT_TITLE_PREFERENCE: [TitlePreference; 4] = [
+    TitlePreference::Language,
+    TitlePreference::Romanized,
+    TitlePreference::Code(MdLanguage::new(Language::Eng)),
+    TitlePreference::Original,
+];
+
+/// Sets the order titles are looked for in by [`Manga::title`] (from the
+/// `client.title_preference` option). Only the first call takes effect, calling
+/// it again with a different preference is ignored with a warning.
+pub fn set_title_preference(preference: Vec<TitlePreference>) {
+    if TITLE_PREFERENCE.get().is_some_and(|p| *p == preference) {
+        return;
+    }
+    if TITLE_PREFERENCE.set(preference).is_err() {
+        warn!("`set_title_preference()` called more than once, ignoring");
+    }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct MangaAttributes {
-    #[serde(deserialize_with = "deserialize_langcode_map")]
-    pub title: HashMap<Language, String>,
-    #[serde(deserialize_with = "deserialize_langcode_map_vec")]
-    pub alt_titles: Vec<HashMap<Language, String>>,
+    pub title: HashMap<MdLanguage, String>,
+    pub alt_titles: Vec<HashMap<MdLanguage, String>>,
     #[serde(deserialize_with = "deserialize_langcode_map")]
     pub description: HashMap<Language, String>,
     pub is_locked: bool,
@@ -288,8 +823,10 @@ pub struct MangaAttributes {
     // https://api.mangadex.org/docs/3-enumerations/#manga-links-data
     pub links: Option<HashMap<String, String>>,
     pub official_links: Option<HashMap<String, String>>,
-    #[serde(deserialize_with = "deserialize_langcode")]
-    pub original_language: Language,
+    pub original_language: MdLanguage,
+    /// The languages this manga has chapters translated into.
+    #[serde(default, deserialize_with = "deserialize_md_language_vec")]
+    pub available_translated_languages: Vec<MdLanguage>,
     pub last_volume: Option<String>,
     pub last_chapter: Option<String>,
     pub publication_demographic: Option<PublicationDemographic>,
@@ -329,19 +866,18 @@ impl Manga {
     ///
     /// If the response can't be parsed as a [`Manga`].
     pub async fn new(client: &ApiClient, manga_uuid: Uuid) -> Result<Self> {
-        let r_json = client.get_ok_json(Endpoint::GetManga(manga_uuid)).await?;
-        let manga = serde_json::from_value::<Self>(r_json).map_err(|e| {
-            miette::miette!("Failed to parse manga with manga_uuid={manga_uuid}: {e}")
-        })?;
-
-        Ok(manga)
+        client
+            .get_ok_parsed(Endpoint::GetManga(manga_uuid))
+            .await
+            .wrap_err_with(|| format!("failed to fetch manga with manga_uuid={manga_uuid}"))
     }
 
-    /// Helper for accessing title field given a language. This
-    /// searches through the `title` and `alt_titles` fields.
+    /// Helper for accessing title field given a language. This searches through the
+    /// `title` and `alt_titles` fields, in the order of the [title preference](`set_title_preference`)
+    /// (where [`TitlePreference::Language`] is `language`).
     ///
     /// Defaults to the first title in [`MangaAttributes::title`]
-    /// if the language provided wasn't available.
+    /// if none of the preferred languages were available.
     ///
     /// ## Panics
     ///
@@ -349,25 +885,40 @@ impl Manga {
     #[must_use]
     pub fn title(&self, language: Language) -> String {
         let attrs = &self.data.attributes;
+        let preference = TITLE_PREFERENCE
+            .get()
+            .map_or(&DEFAULT_TITLE_PREFERENCE[..], Vec::as_slice);
 
-        // check normal titles
-        if let Some(v) = attrs.title.get(&language) {
-            return v.clone();
-        }
+        let original = attrs.original_language.language;
 
-        // check alt titles
-        for map in &attrs.alt_titles {
-            for (k, v) in map {
-                if *k == language {
-                    return v.clone();
+        for wanted in preference {
+            // regional variants (e.g. `pt-br`) count as their language, unless one was asked for
+            let matches = |k: &MdLanguage| match *wanted {
+                TitlePreference::Language => k.language == language && !k.is_romanized(),
+                TitlePreference::Romanized => k.language == original && k.is_romanized(),
+                TitlePreference::Original => k.language == original && !k.is_romanized(),
+                TitlePreference::Code(code) if code.variant.is_none() => {
+                    k.language == code.language && !k.is_romanized()
                 }
+                TitlePreference::Code(code) => *k == code,
+            };
+
+            // normal titles come first
+            let found = attrs
+                .title
+                .iter()
+                .chain(attrs.alt_titles.iter().flatten())
+                .find(|(k, _)| matches(k));
+
+            if let Some((_, v)) = found {
+                return v.clone();
             }
         }
 
         warn!(
             concat!(
                 "Could not find a title in both `title` and `alt_titles`",
-                " for language {:?}, falling back to any available title"
+                " for language {:?} (or any preferred one), falling back to any available title"
             ),
             language.to_name()
         );
@@ -386,6 +937,181 @@ impl Manga {
     pub const fn uuid(&self) -> Uuid {
         self.data.id
     }
+
+    /// Returns the uuids of manga related to this one (e.g. sequels and spin-offs),
+    /// along with how they relate to it.
+    pub fn related(&self) -> impl Iterator<Item = (Uuid, &MangaRelation)> {
+        self.data
+            .relationships
+            .iter()
+            .filter(|r| r.entity_type == "manga")
+            .filter_map(|r| Some((r.uuid(), r.related.as_ref()?)))
+    }
+
+    /// Returns the names of the manga's related people of `entity_type`
+    /// (`"author"` or `"artist"`), if they were included.
+    #[must_use]
+    pub fn people(&self, entity_type: &str) -> Vec<String> {
+        self.data
+            .relationships
+            .iter()
+            .filter(|r| r.entity_type == entity_type)
+            .filter_map(|r| r.attributes.as_ref()?.name.clone())
+            .collect()
+    }
+
+    /// Returns the description in `language`, falling back to English (or nothing).
+    #[must_use]
+    pub fn description(&self, language: Language) -> String {
+        let description = &self.data.attributes.description;
+
+        description
+            .get(&language)
+            .or_else(|| description.get(&Language::Eng))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Returns the names of the manga's tags in `language`, falling back to English.
+    #[must_use]
+    pub fn tag_names(&self, language: Language) -> Vec<String> {
+        self.data
+            .attributes
+            .tags
+            .iter()
+            .filter_map(|t| {
+                let name = &t.attributes.name;
+                name.get(&language)
+                    .or_else(|| name.get(&Language::Eng))
+                    .cloned()
+            })
+            .collect()
+    }
+
+    /// Returns the url of the manga's (full size) cover, if it was included.
+    ///
+    /// ## References
+    ///
+    /// - [MangaDex docs](https://api.mangadex.org/docs/03-manga/covers/)
+    #[must_use]
+    pub fn cover_url(&self) -> Option<Url> {
+        let file_name = self
+            .data
+            .relationships
+            .iter()
+            .find(|r| r.entity_type == "cover_art")?
+            .attributes
+            .as_ref()?
+            .file_name
+            .as_ref()?;
+
+        Url::parse(&format!(
+            "https://uploads.mangadex.org/covers/{}/{file_name}",
+            self.uuid()
+        ))
+        .ok()
+    }
+}
+
+/// The rating part of [`MangaStatistics`].
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct StatisticsRating {
+    /// The mean of every rating (out of 10), if the manga has any.
+    pub average: Option<f64>,
+    /// The average, weighted towards the site-wide average for manga with few ratings.
+    pub bayesian: Option<f64>,
+}
+
+/// The comments part of [`MangaStatistics`] and [`ChapterStatistics`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct StatisticsComments {
+    /// The id of the forum thread, which is only made once someone comments.
+    pub thread_id: u64,
+    pub replies_count: u64,
+}
+
+impl StatisticsComments {
+    /// Returns the url of the forum thread.
+    #[must_use]
+    pub fn thread_url(&self) -> String {
+        format!("https://forums.mangadex.org/threads/{}", self.thread_id)
+    }
+}
+
+/// The most manga or chapters whose statistics are fetched in a single request.
+pub const MAX_STATISTICS_IDS: usize = 100;
+
+#[derive(Deserialize, Debug)]
+struct StatisticsResults<T> {
+    statistics: HashMap<Uuid, T>,
+}
+
+/// A manga's statistics, from [`Endpoint::GetMangaStatistics`].
+///
+/// ## References
+///
+/// - [MangaDex docs](https://api.mangadex.org/docs/redoc.html#tag/Statistics/operation/get-statistics-manga)
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MangaStatistics {
+    #[serde(default)]
+    pub rating: StatisticsRating,
+    /// How many users follow the manga.
+    #[serde(default)]
+    pub follows: Option<u64>,
+    /// The manga's forum thread, if it has one.
+    #[serde(default)]
+    pub comments: Option<StatisticsComments>,
+}
+
+impl MangaStatistics {
+    /// Fetches the statistics of the manga with `uuids` (at most [`MAX_STATISTICS_IDS`]).
+    ///
+    /// ## Errors
+    ///
+    /// From [`ApiClient::get_ok_parsed`].
+    pub async fn fetch(client: &ApiClient, uuids: Vec<Uuid>) -> Result<HashMap<Uuid, Self>> {
+        let results: StatisticsResults<Self> = client
+            .get_ok_parsed(Endpoint::GetMangaStatistics(uuids))
+            .await?;
+
+        Ok(results.statistics)
+    }
+}
+
+/// A chapter's statistics, from [`Endpoint::GetChapterStatistics`].
+///
+/// ## References
+///
+/// - [MangaDex docs](https://api.mangadex.org/docs/redoc.html#tag/Statistics/operation/get-statistics-chapters-uuids)
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ChapterStatistics {
+    /// The chapter's forum thread, if it has one.
+    #[serde(default)]
+    pub comments: Option<StatisticsComments>,
+}
+
+impl ChapterStatistics {
+    /// Fetches the statistics of the chapters with `uuids` (at most [`MAX_STATISTICS_IDS`]).
+    ///
+    /// ## Errors
+    ///
+    /// From [`ApiClient::get_ok_parsed`].
+    pub async fn fetch(client: &ApiClient, uuids: Vec<Uuid>) -> Result<HashMap<Uuid, Self>> {
+        let results: StatisticsResults<Self> = client
+            .get_ok_parsed(Endpoint::GetChapterStatistics(uuids))
+            .await?;
+
+        Ok(results.statistics)
+    }
+}
+
+impl MangaData {
+    /// Trivial UUID getter.
+    #[must_use]
+    pub const fn uuid(&self) -> Uuid {
+        self.id
+    }
 }
 
 impl From<ChapterData> for Chapter {
@@ -0,0 +1,130 @@
+//! Contains [`preflight`], which checks that Manga-Dex is up before a batch of chapters
+//! is downloaded, so that an outage fails the whole batch right away with a
+//! [`PreflightError`] instead of failing every chapter one by one.
+//!
+//! The API is checked with [`Endpoint::Ping`], and optionally a Statuspage-style status
+//! page (`preflight.status_url`), e.g. for outages of MangaDex@Home's image servers.
+
+use crate::{
+    api::{client::ApiClient, endpoints::Endpoint},
+    config::Preflight,
+    errors::PreflightError,
+};
+
+use miette::{IntoDiagnostic, Result};
+use reqwest::{Client, Url};
+use serde::Deserialize;
+
+/// The `status` field of a Statuspage `status.json`.
+#[derive(Deserialize, Debug)]
+struct PageStatus {
+    /// One of `none`, `minor`, `major` or `critical`.
+    indicator: String,
+    description: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct StatusResponse {
+    status: PageStatus,
+}
+
+/// Pings Manga-Dex's API, expecting `pong` back.
+///
+/// ## Errors
+///
+/// A [`PreflightError`] if the ping can't be sent or gets anything other than `pong`.
+pub async fn ping(api: &ApiClient) -> Result<()> {
+    let r = api
+        .get(Endpoint::Ping)
+        .await
+        .map_err(|e| PreflightError::Unreachable {
+            reason: e.to_string(),
+        })?;
+
+    let status = r.status();
+    let body = r.text().await.unwrap_or_default();
+
+    if !status.is_success() || body.trim() != "pong" {
+        debug!("Ping returned status {status} with body {body:?}");
+        return Err(PreflightError::Down { status }.into());
+    }
+
+    Ok(())
+}
+
+/// Checks the Statuspage-style status page at `url` with `client`.
+///
+/// A minor outage is only warned about, as is failing to fetch the status page
+/// (since it says nothing about Manga-Dex itself).
+///
+/// ## Errors
+///
+/// A [`PreflightError::Outage`] if the status page reports a major or critical outage.
+pub async fn check_status_page(client: &Client, url: &Url) -> Result<()> {
+    let status = match fetch_status(client, url).await {
+        Ok(r) => r.status,
+        Err(e) => {
+            warn!("Failed to check the status page at {url}: {e}");
+            return Ok(());
+        }
+    };
+
+    match status.indicator.as_str() {
+        "none" => Ok(()),
+        "major" | "critical" => Err(PreflightError::Outage {
+            indicator: status.indicator,
+            description: status.description,
+        }
+        .into()),
+        _ => {
+            warn!(
+                "Manga-Dex's status page reports a {} outage: {}",
+                status.indicator, status.description
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Helper for [`check_status_page`], which fetches and parses the status page.
+async fn fetch_status(client: &Client, url: &Url) -> Result<StatusResponse> {
+    let body = client
+        .get(url.clone())
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .into_diagnostic()?
+        .bytes()
+        .await
+        .into_diagnostic()?;
+
+    serde_json::from_slice(&body).into_diagnostic()
+}
+
+/// Checks that Manga-Dex is up before downloading `chapters` chapters, if `cfg`
+/// enables it and there are at least `preflight.min_chapters` of them.
+///
+/// `client` is used for the status page, since it isn't part of the API.
+///
+/// ## Errors
+///
+/// A [`PreflightError`] if propagated from [`ping`] or [`check_status_page`].
+pub async fn preflight(
+    api: &ApiClient,
+    client: &Client,
+    cfg: &Preflight,
+    chapters: usize,
+) -> Result<()> {
+    if !cfg.enabled || chapters < cfg.min_chapters {
+        return Ok(());
+    }
+
+    debug!("Checking that Manga-Dex is up before downloading {chapters} chapters");
+    ping(api).await?;
+
+    if let Some(url) = &cfg.status_url {
+        check_status_page(client, url).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,108 @@
+//! Contains [`RateLimiter`], which keeps requests to an endpoint under its rate limit.
+
+use crate::api::response::ResponseHeaders;
+
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// A sliding window rate limiter, allowing at most `max` requests per `window`.
+///
+/// This is shared (behind an [`Arc`](`std::sync::Arc`)) by everything that sends the
+/// limited requests, so that concurrent downloads can't exceed the limit together.
+#[derive(Debug)]
+pub struct RateLimiter {
+    max: usize,
+    window: Duration,
+    /// When each request in the current window was sent, oldest first.
+    sent: Mutex<VecDeque<Instant>>,
+    /// No requests are sent until then, see [`Self::observe`].
+    held_until: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    /// Constructs a new [`RateLimiter`] allowing `max` requests per `window`.
+    #[must_use]
+    pub fn new(max: usize, window: Duration) -> Self {
+        Self {
+            max,
+            window,
+            sent: Mutex::new(VecDeque::with_capacity(max)),
+            held_until: Mutex::new(None),
+        }
+    }
+
+    /// Reads the ratelimit headers of a response to one of the limited requests, holding
+    /// every request until its `retry_after` if it used up the endpoint's ratelimit.
+    ///
+    /// This catches requests this limiter doesn't know about, e.g. ones sent by another
+    /// process, which would otherwise only be noticed once they're refused.
+    ///
+    /// ## Panics
+    ///
+    /// If the lock is poisoned.
+    pub fn observe(&self, headers: &ResponseHeaders) {
+        if headers.ratelimit_remaining != Some(0) {
+            return;
+        }
+
+        let Some(retry_after) = headers.retry_after else {
+            return;
+        };
+
+        let until = Instant::now() + retry_after;
+        let mut held_until = self.held_until.lock().expect("rate limiter lock poisoned");
+
+        if held_until.is_none_or(|held| held < until) {
+            debug!(
+                "Ratelimit used up, holding requests for {}ms",
+                retry_after.as_millis()
+            );
+            *held_until = Some(until);
+        }
+    }
+
+    /// Waits until another request can be sent, then records it as sent.
+    ///
+    /// ## Panics
+    ///
+    /// If the lock is poisoned.
+    pub async fn acquire(&self) {
+        loop {
+            let held = {
+                let held_until = self.held_until.lock().expect("rate limiter lock poisoned");
+                held_until.map(|until| until.saturating_duration_since(Instant::now()))
+            };
+
+            if let Some(wait) = held.filter(|w| !w.is_zero()) {
+                tokio::time::sleep(wait).await;
+                continue;
+            }
+
+            let wait = {
+                let mut sent = self.sent.lock().expect("rate limiter lock poisoned");
+                let now = Instant::now();
+
+                while sent
+                    .front()
+                    .is_some_and(|t| now.duration_since(*t) >= self.window)
+                {
+                    sent.pop_front();
+                }
+
+                if sent.len() < self.max {
+                    sent.push_back(now);
+                    return;
+                }
+
+                // the oldest request leaves the window first
+                self.window.saturating_sub(now.duration_since(sent[0]))
+            };
+
+            debug!("Rate limit reached, waiting {}ms", wait.as_millis());
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
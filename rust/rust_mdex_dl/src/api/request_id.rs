@@ -0,0 +1,43 @@
+//! Contains [`RequestId`], a short ID given to each API request and image download
+//! so that their log lines can be told apart when many run concurrently.
+
+use std::{
+    fmt,
+    sync::{
+        OnceLock,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+static NEXT: AtomicU32 = AtomicU32::new(0);
+
+/// IDs start from a different point each run, so that logs
+/// appended to the same file by different runs don't share IDs.
+static SEED: OnceLock<u32> = OnceLock::new();
+
+/// A short ID for a request, displayed as six hex digits (e.g. `3fa9c1`).
+///
+/// IDs are sequential within a run, and wrap around after 16,777,216 requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestId(u32);
+
+impl RequestId {
+    /// Returns a new ID, different from the last 16,777,215 returned.
+    #[must_use]
+    pub fn next() -> Self {
+        let seed = *SEED.get_or_init(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.subsec_nanos())
+        });
+
+        Self(seed.wrapping_add(NEXT.fetch_add(1, Ordering::Relaxed)) & 0x00ff_ffff)
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:06x}", self.0)
+    }
+}
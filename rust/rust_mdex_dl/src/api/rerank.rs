@@ -0,0 +1,129 @@
+//! Contains [`rerank`], which reorders search results by how closely their titles match the
+//! query (see `search.rerank`), since Manga-Dex's relevance order often buries exact matches.
+//!
+//! Every title and alt title (in any language) of a manga is scored against the query, and its
+//! best one is its [`TitleMatch`]. A score mixes two similarities of the normalized titles:
+//!
+//! - the Levenshtein distance, relative to the longer title, which favours near-exact matches
+//! - how many of the query's words are in the title, which favours titles with extra words
+//!   (e.g. subtitles) that would otherwise be far off by distance alone
+
+use crate::api::{models::MangaData, search::SearchResults};
+
+use std::collections::HashSet;
+
+/// How closely a manga's titles match a query, see [`best_match`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TitleMatch {
+    /// From `0.0` (nothing in common) to `1.0` (the same, once normalized).
+    pub score: f64,
+    /// The title (or alt title) that matched best.
+    pub title: String,
+    /// Whether [`Self::title`] is an alt title.
+    pub is_alt: bool,
+}
+
+/// Lowercases `s` and replaces everything but letters and digits with single spaces,
+/// so that e.g. `"Yotsuba&!"` and `"yotsuba"` are the same.
+#[must_use]
+pub fn normalize(s: &str) -> String {
+    s.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Returns the Levenshtein distance between `a` and `b`, by characters.
+#[must_use]
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // the distances from a prefix of `a` to every prefix of `b`
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+/// Scores how closely `title` matches `query`, both normalized (see [`normalize`]).
+#[allow(clippy::cast_precision_loss)]
+#[must_use]
+pub fn similarity(query: &str, title: &str) -> f64 {
+    if query == title {
+        return 1.0;
+    }
+
+    let longest = query.chars().count().max(title.chars().count());
+
+    if longest == 0 {
+        return 0.0;
+    }
+
+    let distance = 1.0 - levenshtein(query, title) as f64 / longest as f64;
+
+    let title_words: HashSet<&str> = title.split(' ').collect();
+    let query_words: Vec<&str> = query.split(' ').collect();
+    let overlap = query_words
+        .iter()
+        .filter(|w| title_words.contains(*w))
+        .count() as f64
+        / query_words.len() as f64;
+
+    // an exact match is always best, so only get close to one otherwise
+    (distance * 0.5 + overlap * 0.45).min(0.99)
+}
+
+/// Returns how closely the best of `md`'s titles (and alt titles) matches `query`,
+/// or `None` if it has no titles.
+#[must_use]
+pub fn best_match(query: &str, md: &MangaData) -> Option<TitleMatch> {
+    let query = normalize(query);
+    let attrs = &md.attributes;
+
+    attrs
+        .title
+        .values()
+        .map(|t| (t, false))
+        .chain(
+            attrs
+                .alt_titles
+                .iter()
+                .flat_map(|m| m.values().map(|t| (t, true))),
+        )
+        .map(|(title, is_alt)| TitleMatch {
+            score: similarity(&query, &normalize(title)),
+            title: title.clone(),
+            is_alt,
+        })
+        // on ties, prefer the main title, which comes first
+        .reduce(|best, m| if m.score > best.score { m } else { best })
+}
+
+/// Scores every manga in `results` against `query` (see [`best_match`]), storing the scores
+/// in [`SearchResults::matches`], and sorts them by score (best first).
+///
+/// Manga with the same score keep Manga-Dex's order. Note that this only reorders
+/// the results in `results`, i.e. within a single page.
+pub fn rerank(results: &mut SearchResults, query: &str) {
+    results.matches = results
+        .data
+        .iter()
+        .filter_map(|md| Some((md.uuid(), best_match(query, md)?)))
+        .collect();
+
+    let score = |md: &MangaData| results.matches.get(&md.uuid()).map_or(0.0, |m| m.score);
+    let mut data = std::mem::take(&mut results.data);
+    data.sort_by(|a, b| score(b).total_cmp(&score(a)));
+
+    results.data = data;
+}
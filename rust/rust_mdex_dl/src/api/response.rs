@@ -0,0 +1,78 @@
+//! Contains [`ApiResponse`], a parsed response from Manga-Dex along with its status and
+//! the [`ResponseHeaders`] worth keeping, which are used for handling ratelimits,
+//! recording requests and building [`ApiError`](`crate::errors::ApiError`)s.
+//!
+//! ## References
+//!
+//! - [Rate limits](https://api.mangadex.org/docs/2-limitations/#general-rate-limit)
+
+use crate::api::deprecation::ServerNotice;
+
+use std::time::Duration;
+
+use chrono::Utc;
+use reqwest::{StatusCode, header::HeaderMap};
+
+/// The headers of a response from Manga-Dex that are kept in an [`ApiResponse`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResponseHeaders {
+    /// How many requests can be sent to the endpoint per window (`X-RateLimit-Limit`).
+    pub ratelimit_limit: Option<u32>,
+    /// How many of those are left in the current window (`X-RateLimit-Remaining`).
+    pub ratelimit_remaining: Option<u32>,
+    /// How long to wait before sending another request, from `Retry-After` (in seconds),
+    /// `X-RateLimit-Retry-In` (also in seconds) or `X-RateLimit-Retry-After` (a timestamp).
+    pub retry_after: Option<Duration>,
+    /// Manga-Dex's own request id and any notices about the API changing.
+    pub notice: ServerNotice,
+}
+
+impl ResponseHeaders {
+    /// Reads the kept headers from a response's `headers`, ignoring any that can't be parsed.
+    #[must_use]
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let number =
+            |name: &str| -> Option<u64> { headers.get(name)?.to_str().ok()?.trim().parse().ok() };
+        let count = |name: &str| number(name).and_then(|n| u32::try_from(n).ok());
+
+        let retry_after = number("retry-after")
+            .or_else(|| number("x-ratelimit-retry-in"))
+            .map(Duration::from_secs)
+            .or_else(|| {
+                // a unix timestamp of when the window resets
+                let at = i64::try_from(number("x-ratelimit-retry-after")?).ok()?;
+                let secs = (at - Utc::now().timestamp()).max(0);
+                Some(Duration::from_secs(secs.unsigned_abs()))
+            });
+
+        Self {
+            ratelimit_limit: count("x-ratelimit-limit"),
+            ratelimit_remaining: count("x-ratelimit-remaining"),
+            retry_after,
+            notice: ServerNotice::from_headers(headers),
+        }
+    }
+
+    /// Manga-Dex's own id for the request (its `X-Request-ID`), if it sent one.
+    #[must_use]
+    pub fn request_id(&self) -> Option<&str> {
+        self.notice.request_id.as_deref()
+    }
+}
+
+/// A successful response from Manga-Dex, parsed into `T`, see
+/// [`ApiClient::get_ok`](`crate::api::client::ApiClient::get_ok`).
+#[derive(Debug, Clone)]
+pub struct ApiResponse<T> {
+    pub body: T,
+    pub status: StatusCode,
+    pub headers: ResponseHeaders,
+}
+
+impl<T> ApiResponse<T> {
+    /// Returns the parsed body, discarding the status and headers.
+    #[must_use]
+    pub fn into_body(self) -> T {
+        self.body
+    }
+}
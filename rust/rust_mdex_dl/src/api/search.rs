@@ -3,15 +3,35 @@
 //! [`SearchClient`] is implemented with methods to assist with
 //! constructing search parameters for the `SearchManga` endpoint.
 
-use crate::api::{
-    client::ApiClient,
-    endpoints::Endpoint,
-    models::{Chapter, ChapterData, ContentRating, Manga, MangaData},
+use crate::{
+    api::{
+        cache::SearchCache,
+        client::ApiClient,
+        endpoints::Endpoint,
+        models::{
+            Aggregate, Chapter, ChapterData, ChapterNumber, ContentRating, Manga, MangaData,
+            MangaStatistics,
+        },
+        ratelimit::RateLimiter,
+        rerank::{TitleMatch, rerank},
+    },
+    deserializers::from_value_with_path,
+    progress::print_line,
 };
 
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use console::{Alignment, measure_text_width, pad_str, style, truncate_str};
+use futures::{Stream, StreamExt, TryStreamExt, stream};
 use isolang::Language;
-use miette::{IntoDiagnostic, Result};
+use miette::{IntoDiagnostic, Result, bail};
 use serde::Deserialize;
+use uuid::Uuid;
 
 /// Represents the search results (manga) for a query.
 #[derive(Deserialize, Debug, Clone)]
@@ -23,21 +43,185 @@ pub struct SearchResults {
     /// Note that this isn't the same as `data.len()`, since `data` is
     /// usually just for a single page. (since, y'know, pagination limits).
     pub total: u32,
+    /// The statistics of each manga in [`Self::data`], if they were fetched.
+    #[serde(skip)]
+    pub statistics: HashMap<Uuid, MangaStatistics>,
+    /// How closely each manga in [`Self::data`] matched the query, if they were reranked
+    /// (see [`rerank`]).
+    #[serde(skip)]
+    pub matches: HashMap<Uuid, TitleMatch>,
+}
+
+/// The widest a title can be in [`SearchResults::display`] before it's truncated.
+const MAX_TITLE_WIDTH: usize = 48;
+
+/// The widest a matched alt title can be in [`SearchResults::display`] before it's truncated.
+const MAX_ALT_TITLE_WIDTH: usize = 32;
+
+/// Returns the flag emoji of the country most associated with `language`,
+/// or its ISO 639-1 code (uppercase) if there isn't an obvious one.
+fn language_flag(language: Language) -> String {
+    let code = language.to_639_1().unwrap_or("??");
+
+    let country = match code {
+        "ja" => "JP",
+        "ko" => "KR",
+        "zh" => "CN",
+        "en" => "GB",
+        "vi" => "VN",
+        "id" => "ID",
+        "th" => "TH",
+        "fr" => "FR",
+        "es" => "ES",
+        "de" => "DE",
+        "it" => "IT",
+        "pt" => "PT",
+        "ru" => "RU",
+        "pl" => "PL",
+        "tr" => "TR",
+        "ar" => "SA",
+        _ => return code.to_uppercase(),
+    };
+
+    // a flag is its country code written in "regional indicator" letters
+    country
+        .chars()
+        .filter_map(|c| char::from_u32(0x1F1E6 + (c as u32 - 'A' as u32)))
+        .collect()
+}
+
+/// Formats a count of follows compactly, e.g. `12.3k` or `1.2M`.
+#[allow(clippy::cast_precision_loss)]
+fn compact_count(count: u64) -> String {
+    match count {
+        0..1_000 => count.to_string(),
+        1_000..1_000_000 => format!("{:.1}k", count as f64 / 1e3),
+        _ => format!("{:.1}M", count as f64 / 1e6),
+    }
 }
 
 impl SearchResults {
-    /// Returns every manga's title stored in [`Self::data`] enumerated.
-    #[must_use]
-    pub fn display(&self, language: Language) -> Vec<String> {
-        let mut titles = Vec::with_capacity(self.data.len() + 1);
+    /// Returns the columns of a row of [`Self::display`] for `md`, unpadded.
+    fn columns(&self, number: usize, md: &MangaData, languages: &[Language]) -> Vec<String> {
+        let attrs = &md.attributes;
+        let title_language = languages.first().copied().unwrap_or(Language::Eng);
+        let title = Manga::from(md.clone()).title(title_language);
 
-        for (i, md) in self.data.iter().enumerate() {
-            let m: Manga = md.clone().into();
-            let option = format!("[{}] {}", i + 1, m.title(language));
-            titles.push(option);
+        let stats = self.statistics.get(&md.uuid()).map(|s| {
+            let rating = s
+                .rating
+                .bayesian
+                .or(s.rating.average)
+                .map_or_else(|| "-".to_string(), |r| format!("{r:.2}"));
+            let follows = compact_count(s.follows.unwrap_or_default());
+
+            format!("★ {rating}  {follows} follows")
+        });
+
+        let last = match (attrs.last_volume.as_deref(), attrs.last_chapter.as_deref()) {
+            (Some(v), Some(c)) if !v.is_empty() && !c.is_empty() => {
+                format!("last: vol. {v} ch. {c}")
+            }
+            (_, Some(c)) if !c.is_empty() => format!("last: ch. {c}"),
+            _ => String::new(),
+        };
+
+        let mut columns = vec![
+            format!("[{number}]"),
+            truncate_str(&title, MAX_TITLE_WIDTH, "…").into_owned(),
+        ];
+
+        if !self.matches.is_empty() {
+            columns.push(self.matches.get(&md.uuid()).map_or_else(String::new, |m| {
+                // point out alt titles, since the displayed title may look nothing like the query
+                if m.is_alt && m.title != title {
+                    let alt = truncate_str(&m.title, MAX_ALT_TITLE_WIDTH, "…");
+                    format!("{:.0}% ≈ {alt}", m.score * 100.0)
+                } else {
+                    format!("{:.0}%", m.score * 100.0)
+                }
+            }));
         }
 
-        titles
+        columns.extend([
+            attrs
+                .year
+                .map_or_else(|| "----".to_string(), |y| y.to_string()),
+            attrs.status.to_string(),
+            attrs.content_rating.to_string(),
+            language_flag(attrs.original_language.into()),
+            stats.unwrap_or_default(),
+            last,
+        ]);
+
+        // if there's more than one language, show which of them each manga is translated into
+        if languages.len() > 1 {
+            let available: Vec<&str> = languages
+                .iter()
+                .filter(|l| {
+                    attrs
+                        .available_translated_languages
+                        .iter()
+                        .any(|a| a.language == **l)
+                })
+                .filter_map(Language::to_639_1)
+                .collect();
+
+            columns.push(format!("({})", available.join(", ")));
+        }
+
+        columns
+    }
+
+    /// Returns a line for every manga stored in [`Self::data`] enumerated, with columns for
+    /// its title, match score (and the alt title that matched, if [`Self::matches`] were
+    /// scored), year, status, content rating, original language (as a flag), rating and
+    /// follows (if [`Self::statistics`] were fetched) and last chapter, aligned across lines.
+    ///
+    /// Results are numbered from `first` (one-indexed), i.e. by their index among every result
+    /// when they're a later page. Titles are in the first of `languages`. If more than one
+    /// language is given, each manga is also annotated with which of `languages` it's
+    /// translated into.
+    #[must_use]
+    pub fn display(&self, first: usize, languages: &[Language]) -> Vec<String> {
+        let rows: Vec<Vec<String>> = self
+            .data
+            .iter()
+            .enumerate()
+            .map(|(i, md)| self.columns(first + i, md, languages))
+            .collect();
+
+        let column_count = rows.first().map_or(0, Vec::len);
+        let widths: Vec<usize> = (0..column_count)
+            .map(|c| {
+                rows.iter()
+                    .map(|r| measure_text_width(&r[c]))
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        rows.into_iter()
+            .map(|row| {
+                let cells: Vec<String> = row
+                    .iter()
+                    .zip(&widths)
+                    .enumerate()
+                    .map(|(c, (cell, width))| {
+                        let padded = pad_str(cell, *width, Alignment::Left, None).into_owned();
+
+                        // everything but the title is secondary
+                        if c == 1 {
+                            padded
+                        } else {
+                            style(padded).dim().to_string()
+                        }
+                    })
+                    .collect();
+
+                cells.join("  ").trim_end().to_string()
+            })
+            .collect()
     }
 
     /// Returns the [`MangaData`] as [`Manga`] of the specified `manga_index` at [`Self::data`].
@@ -55,12 +239,134 @@ struct ChapterResults {
     total: u32,
 }
 
+/// Filters for [`SearchClient::search_stream`].
+#[derive(Debug, Clone, Default)]
+pub struct SearchFilters {
+    /// Only include manga translated into any of these, or into any
+    /// of [`SearchClient::languages`] if empty.
+    pub languages: Vec<Language>,
+    /// Only include manga with these content ratings, or with any if empty.
+    pub content_ratings: Vec<ContentRating>,
+}
+
+/// Filters for [`SearchClient::fetch_chapters`], which are sent as query
+/// parameters so that Manga-Dex does the filtering.
+///
+/// The manga feed endpoint can't filter by volume or group, so if either of those
+/// are set, [`Endpoint::GetChapters`] is used instead.
+#[derive(Debug, Clone)]
+pub struct ChapterFilter {
+    /// Only include chapters with numbers in these (inclusive) ranges.
+    ///
+    /// Manga-Dex only matches exact chapter numbers, so this is the one filter
+    /// applied after fetching.
+    pub numbers: Vec<(ChapterNumber, ChapterNumber)>,
+    /// Only include chapters in these volumes (`"none"` for chapters without one).
+    pub volumes: Vec<String>,
+    /// Only include chapters published on Manga-Dex since this.
+    pub published_after: Option<DateTime<Utc>>,
+    /// Only include chapters that became readable since this, which (unlike
+    /// [`Self::published_after`]) catches chapters whose release was delayed.
+    pub readable_after: Option<DateTime<Utc>>,
+    /// Only include chapters from these scanlation groups.
+    pub groups: Vec<Uuid>,
+    /// Leave out chapters from these scanlation groups.
+    pub excluded_groups: Vec<Uuid>,
+    /// Whether to include chapters hosted outside of Manga-Dex,
+    /// see [`Chapter::is_external`].
+    pub include_external: bool,
+}
+
+impl Default for ChapterFilter {
+    fn default() -> Self {
+        Self {
+            numbers: Vec::new(),
+            volumes: Vec::new(),
+            published_after: None,
+            readable_after: None,
+            groups: Vec::new(),
+            excluded_groups: Vec::new(),
+            include_external: true,
+        }
+    }
+}
+
+impl ChapterFilter {
+    /// Returns the query parameters for this filter.
+    fn params(&self) -> Vec<(String, String)> {
+        let mut params: Vec<(String, String)> = Vec::new();
+
+        params.extend(self.volumes.iter().map(|v| ("volume[]".into(), v.clone())));
+        params.extend(
+            self.groups
+                .iter()
+                .map(|g| ("groups[]".into(), g.to_string())),
+        );
+        params.extend(
+            self.excluded_groups
+                .iter()
+                .map(|g| ("excludedGroups[]".into(), g.to_string())),
+        );
+
+        if !self.include_external {
+            params.push(("includeExternalUrl".into(), "0".into()));
+        }
+
+        if let Some(after) = self.readable_after {
+            params.push((
+                "readableAtSince".into(),
+                after.format("%Y-%m-%dT%H:%M:%S").to_string(),
+            ));
+        }
+
+        params
+    }
+
+    /// Returns true if `chapter` is in [`Self::numbers`] (or that's empty).
+    #[must_use]
+    pub fn matches_number(&self, chapter: &Chapter) -> bool {
+        self.numbers.is_empty()
+            || chapter.number().is_some_and(|n| {
+                self.numbers
+                    .iter()
+                    .any(|(start, end)| (*start..=*end).contains(&n))
+            })
+    }
+}
+
+/// Where [`SearchClient::fetch_chapters`] fetches chapters from.
+enum ChapterFeed {
+    /// [`Endpoint::GetMangaChapters`] of the manga with this uuid.
+    Manga(Uuid),
+    /// [`Endpoint::GetChapters`], with the manga given as a parameter.
+    Chapters,
+}
+
+impl ChapterFeed {
+    fn endpoint(&self, params: Vec<(String, String)>) -> Endpoint {
+        match self {
+            Self::Manga(uuid) => Endpoint::GetMangaChapters(*uuid, params),
+            Self::Chapters => Endpoint::GetChapters(params),
+        }
+    }
+}
+
 /// A wrapper over [`ApiClient`] for searching for manga.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SearchClient {
     api: ApiClient,
     language: Language,
+    /// Other languages (besides [`Self::language`]) to include in searches.
+    extra_languages: Vec<Language>,
     manga_pagination: u32,
+    /// Whether to fetch [`SearchResults::statistics`] along with search results.
+    statistics: bool,
+    cache: Option<SearchCache>,
+    /// Whether to [`rerank`] the results of [`Self::search`] by how closely they match.
+    rerank: bool,
+    /// Keeps concurrent chapter feed requests under [`Self::RATELIMIT`], shared by clones
+    /// (and anything else it's given to, see [`Self::limiter`]).
+    limiter: Arc<RateLimiter>,
 }
 
 impl SearchClient {
@@ -75,21 +381,112 @@ impl SearchClient {
     ///
     /// - <https://api.mangadex.org/docs/2-limitations/#collection-result-sizes>
     const MAX_OFFSET_SIZE_SUM: u32 = 10_000;
-
-    /// Creates a new [`SearchClient`].
+    /// The global rate limit of Manga-Dex's API, per second.
     ///
-    /// Clamps if `manga_pagination` > [`Self::MAX_MANGA_PAGINATION`]
+    /// ## References:
+    ///
+    /// - <https://api.mangadex.org/docs/2-limitations/#general-rate-limit>
+    const RATELIMIT: usize = 5;
+    /// The max amount of chapter pages fetched at once by [`Self::fetch_chapters`].
+    const MAX_CONCURRENT_PAGES: usize = 4;
+
+    /// Creates a new [`SearchClient`], which fetches [`Self::MAX_MANGA_PAGINATION`]
+    /// results per page (see [`Self::with_page_size`]).
     #[must_use]
-    pub const fn new(api: ApiClient, language: Language) -> Self {
+    pub fn new(api: ApiClient, language: Language) -> Self {
         let manga_pagination = Self::MAX_MANGA_PAGINATION;
 
         Self {
             api,
             language,
+            extra_languages: Vec::new(),
             manga_pagination,
+            statistics: false,
+            cache: None,
+            rerank: false,
+            limiter: Arc::new(RateLimiter::new(Self::RATELIMIT, Duration::from_secs(1))),
         }
     }
 
+    /// Sets other languages to include in searches, besides the main language.
+    ///
+    /// A manga is included in results if it's translated into any of these languages.
+    #[must_use]
+    pub fn with_extra_languages(mut self, extra_languages: Vec<Language>) -> Self {
+        self.extra_languages = extra_languages;
+        self
+    }
+
+    /// Sets how many results are fetched per page of [`Self::search`].
+    ///
+    /// Clamps from 1 to [`Self::MAX_MANGA_PAGINATION`].
+    #[must_use]
+    pub fn with_page_size(mut self, page_size: u32) -> Self {
+        self.manga_pagination = page_size.clamp(1, Self::MAX_MANGA_PAGINATION);
+        self
+    }
+
+    /// Returns how many results are fetched per page of [`Self::search`].
+    #[must_use]
+    pub const fn page_size(&self) -> u32 {
+        self.manga_pagination
+    }
+
+    /// Returns how many pages `total` results take up, up to Manga-Dex's
+    /// cap of [`Self::MAX_OFFSET_SIZE_SUM`] results.
+    #[must_use]
+    pub fn page_count(&self, total: u32) -> u32 {
+        total
+            .min(Self::MAX_OFFSET_SIZE_SUM)
+            .div_ceil(self.manga_pagination)
+    }
+
+    /// Sets whether to fetch the ratings and follows of search results, which
+    /// takes another request per page of results.
+    #[must_use]
+    pub const fn with_statistics(mut self, statistics: bool) -> Self {
+        self.statistics = statistics;
+        self
+    }
+
+    /// Sets whether to [`rerank`] search results by how closely their titles match the query.
+    #[must_use]
+    pub const fn with_rerank(mut self, rerank: bool) -> Self {
+        self.rerank = rerank;
+        self
+    }
+
+    /// Sets the cache to reuse search results from, see [`SearchCache`].
+    #[must_use]
+    pub fn with_cache(mut self, cache: SearchCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Sets the [`RateLimiter`] that chapter feed requests wait for, e.g. to share one
+    /// with other clients so that their requests count towards the same limit.
+    #[must_use]
+    pub fn with_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    /// Returns the [`RateLimiter`] that chapter feed requests wait for, so that
+    /// it can be shared with other clients (see [`Self::with_limiter`]).
+    #[must_use]
+    pub fn limiter(&self) -> Arc<RateLimiter> {
+        self.limiter.clone()
+    }
+
+    /// Returns [`Self::language`] followed by [`Self::extra_languages`].
+    #[must_use]
+    pub fn languages(&self) -> Vec<Language> {
+        let mut languages = vec![self.language];
+        languages.extend(self.extra_languages.iter().filter(|l| **l != self.language));
+
+        languages
+    }
+
     /// Helper for constructing language filters for manga or chapters.
     fn language_filter_param(
         allowed_languages: &[Language],
@@ -123,51 +520,82 @@ impl SearchClient {
         let key = "contentRating[]".to_string();
 
         for rating in allowed_ratings {
-            let key = key.clone();
-
-            match rating {
-                ContentRating::Safe => params.push((key, "safe".into())),
-                ContentRating::Suggestive => params.push((key, "suggestive".into())),
-                ContentRating::Erotica => params.push((key, "erotica".into())),
-                ContentRating::Pornographic => params.push((key, "pornographic".into())),
-            }
+            params.push((key.clone(), rating.as_str().to_string()));
         }
 
         params
     }
 
-    /// Searches for the given `query`.
-    ///
-    /// ## Errors
-    ///
-    /// If either the GET request fails, or the response is
-    /// faulty and can't be parsed as [`SearchResults`].
-    pub async fn search(&self, query: &str, page: u32) -> Result<SearchResults> {
-        // placeholder for now
+    /// Builds the parameters of a search for `query` (normalized) with `filters`.
+    fn search_params(
+        &self,
+        query: &str,
+        filters: &SearchFilters,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<(String, String)>> {
+        // title searches are case-insensitive anyway, and this makes for better cache hits
+        let query = query
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase();
         let mut params: Vec<(String, String)> = Vec::new();
 
-        params.push(("title".into(), query.into()));
-        params.extend(Self::language_filter_param(&[self.language], false)?);
+        let languages = if filters.languages.is_empty() {
+            self.languages()
+        } else {
+            filters.languages.clone()
+        };
+
+        params.push(("title".into(), query));
+        params.extend(Self::language_filter_param(&languages, false)?);
 
         // set pagination
-        let offset = self.manga_pagination * page;
-        params.push(("limit".into(), self.manga_pagination.to_string()));
+        params.push(("limit".into(), limit.to_string()));
         params.push(("offset".into(), offset.to_string()));
 
         // useful ux params
         params.push(("order[relevance]".into(), "desc".into()));
-        params.extend(Self::content_rating_param(&[
-            ContentRating::Safe,
-            ContentRating::Suggestive,
-            ContentRating::Erotica,
-            ContentRating::Pornographic,
-        ]));
 
+        if filters.content_ratings.is_empty() {
+            params.extend(Self::content_rating_param(&[
+                ContentRating::Safe,
+                ContentRating::Suggestive,
+                ContentRating::Erotica,
+                ContentRating::Pornographic,
+            ]));
+        } else {
+            params.extend(Self::content_rating_param(&filters.content_ratings));
+        }
+
+        Ok(params)
+    }
+
+    /// Makes a search request with `params`, fetching the statistics
+    /// of the results too if `statistics`.
+    ///
+    /// Returns the raw response along with the parsed results.
+    async fn request_results(
+        &self,
+        params: Vec<(String, String)>,
+        statistics: bool,
+    ) -> Result<(serde_json::Value, SearchResults)> {
         let endpoint = Endpoint::SearchManga(params);
         info!("Searching with URI {:?}", endpoint.as_string());
 
-        let r = self.api.get_ok_json(endpoint).await?;
-        let results = serde_json::from_value::<SearchResults>(r).into_diagnostic()?;
+        let r: serde_json::Value = self.api.get_ok_parsed(endpoint).await?;
+        let mut results: SearchResults = from_value_with_path(&r).into_diagnostic()?;
+
+        if statistics && !results.data.is_empty() {
+            let uuids = results.data.iter().map(MangaData::uuid).collect();
+
+            // statistics are only for show, so searching still works without them
+            match self.fetch_statistics(uuids).await {
+                Ok(statistics) => results.statistics = statistics,
+                Err(e) => warn!("Failed to fetch statistics of search results: {e}"),
+            }
+        }
 
         trace!("Results: {results:?}");
 
@@ -177,90 +605,425 @@ impl SearchClient {
             results.total
         );
 
+        Ok((r, results))
+    }
+
+    /// Searches for the given `query`, [reranking](`rerank`) the results if enabled.
+    ///
+    /// ## Errors
+    ///
+    /// If either the GET request fails, the response is faulty and can't be parsed
+    /// as [`SearchResults`], or `page` starts past [`Self::MAX_OFFSET_SIZE_SUM`].
+    pub async fn search(&self, query: &str, page: u32) -> Result<SearchResults> {
+        let offset = self.manga_pagination * page;
+        // `offset + limit` can't be over the cap, so the last page may be smaller
+        let limit = self
+            .manga_pagination
+            .min(Self::MAX_OFFSET_SIZE_SUM.saturating_sub(offset));
+
+        if limit == 0 {
+            bail!(
+                "page {} is past Manga-Dex's cap of {} results",
+                page + 1,
+                Self::MAX_OFFSET_SIZE_SUM
+            );
+        }
+
+        let params = self.search_params(query, &SearchFilters::default(), limit, offset)?;
+        let key = SearchCache::key(&params);
+
+        let mut results = if let Some(results) = self.cache.as_ref().and_then(|c| c.get(&key)) {
+            info!("Using cached search results for {key:?}");
+            results
+        } else {
+            let (r, results) = self.request_results(params, self.statistics).await?;
+
+            if let Some(cache) = &self.cache {
+                cache.insert(&key, r, &results);
+            }
+
+            results
+        };
+
+        if self.rerank {
+            rerank(&mut results, query);
+        }
+
         Ok(results)
     }
 
+    /// Searches for the given `query` with `filters`, streaming every result.
+    ///
+    /// Pages of [`Self::MAX_MANGA_PAGINATION`] results are fetched as the stream is
+    /// polled, stopping at Manga-Dex's cap of [`Self::MAX_OFFSET_SIZE_SUM`] results.
+    /// Statistics aren't fetched and the cache isn't used, since every result is
+    /// usually only wanted once.
+    ///
+    /// The stream ends after yielding an error.
+    pub fn search_stream(
+        &self,
+        query: &str,
+        filters: SearchFilters,
+    ) -> impl Stream<Item = Result<MangaData>> {
+        /// Where the stream is up to.
+        struct State {
+            offset: u32,
+            total: Option<u32>,
+            buffer: VecDeque<MangaData>,
+            done: bool,
+        }
+
+        let state = State {
+            offset: 0,
+            total: None,
+            buffer: VecDeque::new(),
+            done: false,
+        };
+        let query = query.to_string();
+
+        stream::unfold(state, move |mut state| {
+            let (query, filters) = (query.clone(), filters.clone());
+
+            async move {
+                loop {
+                    if let Some(md) = state.buffer.pop_front() {
+                        return Some((Ok(md), state));
+                    }
+
+                    let total = state.total.unwrap_or(u32::MAX);
+                    // `offset + limit` can't be over the cap, so the last page may be smaller
+                    let limit = Self::MAX_MANGA_PAGINATION
+                        .min(Self::MAX_OFFSET_SIZE_SUM.saturating_sub(state.offset));
+
+                    if state.done || state.offset >= total {
+                        return None;
+                    }
+
+                    if limit == 0 {
+                        warn!(
+                            "Stopped streaming results for {query:?} at the cap of {} (out of {total})",
+                            Self::MAX_OFFSET_SIZE_SUM
+                        );
+                        return None;
+                    }
+
+                    let results = match self.search_params(&query, &filters, limit, state.offset) {
+                        Ok(params) => self.request_results(params, false).await.map(|(_, r)| r),
+                        Err(e) => Err(e),
+                    };
+
+                    match results {
+                        Ok(results) => {
+                            state.total = Some(results.total);
+                            state.offset += limit;
+                            state.done = results.data.is_empty();
+                            state.buffer.extend(results.data);
+                        }
+                        Err(e) => {
+                            state.done = true;
+                            return Some((Err(e), state));
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    /// Fetches the statistics of the manga with `uuids` (at most 100).
+    ///
+    /// ## Errors
+    ///
+    /// From [`ApiClient::get_ok_parsed`].
+    pub async fn fetch_statistics(
+        &self,
+        uuids: Vec<Uuid>,
+    ) -> Result<HashMap<Uuid, MangaStatistics>> {
+        MangaStatistics::fetch(&self.api, uuids).await
+    }
+
+    /// Fetches the manga with `uuids`, of any content rating, using the `ids[]` filter
+    /// of [`Endpoint::SearchManga`] to fetch [`Self::MAX_MANGA_PAGINATION`] per request
+    /// (instead of one per request with [`Manga::new`]).
+    ///
+    /// Manga that don't exist (anymore) are left out. Like [`Manga::new`],
+    /// their authors, artists and covers are included.
+    ///
+    /// ## Errors
+    ///
+    /// From [`ApiClient::get_ok_parsed`].
+    pub async fn fetch_manga_bulk(&self, uuids: &[Uuid]) -> Result<HashMap<Uuid, Manga>> {
+        let mut manga = HashMap::with_capacity(uuids.len());
+
+        for batch in uuids.chunks(Self::MAX_MANGA_PAGINATION as usize) {
+            let mut params: Vec<(String, String)> = batch
+                .iter()
+                .map(|uuid| ("ids[]".to_string(), uuid.to_string()))
+                .collect();
+            params.push(("limit".into(), Self::MAX_MANGA_PAGINATION.to_string()));
+
+            for include in ["author", "artist", "cover_art"] {
+                params.push(("includes[]".into(), include.into()));
+            }
+
+            params.extend(Self::content_rating_param(&[
+                ContentRating::Safe,
+                ContentRating::Suggestive,
+                ContentRating::Erotica,
+                ContentRating::Pornographic,
+            ]));
+
+            let results: SearchResults = self
+                .api
+                .get_ok_parsed(Endpoint::SearchManga(params))
+                .await?;
+            manga.extend(
+                results
+                    .data
+                    .into_iter()
+                    .map(|md| (md.uuid(), Manga::from(md))),
+            );
+        }
+
+        debug!("Fetched {} of {} manga in bulk", manga.len(), uuids.len());
+        Ok(manga)
+    }
+
     /// Fetches all chapters of the given [`Manga`] with the specified [`Self::language`]
     ///
     /// ## Errors
     ///
-    /// From [`ApiClient::get_ok_json`] or if the response
+    /// From [`ApiClient::get_ok`] or if the response
     /// can't be parsed as [`ChapterResults`].
     pub async fn fetch_all_chapters(&self, manga: &Manga) -> Result<Vec<Chapter>> {
-        let mut offset = 0u32;
+        self.fetch_chapters(manga, &[self.language], &ChapterFilter::default())
+            .await
+    }
 
+    /// Fetches the chapters of the given [`Manga`] with the specified [`Self::language`]
+    /// that became readable since `since`, for checking for new chapters without
+    /// fetching the whole feed again.
+    ///
+    /// ## Errors
+    ///
+    /// See [`Self::fetch_all_chapters`].
+    pub async fn fetch_chapters_since(
+        &self,
+        manga: &Manga,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<Chapter>> {
+        let filter = ChapterFilter {
+            readable_after: Some(since),
+            ..ChapterFilter::default()
+        };
+
+        self.fetch_chapters(manga, &[self.language], &filter).await
+    }
+
+    /// Fetches the volumes of the given [`Manga`], only counting chapters in [`Self::language`].
+    ///
+    /// ## Errors
+    ///
+    /// From [`ApiClient::get_ok_parsed`].
+    pub async fn fetch_aggregate(&self, manga: &Manga) -> Result<Aggregate> {
+        let params = Self::language_filter_param(&[self.language], true)?;
+
+        self.api
+            .get_ok_parsed(Endpoint::GetMangaAggregate(manga.uuid(), params))
+            .await
+    }
+
+    /// Fetches all chapters of the given [`Manga`] translated into any of `languages`
+    /// (or into any language at all if `languages` is empty) which match `filter`.
+    ///
+    /// ## Errors
+    ///
+    /// See [`Self::fetch_all_chapters`].
+    pub async fn fetch_chapters(
+        &self,
+        manga: &Manga,
+        languages: &[Language],
+        filter: &ChapterFilter,
+    ) -> Result<Vec<Chapter>> {
         let mut params: Vec<(String, String)> = Vec::new();
-        params.push(("offset".into(), offset.to_string()));
-        params.push(("limit".into(), Self::MAX_CHAPTER_PAGINATION.to_string()));
-        params.extend(Self::language_filter_param(&[self.language], true)?);
+        params.extend(Self::language_filter_param(languages, true)?);
+        params.push(("includes[]".into(), "scanlation_group".into()));
+        params.push(("includes[]".into(), "user".into()));
+        // oldest first, so that the cap can be worked around with `publishAtSince`
+        params.push(("order[publishAt]".into(), "asc".into()));
         params.extend(Self::content_rating_param(&[
             ContentRating::Safe,
             ContentRating::Suggestive,
             ContentRating::Erotica,
             ContentRating::Pornographic,
         ]));
+        params.extend(filter.params());
 
-        let endpoint = Endpoint::GetMangaChapters(manga.uuid(), params.clone());
+        let feed = if filter.volumes.is_empty() && filter.groups.is_empty() {
+            ChapterFeed::Manga(manga.uuid())
+        } else {
+            params.push(("manga".into(), manga.uuid().to_string()));
+            ChapterFeed::Chapters
+        };
 
         info!(
             "Fetching chapters of the manga {:?}",
             manga.title(self.language)
         );
+        debug!("Fetching chapters using base endpoint params={params:?}");
 
-        // "initial" because pagination params are modified later on
-        debug!("Fetching chapters using initial endpoint params={params:?}");
-
-        // first fetch is outside the loop to find `total`
-        let raw_results = self.api.get_ok_json(endpoint).await?;
+        let mut all_chapters: Vec<Chapter> = Vec::new();
+        let mut seen: HashSet<Uuid> = HashSet::new();
+        let mut total = None;
+        let mut since = filter.published_after;
 
-        let chapter_results: ChapterResults =
-            serde_json::from_value(raw_results).into_diagnostic()?;
+        // each window is paginated up to the cap, after which the next window
+        // starts from the latest chapter fetched so far
+        loop {
+            let (chapters, window_total, capped) =
+                self.fetch_chapter_window(&feed, &params, since).await?;
 
-        let chapters: Vec<Chapter> = chapter_results.data.into_iter().map(Into::into).collect();
+            total.get_or_insert(window_total);
 
-        let total = chapter_results.total;
-        offset += Self::MAX_CHAPTER_PAGINATION;
+            for chapter in chapters {
+                if seen.insert(chapter.uuid()) {
+                    all_chapters.push(chapter);
+                }
+            }
 
-        let mut all_chapters = Vec::with_capacity(total as usize);
-        all_chapters.extend(chapters);
+            if !capped {
+                break;
+            }
 
-        // paginates through the whole collection to fetch all chapters of `manga`
-        //
-        // https://api.mangadex.org/docs/01-concepts/pagination/
-        while offset < total {
-            debug!("Current pagination offset={offset}");
+            let latest = all_chapters
+                .iter()
+                .map(|c| c.data.attributes.publish_at)
+                .max();
 
-            if offset + Self::MAX_CHAPTER_PAGINATION > Self::MAX_OFFSET_SIZE_SUM {
+            // chapters published at exactly `since` are fetched again, so if there's
+            // more than a whole window of them, there's no way past them
+            if latest.is_none() || latest == since {
                 warn!(
-                    concat!(
-                        "Fetching chapters halted; exceeded max collection ",
-                        "result size bound of (offset + limit > {})"
-                    ),
-                    Self::MAX_OFFSET_SIZE_SUM
+                    "Couldn't fetch past the collection cap, chapters published after {since:?} are missing"
                 );
+                break;
             }
 
-            // update params
-            let mut params = params.clone();
-            params[0].1 = offset.to_string();
-
-            // fetch chapters and turn them into `Vec<Chapter>`
-            let chapters: Vec<Chapter> = serde_json::from_value::<ChapterResults>(
-                self.api
-                    .get_ok_json(Endpoint::GetMangaChapters(manga.uuid(), params))
-                    .await?,
-            )
-            .into_diagnostic()?
-            .data
-            .into_iter()
-            .map(Into::into)
-            .collect();
+            info!(
+                "Reached the collection cap of {}, fetching chapters published since {}",
+                Self::MAX_OFFSET_SIZE_SUM,
+                latest.unwrap_or_default()
+            );
+            since = latest;
+        }
 
-            all_chapters.extend(chapters);
-            offset += Self::MAX_CHAPTER_PAGINATION;
+        let total = total.unwrap_or_default();
+        let fetched = all_chapters.len();
+
+        if fetched < total {
+            warn!("Only fetched {fetched} of the {total} chapters available");
+            print_line(
+                style(format!(
+                    "Only {fetched} of the {total} chapters could be fetched (see the logs)"
+                ))
+                .yellow(),
+            );
+        } else {
+            info!("Fetched all {fetched} chapters");
+        }
+
+        if !filter.numbers.is_empty() {
+            all_chapters.retain(|c| filter.matches_number(c));
+            info!("{} chapters are in the chapter ranges", all_chapters.len());
+        }
+
+        let external = all_chapters.iter().filter(|c| c.is_external()).count();
+
+        if external > 0 {
+            info!("{external} of {total} chapters are hosted outside of Manga-Dex");
         }
 
         trace!("All fetched chapters: {all_chapters:?}");
         Ok(all_chapters)
     }
+
+    /// Fetches a page of the chapters with `params` published since `since` (if given).
+    async fn fetch_chapter_page(
+        &self,
+        feed: &ChapterFeed,
+        params: &[(String, String)],
+        since: Option<DateTime<Utc>>,
+        offset: u32,
+        limit: u32,
+    ) -> Result<ChapterResults> {
+        debug!("Fetching chapters at offset={offset}, limit={limit}, since={since:?}");
+
+        let mut params = params.to_vec();
+        params.push(("offset".into(), offset.to_string()));
+        params.push(("limit".into(), limit.to_string()));
+
+        if let Some(since) = since {
+            params.push((
+                "publishAtSince".into(),
+                since.format("%Y-%m-%dT%H:%M:%S").to_string(),
+            ));
+        }
+
+        self.limiter.acquire().await;
+        let response = self.api.get_ok(feed.endpoint(params)).await?;
+        self.limiter.observe(&response.headers);
+
+        Ok(response.body)
+    }
+
+    /// Helper for [`Self::fetch_chapters`], which paginates through the chapters with
+    /// `params` published since `since` (if given) until the collection cap.
+    ///
+    /// The first page is fetched to find how many chapters there are, then the rest
+    /// are fetched concurrently (at most [`Self::MAX_CONCURRENT_PAGES`] at a time).
+    ///
+    /// Returns the chapters, how many there are in total (since `since`)
+    /// and whether the cap was reached before fetching all of them.
+    ///
+    /// ## References
+    ///
+    /// - <https://api.mangadex.org/docs/01-concepts/pagination/>
+    async fn fetch_chapter_window(
+        &self,
+        feed: &ChapterFeed,
+        params: &[(String, String)],
+        since: Option<DateTime<Utc>>,
+    ) -> Result<(Vec<Chapter>, usize, bool)> {
+        let first = self
+            .fetch_chapter_page(feed, params, since, 0, Self::MAX_CHAPTER_PAGINATION)
+            .await?;
+
+        let total = first.total;
+        let end = total.min(Self::MAX_OFFSET_SIZE_SUM);
+        let mut chapters: Vec<Chapter> = first.data.into_iter().map(Chapter::from).collect();
+
+        // `offset + limit` can't be over the cap, so the last page may be smaller
+        let pages: Vec<(u32, u32)> = (Self::MAX_CHAPTER_PAGINATION..end)
+            .step_by(Self::MAX_CHAPTER_PAGINATION as usize)
+            .map(|offset| {
+                let limit = Self::MAX_CHAPTER_PAGINATION.min(Self::MAX_OFFSET_SIZE_SUM - offset);
+                (offset, limit)
+            })
+            .collect();
+
+        debug!(
+            "Fetching {} more pages of chapters concurrently",
+            pages.len()
+        );
+
+        // `buffered` keeps the pages in order
+        let rest: Vec<ChapterResults> = stream::iter(pages)
+            .map(|(offset, limit)| self.fetch_chapter_page(feed, params, since, offset, limit))
+            .buffered(Self::MAX_CONCURRENT_PAGES)
+            .try_collect()
+            .await?;
+
+        chapters.extend(rest.into_iter().flat_map(|r| r.data).map(Chapter::from));
+
+        Ok((chapters, total as usize, total > Self::MAX_OFFSET_SIZE_SUM))
+    }
 }
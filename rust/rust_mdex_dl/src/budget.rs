@@ -0,0 +1,162 @@
+//! Contains the run's retry and failure budget (`[budget]` in the config), which aborts the
+//! run once too many requests were retried or failed, e.g. during an outage, instead of
+//! retrying every chapter (or in `watch`, every update) for hours.
+//!
+//! Every API request and image download is [recorded](`record_request`), along with every
+//! [retry](`record_budget_retry`). Once the budget is exceeded, it stays exhausted for the rest
+//! of the run, and [`check_budget`] (called before every request) fails with [`BudgetExhausted`].
+
+use crate::{config, errors::BudgetExhausted};
+
+use std::sync::{
+    OnceLock, RwLock,
+    atomic::{AtomicU64, Ordering},
+};
+
+use miette::Result;
+use reqwest::StatusCode;
+use serde::Serialize;
+
+static REQUESTS: AtomicU64 = AtomicU64::new(0);
+static FAILURES: AtomicU64 = AtomicU64::new(0);
+static RETRIES: AtomicU64 = AtomicU64::new(0);
+
+/// The limits set by [`set_budget`].
+static LIMITS: RwLock<Option<config::Budget>> = RwLock::new(None);
+
+/// Why the budget was exhausted, set once it is.
+static EXHAUSTED: OnceLock<BudgetExhausted> = OnceLock::new();
+
+/// Sets the budget's limits from the config. Until this is called, nothing is limited.
+///
+/// ## Panics
+///
+/// If another thread panicked while setting the limits.
+pub fn set_budget(limits: config::Budget) {
+    *LIMITS.write().expect("budget lock poisoned") = Some(limits);
+}
+
+/// Records a request (an API call or image download) with the `status` of its response,
+/// or `None` if it got no response.
+///
+/// Only requests without a response or with a server error count as failures, since
+/// ratelimited requests are already counted by [`record_budget_retry`] when retried.
+pub fn record_request(status: Option<StatusCode>) {
+    REQUESTS.fetch_add(1, Ordering::Relaxed);
+
+    if status.is_none_or(|s| s.is_server_error()) {
+        FAILURES.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Records a request being retried, see [`crate::metrics::record_retry`].
+pub fn record_budget_retry() {
+    RETRIES.fetch_add(1, Ordering::Relaxed);
+}
+
+/// How much of the budget was spent so far, see [`budget_state`].
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetState {
+    pub requests: u64,
+    pub failures: u64,
+    pub retries: u64,
+    /// The limits, or `None` if they were never [set](`set_budget`).
+    #[serde(skip)]
+    pub limits: Option<config::Budget>,
+    /// Why the budget was exhausted, if it was.
+    pub exhausted: Option<String>,
+}
+
+impl BudgetState {
+    /// Returns the share of requests which failed, in percent.
+    #[must_use]
+    pub fn failure_percent(&self) -> u64 {
+        (self.failures * 100)
+            .checked_div(self.requests)
+            .unwrap_or(0)
+    }
+
+    /// Returns why `limits` are exceeded, if they are.
+    fn exceeded(&self, limits: &config::Budget) -> Option<BudgetExhausted> {
+        if limits.max_retries > 0 && self.retries > limits.max_retries {
+            return Some(BudgetExhausted::Retries {
+                retries: self.retries,
+                max: limits.max_retries,
+            });
+        }
+
+        let percent = self.failure_percent();
+
+        if limits.max_failure_percent > 0
+            && self.requests >= limits.min_requests
+            && percent > limits.max_failure_percent
+        {
+            return Some(BudgetExhausted::Failures {
+                failures: self.failures,
+                requests: self.requests,
+                percent,
+                max: limits.max_failure_percent,
+            });
+        }
+
+        None
+    }
+
+    /// Returns a line such as `12 retries (limit 500), 3% of 410 requests failed (limit 50%)`.
+    #[must_use]
+    pub fn describe(&self) -> String {
+        let limit = |max: Option<u64>, unit: &str| match max {
+            Some(max) if max > 0 => format!("limit {max}{unit}"),
+            _ => "no limit".to_string(),
+        };
+        let limits = self.limits.as_ref();
+
+        format!(
+            "{} retries ({}), {}% of {} requests failed ({})",
+            self.retries,
+            limit(limits.map(|l| l.max_retries), ""),
+            self.failure_percent(),
+            self.requests,
+            limit(limits.map(|l| l.max_failure_percent), "%"),
+        )
+    }
+}
+
+/// Returns how much of the budget was spent so far.
+///
+/// ## Panics
+///
+/// If another thread panicked while setting the limits.
+#[must_use]
+pub fn budget_state() -> BudgetState {
+    BudgetState {
+        requests: REQUESTS.load(Ordering::Relaxed),
+        failures: FAILURES.load(Ordering::Relaxed),
+        retries: RETRIES.load(Ordering::Relaxed),
+        limits: LIMITS.read().expect("budget lock poisoned").clone(),
+        exhausted: EXHAUSTED.get().map(ToString::to_string),
+    }
+}
+
+/// Checks that the budget isn't exhausted, which is called before every request.
+///
+/// ## Errors
+///
+/// A [`BudgetExhausted`] if too many requests were retried or failed. This
+/// is logged the first time, and returned for every check after that.
+pub fn check_budget() -> Result<()> {
+    if let Some(exhausted) = EXHAUSTED.get() {
+        return Err(exhausted.clone().into());
+    }
+
+    let state = budget_state();
+    let Some(exhausted) = state.limits.as_ref().and_then(|l| state.exceeded(l)) else {
+        return Ok(());
+    };
+
+    if EXHAUSTED.set(exhausted.clone()).is_ok() {
+        error!("{exhausted}, aborting the run ({})", state.describe());
+    }
+
+    Err(exhausted.into())
+}
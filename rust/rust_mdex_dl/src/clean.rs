@@ -0,0 +1,464 @@
+//! Contains [`display_clean`], which finds [leftovers](`Leftover`) in the library (e.g. from
+//! interrupted downloads) and deletes them once confirmed.
+//!
+//! Only dirs are ever treated as orphans, so files next to chapters (like `series.json`,
+//! `.url` shortcuts or `covers/`) are left alone. So is the [page store](`crate::store`).
+//!
+//! Orphans are only listed by default, since chapters downloaded before the library index
+//! existed aren't in it either. With `--orphans`, they're moved to the
+//! [trash](`crate::paths::trash_dir`) rather than deleted.
+//!
+//! Chapters that may still be downloading (i.e. are pending in the
+//! [queue](`crate::queue::DownloadQueue`), or were changed in the last
+//! [`IN_PROGRESS_WINDOW`]) are left alone, and so are their unfinished files.
+
+use crate::{
+    api::download::is_dry_run,
+    library::{ChapterEntry, LibraryIndex, MangaEntry},
+    manifest::ChapterManifest,
+    paths::{manga_save_dir, trash_dir},
+    progress::print_line,
+    queue::DownloadQueue,
+    store::prune_store,
+};
+
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use console::style;
+use dialoguer::{Confirm, theme::ColorfulTheme};
+use miette::{IntoDiagnostic, Result};
+use uuid::Uuid;
+
+/// How recently a chapter dir must have been changed to be treated as still downloading.
+pub const IN_PROGRESS_WINDOW: Duration = Duration::from_mins(10);
+
+/// Something in the library that's safe to delete, found by [`find_leftovers`].
+#[derive(Debug, Clone)]
+pub enum Leftover {
+    /// An unfinished (`.partial` or `.tmp`) file, left by an interrupted write.
+    PartialFile(PathBuf),
+    /// A downloaded chapter whose pages don't match its manifest, or that never finished.
+    ///
+    /// Deleting it also removes it from the index, so `update` downloads it again.
+    IncompleteChapter {
+        manga: Uuid,
+        chapter: Uuid,
+        dir: PathBuf,
+        reason: String,
+    },
+    /// A dir that isn't a manga or chapter in the index, which is moved to the trash
+    /// instead of being deleted.
+    Orphan(PathBuf),
+    /// A dir with nothing in it.
+    EmptyDir(PathBuf),
+}
+
+impl Leftover {
+    /// Returns the path of the file or dir.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::PartialFile(path) | Self::Orphan(path) | Self::EmptyDir(path) => path,
+            Self::IncompleteChapter { dir, .. } => dir,
+        }
+    }
+
+    /// Deletes the file or dir (moving orphans to the trash).
+    fn delete(&self) -> Result<()> {
+        match self {
+            Self::PartialFile(path) => fs::remove_file(path).into_diagnostic(),
+            Self::EmptyDir(path) => fs::remove_dir(path).into_diagnostic(),
+            Self::Orphan(path) => move_to_trash(path),
+            Self::IncompleteChapter { dir, .. } => fs::remove_dir_all(dir).into_diagnostic(),
+        }
+    }
+}
+
+impl fmt::Display for Leftover {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PartialFile(path) => write!(f, "unfinished file {}", path.display()),
+            Self::IncompleteChapter { dir, reason, .. } => {
+                write!(f, "incomplete chapter {} ({reason})", dir.display())
+            }
+            Self::Orphan(path) => write!(f, "dir not in the library index {}", path.display()),
+            Self::EmptyDir(path) => write!(f, "empty dir {}", path.display()),
+        }
+    }
+}
+
+/// Moves the dir at `path` (in the library) into the [trash](`trash_dir`), keeping its
+/// path relative to the library so that it can be moved back.
+fn move_to_trash(path: &Path) -> Result<()> {
+    let library = manga_save_dir()?;
+    let relative = path.strip_prefix(&library).unwrap_or(path);
+    let mut dest = trash_dir()?.join(relative);
+
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).into_diagnostic()?;
+    }
+
+    // an earlier `clean` may have trashed a dir with the same path
+    let mut n = 1;
+    while dest.try_exists().into_diagnostic()? {
+        n += 1;
+        let mut name = relative.as_os_str().to_owned();
+        name.push(format!(" ({n})"));
+        dest = trash_dir()?.join(name);
+    }
+
+    fs::rename(path, &dest).into_diagnostic()
+}
+
+/// Returns true if `path` was modified in the last [`IN_PROGRESS_WINDOW`].
+fn modified_recently(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .is_ok_and(|modified| {
+            SystemTime::now()
+                .duration_since(modified)
+                .unwrap_or_default()
+                < IN_PROGRESS_WINDOW
+        })
+}
+
+/// Returns true if `path` is a file left by an interrupted write
+/// (and not one that may still be being written).
+fn is_partial_file(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|ext| ext == "partial" || ext == "tmp")
+        && !modified_recently(path)
+}
+
+/// Returns true if the chapter in `dir` may still be downloading, i.e. it's pending in
+/// the queue (its uuid is in `queued`), or its pending manifest or anything in it was
+/// changed recently.
+fn is_in_progress(chapter: &ChapterEntry, dir: &Path, queued: &HashSet<Uuid>) -> Result<bool> {
+    if queued.contains(&chapter.uuid) {
+        return Ok(true);
+    }
+
+    if !dir.join(ChapterManifest::PENDING_FILENAME).exists() {
+        return Ok(false);
+    }
+
+    Ok(modified_recently(dir) || dir_entries(dir)?.iter().any(|p| modified_recently(p)))
+}
+
+/// Returns the entries of the dir at `path`.
+fn dir_entries(path: &Path) -> Result<Vec<PathBuf>> {
+    fs::read_dir(path)
+        .into_diagnostic()?
+        .map(|entry| entry.map(|e| e.path()).into_diagnostic())
+        .collect()
+}
+
+/// Returns why the chapter in `dir` is incomplete, or `None` if it isn't (as far as
+/// can be told, since chapters downloaded before manifests existed can't be checked).
+fn incomplete_reason(chapter: &ChapterEntry, dir: &Path) -> Result<Option<String>> {
+    let Some(manifest) = ChapterManifest::read(dir)? else {
+        if dir.join(ChapterManifest::PENDING_FILENAME).exists() {
+            return Ok(Some("the download never finished".to_string()));
+        }
+
+        return Ok(None);
+    };
+
+    let missing = manifest
+        .pages
+        .iter()
+        .filter(|p| !dir.join(&p.file).is_file())
+        .count();
+
+    if missing > 0 {
+        return Ok(Some(format!(
+            "{missing} of {} pages missing",
+            manifest.pages.len()
+        )));
+    }
+
+    if chapter.pages != manifest.pages.len() {
+        return Ok(Some(format!(
+            "{} pages in the index, but {} in its manifest",
+            chapter.pages,
+            manifest.pages.len()
+        )));
+    }
+
+    Ok(None)
+}
+
+/// Adds the leftovers in the dir at `path` (which isn't in the index) to `found`.
+///
+/// Dirs that contain something in the index (i.e. are in `indexed`) are looked into
+/// instead, since only their other contents can be orphaned.
+fn find_unindexed(
+    path: &Path,
+    indexed: &HashSet<PathBuf>,
+    found: &mut Vec<Leftover>,
+) -> Result<()> {
+    if indexed.iter().any(|p| p.starts_with(path)) {
+        for entry in dir_entries(path)? {
+            if is_partial_file(&entry) {
+                found.push(Leftover::PartialFile(entry));
+            } else if entry.is_dir() && !indexed.contains(&entry) {
+                find_unindexed(&entry, indexed, found)?;
+            }
+        }
+    } else if dir_entries(path)?.is_empty() {
+        found.push(Leftover::EmptyDir(path.to_path_buf()));
+    } else {
+        found.push(Leftover::Orphan(path.to_path_buf()));
+    }
+
+    Ok(())
+}
+
+/// Adds the leftovers in the dir of `entry` (a manga in the index) to `found`,
+/// skipping chapters that are [in progress](`is_in_progress`).
+fn find_in_manga(
+    entry: &MangaEntry,
+    manga_dir: &Path,
+    indexed: &HashSet<PathBuf>,
+    queued: &HashSet<Uuid>,
+    found: &mut Vec<Leftover>,
+) -> Result<()> {
+    for chapter in entry.chapters.values() {
+        let dir = manga_dir.join(&chapter.dir);
+
+        if !dir.is_dir() {
+            continue;
+        }
+
+        if is_in_progress(chapter, &dir, queued)? {
+            debug!("Skipping {}, which may still be downloading", dir.display());
+            continue;
+        }
+
+        if let Some(reason) = incomplete_reason(chapter, &dir)? {
+            found.push(Leftover::IncompleteChapter {
+                manga: entry.uuid,
+                chapter: chapter.uuid,
+                dir,
+                reason,
+            });
+            continue;
+        }
+
+        for file in dir_entries(&dir)?
+            .into_iter()
+            .filter(|p| is_partial_file(p))
+        {
+            found.push(Leftover::PartialFile(file));
+        }
+    }
+
+    for path in dir_entries(manga_dir)? {
+        let is_covers = path.file_name().is_some_and(|name| name == "covers");
+
+        if is_partial_file(&path) {
+            found.push(Leftover::PartialFile(path));
+        } else if path.is_dir() && !is_covers && !indexed.contains(&path) {
+            find_unindexed(&path, indexed, found)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Finds the leftovers in the library, only looking at manga whose title contains
+/// `manga_filter` (case-insensitive) if it's given. Dirs outside of every manga's
+/// dir are only checked without a filter.
+///
+/// ## Errors
+///
+/// If a dir, manifest or the [queue](`DownloadQueue`) can't be read.
+pub fn find_leftovers(index: &LibraryIndex, manga_filter: Option<&str>) -> Result<Vec<Leftover>> {
+    let library = manga_save_dir()?;
+    let manga_filter = manga_filter.map(str::to_lowercase);
+
+    if !library.try_exists().into_diagnostic()? {
+        return Ok(Vec::new());
+    }
+
+    let indexed: HashSet<PathBuf> = index
+        .manga
+        .values()
+        .flat_map(|m| {
+            let manga_dir = library.join(&m.dir);
+            m.chapters
+                .values()
+                .map(move |c| manga_dir.join(&c.dir))
+                .chain([library.join(&m.dir)])
+        })
+        .collect();
+
+    let queued: HashSet<Uuid> = DownloadQueue::load()?
+        .entries
+        .iter()
+        .flat_map(|e| e.pending().map(|c| c.uuid))
+        .collect();
+
+    let mut found = Vec::new();
+
+    for entry in index.manga.values().filter(|m| {
+        manga_filter
+            .as_ref()
+            .is_none_or(|f| m.title.to_lowercase().contains(f))
+    }) {
+        let manga_dir = library.join(&entry.dir);
+
+        if manga_dir.is_dir() {
+            find_in_manga(entry, &manga_dir, &indexed, &queued, &mut found)?;
+        }
+    }
+
+    if manga_filter.is_some() {
+        return Ok(found);
+    }
+
+    for path in dir_entries(&library)? {
+        // e.g. the page store
+        let is_hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+
+        if is_partial_file(&path) {
+            found.push(Leftover::PartialFile(path));
+        } else if path.is_dir() && !is_hidden && !indexed.contains(&path) {
+            find_unindexed(&path, &indexed, &mut found)?;
+        }
+    }
+
+    Ok(found)
+}
+
+/// Lists the [leftovers](`find_leftovers`) in the library, then deletes them if confirmed
+/// (or right away with `yes`). Afterwards, pages in the [page store](`crate::store`) that
+/// no chapter links to anymore are [pruned](`prune_store`). Nothing is deleted in a dry run.
+///
+/// [Orphans](`Leftover::Orphan`) are only listed, unless `orphans` is set, in which case
+/// they're moved to the [trash](`trash_dir`).
+///
+/// Deleted incomplete chapters are removed from a freshly loaded index after confirming,
+/// so that nothing written to it in the meantime (e.g. by `watch`) is lost.
+///
+/// ## Errors
+///
+/// If propagated from [`find_leftovers`] or [`prune_store`], prompting fails, or the
+/// index can't be saved. Leftovers that can't be deleted are logged instead.
+pub fn display_clean(manga_filter: Option<&str>, yes: bool, orphans: bool) -> Result<()> {
+    clean_leftovers(manga_filter, yes, orphans)?;
+
+    if is_dry_run() {
+        return Ok(());
+    }
+
+    let freed = prune_store()?;
+
+    if freed > 0 {
+        #[allow(clippy::cast_precision_loss)]
+        let mib = freed as f64 / 1_048_576.0;
+        print_line(
+            style(format!(
+                "Freed {mib:.1} MiB of unused pages from the page store"
+            ))
+            .green(),
+        );
+    }
+
+    Ok(())
+}
+
+/// Helper for [`display_clean`], which lists and deletes the leftovers.
+fn clean_leftovers(manga_filter: Option<&str>, yes: bool, orphans: bool) -> Result<()> {
+    let index = LibraryIndex::load()?;
+    let (found_orphans, leftovers): (Vec<Leftover>, Vec<Leftover>) =
+        find_leftovers(&index, manga_filter)?
+            .into_iter()
+            .partition(|l| matches!(l, Leftover::Orphan(_)));
+
+    for orphan in &found_orphans {
+        print_line(format!("  {} {orphan}", style("?").yellow()));
+    }
+
+    let leftovers = if orphans {
+        [leftovers, found_orphans].concat()
+    } else {
+        if !found_orphans.is_empty() {
+            print_line(
+                style(format!(
+                    "Found {} dirs that aren't in the library index, which may have been \
+                    downloaded before it existed. Use `--orphans` to move them to {}",
+                    found_orphans.len(),
+                    trash_dir()?.display()
+                ))
+                .yellow(),
+            );
+        }
+
+        leftovers
+    };
+
+    if leftovers.is_empty() {
+        print_line(style("Nothing to clean up").green());
+        return Ok(());
+    }
+
+    for leftover in leftovers
+        .iter()
+        .filter(|l| !matches!(l, Leftover::Orphan(_)))
+    {
+        print_line(format!("  {} {leftover}", style("✗").red()));
+    }
+
+    print_line(style(format!("Found {} things to clean up", leftovers.len())).yellow());
+
+    if is_dry_run()
+        || !(yes
+            || Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt("Delete them?")
+                .default(false)
+                .interact()
+                .into_diagnostic()?)
+    {
+        return Ok(());
+    }
+
+    let mut deleted = 0;
+    let mut removed: Vec<(Uuid, Uuid)> = Vec::new();
+
+    for leftover in &leftovers {
+        match leftover.delete() {
+            Ok(()) => {
+                deleted += 1;
+
+                if let Leftover::IncompleteChapter { manga, chapter, .. } = leftover {
+                    removed.push((*manga, *chapter));
+                }
+            }
+            Err(e) => error!("Failed to delete {}: {e}", leftover.path().display()),
+        }
+    }
+
+    if !removed.is_empty() {
+        LibraryIndex::update(|index| {
+            for (manga, chapter) in &removed {
+                if let Some(entry) = index.manga.get_mut(manga) {
+                    entry.chapters.remove(chapter);
+                }
+            }
+        })?;
+    }
+
+    print_line(style(format!("Cleaned up {deleted} of {}", leftovers.len())).green());
+
+    Ok(())
+}
@@ -0,0 +1,326 @@
+//! Contains the command-line interface, parsed using [`clap`].
+//!
+//! Running without a subcommand starts the interactive search and download menu.
+
+use crate::{
+    config::ConfigOverride,
+    export::ExportLayout,
+    library::LibrarySort,
+    metadata::MetadataFormat,
+    progress::ProgressMode,
+    repackage::{PackageFormat, PackageUnit},
+    update::parse_interval,
+};
+
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+
+use clap::{Parser, Subcommand};
+
+// these are independent flags, so a state machine wouldn't make sense
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Write a zip of the (sanitized) config, versions, API requests
+    /// and final error to this file, for attaching to bug reports.
+    #[arg(long, global = true, value_name = "FILE")]
+    pub trace_bundle: Option<PathBuf>,
+
+    /// How to show download progress. `json` prints JSON lines
+    /// (e.g. `{"event":"chapter_finished",...}`) to stdout instead of progress bars.
+    #[arg(long, global = true, value_enum, default_value_t)]
+    pub progress: ProgressMode,
+
+    /// Hide progress bars, only printing summaries and errors.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+    /// Also print debug logs to stderr.
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+
+    /// Only print what would be downloaded (with estimated sizes), without writing anything.
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// Don't connect to Manga-Dex: search the library (and cached search results) instead,
+    /// and verify or repackage downloaded chapters rather than downloading them.
+    #[arg(long, global = true)]
+    pub offline: bool,
+
+    /// Use the options of this profile (`[profile.NAME]` in the config) for this run.
+    #[arg(long, global = true, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Override `client.language` for this run.
+    #[arg(long, global = true, value_name = "CODE")]
+    pub language: Option<String>,
+    /// Override `images.quality` for this run.
+    #[arg(long, global = true, value_parser = ["lossless", "lossy"])]
+    pub quality: Option<String>,
+    /// Override `concurrency.image_permits` for this run.
+    #[arg(long, global = true, value_name = "N")]
+    pub image_permits: Option<usize>,
+    /// Override `concurrency.chapter_permits` for this run.
+    #[arg(long, global = true, value_name = "N")]
+    pub chapter_permits: Option<usize>,
+    /// Only download the newest N chapters of each manga (`download.latest`), skipping
+    /// the chapter menu.
+    #[arg(long, global = true, value_name = "N")]
+    pub latest: Option<usize>,
+    /// Override any config option for this run, e.g. `--set images.verify=true`.
+    ///
+    /// These take priority over `MDEX_DL_*` environment variables and the config file.
+    #[arg(long = "set", global = true, value_name = "KEY=VALUE")]
+    pub overrides: Vec<ConfigOverride>,
+}
+
+impl Cli {
+    /// Returns every config override given as a flag, in order of priority (lowest first).
+    #[must_use]
+    pub fn config_overrides(&self) -> Vec<ConfigOverride> {
+        let flags = [
+            ("client.language", "--language", self.language.clone()),
+            ("images.quality", "--quality", self.quality.clone()),
+            (
+                "concurrency.image_permits",
+                "--image-permits",
+                self.image_permits.map(|n| n.to_string()),
+            ),
+            (
+                "concurrency.chapter_permits",
+                "--chapter-permits",
+                self.chapter_permits.map(|n| n.to_string()),
+            ),
+            (
+                "download.latest",
+                "--latest",
+                self.latest.map(|n| n.to_string()),
+            ),
+        ];
+
+        flags
+            .into_iter()
+            .filter_map(|(key, flag, value)| Some(ConfigOverride::new(key, value?, flag)))
+            .chain(self.overrides.iter().cloned())
+            .collect()
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Show past download runs, newest first.
+    History {
+        /// Only show runs for manga whose title contains this (case-insensitive).
+        #[arg(short, long)]
+        manga: Option<String>,
+        /// The max number of runs to show.
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+    },
+    /// Re-hash downloaded chapters and report corrupted or missing pages.
+    Verify {
+        /// Only verify manga whose directory name contains this (case-insensitive).
+        #[arg(short, long)]
+        manga: Option<String>,
+    },
+    /// Find leftovers in the library (unfinished files, incomplete chapters, empty dirs and
+    /// dirs that aren't in the library index) and delete them once confirmed.
+    Clean {
+        /// Only check manga whose title contains this (case-insensitive).
+        #[arg(short, long)]
+        manga: Option<String>,
+        /// Delete without asking.
+        #[arg(short, long)]
+        yes: bool,
+        /// Also move dirs that aren't in the library index to the trash (`.trash` in the
+        /// library), instead of only listing them.
+        #[arg(long)]
+        orphans: bool,
+    },
+    /// Find chapters downloaded more than once (e.g. from several groups) and remove the
+    /// extra copies, keeping the one from the most preferred group (`groups.preferred`).
+    Dedupe {
+        /// Only dedupe manga whose title contains this (case-insensitive).
+        #[arg(short, long)]
+        manga: Option<String>,
+        /// Move the extra copies into this dir instead of deleting them.
+        #[arg(short, long)]
+        archive: Option<PathBuf>,
+        /// Remove without asking.
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Download new chapters for every manga in the library.
+    Update {
+        /// Only update manga whose title contains this (case-insensitive).
+        #[arg(short, long)]
+        manga: Option<String>,
+        /// Only check for new chapters, without downloading them.
+        #[arg(long)]
+        check: bool,
+    },
+    /// Download chapters saved in a lower quality than `images.quality` again,
+    /// e.g. after switching from "lossy" to "lossless".
+    Upgrade {
+        /// Only upgrade manga whose title contains this (case-insensitive).
+        #[arg(short, long)]
+        manga: Option<String>,
+        /// Only list the chapters to upgrade, without downloading them.
+        #[arg(long)]
+        check: bool,
+        /// Export the upgraded manga into this dir afterwards, repackaging their archives.
+        #[arg(long)]
+        export: Option<PathBuf>,
+    },
+    /// Keep running, downloading new chapters for every manga in the library periodically.
+    Watch {
+        /// How often to check for new chapters, e.g. "6h", "90m" or "1d" (at least 10m).
+        #[arg(short, long, default_value = "6h", value_parser = parse_interval)]
+        interval: Duration,
+        /// Only update manga whose title contains this (case-insensitive).
+        #[arg(short, long)]
+        manga: Option<String>,
+    },
+    /// List a manga's covers (e.g. each volume's, in each locale) and download the chosen
+    /// ones at their original resolution into `covers/` in its dir.
+    Covers {
+        /// A link to the manga, its uuid, or part of its title if it's in the library.
+        manga: String,
+        /// Only list covers in these locales (ISO 639-1 codes, e.g. "ja").
+        #[arg(short, long)]
+        locale: Vec<String>,
+        /// Download every cover without asking.
+        #[arg(short, long)]
+        all: bool,
+    },
+    /// Manage chapters left in the download queue by interrupted runs.
+    Queue {
+        #[command(subcommand)]
+        action: QueueAction,
+    },
+    /// Inspect the library of downloaded manga.
+    Library {
+        #[command(subcommand)]
+        action: LibraryAction,
+    },
+    /// Show how much disk space the library takes up, per manga, language and format.
+    Stats {
+        /// How many of the largest manga to list.
+        #[arg(short = 'n', long, default_value_t = 10)]
+        top: usize,
+    },
+    /// Export the library into a dir, in a layout other readers can open directly.
+    Export {
+        /// The dir to export into, e.g. Mihon's `local` dir.
+        dest: PathBuf,
+        /// The layout to export in, `export.library_layout` in the config by default.
+        #[arg(short, long, value_enum)]
+        layout: Option<ExportLayout>,
+        /// Only export manga whose title contains this (case-insensitive).
+        #[arg(short, long)]
+        manga: Option<String>,
+    },
+    /// Write each manga's metadata (titles, description, tags, status, links, authors)
+    /// as `series.json` and/or `tvshow.nfo`, for media managers.
+    ExportMetadata {
+        /// Write into this dir (in a dir per manga) instead of the library.
+        #[arg(short, long)]
+        dest: Option<PathBuf>,
+        /// Which files to write.
+        #[arg(short, long, value_enum, default_value_t)]
+        format: MetadataFormat,
+        /// Only export manga whose title contains this (case-insensitive).
+        #[arg(short, long)]
+        manga: Option<String>,
+    },
+    /// Repackage the library into another format (e.g. CBZ or PDF), per chapter or per
+    /// volume, without downloading anything.
+    Repackage {
+        /// The dir to write into, in a dir per manga.
+        dest: PathBuf,
+        /// The format to write.
+        #[arg(short, long, value_enum, default_value_t)]
+        format: PackageFormat,
+        /// Whether to write a package per chapter or per volume.
+        #[arg(short, long, value_enum, default_value_t)]
+        by: PackageUnit,
+        /// Only repackage manga whose title contains this (case-insensitive).
+        #[arg(short, long)]
+        manga: Option<String>,
+    },
+    /// Queue the Manga-Dex manga in a Tachiyomi/Mihon backup for download.
+    Import {
+        /// The backup file (`.tachibk` or `.proto.gz`).
+        backup: PathBuf,
+        /// Skip chapters that are marked as read in the backup.
+        #[arg(long)]
+        skip_read: bool,
+    },
+    /// Serve the library as an OPDS catalog, so e-reader apps can browse and download chapters.
+    Serve {
+        /// The address to listen on. Use `0.0.0.0:8080` to allow other devices on the network.
+        #[arg(short, long, default_value = "127.0.0.1:8080")]
+        bind: SocketAddr,
+    },
+    /// Manage the config file.
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+impl Command {
+    /// Returns the name of this command if it can't run without reaching Manga-Dex,
+    /// i.e. in [offline mode](`crate::offline`).
+    #[must_use]
+    pub const fn needs_network(&self) -> Option<&'static str> {
+        match self {
+            Self::Update { .. } => Some("update"),
+            Self::Upgrade { .. } => Some("upgrade"),
+            Self::Watch { .. } => Some("watch"),
+            Self::Covers { .. } => Some("covers"),
+            Self::Queue {
+                action: QueueAction::Resume,
+            } => Some("queue resume"),
+            Self::Import { .. } => Some("import"),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+    /// Interactively set up the config, asking for the most common options.
+    Init,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum QueueAction {
+    /// List manga with chapters left to download.
+    List,
+    /// Download every chapter left in the queue.
+    Resume,
+    /// Forget every chapter left in the queue.
+    Clear,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum LibraryAction {
+    /// List downloaded manga with their chapter counts, languages, sizes and update dates.
+    List {
+        /// Only list manga whose title contains this (case-insensitive).
+        #[arg(short, long)]
+        manga: Option<String>,
+        /// Only count chapters in this language (an ISO 639-1 code, e.g. "en").
+        #[arg(short, long)]
+        language: Option<String>,
+        /// What to sort by.
+        #[arg(short, long, value_enum, default_value_t)]
+        sort: LibrarySort,
+        /// Reverse the sort order.
+        #[arg(short, long)]
+        reverse: bool,
+    },
+}
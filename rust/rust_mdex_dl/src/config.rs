@@ -2,17 +2,39 @@
 //! options using [`serde`] and [`toml`].
 
 use crate::{
-    deserializers::{deserialize_langcode, deserialize_logging_filter},
-    paths::{config_toml, log_save_dir, manga_save_dir},
+    api::{
+        auth::Credentials,
+        client::DEFAULT_USER_AGENT,
+        models::{DEFAULT_TITLE_PREFERENCE, ReadingStatus, TitlePreference, set_title_preference},
+        search::SearchClient,
+    },
+    budget::set_budget,
+    deserializers::{deserialize_langcode, deserialize_langcode_vec, deserialize_logging_filter},
+    errors::ConfigError,
+    export::ExportLayout,
+    naming::{CHAPTER_FIELDS, MANGA_FIELDS, PAGE_FIELDS, Template},
+    path_policy::PathPolicy,
+    paths::{config_toml, is_within, log_save_dir, manga_save_dir, set_library_dir},
+    progress::{DEFAULT_TEMPLATE, job_style, print_status},
+    repackage::PackageFormat,
 };
 
-use std::fs;
+use std::{
+    fmt, fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
 
 use isolang::Language;
 use miette::{IntoDiagnostic, Result, bail, miette};
 use reqwest::Url;
-use serde::Deserialize;
-use toml;
+use serde::{Deserialize, Serialize};
+use toml::{
+    self,
+    de::{DeTable, DeValue},
+};
 
 const CONFIG_DEFAULT: &str = "\
 # ==> For rust_mdex_dl
@@ -25,10 +47,27 @@ const CONFIG_DEFAULT: &str = "\
 
 [client]
 base_url = \"https://api.mangadex.org\"
-user_agent = \"hachispin/learning-projects\"
+# Sent with every request. Manga-Dex wants it to name the app and its version, and give a
+# way to reach you (a url or email). `{version}` is replaced with this program's version
+user_agent = \"rust_mdex_dl/{version} (+https://github.com/hachispin/learning-projects)\"
 max_retries = 3  # how many times to retry upon being ratelimited
 language = \"en\"     # * must be an ISO 639-1 code, which are two letters long
                     #   https://en.wikipedia.org/wiki/List_of_ISO_639_language_codes
+extra_languages = []    # other languages you read, e.g. [\"ja\", \"es\"]. searches include
+                        # manga translated into any of these, not just `language`
+max_response_mib = 16   # responses (JSON) larger than this are rejected instead of parsed
+# Which title manga are shown and saved with, the first one a manga has. Entries are
+# \"language\" (the one above), \"romanized\" (the original language in latin characters,
+# e.g. ja-ro), \"original\" or a language code (also with -ro, e.g. \"ko-ro\")
+title_preference = [\"language\", \"romanized\", \"en\", \"original\"]
+
+# The interactive search menu
+[search]
+statistics = true   # show each result's rating and follows (takes another request per page)
+cache_minutes = 10  # reuse results of the same search for this long, 0 disables the cache
+cache_on_disk = false   # also keep the cache on disk, so it's reused across runs
+rerank = true   # reorder results by how closely their titles (or alt titles) match
+page_size = 25  # results per page, up to 100 (can also be changed from the results menu)
 
 # This how many of these can be processed (or \"permitted\") at the same time.
 #
@@ -46,14 +85,178 @@ image_permits = 10      # * undocumented ratelimit but hard to hit unless done i
 chapter_permits = 3     # * max is 40 reqs per minute for this endpoint
                         #   scale this against your download speed accordingly
                         #   https://api.mangadex.org/docs/2-limitations/#endpoint-specific-rate-limits
+manga_permits = 2       # * how many manga are downloaded at once by `update` and `queue resume`.
+                        #   these share the permits above (and the ratelimit), so this
+                        #   mostly helps when downloading many manga with few new chapters
+adaptive = false        # * raise or lower image and chapter permits while downloading, depending on
+                        #   how often the cdn throttles (429) or errors (5xx) and how slow it gets.
+                        #   the permits above are where it starts, staying within these bounds
+min_image_permits = 2
+max_image_permits = 32
+min_chapter_permits = 1
+max_chapter_permits = 6
+package_permits = 2     # * how many chapters are packaged (see `[package]`) at once. packaging
+                        #   runs separately from downloading, so it doesn't hold up the next chapters
 
 [images]
 quality = \"lossless\"    # options: \"lossless\", \"lossy\"
 save_format = \"raw\"     # not implemented yet, does nothing for now
+verify = false          # decode every saved page and re-download any corrupt ones
+convert_to = \"none\"     # re-encode pages, options: \"none\", \"png\", \"jpeg\", \"webp\" (lossless)
+convert_quality = 85    # jpeg quality from 1 to 100, only used if `convert_to = \"jpeg\"`
+archive_compression = \"store\"  # how pages are compressed in CBZs and EPUBs (from `export`, `repackage`,
+                                # `[package]`...): \"store\" (not at all, since they're already
+                                # compressed images), \"fast\" or \"best\" (deflate, slower)
+spreads = \"keep\"        # what to do with wide (double-page) spreads, options:
+                        # \"keep\", \"split\" (into two pages), \"rotate\", \"tag\" (in the manifest)
+spread_order = \"rtl\"    # reading order when splitting or rotating, and of EPUBs:
+                        # \"rtl\" (manga) or \"ltr\"
+max_width = 0           # pages larger than these (in pixels) are downscaled to fit,
+max_height = 0          # keeping their aspect ratio. 0 means no limit
 
-[logging]
+# How manga dirs, chapter dirs and pages are named. Fields are written as `{field}`
+# and can be padded like in Rust, e.g. `{num:0>4}` pads the chapter number to 4 digits.
+#
+# * manga fields:    {title}, {uuid}, {year}
+# * chapter fields:  {num}, {volume}, {title}, {group}, {uploader}, {lang}, {uuid}, {uuid8}
+# * page fields:     {page}, along with every chapter field
+#
+# `{uuid8}` is the first 8 characters of the chapter's uuid, which prevents naming conflicts.
+# `{num}` is already zero-padded to the most digits of any chapter in the series (at least 3),
+# and `{page}` to the most digits of any page in the chapter, so that both sort correctly.
+#
+# Names longer than `max_length` characters (0 means no limit) are shortened, either by
+# cutting off the end (\"cut\") or by also appending a short uuid to keep them unique (\"uuid\").
+# Long paths break on Windows, so it's 100 there unless set, and no limit elsewhere. Keep it
+# well under 260 once your library dir is included.
+# Changing it also changes the names of existing manga and chapters, so set it before downloading.
+[naming]
+manga = \"{title}\"
+chapter = \"[{num:0>3}] {title} ({uuid8})\"
+page = \"{page}\"
+# max_length = 100
+ascii = false           # transliterate names to ASCII, e.g. \"Pokémon\" to \"Pokemon\"
+truncation = \"uuid\"
+
+# Scanlation groups, by name (case-insensitive) or uuid.
+[groups]
+preferred = []  # if a chapter has versions from several groups, only the one from the
+                # earliest group in this list is downloaded
+blocked = []    # chapters from these groups are never downloaded
+
+# Which chapters are downloaded, besides the `[groups]` options
+[chapters]
+attempt_unavailable = false # chapters marked as unavailable (e.g. after a copyright takedown)
+                            # are skipped by default, since their pages usually can't be fetched
+suggest_languages = false   # when chapters are missing in `language`, fetch the chapters in
+                            # every language to suggest ones which have them (slower)
+preview = true              # before choosing chapters, show a summary of each volume (chapters,
+                            # languages, groups) and ask whether to carry on
+related = false             # before choosing chapters, list related works (sequels, prequels,
+                            # spin-offs...) and offer to queue them for download
+
+# How chosen chapters are downloaded
+[download]
+order = \"feed\"  # the order chapters are downloaded in: \"feed\" (as MangaDex lists them, oldest
+                # upload first), \"ascending\" (by chapter number), \"descending\" (newest first)
+                # or \"volume\" (by volume, then chapter number)
+latest = 0      # only download the newest this many chapters (also `--latest N`), 0 for all
+
+# Packaging each chapter as soon as it's downloaded, like `repackage` does for the whole
+# library. The raw chapters are still saved to the library, and packages go into `dest`
+# (in a dir per manga). Packaging is done by its own workers (`concurrency.package_permits`)
+[package]
+# format = \"cbz\"              # options: \"raw\", \"cbz\", \"epub\", \"pdf\" (unset packages nothing)
+# dest = \"/path/to/packages\"  # needed along with `format`
+
+# Where files are stored. By default, manga is saved in your platform's data dir (see the README)
+[storage]
+# library_dir = \"/path/to/manga\"    # where downloaded manga is saved
+min_free_mib = 1024         # how much space to leave free after downloading
+on_low_space = \"abort\"    # if a download (estimated from its page count) would leave less
+                            # than that free: \"abort\", \"warn\" or \"off\" (don't check).
+                            # this is checked before starting, and again before each chapter
+external_shortcuts = true   # write a `.url` shortcut for chapters hosted outside of MangaDex
+chapter_json = false        # write a `chapter.json` with each chapter's details into its dir
+dedupe = false              # store identical pages (e.g. credit pages) once, hardlinking the rest.
+                            # pages are kept by hash in `.pages` in the library, which must be
+                            # on a file system with hardlinks (otherwise pages are stored as usual)
+
+# Before downloading a batch of chapters, MangaDex is pinged to make sure it's up, failing
+# right away with an explanation (instead of every chapter failing one by one) if it isn't
+[preflight]
 enabled = true
-filter = \"DEBUG\"  # options: \"TRACE\", \"DEBUG\", \"INFO\", \"WARN\", \"ERROR\"
+min_chapters = 10   # only check batches of at least this many chapters (across every manga)
+# A Statuspage-style `status.json` to check too, e.g. for outages of MangaDex@Home (the image
+# servers). A major outage fails the check, while a minor one only warns
+# status_url = \"https://status.mangadex.org/api/v2/status.json\"
+
+# Logging in to MangaDex, which is only needed for `[tracking]`. Create a personal API client
+# in MangaDex's settings (under \"API Clients\") for the client id and secret. Secrets are
+# better set with `MDEX_DL_AUTH_PASSWORD` and `MDEX_DL_AUTH_CLIENT_SECRET` than written here
+[auth]
+# username = \"...\"
+# password = \"...\"
+# client_id = \"personal-client-...\"
+# client_secret = \"...\"
+
+# Setting each manga's reading status in your MangaDex library after downloading it.
+# Statuses: \"reading\", \"on_hold\", \"plan_to_read\", \"dropped\", \"re_reading\",
+# \"completed\", or \"none\" to leave it as is. Statuses set to anything else by hand are kept
+[tracking]
+sync_status = false         # needs `[auth]`
+downloaded = \"reading\"      # after downloading chapters of a manga
+completed = \"completed\"     # after downloading the last chapter of a finished manga
+
+# Notifications are sent when a chapter, manga or update (from `update` or `watch`)
+# finishes or fails. `command` is run through the shell with `MDEX_NOTIFY_EVENT`,
+# `MDEX_NOTIFY_STATUS`, `MDEX_NOTIFY_TITLE`, `MDEX_NOTIFY_CHAPTERS`, `MDEX_NOTIFY_SIZE`
+# (in bytes), `MDEX_NOTIFY_ERROR` and `MDEX_NOTIFY_MESSAGE` set, and `webhook_url`
+# receives the same as JSON (with `content` and `text` set, for Discord and Slack)
+[notifications]
+# command = \"notify-send rust_mdex_dl \\\"$MDEX_NOTIFY_MESSAGE\\\"\"
+# webhook_url = \"https://discord.com/api/webhooks/...\"
+events = [\"manga\", \"update\"]    # options: \"chapter\", \"manga\", \"update\"
+
+# The overall progress bar shown while downloading. Besides indicatif's keys
+# (https://docs.rs/indicatif/latest/indicatif/#templates), this can use `{mib}` (MiB saved),
+# `{speed}` (current MiB/s) and `{avg_speed}` (average MiB/s)
+[progress]
+template = \"{spinner:.green} [{elapsed_precise}] [{bar:40.green/white}] {pos}/{len} chapters, {mib} MiB at {speed} MiB/s (avg {avg_speed} MiB/s), eta {eta}\"
+
+# The `export` command, which copies the library into a layout other readers can open
+[export]
+library_layout = \"mihon\"    # options: \"mihon\" (Mihon's local source), \"komga\" (Komga/Kavita,
+                            # with `Series Name - Vol.X Ch.Y.cbz` files and ComicInfo.xml)
+
+# Limits on how much can go wrong in a run (including all of `watch`) before giving up, so that
+# an outage doesn't keep retrying for hours. Both count API requests and image downloads
+[budget]
+max_retries = 500           # retries (after ratelimits, refused or corrupt images...), 0 for no limit
+max_failure_percent = 50    # abort once more than this % of requests failed, 0 for no limit
+min_requests = 50           # ...but only once at least this many were sent
+
+# Stats about the run (API calls, retries, ratelimits, images and chapter durations)
+[metrics]
+summary = true    # print them when the run finishes
+# json_path = \"/path/to/metrics.json\"    # also write them here as JSON (after every update in `watch`)
+
+[logging]
+enabled = true                # write a log file for every run
+filter = \"DEBUG\"              # options: \"TRACE\", \"DEBUG\", \"INFO\", \"WARN\", \"ERROR\", or per-module
+                              # directives like \"info,rust_mdex_dl::api=trace\" (`RUST_LOG` overrides this)
+console_filter = \"WARN\"       # what's also printed to stderr (`--verbose` raises it to
+                              # \"DEBUG\", unless it's already \"TRACE\")
+
+# Profiles override any of the options above, and are chosen with `--profile NAME`
+# (or from a prompt when starting, if any exist). For example:
+#
+# [profile.phone.images]
+# quality = \"lossy\"
+# max_height = 1600
+#
+# [profile.phone.storage]
+# library_dir = \"/path/to/phone/manga\"
 ";
 
 #[derive(Debug, Clone, Deserialize)]
@@ -63,13 +266,86 @@ pub enum SaveFormat {
     ComicBookZip,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConvertFormat {
+    #[default]
+    None,
+    Png,
+    Jpeg,
+    Webp,
+}
+
+/// How pages are compressed in CBZs, see [`archive_options`](`crate::export::archive_options`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveCompression {
+    /// Not compressed at all, since pages are already compressed images.
+    #[default]
+    Store,
+    /// Deflated at the fastest level.
+    Fast,
+    /// Deflated at the smallest level, which is much slower for little gain.
+    Best,
+}
+
+/// The order chapters are downloaded in, see [`crate::order`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadOrder {
+    /// As Manga-Dex lists them, i.e. the oldest upload first.
+    #[default]
+    Feed,
+    /// By chapter number, lowest first.
+    Ascending,
+    /// By chapter number, highest (usually the newest) first.
+    Descending,
+    /// By volume, then by chapter number within each volume.
+    Volume,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpreadMode {
+    #[default]
+    Keep,
+    Split,
+    Rotate,
+    Tag,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpreadOrder {
+    #[default]
+    Rtl,
+    Ltr,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum ImageQuality {
     Lossless,
     Lossy,
 }
 
+impl ImageQuality {
+    /// Returns the opposite quality, used as a fallback when a chapter has no images in this one.
+    #[must_use]
+    pub const fn other(&self) -> Self {
+        match self {
+            Self::Lossless => Self::Lossy,
+            Self::Lossy => Self::Lossless,
+        }
+    }
+
+    /// Returns true if this is a lower quality than `other`, see [`crate::upgrade`].
+    #[must_use]
+    pub const fn is_lower_than(&self, other: &Self) -> bool {
+        matches!((self, other), (Self::Lossy, Self::Lossless))
+    }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct Client {
     pub base_url: Url,
@@ -77,6 +353,48 @@ pub struct Client {
     pub max_retries: u32,
     #[serde(deserialize_with = "deserialize_langcode")]
     pub language: Language,
+    #[serde(default, deserialize_with = "deserialize_langcode_vec")]
+    pub extra_languages: Vec<Language>,
+    #[serde(default = "default_max_response_mib")]
+    pub max_response_mib: usize,
+    /// The order titles are looked for in, see [`Manga::title`](`crate::api::models::Manga::title`).
+    #[serde(default = "default_title_preference")]
+    pub title_preference: Vec<TitlePreference>,
+}
+
+fn default_title_preference() -> Vec<TitlePreference> {
+    DEFAULT_TITLE_PREFERENCE.to_vec()
+}
+
+/// The default for `client.max_response_mib`.
+#[must_use]
+pub const fn default_max_response_mib() -> usize {
+    16
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Search {
+    /// Whether to fetch the ratings and follows of search results.
+    pub statistics: bool,
+    /// How long search results are cached for, see [`crate::api::cache::SearchCache`].
+    pub cache_minutes: u64,
+    pub cache_on_disk: bool,
+    /// Whether to rerank search results, see [`crate::api::rerank`].
+    pub rerank: bool,
+    pub page_size: u32,
+}
+
+impl Default for Search {
+    fn default() -> Self {
+        Self {
+            statistics: true,
+            cache_minutes: 10,
+            cache_on_disk: false,
+            rerank: true,
+            page_size: 25,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -84,30 +402,663 @@ pub struct Concurrency {
     // semaphores take `usize`, so don't use `u32` here
     pub image_permits: usize,
     pub chapter_permits: usize,
+    #[serde(default = "default_manga_permits")]
+    pub manga_permits: usize,
+    /// Whether image and chapter permits adapt within the bounds below,
+    /// see [`AdaptiveLimit`](`crate::api::adaptive::AdaptiveLimit`).
+    #[serde(default)]
+    pub adaptive: bool,
+    #[serde(default = "default_min_image_permits")]
+    pub min_image_permits: usize,
+    #[serde(default = "default_max_image_permits")]
+    pub max_image_permits: usize,
+    #[serde(default = "default_min_chapter_permits")]
+    pub min_chapter_permits: usize,
+    #[serde(default = "default_max_chapter_permits")]
+    pub max_chapter_permits: usize,
+    #[serde(default = "default_package_permits")]
+    pub package_permits: usize,
+}
+
+/// The default for `concurrency.manga_permits`.
+#[must_use]
+pub const fn default_manga_permits() -> usize {
+    2
+}
+
+/// The default for `concurrency.package_permits`.
+#[must_use]
+pub const fn default_package_permits() -> usize {
+    2
+}
+
+/// The default for `concurrency.min_image_permits`.
+#[must_use]
+pub const fn default_min_image_permits() -> usize {
+    2
+}
+
+/// The default for `concurrency.max_image_permits`.
+#[must_use]
+pub const fn default_max_image_permits() -> usize {
+    32
+}
+
+/// The default for `concurrency.min_chapter_permits`.
+#[must_use]
+pub const fn default_min_chapter_permits() -> usize {
+    1
+}
+
+/// The default for `concurrency.max_chapter_permits`.
+#[must_use]
+pub const fn default_max_chapter_permits() -> usize {
+    6
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Images {
     pub quality: ImageQuality,
     pub save_format: SaveFormat,
+    #[serde(default)]
+    pub verify: bool,
+    #[serde(default)]
+    pub convert_to: ConvertFormat,
+    #[serde(default = "default_convert_quality")]
+    pub convert_quality: u8,
+    #[serde(default)]
+    pub archive_compression: ArchiveCompression,
+    #[serde(default)]
+    pub spreads: SpreadMode,
+    #[serde(default)]
+    pub spread_order: SpreadOrder,
+    #[serde(default)]
+    pub max_width: u32,
+    #[serde(default)]
+    pub max_height: u32,
+}
+
+const fn default_convert_quality() -> u8 {
+    85
+}
+
+/// Naming templates, see [`crate::naming`].
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Naming {
+    pub manga: Template,
+    pub chapter: Template,
+    pub page: Template,
+    /// How rendered names are shortened and made safe, see [`crate::path_policy`].
+    #[serde(flatten)]
+    pub policy: PathPolicy,
+}
+
+impl Default for Naming {
+    fn default() -> Self {
+        let parse = |raw| Template::parse(raw).expect("default naming template should be valid");
+
+        Self {
+            manga: parse("{title}"),
+            chapter: parse("[{num:0>3}] {title} ({uuid8})"),
+            page: parse("{page}"),
+            policy: PathPolicy::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Groups {
+    /// Groups whose chapter versions are preferred, most preferred first.
+    pub preferred: Vec<String>,
+    /// Groups whose chapters are never downloaded.
+    pub blocked: Vec<String>,
+}
+
+// these are independent toggles, so a state machine wouldn't make sense
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Chapters {
+    /// Whether to try downloading chapters marked as unavailable, see
+    /// [`crate::api::models::Chapter::is_unavailable`].
+    pub attempt_unavailable: bool,
+    /// Whether to look up which languages have chapters that `client.language` is missing,
+    /// see [`crate::api::gaps::report_gaps`].
+    pub suggest_languages: bool,
+    /// Whether to preview the chapters before choosing them, see
+    /// [`crate::selection::preview_chapters`].
+    pub preview: bool,
+    /// Whether to list related works before choosing chapters, see
+    /// [`crate::relations::related_menu`].
+    pub related: bool,
+}
+
+impl Default for Chapters {
+    fn default() -> Self {
+        Self {
+            attempt_unavailable: false,
+            suggest_languages: false,
+            preview: true,
+            related: false,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Download {
+    pub order: DownloadOrder,
+    /// Only the newest this many chapters are downloaded, or every one if `0`,
+    /// see [`latest_chapters`](`crate::order::latest_chapters`).
+    pub latest: usize,
+}
+
+/// Packaging chapters as they're downloaded, see [`package_chapter`](`crate::repackage::package_chapter`).
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Package {
+    /// The format chapters are packaged as, or `None` to not package them.
+    pub format: Option<PackageFormat>,
+    /// Where packages are written, which is needed if `format` is set.
+    pub dest: Option<PathBuf>,
+}
+
+/// What to do when a download would leave less than `storage.min_free_mib` free.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LowSpaceAction {
+    /// Stop before downloading (any more chapters).
+    #[default]
+    Abort,
+    /// Log a warning and carry on.
+    Warn,
+    /// Don't check the free space at all.
+    Off,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Storage {
+    /// Overrides [`manga_save_dir()`](`crate::paths::manga_save_dir()`) if set.
+    pub library_dir: Option<PathBuf>,
+    /// The space (in MiB) to leave free after downloading, see [`crate::disk`].
+    pub min_free_mib: u64,
+    pub on_low_space: LowSpaceAction,
+    /// Whether to write `.url` shortcuts for chapters hosted outside of Manga-Dex.
+    pub external_shortcuts: bool,
+    /// Whether to write [`ChapterMetadata`](`crate::metadata::ChapterMetadata`) into chapter dirs.
+    pub chapter_json: bool,
+    /// Whether identical pages are hardlinked from the [page store](`crate::store`).
+    pub dedupe: bool,
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        Self {
+            library_dir: None,
+            min_free_mib: 1024,
+            on_low_space: LowSpaceAction::default(),
+            external_shortcuts: true,
+            chapter_json: false,
+            dedupe: false,
+        }
+    }
+}
+
+/// What [notifications](`crate::notify`) are sent for.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyEvent {
+    /// A single chapter was downloaded.
+    Chapter,
+    /// Every chosen chapter of a manga was downloaded.
+    Manga,
+    /// An `update` (or one run of `watch`) finished.
+    Update,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Notifications {
+    /// Run through the shell, see the default config for the environment variables set.
+    pub command: Option<String>,
+    pub webhook_url: Option<Url>,
+    pub events: Vec<NotifyEvent>,
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Self {
+            command: None,
+            webhook_url: None,
+            events: vec![NotifyEvent::Manga, NotifyEvent::Update],
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Preflight {
+    /// Whether to check that Manga-Dex is up before downloading, see [`crate::api::preflight`].
+    pub enabled: bool,
+    /// The fewest chapters a download needs for it to be checked.
+    pub min_chapters: usize,
+    /// A Statuspage-style `status.json` to check as well, if set.
+    pub status_url: Option<Url>,
+}
+
+impl Default for Preflight {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_chapters: 10,
+            status_url: None,
+        }
+    }
+}
+
+/// The credentials of a personal API client, see [`crate::api::auth`].
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Auth {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+/// A reading status to set in `[tracking]`, or `None` (written as `"none"`) to leave it.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct StatusChoice(pub Option<ReadingStatus>);
+
+impl TryFrom<String> for StatusChoice {
+    type Error = String;
+
+    fn try_from(raw: String) -> std::result::Result<Self, Self::Error> {
+        if raw == "none" {
+            return Ok(Self(None));
+        }
+
+        ReadingStatus::ALL
+            .into_iter()
+            .find(|s| s.as_str() == raw)
+            .map(|s| Self(Some(s)))
+            .ok_or_else(|| {
+                format!(
+                    "invalid reading status {raw:?}, expected \"reading\", \"on_hold\", \"plan_to_read\", \"dropped\", \"re_reading\", \"completed\" or \"none\""
+                )
+            })
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Tracking {
+    /// Whether to set reading statuses after downloading, see [`crate::tracking`].
+    pub sync_status: bool,
+    pub downloaded: StatusChoice,
+    pub completed: StatusChoice,
+}
+
+impl Default for Tracking {
+    fn default() -> Self {
+        Self {
+            sync_status: false,
+            downloaded: StatusChoice(Some(ReadingStatus::Reading)),
+            completed: StatusChoice(Some(ReadingStatus::Completed)),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Progress {
+    /// The template of the overall progress bar, see [`crate::progress`].
+    pub template: String,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self {
+            template: DEFAULT_TEMPLATE.to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Export {
+    /// The layout used by the `export` command if `--layout` isn't given.
+    pub library_layout: ExportLayout,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Budget {
+    /// The retries allowed in a run, or `0` for no limit, see [`crate::budget`].
+    pub max_retries: u64,
+    /// The share of requests (in percent) allowed to fail, or `0` for no limit.
+    pub max_failure_percent: u64,
+    /// How many requests are sent before `max_failure_percent` is checked.
+    pub min_requests: u64,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Self {
+            max_retries: 500,
+            max_failure_percent: 50,
+            min_requests: 50,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Metrics {
+    /// Whether to print stats when the run finishes, see [`crate::metrics`].
+    pub summary: bool,
+    /// Where to write the stats as JSON, if anywhere.
+    pub json_path: Option<PathBuf>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            summary: true,
+            json_path: None,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Logging {
     pub enabled: bool,
+    /// What's written to the log file, as a level or per-module directives.
     #[serde(deserialize_with = "deserialize_logging_filter")]
-    pub filter: log::LevelFilter,
+    pub filter: String,
+    /// What's printed to stderr, independent of [`Logging::filter`].
+    #[serde(
+        default = "default_console_filter",
+        deserialize_with = "deserialize_logging_filter"
+    )]
+    pub console_filter: String,
+}
+
+fn default_console_filter() -> String {
+    "WARN".to_string()
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Config {
     pub client: Client,
+    #[serde(default)]
+    pub search: Search,
     pub concurrency: Concurrency,
     pub images: Images,
+    #[serde(default)]
+    pub naming: Naming,
+    #[serde(default)]
+    pub groups: Groups,
+    #[serde(default)]
+    pub chapters: Chapters,
+    #[serde(default)]
+    pub download: Download,
+    #[serde(default)]
+    pub package: Package,
+    #[serde(default)]
+    pub storage: Storage,
+    #[serde(default)]
+    pub preflight: Preflight,
+    #[serde(default)]
+    pub auth: Auth,
+    #[serde(default)]
+    pub tracking: Tracking,
+    #[serde(default)]
+    pub notifications: Notifications,
+    #[serde(default)]
+    pub progress: Progress,
+    #[serde(default)]
+    pub export: Export,
+    #[serde(default)]
+    pub budget: Budget,
+    #[serde(default)]
+    pub metrics: Metrics,
     pub logging: Logging,
 }
 
-/// Loads the config stored in [`config_toml()`](`crate::paths::config_toml()`)
+/// Overrides a single config option, taking priority over the config file.
+///
+/// Overrides come from `MDEX_DL_*` environment variables (see [`Self::from_env`]),
+/// then from CLI flags, so that CLI flags win if both are set.
+#[derive(Debug, Clone)]
+pub struct ConfigOverride {
+    /// The dotted key of the option, e.g. `client.language`.
+    pub key: String,
+    /// The value, parsed as TOML if possible and as a string otherwise
+    /// (except for `auth` options, which are always strings).
+    pub value: String,
+    /// Where the override came from, e.g. `MDEX_DL_CLIENT_LANGUAGE` or `--language`.
+    pub source: String,
+}
+
+impl ConfigOverride {
+    /// The prefix of environment variables which override options.
+    pub const ENV_PREFIX: &str = "MDEX_DL_";
+
+    /// Constructs a new [`ConfigOverride`].
+    pub fn new(
+        key: impl Into<String>,
+        value: impl Into<String>,
+        source: impl Into<String>,
+    ) -> Self {
+        Self {
+            key: key.into(),
+            value: value.into(),
+            source: source.into(),
+        }
+    }
+
+    /// Collects overrides from environment variables, which are named as
+    /// `MDEX_DL_{SECTION}_{OPTION}`, e.g. `MDEX_DL_CONCURRENCY_IMAGE_PERMITS=4`.
+    #[must_use]
+    pub fn from_env() -> Vec<Self> {
+        let mut overrides: Vec<Self> = std::env::vars()
+            .filter_map(|(name, value)| {
+                let rest = name.strip_prefix(Self::ENV_PREFIX)?.to_lowercase();
+                let (section, option) = rest.split_once('_')?;
+
+                Some(Self::new(format!("{section}.{option}"), value, name))
+            })
+            .collect();
+
+        // `std::env::vars()` has no particular order
+        overrides.sort_by(|a, b| a.source.cmp(&b.source));
+        overrides
+    }
+
+    /// Returns true if `section.option` is set (or commented out) in the default config.
+    fn is_known_option(section: &str, option: &str) -> bool {
+        let mut current_section = "";
+
+        for line in CONFIG_DEFAULT.lines() {
+            // headers may be commented out too, e.g. in the profile example
+            let line = line.trim_start_matches('#').trim_start();
+
+            if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                current_section = header;
+                continue;
+            }
+
+            if current_section == section
+                && line
+                    .strip_prefix(option)
+                    .is_some_and(|rest| rest.trim_start().starts_with('='))
+            {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Sets this override's option in `table`, which is the parsed config file.
+    ///
+    /// ## Errors
+    ///
+    /// If the key isn't `section.option`, or the option doesn't exist.
+    fn apply(&self, table: &mut toml::Table) -> Result<()> {
+        let Some((section, option)) = self.key.split_once('.') else {
+            bail!(
+                "Expected a key like `section.option` from {}, got {:?}",
+                self.source,
+                self.key
+            );
+        };
+
+        if !Self::is_known_option(section, option) {
+            bail!(
+                "Unknown config option `{}` from {}, check the default config for valid options",
+                self.key,
+                self.source
+            );
+        }
+
+        // e.g. "4" or "true" or "[\"ja\"]", but bare strings such as "ja" are fine too.
+        // credentials are always strings, even if they look like numbers (or TOML)
+        let value = if section == "auth" {
+            toml::Value::String(self.value.clone())
+        } else {
+            toml::from_str::<toml::Table>(&format!("v = {}", self.value))
+                .ok()
+                .and_then(|mut t| t.remove("v"))
+                .unwrap_or_else(|| toml::Value::String(self.value.clone()))
+        };
+
+        let section = table
+            .entry(section)
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+
+        let Some(section) = section.as_table_mut() else {
+            bail!("Expected `{}` to be a table in the config", self.key);
+        };
+
+        section.insert(option.to_string(), value);
+        Ok(())
+    }
+}
+
+/// Returns the names of the profiles in `raw_cfg`, sorted.
+///
+/// ## Errors
+///
+/// If `raw_cfg` isn't valid TOML.
+pub fn profile_names(raw_cfg: &str) -> Result<Vec<String>> {
+    let table: toml::Table = toml::from_str(raw_cfg).into_diagnostic()?;
+
+    let mut names: Vec<String> = table
+        .get("profile")
+        .and_then(toml::Value::as_table)
+        .map(|profiles| profiles.keys().cloned().collect())
+        .unwrap_or_default();
+
+    names.sort();
+    Ok(names)
+}
+
+/// Returns the options set by the profile `name` in `raw_cfg` as overrides.
+///
+/// ## Errors
+///
+/// If the profile doesn't exist, or isn't made of `[profile.name.section]` tables.
+fn profile_overrides(raw_cfg: &str, name: &str) -> Result<Vec<ConfigOverride>> {
+    let table: toml::Table = toml::from_str(raw_cfg).into_diagnostic()?;
+    let source = format!("profile `{name}`");
+
+    let Some(profile) = table
+        .get("profile")
+        .and_then(|p| p.get(name))
+        .and_then(toml::Value::as_table)
+    else {
+        let names = profile_names(raw_cfg)?;
+
+        if names.is_empty() {
+            bail!(
+                help = "see the end of the default config for how to add profiles",
+                "Profile `{name}` doesn't exist, no profiles are defined"
+            );
+        }
+
+        bail!(
+            "Profile `{name}` doesn't exist, expected one of: {}",
+            names.join(", ")
+        );
+    };
+
+    let mut overrides = Vec::new();
+
+    for (section, options) in profile {
+        let Some(options) = options.as_table() else {
+            bail!(
+                "Expected `profile.{name}.{section}` to be a table, e.g. [profile.{name}.{section}]"
+            );
+        };
+
+        for (option, value) in options {
+            overrides.push(ConfigOverride::new(
+                format!("{section}.{option}"),
+                value.to_string(),
+                source.clone(),
+            ));
+        }
+    }
+
+    Ok(overrides)
+}
+
+/// Returns the names of the profiles in the [config](`crate::paths::config_toml`),
+/// or nothing if it doesn't exist.
+///
+/// ## Errors
+///
+/// If the config exists but can't be read or isn't valid TOML.
+pub fn available_profiles() -> Result<Vec<String>> {
+    let path = config_toml()?;
+
+    if !path.try_exists().into_diagnostic()? {
+        return Ok(Vec::new());
+    }
+
+    profile_names(&fs::read_to_string(path).into_diagnostic()?)
+}
+
+impl FromStr for ConfigOverride {
+    type Err = String;
+
+    /// Parses `key=value`, as used by `--set`.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected `key=value`, got {s:?}"))?;
+
+        Ok(Self::new(key.trim(), value.trim(), format!("--set {s}")))
+    }
+}
+
+impl fmt::Display for ConfigOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={} (from {})", self.key, self.value, self.source)
+    }
+}
+
+/// Loads the config stored in [`config_toml()`](`crate::paths::config_toml()`), then applies
+/// the options of `profile` (if given), [environment variables](`ConfigOverride::from_env`)
+/// and `cli_overrides`, in that order.
+///
+/// If the config doesn't exist yet (e.g. on the first run), a default one is created.
 ///
 /// This also creates any dirs stored in [`crate::paths`] such as [`manga_save_dir()`](`crate::paths::manga_save_dir()`)
 ///
@@ -115,36 +1066,499 @@ pub struct Config {
 ///
 /// If some options fail extra validation,
 /// such as `image_permits` being zero.
-pub fn load_config() -> Result<Config> {
+pub fn load_config(profile: Option<&str>, cli_overrides: &[ConfigOverride]) -> Result<Config> {
     let path = config_toml()?;
 
     if !path.try_exists().into_diagnostic()? {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).into_diagnostic()?;
+        }
+
         fs::write(&path, CONFIG_DEFAULT).map_err(|e| {
             miette!(
                 "failed to write (default config) to {}: {e}",
                 path.display()
             )
         })?;
+
+        print_status(format!("Created a default config at {}", path.display()));
     }
 
-    let raw_cfg = fs::read_to_string(path).into_diagnostic()?;
-    let cfg: Config = toml::de::from_str(&raw_cfg).into_diagnostic()?;
+    let raw_cfg = fs::read_to_string(&path).into_diagnostic()?;
+    let mut overrides = match profile {
+        Some(name) => profile_overrides(&raw_cfg, name)?,
+        None => Vec::new(),
+    };
 
-    let non_zero_options: [(&str, usize); 3] = [
-        ("max_retries", cfg.client.max_retries as usize),
-        ("image_permits", cfg.concurrency.image_permits),
-        ("chapter_permits", cfg.concurrency.chapter_permits),
-    ];
+    overrides.extend(ConfigOverride::from_env());
+    overrides.extend(cli_overrides.iter().cloned());
 
-    for (option, value) in non_zero_options {
-        if value == 0 {
-            bail!("Expected option `{option}` to be non-zero, got {option}={value}");
-        }
+    let cfg = parse_config(&raw_cfg, &path.display().to_string(), &overrides)?;
+
+    if let Some(library_dir) = &cfg.storage.library_dir {
+        set_library_dir(library_dir.clone());
     }
 
+    set_title_preference(cfg.client.title_preference.clone());
+    set_budget(cfg.budget.clone());
+
     for p in [manga_save_dir(), log_save_dir()] {
         fs::create_dir_all(p?).into_diagnostic()?;
     }
 
     Ok(cfg)
 }
+
+/// Parses `raw_cfg` (the contents of the config at `name`), applies `overrides`
+/// and validates options that can't be checked by [`serde`] alone.
+///
+/// Problems with the file itself are reported as a [`ConfigError`] pointing at the
+/// offending option, while problems with an override mention where it came from.
+///
+/// ## Errors
+///
+/// If `raw_cfg` isn't a valid config, or some options fail extra validation.
+pub fn parse_config(raw_cfg: &str, name: &str, overrides: &[ConfigOverride]) -> Result<Config> {
+    let deserializer = toml::Deserializer::parse(raw_cfg)
+        .map_err(|e| ConfigError::from_toml(name, raw_cfg, ".", &e))?;
+    let mut cfg: Config = serde_path_to_error::deserialize(deserializer)
+        .map_err(|e| ConfigError::from_toml(name, raw_cfg, &e.path().to_string(), e.inner()))?;
+
+    if !overrides.is_empty() {
+        let mut table: toml::Table = toml::from_str(raw_cfg).into_diagnostic()?;
+
+        for o in overrides {
+            o.apply(&mut table)?;
+        }
+
+        cfg = serde_path_to_error::deserialize(toml::Value::Table(table)).map_err(|e| {
+            let sources: Vec<String> = overrides.iter().map(ToString::to_string).collect();
+            miette!(
+                "invalid config after applying overrides at `{}`: {}\noverrides: {}",
+                e.path(),
+                e.inner().message().trim_end(),
+                sources.join(", ")
+            )
+        })?;
+    }
+
+    cfg.client.user_agent = expand_user_agent(&cfg.client.user_agent);
+
+    let Err(invalid) = validate_config(&cfg) else {
+        return Ok(cfg);
+    };
+
+    if let Some(o) = overrides.iter().rev().find(|o| o.key == invalid.key) {
+        bail!("{} (from {})", invalid.error, o.source);
+    }
+
+    match value_span(raw_cfg, &invalid.key) {
+        Some(span) => {
+            Err(
+                ConfigError::invalid_option(name, raw_cfg, span, invalid.error, invalid.help)
+                    .into(),
+            )
+        }
+        None => Err(miette!(help = invalid.help, "{}", invalid.error)),
+    }
+}
+
+/// An option that failed validation in [`validate_config`].
+struct InvalidOption {
+    /// The dotted key of the option, e.g. `concurrency.image_permits`.
+    key: String,
+    error: String,
+    help: String,
+}
+
+impl InvalidOption {
+    fn new(key: &str, error: impl Into<String>, help: impl Into<String>) -> Self {
+        Self {
+            key: key.to_string(),
+            error: error.into(),
+            help: help.into(),
+        }
+    }
+}
+
+/// Validates that every `[auth]` option is set, which is needed for `tracking.sync_status`.
+fn validate_auth(auth: &Auth) -> std::result::Result<(), InvalidOption> {
+    Credentials::from_config(auth).map(|_| ()).map_err(|key| {
+        InvalidOption::new(
+            key,
+            format!("Expected option `{key}` to be set, since `tracking.sync_status` is enabled"),
+            "create a personal API client in Manga-Dex's settings, see the `[auth]` section",
+        )
+    })
+}
+
+/// Validates the bounds of adaptive permits, which must contain the initial permits.
+fn validate_adaptive(concurrency: &Concurrency) -> std::result::Result<(), InvalidOption> {
+    let bounds = [
+        (
+            "image",
+            concurrency.image_permits,
+            concurrency.min_image_permits,
+            concurrency.max_image_permits,
+        ),
+        (
+            "chapter",
+            concurrency.chapter_permits,
+            concurrency.min_chapter_permits,
+            concurrency.max_chapter_permits,
+        ),
+    ];
+
+    for (name, permits, min, max) in bounds {
+        if min == 0 {
+            let key = format!("concurrency.min_{name}_permits");
+            return Err(InvalidOption::new(
+                &key,
+                format!("Expected option `{key}` to be non-zero, got {min}"),
+                "set this to at least 1",
+            ));
+        }
+
+        if !(min..=max).contains(&permits) {
+            let key = format!("concurrency.{name}_permits");
+            return Err(InvalidOption::new(
+                &key,
+                format!("Expected option `{key}` to be from {min} to {max}, got {permits}"),
+                format!(
+                    "with `concurrency.adaptive` enabled, this must be within \
+                     `min_{name}_permits` and `max_{name}_permits`"
+                ),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// The user agent of older default configs, which didn't name a version or contact.
+const LEGACY_USER_AGENT: &str = "hachispin/learning-projects";
+
+/// Returns `user_agent` with `{version}` replaced by the crate's version.
+///
+/// The [legacy](`LEGACY_USER_AGENT`) default is swapped for the current
+/// [default](`DEFAULT_USER_AGENT`), so that older configs keep working.
+fn expand_user_agent(user_agent: &str) -> String {
+    if user_agent.trim() == LEGACY_USER_AGENT {
+        debug!("Replacing the old default user agent with {DEFAULT_USER_AGENT:?}");
+        return DEFAULT_USER_AGENT.to_string();
+    }
+
+    user_agent.replace("{version}", env!("CARGO_PKG_VERSION"))
+}
+
+/// Validates that `client.user_agent` (after [expanding](`expand_user_agent`) it) identifies
+/// this program, since Manga-Dex may block generic or browser-like user agents.
+///
+/// It must start with a `name/version` product token and include a contact,
+/// which is a url or an email address.
+fn validate_user_agent(user_agent: &str) -> std::result::Result<(), InvalidOption> {
+    let key = "client.user_agent";
+    let help = format!("e.g. \"{DEFAULT_USER_AGENT}\"");
+    let user_agent = user_agent.trim();
+
+    if user_agent.is_empty() {
+        return Err(InvalidOption::new(
+            key,
+            format!("Expected option `{key}` to be set"),
+            help,
+        ));
+    }
+
+    if user_agent.starts_with("Mozilla/") {
+        return Err(InvalidOption::new(
+            key,
+            format!("Expected option `{key}` to identify this program, not a browser"),
+            help,
+        ));
+    }
+
+    let product = user_agent.split_whitespace().next().unwrap_or_default();
+    let is_product = product
+        .split_once('/')
+        .is_some_and(|(name, version)| !name.is_empty() && !version.is_empty());
+
+    if !is_product {
+        return Err(InvalidOption::new(
+            key,
+            format!("Expected option `{key}` to start with `name/version`, got {product:?}"),
+            format!("use `{{version}}` for this program's version, {help}"),
+        ));
+    }
+
+    let has_contact = user_agent.split_whitespace().skip(1).any(|part| {
+        let part = part.trim_matches(|c: char| "()<>+;,".contains(c));
+        part.starts_with("http://") || part.starts_with("https://") || part.contains('@')
+    });
+
+    if !has_contact {
+        return Err(InvalidOption::new(
+            key,
+            format!("Expected option `{key}` to include a contact (a url or email)"),
+            help,
+        ));
+    }
+
+    Ok(())
+}
+
+/// Validates that the urls in `cfg` (which [`Url`] alone allows any scheme for) are http(s).
+fn validate_urls(cfg: &Config) -> std::result::Result<(), InvalidOption> {
+    let urls = [
+        (
+            "notifications.webhook_url",
+            cfg.notifications.webhook_url.as_ref(),
+            "https://discord.com/api/webhooks/...",
+        ),
+        (
+            "preflight.status_url",
+            cfg.preflight.status_url.as_ref(),
+            "https://status.mangadex.org/api/v2/status.json",
+        ),
+    ];
+
+    for (key, url, example) in urls {
+        if let Some(url) = url
+            && !matches!(url.scheme(), "http" | "https")
+        {
+            return Err(InvalidOption::new(
+                key,
+                format!("Expected option `{key}` to be an http(s) url, got {url}"),
+                format!("e.g. \"{example}\""),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Validates that options which can't be zero (e.g. permits) aren't.
+fn validate_non_zero(cfg: &Config) -> std::result::Result<(), InvalidOption> {
+    let non_zero_options: [(&str, usize); 6] = [
+        ("client.max_retries", cfg.client.max_retries as usize),
+        ("client.max_response_mib", cfg.client.max_response_mib),
+        ("concurrency.image_permits", cfg.concurrency.image_permits),
+        (
+            "concurrency.chapter_permits",
+            cfg.concurrency.chapter_permits,
+        ),
+        ("concurrency.manga_permits", cfg.concurrency.manga_permits),
+        (
+            "concurrency.package_permits",
+            cfg.concurrency.package_permits,
+        ),
+    ];
+
+    for (key, value) in non_zero_options {
+        if value == 0 {
+            return Err(InvalidOption::new(
+                key,
+                format!("Expected option `{key}` to be non-zero, got {value}"),
+                "set this to at least 1",
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Validates that `package.dest` isn't inside the library (`library_dir`, or the
+/// default one), where packages would be mistaken for orphaned dirs by `clean`.
+fn validate_package_dest(
+    dest: &Path,
+    library_dir: Option<&Path>,
+) -> std::result::Result<(), InvalidOption> {
+    let library = match library_dir {
+        Some(dir) => dir.to_path_buf(),
+        None => match manga_save_dir() {
+            Ok(dir) => dir,
+            Err(_) => return Ok(()),
+        },
+    };
+
+    if is_within(dest, &library) {
+        return Err(InvalidOption::new(
+            "package.dest",
+            format!(
+                "Expected option `package.dest` to be outside of the library ({}), got {}",
+                library.display(),
+                dest.display()
+            ),
+            "set this to the dir packages should be written to, outside of the library",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Validates options in `cfg` that can't be checked by [`serde`] alone.
+fn validate_config(cfg: &Config) -> std::result::Result<(), InvalidOption> {
+    validate_non_zero(cfg)?;
+    validate_user_agent(&cfg.client.user_agent)?;
+
+    if cfg.concurrency.adaptive {
+        validate_adaptive(&cfg.concurrency)?;
+    }
+
+    if cfg.tracking.sync_status {
+        validate_auth(&cfg.auth)?;
+    }
+
+    if cfg.package.format.is_some() && cfg.package.dest.is_none() {
+        return Err(InvalidOption::new(
+            "package.dest",
+            "Expected option `package.dest` to be set, since `package.format` is",
+            "set this to the dir packages should be written to, outside of the library",
+        ));
+    }
+
+    if let Some(dest) = &cfg.package.dest {
+        validate_package_dest(dest, cfg.storage.library_dir.as_deref())?;
+    }
+
+    if !(1..=SearchClient::MAX_MANGA_PAGINATION).contains(&cfg.search.page_size) {
+        return Err(InvalidOption::new(
+            "search.page_size",
+            format!(
+                "Expected option `search.page_size` to be from 1 to {}, got {}",
+                SearchClient::MAX_MANGA_PAGINATION,
+                cfg.search.page_size
+            ),
+            "Manga-Dex returns at most 100 results per request",
+        ));
+    }
+
+    if cfg.budget.max_failure_percent > 100 {
+        return Err(InvalidOption::new(
+            "budget.max_failure_percent",
+            format!(
+                "Expected option `budget.max_failure_percent` to be from 0 to 100, got {}",
+                cfg.budget.max_failure_percent
+            ),
+            "0 means no limit",
+        ));
+    }
+
+    if !(1..=100).contains(&cfg.images.convert_quality) {
+        return Err(InvalidOption::new(
+            "images.convert_quality",
+            format!(
+                "Expected option `images.convert_quality` to be from 1 to 100, got {}",
+                cfg.images.convert_quality
+            ),
+            "higher is better quality, 85 is a good default",
+        ));
+    }
+
+    let templates = [
+        ("naming.manga", &cfg.naming.manga, &MANGA_FIELDS[..]),
+        ("naming.chapter", &cfg.naming.chapter, &CHAPTER_FIELDS[..]),
+        ("naming.page", &cfg.naming.page, &PAGE_FIELDS[..]),
+    ];
+
+    for (key, template, fields) in templates {
+        template.check_fields(key, fields).map_err(|e| {
+            InvalidOption::new(
+                key,
+                e.to_string(),
+                "fields are listed in the default config",
+            )
+        })?;
+    }
+
+    let max_length = cfg.naming.policy.max_length;
+    if (1..PathPolicy::MIN_MAX_LENGTH).contains(&max_length) {
+        return Err(InvalidOption::new(
+            "naming.max_length",
+            format!(
+                "Expected option `naming.max_length` to be 0 or at least {}, got {max_length}",
+                PathPolicy::MIN_MAX_LENGTH
+            ),
+            "shorter names wouldn't leave room for an extension and uuid",
+        ));
+    }
+
+    validate_urls(cfg)?;
+
+    if let Err(e) = job_style(&cfg.progress.template, &Arc::default()) {
+        return Err(InvalidOption::new(
+            "progress.template",
+            format!("Invalid progress bar template: {e}"),
+            "see https://docs.rs/indicatif/latest/indicatif/#templates",
+        ));
+    }
+
+    if !cfg.naming.page.fields().any(|f| f == "page") {
+        return Err(InvalidOption::new(
+            "naming.page",
+            "Expected option `naming.page` to contain `{page}`",
+            "otherwise, every page of a chapter would be saved with the same name",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Returns the span of the value of `key` (e.g. `client.language`) in `raw_cfg`, if it's set.
+#[must_use]
+pub fn value_span(raw_cfg: &str, key: &str) -> Option<Range<usize>> {
+    let root = DeTable::parse(raw_cfg).ok()?;
+    let mut table = root.get_ref();
+    let mut parts = key.split('.').peekable();
+
+    while let Some(part) = parts.next() {
+        let (_, value) = table.iter().find(|(k, _)| k.get_ref() == part)?;
+
+        if parts.peek().is_none() {
+            return Some(value.span());
+        }
+
+        match value.get_ref() {
+            DeValue::Table(inner) => table = inner,
+            _ => return None,
+        }
+    }
+
+    None
+}
+
+/// Returns the default config (with its comments), with the given options set.
+///
+/// `options` are `(key, value)` pairs, where `value` is a TOML literal (e.g. `"\"ja\""` or `"4"`).
+/// The first line setting `key` (even if it's commented out) is replaced, keeping its comment.
+///
+/// ## Panics
+///
+/// If `key` isn't in the default config.
+#[must_use]
+pub fn config_with_options(options: &[(&str, String)]) -> String {
+    let mut lines: Vec<String> = CONFIG_DEFAULT.lines().map(str::to_string).collect();
+
+    for (key, value) in options {
+        let line = lines
+            .iter_mut()
+            .find(|l| {
+                let l = l.trim_start_matches('#').trim_start();
+                l.strip_prefix(key)
+                    .is_some_and(|rest| rest.trim_start().starts_with('='))
+            })
+            .unwrap_or_else(|| panic!("option {key:?} isn't in the default config"));
+
+        let setting = format!("{key} = {value}");
+
+        // keep the comment in the same column if it still fits
+        *line = match line.find("  #") {
+            Some(i) => {
+                let comment = line[i..].trim_start();
+                format!("{setting:<width$}  {comment}", width = i.max(setting.len()))
+            }
+            None => setting,
+        };
+    }
+
+    lines.join("\n") + "\n"
+}
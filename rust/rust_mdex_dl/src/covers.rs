@@ -0,0 +1,374 @@
+//! Contains [`display_covers`] (the `covers` command), which lists every cover of a manga
+//! (e.g. each volume's, in each locale) and downloads the chosen ones at their original
+//! resolution into `covers/` in the manga's dir.
+//!
+//! Covers are named after their volume and locale, e.g. `Vol. 3 (ja).jpg`, and ones
+//! that were already downloaded are skipped.
+
+use crate::{
+    api::{
+        client::ApiClient,
+        download::{DownloadClient, is_dry_run},
+        endpoints::Endpoint,
+        legacy::MdLink,
+        models::Manga,
+    },
+    config::Config,
+    deserializers::deserialize_uuid,
+    library::LibraryIndex,
+    paths::manga_save_dir,
+    progress::print_line,
+};
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use console::style;
+use dialoguer::{MultiSelect, theme::ColorfulTheme};
+use futures::{StreamExt, stream};
+use miette::{IntoDiagnostic, Result, bail, miette};
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use uuid::Uuid;
+
+/// The most covers fetched per request.
+const MAX_COVER_PAGINATION: u32 = 100;
+
+/// The most covers downloaded at once.
+const CONCURRENT_COVERS: usize = 4;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverAttributes {
+    /// The volume the cover is for, or `None` if it isn't for a specific one.
+    pub volume: Option<String>,
+    pub file_name: String,
+    pub description: Option<String>,
+    /// The locale of the cover, e.g. `"ja"` for the original Japanese release.
+    pub locale: Option<String>,
+}
+
+/// A cover of a manga, see [`fetch_covers`].
+#[derive(Deserialize, Debug, Clone)]
+pub struct Cover {
+    #[serde(deserialize_with = "deserialize_uuid")]
+    id: Uuid,
+    pub attributes: CoverAttributes,
+}
+
+impl Cover {
+    /// Trivial UUID getter.
+    #[must_use]
+    pub const fn uuid(&self) -> Uuid {
+        self.id
+    }
+
+    /// Returns the url of the cover at its original resolution, for the manga with `manga_uuid`.
+    ///
+    /// ## Errors
+    ///
+    /// If the url can't be parsed, e.g. if the file name is malformed.
+    pub fn url(&self, manga_uuid: Uuid) -> Result<Url> {
+        Url::parse(&format!(
+            "https://uploads.mangadex.org/covers/{manga_uuid}/{}",
+            self.attributes.file_name
+        ))
+        .into_diagnostic()
+    }
+
+    /// Returns the cover's name, e.g. `Vol. 3 (ja)`.
+    #[must_use]
+    pub fn label(&self) -> String {
+        let attrs = &self.attributes;
+        let volume = attrs
+            .volume
+            .as_deref()
+            .filter(|v| !v.is_empty())
+            .map_or_else(|| "No volume".to_string(), |v| format!("Vol. {v}"));
+
+        match attrs.locale.as_deref() {
+            Some(locale) => format!("{volume} ({locale})"),
+            None => volume,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct CoverResults {
+    data: Vec<Cover>,
+    total: u32,
+}
+
+/// Fetches every cover of the manga with `manga_uuid` in order of volume, only
+/// including ones in `locales` (e.g. `["ja"]`) if it isn't empty.
+///
+/// ## Errors
+///
+/// If a request fails or its response can't be parsed.
+pub async fn fetch_covers(
+    api: &ApiClient,
+    manga_uuid: Uuid,
+    locales: &[String],
+) -> Result<Vec<Cover>> {
+    let mut covers = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        let mut params: Vec<(String, String)> = vec![
+            ("manga[]".into(), manga_uuid.to_string()),
+            ("limit".into(), MAX_COVER_PAGINATION.to_string()),
+            ("offset".into(), offset.to_string()),
+            ("order[volume]".into(), "asc".into()),
+        ];
+        params.extend(locales.iter().map(|l| ("locales[]".into(), l.clone())));
+
+        let r: CoverResults = api.get_ok_parsed(Endpoint::GetCovers(params)).await?;
+        let fetched = u32::try_from(r.data.len()).unwrap_or(u32::MAX);
+        covers.extend(r.data);
+        offset += MAX_COVER_PAGINATION;
+
+        if fetched == 0 || offset >= r.total {
+            break;
+        }
+    }
+
+    debug!("Fetched {} covers of manga {manga_uuid}", covers.len());
+    Ok(covers)
+}
+
+/// Finds the manga that `manga` refers to, which is either a link or uuid (see [`MdLink`])
+/// or part of the title of a manga in the library (case-insensitive).
+async fn find_manga(api: &ApiClient, manga: &str) -> Result<Manga> {
+    if let Some(link) = MdLink::parse(manga) {
+        return Manga::new(api, link.manga_uuid(api).await?).await;
+    }
+
+    let filter = manga.to_lowercase();
+    let index = LibraryIndex::load()?;
+    let matches: Vec<_> = index
+        .manga
+        .values()
+        .filter(|m| m.title.to_lowercase().contains(&filter))
+        .collect();
+
+    match matches.as_slice() {
+        [entry] => Manga::new(api, entry.uuid).await,
+        [] => bail!("no manga in the library matches {manga:?}, try a link or uuid instead"),
+        _ => {
+            let titles: Vec<&str> = matches.iter().map(|m| m.title.as_str()).collect();
+            Err(miette!(
+                "{manga:?} matches more than one manga in the library: {}",
+                titles.join(", ")
+            ))
+        }
+    }
+}
+
+/// Returns where the covers of `manga` are saved, in its dir in the library
+/// (or where it would be, if it hasn't been downloaded).
+fn covers_dir(cfg: &Config, manga: &Manga) -> Result<PathBuf> {
+    let manga_dir = match LibraryIndex::load()?.manga.get(&manga.uuid()) {
+        Some(entry) => manga_save_dir()?.join(&entry.dir),
+        None => DownloadClient::new(cfg)?.manga_dir(manga)?,
+    };
+
+    Ok(manga_dir.join("covers"))
+}
+
+/// Returns the file name of each of `covers`, named after their [labels](`Cover::label`)
+/// and keeping their extension.
+///
+/// Repeated labels get the start of the cover's uuid appended, so that a cover keeps
+/// its name however the covers are ordered.
+fn file_names(cfg: &Config, covers: &[Cover]) -> Vec<String> {
+    let mut label_counts: HashMap<String, usize> = HashMap::new();
+
+    for cover in covers {
+        *label_counts.entry(cover.label()).or_default() += 1;
+    }
+
+    covers
+        .iter()
+        .map(|cover| {
+            let ext = Path::new(&cover.attributes.file_name)
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or("jpg");
+            let label = cover.label();
+            let name = if label_counts[&label] > 1 {
+                format!("{label} ({})", &cover.uuid().to_string()[..8])
+            } else {
+                label
+            };
+
+            format!("{}.{ext}", cfg.naming.policy.file_stem(&name, None))
+        })
+        .collect()
+}
+
+/// Downloads the cover at `url` into `dest`, through a partial file.
+async fn save_cover(client: &Client, url: Url, dest: &Path) -> Result<()> {
+    let bytes = client
+        .get(url)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .into_diagnostic()?
+        .bytes()
+        .await
+        .into_diagnostic()?;
+
+    let partial = dest.with_extension("partial");
+    tokio::fs::write(&partial, &bytes).await.into_diagnostic()?;
+    tokio::fs::rename(&partial, dest).await.into_diagnostic()?;
+
+    Ok(())
+}
+
+/// Returns a line for each of `covers` (saved as `names` in `dir`) with its
+/// label and description, pointing out the ones already saved.
+fn cover_rows(covers: &[Cover], names: &[String], dir: &Path) -> Vec<String> {
+    covers
+        .iter()
+        .zip(names)
+        .map(|(cover, name)| {
+            let description = cover
+                .attributes
+                .description
+                .as_deref()
+                .filter(|d| !d.is_empty())
+                .map(|d| format!("  {}", style(d).dim()))
+                .unwrap_or_default();
+            let saved = if dir.join(name).exists() {
+                style("  (saved)").green().to_string()
+            } else {
+                String::new()
+            };
+
+            format!("{}{description}{saved}", cover.label())
+        })
+        .collect()
+}
+
+/// Downloads the `chosen` (indices) of `covers` into `dir` as `names`,
+/// skipping ones already saved. Returns how many were saved, skipped and failed.
+///
+/// ## Errors
+///
+/// If `dir` can't be created or the HTTP client can't be constructed.
+async fn download_covers(
+    cfg: &Config,
+    manga_uuid: Uuid,
+    covers: &[Cover],
+    names: &[String],
+    chosen: Vec<usize>,
+    dir: &Path,
+) -> Result<(usize, usize, usize)> {
+    tokio::fs::create_dir_all(dir).await.into_diagnostic()?;
+    let client = Client::builder()
+        .user_agent(&cfg.client.user_agent)
+        .build()
+        .into_diagnostic()?;
+
+    let results: Vec<Result<bool>> = stream::iter(chosen)
+        .map(|i| {
+            let (client, cover, dest) = (&client, &covers[i], dir.join(&names[i]));
+
+            async move {
+                if dest.exists() {
+                    return Ok(false);
+                }
+
+                save_cover(client, cover.url(manga_uuid)?, &dest)
+                    .await
+                    .inspect_err(|e| warn!("Failed to download cover {}: {e}", cover.uuid()))?;
+
+                Ok(true)
+            }
+        })
+        .buffer_unordered(CONCURRENT_COVERS)
+        .collect()
+        .await;
+
+    let saved = results.iter().filter(|r| matches!(r, Ok(true))).count();
+    let skipped = results.iter().filter(|r| matches!(r, Ok(false))).count();
+
+    Ok((saved, skipped, results.len() - saved - skipped))
+}
+
+/// Lists the covers of `manga` (a link, uuid, or part of a title in the library), asks which
+/// to download (unless `all`), and downloads them into `covers/` in the manga's dir.
+///
+/// Only covers in `locales` are listed, if it isn't empty.
+///
+/// ## Errors
+///
+/// If the manga can't be found, its covers can't be fetched or prompting fails.
+/// Covers which fail to download are counted as failed instead.
+pub async fn display_covers(
+    cfg: &Config,
+    manga: &str,
+    locales: &[String],
+    all: bool,
+) -> Result<()> {
+    let api = ApiClient::new(&cfg.client)?;
+    let manga = find_manga(&api, manga).await?;
+    let title = manga.title(cfg.client.language);
+    let covers = fetch_covers(&api, manga.uuid(), locales).await?;
+
+    if covers.is_empty() {
+        print_line(style(format!("{title} has no covers")).yellow().italic());
+        return Ok(());
+    }
+
+    let dir = covers_dir(cfg, &manga)?;
+    let names = file_names(cfg, &covers);
+    let rows = cover_rows(&covers, &names, &dir);
+
+    print_line(style(format!("{title}: {} covers", covers.len())).bold());
+
+    let chosen: Vec<usize> = if all || is_dry_run() {
+        for row in &rows {
+            print_line(format!("  {row}"));
+        }
+        (0..covers.len()).collect()
+    } else {
+        let Some(chosen) = MultiSelect::with_theme(&ColorfulTheme::default())
+            .with_prompt("Pick covers (space toggles, enter confirms)")
+            .items(&rows)
+            .defaults(&vec![true; rows.len()])
+            .max_length(20)
+            .interact_opt()
+            .into_diagnostic()?
+        else {
+            return Ok(());
+        };
+        chosen
+    };
+
+    if is_dry_run() {
+        let message = format!("Would save {} covers to {}", chosen.len(), dir.display());
+        print_line(style(message).green());
+        return Ok(());
+    }
+
+    let (saved, skipped, failed) =
+        download_covers(cfg, manga.uuid(), &covers, &names, chosen, &dir).await?;
+    let message = match skipped {
+        0 => format!("Saved {saved} covers to {}", dir.display()),
+        _ => format!(
+            "Saved {saved} covers to {} ({skipped} already saved)",
+            dir.display()
+        ),
+    };
+
+    print_line(style(message).green());
+
+    if failed > 0 {
+        print_line(style(format!("{failed} covers failed to download")).red());
+    }
+
+    Ok(())
+}
@@ -4,34 +4,165 @@ use std::collections::HashMap;
 
 use chrono::{DateTime, Utc};
 use isolang::Language;
-use log::LevelFilter;
-use serde::Deserialize;
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::Value;
+use serde_path_to_error::{Path, Segment};
+use thiserror::Error;
+use tracing_subscriber::EnvFilter;
 use uuid::Uuid;
 
-/// Deserializer for [`LevelFilter`].
+use crate::api::language::MdLanguage;
+
+/// The most characters of an offending value shown by [`JsonPathError`].
+const MAX_VALUE_CHARS: usize = 120;
+
+/// A failure to deserialize JSON, with where it happened.
+#[derive(Error, Debug)]
+#[error(
+    "failed to parse `{path}`{}: {source}",
+    value.as_ref().map(|v| format!(" (value: {v})")).unwrap_or_default()
+)]
+pub struct JsonPathError {
+    /// Where parsing failed, e.g. `data[3].attributes.title`.
+    pub path: String,
+    /// The value at [`Self::path`], shortened to [`MAX_VALUE_CHARS`], if there is one.
+    pub value: Option<String>,
+    #[source]
+    pub source: serde_json::Error,
+}
+
+impl JsonPathError {
+    fn new(root: Option<&Value>, e: serde_path_to_error::Error<serde_json::Error>) -> Self {
+        let value = root.and_then(|root| value_at(root, e.path())).map(|v| {
+            let v = v.to_string();
+
+            match v.char_indices().nth(MAX_VALUE_CHARS) {
+                Some((end, _)) => format!("{}…", &v[..end]),
+                None => v,
+            }
+        });
+
+        Self {
+            path: e.path().to_string(),
+            value,
+            source: e.into_inner(),
+        }
+    }
+}
+
+/// Returns the value at `path` in `root`, if it exists.
+fn value_at<'a>(root: &'a Value, path: &Path) -> Option<&'a Value> {
+    path.iter().try_fold(root, |value, segment| match segment {
+        Segment::Seq { index } => value.get(index),
+        Segment::Map { key } | Segment::Enum { variant: key } => value.get(key),
+        Segment::Unknown => None,
+    })
+}
+
+/// Like [`serde_json::from_slice`], but errors say where in `bytes` parsing failed.
+///
+/// ## Errors
+///
+/// If `bytes` isn't valid JSON or doesn't match `T`.
+pub fn from_slice_with_path<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, JsonPathError> {
+    let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+
+    serde_path_to_error::deserialize(&mut deserializer).map_err(|e| {
+        // only parsed again to find the offending value
+        let root = serde_json::from_slice(bytes).ok();
+        JsonPathError::new(root.as_ref(), e)
+    })
+}
+
+/// Like [`serde_json::from_value`], but errors say where in `value` parsing failed.
+///
+/// ## Errors
+///
+/// If `value` doesn't match `T`.
+pub fn from_value_with_path<T: DeserializeOwned>(value: &Value) -> Result<T, JsonPathError> {
+    serde_path_to_error::deserialize(value).map_err(|e| JsonPathError::new(Some(value), e))
+}
+
+/// An enum of values from Manga-Dex which keeps values it doesn't know in an
+/// `Unknown` variant instead of failing, so that new values don't break parsing.
+pub trait LenientEnum: Sized {
+    /// What the value is, for logging (e.g. `"content rating"`).
+    const NAME: &'static str;
+
+    /// Returns the known variant for `raw`, if there is one.
+    fn known(raw: &str) -> Option<Self>;
+
+    /// Returns the `Unknown` variant holding `raw`.
+    fn unknown(raw: String) -> Self;
+}
+
+/// Deserializer for [`LenientEnum`]s, which logs (rather than fails on) unknown values.
+///
+/// ## Errors
+///
+/// If the value isn't a string.
+pub fn deserialize_lenient<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: LenientEnum,
+{
+    let raw = String::deserialize(deserializer)?;
+
+    Ok(T::known(&raw).unwrap_or_else(|| {
+        warn!(
+            "Unrecognized {} {raw:?} from Manga-Dex, keeping it as is",
+            T::NAME
+        );
+        T::unknown(raw)
+    }))
+}
+
+/// Deserializer for logging filters, which are either a level (e.g. "DEBUG")
+/// or [`EnvFilter`] directives (e.g. `info,rust_mdex_dl::api=trace`).
 ///
 /// ## Errors
 ///
 /// If initial deserilization as [`String`] fails, or
-/// the isn't a valid logging level (e.g, "DEBUG").
-pub fn deserialize_logging_filter<'de, D>(deserializer: D) -> Result<LevelFilter, D::Error>
+/// it isn't a valid level or list of directives.
+pub fn deserialize_logging_filter<'de, D>(deserializer: D) -> Result<String, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
-    let input_level = String::deserialize(deserializer)?;
-
-    match input_level.as_str() {
-        "TRACE" => Ok(LevelFilter::Trace),
-        "DEBUG" => Ok(LevelFilter::Debug),
-        "INFO" => Ok(LevelFilter::Info),
-        "WARN" => Ok(LevelFilter::Warn),
-        "ERROR" => Ok(LevelFilter::Error),
-        _ => Err(serde::de::Error::custom(format!(
-            "invalid logging level {input_level:?}"
+    let input = String::deserialize(deserializer)?;
+
+    match EnvFilter::try_new(&input) {
+        Ok(_) => Ok(input),
+        Err(e) => Err(serde::de::Error::custom(format!(
+            "invalid logging filter {input:?}: {e}"
         ))),
     }
 }
 
+/// Deserializes a JSON object as a [`HashMap<String, V>`], also accepting an empty
+/// array, which Manga-Dex sends instead of an empty object in some responses.
+///
+/// ## Errors
+///
+/// If the input is neither an object (with values parseable as `V`) nor an empty array.
+pub fn deserialize_map_or_empty<'de, D, V>(deserializer: D) -> Result<HashMap<String, V>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    V: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum MapOrEmpty<V> {
+        Map(HashMap<String, V>),
+        // only matches `[]`, so that other arrays are still rejected
+        Empty(#[allow(dead_code)] [(); 0]),
+    }
+
+    match MapOrEmpty::deserialize(deserializer)? {
+        MapOrEmpty::Map(map) => Ok(map),
+        MapOrEmpty::Empty(_) => Ok(HashMap::new()),
+    }
+}
+
 /// Helper function to deserialize as [`Uuid`].
 ///
 /// ## Errors
@@ -66,38 +197,17 @@ where
     Ok(parsed_datetime.to_utc())
 }
 
-/// shim for Manga-Dex's alpha-5 extensions used for titles.
-///
-/// ref: <https://api.mangadex.org/docs/3-enumerations/#language-codes--localization>
-///
-/// TODO: find a better way of doing this
-fn narrow_langcodes(langcode: &str) -> String {
-    match langcode {
-        "zh-ro" | "ja-ro" | "ko-ro" => "en", // language-romanized => eng
-        "zh-hk" => "zh",                     // remove simplified/traditional distinction
-        "pt-br" => "pt",                     // brazilian portugese => portugese
-        "es-la" => "es",                     // latam spanish => spanish
-        _ => langcode,
-    }
-    .to_string()
-}
-
-/// check <https://api.mangadex.org/manga/0c936660-cb06-491b-8b61-15dacad1bfb4> json for why
-/// i just want to read my manga man
-fn nullify_langcodes(langcode: &str) -> String {
-    const UND_MAPPINGS: [&str; 4] = ["NULL", "Null", "null", ""];
-
-    if UND_MAPPINGS.contains(&langcode) {
-        return "UNKNOWN".to_string();
-    }
-
-    langcode.to_string()
+/// Parses `langcode` as an [`MdLanguage`], for the deserializers below.
+fn parse_langcode<E: serde::de::Error>(langcode: &str) -> Result<MdLanguage, E> {
+    MdLanguage::parse(langcode)
+        .ok_or_else(|| E::custom(format!("invalid iso 639-1 language code {langcode:?}")))
 }
 
 /// Helper function to deserialize as [`Language`]
 ///
-/// The input is parsed using the ISO 639-1 standard, in accordance with
-/// [what MangaDex uses](https://api.mangadex.org/docs/3-enumerations/#language-codes--localization)
+/// The input is parsed as an [`MdLanguage`], in accordance with
+/// [what MangaDex uses](https://api.mangadex.org/docs/3-enumerations/#language-codes--localization),
+/// and its variant is dropped (e.g. `ja-ro` becomes Japanese).
 ///
 /// ## Errors
 ///
@@ -107,86 +217,90 @@ pub fn deserialize_langcode<'de, D>(deserializer: D) -> Result<Language, D::Erro
 where
     D: serde::Deserializer<'de>,
 {
-    let input_langcode = nullify_langcodes(&narrow_langcodes(&String::deserialize(deserializer)?));
+    parse_langcode(&String::deserialize(deserializer)?).map(Language::from)
+}
+
+/// Helper function to deserialize as [`Vec<MdLanguage>`].
+///
+/// Null and unknown entries (which Manga-Dex does return sometimes) are skipped.
+///
+/// ## Errors
+///
+/// If initial deserialization as [`Vec<Option<String>>`]
+/// fails, or any of the strings aren't valid language codes.
+pub fn deserialize_md_language_vec<'de, D>(deserializer: D) -> Result<Vec<MdLanguage>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let input_vec: Vec<Option<String>> = Vec::deserialize(deserializer)?;
+    let mut languages = Vec::with_capacity(input_vec.len());
+
+    for langcode in input_vec.into_iter().flatten() {
+        let lang = parse_langcode(&langcode)?;
 
-    if input_langcode == "UNKNOWN" {
-        return Ok(Language::Und);
+        if lang.language != Language::Und && !languages.contains(&lang) {
+            languages.push(lang);
+        }
     }
 
-    Language::from_639_1(input_langcode.as_str()).ok_or_else(|| {
-        serde::de::Error::custom(format!(
-            "invalid iso 639-1 language code {input_langcode:?}"
-        ))
-    })
+    Ok(languages)
 }
 
-/// Helper function to deserialize as [`HashMap<Language, String>`].
-/// This pattern appears quite often, especially in places like descriptions.
-///
-/// The input is parsed using the ISO 639-1 standard, in accordance with
-/// [what MangaDex uses](https://api.mangadex.org/docs/3-enumerations/#language-codes--localization)
+/// Like [`deserialize_md_language_vec`], but as [`Vec<Language>`] with variants dropped.
 ///
 /// ## Errors
 ///
-/// If initial deserilization as [`HashMap<String, String>`]
-///  fails, or the hashmap's keys aren't valid language codes.
-pub fn deserialize_langcode_map<'de, D>(
-    deserializer: D,
-) -> Result<HashMap<Language, String>, D::Error>
+/// If initial deserialization as [`Vec<Option<String>>`]
+/// fails, or any of the strings aren't valid language codes.
+pub fn deserialize_langcode_vec<'de, D>(deserializer: D) -> Result<Vec<Language>, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
-    let input_map: HashMap<String, String> = HashMap::deserialize(deserializer)?;
+    let mut languages = Vec::new();
 
-    input_map
-        .into_iter()
-        .map(|(k, v)| {
-            let k = nullify_langcodes(&narrow_langcodes(&k));
+    for lang in deserialize_md_language_vec(deserializer)? {
+        let lang = Language::from(lang);
 
-            if k == "UNKNOWN" {
-                return Ok((Language::Und, v));
-            }
+        // e.g. `pt` and `pt-br`
+        if !languages.contains(&lang) {
+            languages.push(lang);
+        }
+    }
 
-            let lang = Language::from_639_1(&k).ok_or_else(|| {
-                serde::de::Error::custom(format!("invalid iso 639-1 language code {k:?}"))
-            })?;
-            Ok((lang, v))
-        })
-        .collect()
+    Ok(languages)
 }
 
-/// Deserializes to [`Vec<HashMap<Language, String>>`].
+/// Helper function to deserialize as [`HashMap<Language, String>`].
+/// This pattern appears quite often, especially in places like descriptions.
+///
+/// The keys are parsed like [`deserialize_langcode`], so variants are dropped. If a language
+/// is there more than once (e.g. `ja` and `ja-ro`), the value without a variant is kept,
+/// falling back to the first variant (by [`MdLanguage`]'s order).
 ///
 /// ## Errors
 ///
-/// If initial deserialization as [`Vec<Hashmap<String, String>>`] fails,
-/// or any of the contained hashmaps; keys aren't valid language codes.
-pub fn deserialize_langcode_map_vec<'de, D>(
+/// If initial deserilization as [`HashMap<String, String>`]
+///  fails, or the hashmap's keys aren't valid language codes.
+pub fn deserialize_langcode_map<'de, D>(
     deserializer: D,
-) -> Result<Vec<HashMap<Language, String>>, D::Error>
+) -> Result<HashMap<Language, String>, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
-    let input_map_vec: Vec<HashMap<String, String>> = Vec::deserialize(deserializer)?;
-    let mut mappings: Vec<HashMap<Language, String>> = Vec::with_capacity(input_map_vec.len() + 1);
-
-    for map in input_map_vec {
-        let mut current = HashMap::with_capacity(map.len() + 1);
+    let input_map: HashMap<String, String> = HashMap::deserialize(deserializer)?;
+    let mut entries = input_map
+        .into_iter()
+        .map(|(k, v)| Ok((parse_langcode::<D::Error>(&k)?, v)))
+        .collect::<Result<Vec<(MdLanguage, String)>, D::Error>>()?;
 
-        for (k, v) in map {
-            let k = nullify_langcodes(&narrow_langcodes(&k));
-            if k == "UNKNOWN" {
-                current.insert(Language::Und, v);
-                continue;
-            }
+    // codes without a variant sort first
+    entries.sort_unstable_by_key(|(language, _)| *language);
 
-            let lang = Language::from_639_1(&k).ok_or_else(|| {
-                serde::de::Error::custom(format!("invalid iso 639-1 language code {k:?}"))
-            })?;
+    let mut map = HashMap::with_capacity(entries.len());
 
-            current.insert(lang, v);
-        }
-        mappings.push(current);
+    for (language, value) in entries {
+        map.entry(language.into()).or_insert(value);
     }
-    Ok(mappings)
+
+    Ok(map)
 }
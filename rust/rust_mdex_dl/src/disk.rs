@@ -0,0 +1,128 @@
+//! Checks that there's enough free disk space for a download (see the `storage` options),
+//! so that it can warn or stop beforehand instead of failing halfway through.
+//!
+//! Sizes are estimated from the chapters' page counts and the average page size of the
+//! [library](`crate::library::LibraryIndex`), since the actual sizes aren't known until
+//! the pages are downloaded.
+
+use crate::{
+    config::{ImageQuality, LowSpaceAction, Storage},
+    library::LibraryIndex,
+    progress::multi_progress,
+};
+
+use std::path::Path;
+
+use console::style;
+use miette::{Result, bail};
+
+const MIB: u64 = 1_048_576;
+
+/// Returns the space available (to unprivileged users) on the filesystem containing `path`,
+/// or its nearest existing ancestor. Returns `None` if this can't be found, or on non-unix
+/// platforms, where the check is skipped.
+#[must_use]
+pub fn available_space(path: &Path) -> Option<u64> {
+    let existing = path.ancestors().find(|p| p.exists())?;
+
+    #[cfg(unix)]
+    {
+        match rustix::fs::statvfs(existing) {
+            Ok(stat) => Some(stat.f_bavail.saturating_mul(stat.f_frsize)),
+            Err(e) => {
+                warn!("Failed to get free space of {}: {e}", existing.display());
+                None
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        debug!(
+            "Free space checks aren't supported on this platform, skipping {}",
+            existing.display()
+        );
+        None
+    }
+}
+
+/// Returns the average size of a page saved in `quality`, in bytes, from the pages
+/// already in the library, or a rough guess if there aren't any.
+#[must_use]
+pub fn average_page_size(quality: &ImageQuality) -> u64 {
+    let (size, pages) = LibraryIndex::load()
+        .map(|index| {
+            index
+                .manga
+                .values()
+                .flat_map(|m| m.chapters.values())
+                .filter(|c| &c.quality == quality)
+                .fold((0u64, 0u64), |(size, pages), c| {
+                    (size + c.size, pages + c.pages as u64)
+                })
+        })
+        .unwrap_or_default();
+
+    size.checked_div(pages).unwrap_or(match quality {
+        ImageQuality::Lossless => MIB,
+        ImageQuality::Lossy => MIB / 4,
+    })
+}
+
+/// Checks that saving `needed` bytes in `dir` would still leave `storage.min_free_mib` free.
+///
+/// If it wouldn't, this follows `storage.on_low_space`. Warnings are only printed
+/// if `announce` is set (e.g. once before starting), and are logged otherwise.
+///
+/// ## Errors
+///
+/// If there isn't enough space and `storage.on_low_space` is `"abort"`.
+pub fn check_space(storage: &Storage, dir: &Path, needed: u64, announce: bool) -> Result<()> {
+    if storage.on_low_space == LowSpaceAction::Off {
+        return Ok(());
+    }
+
+    let Some(available) = available_space(dir) else {
+        return Ok(());
+    };
+
+    let required = needed.saturating_add(storage.min_free_mib.saturating_mul(MIB));
+
+    if available >= required {
+        return Ok(());
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let mib = |bytes: u64| bytes as f64 / MIB as f64;
+
+    let message = format!(
+        "Only {:.1} MiB is free in {}, but about {:.1} MiB is needed \
+        (including the {} MiB kept free by `storage.min_free_mib`)",
+        mib(available),
+        dir.display(),
+        mib(required),
+        storage.min_free_mib
+    );
+
+    match storage.on_low_space {
+        LowSpaceAction::Abort => {
+            bail!(
+                help = "free up some space, or set `storage.on_low_space = \"warn\"`",
+                "{message}"
+            )
+        }
+        LowSpaceAction::Warn => {
+            warn!("{message}");
+
+            if announce {
+                let warning = style(format!("Warning: {message}")).yellow();
+
+                // drawn above any progress bars, rather than through them
+                multi_progress().suspend(|| eprintln!("{warning}"));
+            }
+
+            Ok(())
+        }
+        LowSpaceAction::Off => Ok(()),
+    }
+}
@@ -0,0 +1,416 @@
+//! Contains [`display_dedupe`], which finds chapters downloaded more than once (e.g. from
+//! several groups, before `groups.preferred` was set) and removes or archives the extra copies.
+//!
+//! Copies are the chapters of a manga with the same volume, number and language (so manga whose
+//! numbers restart each volume aren't deduped across volumes). The copy kept is the
+//! one from the most [preferred](`crate::config::Groups::preferred`) group, falling back to
+//! the best quality and then the latest download. Chapters without a number are left alone.
+
+use crate::{
+    api::{download::is_dry_run, groups::preferred_name_rank},
+    config::{Config, Groups, ImageQuality},
+    library::{ChapterEntry, LibraryIndex, MangaEntry},
+    paths::manga_save_dir,
+    progress::print_line,
+    store::prune_store_after_delete,
+};
+
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use console::style;
+use dialoguer::{Confirm, theme::ColorfulTheme};
+use miette::{IntoDiagnostic, Result};
+use uuid::Uuid;
+
+/// The copies of a chapter in a manga, found by [`find_duplicates`].
+#[derive(Debug, Clone)]
+pub struct Duplicates {
+    pub manga: Uuid,
+    pub title: String,
+    /// The copy that's kept.
+    pub keep: ChapterEntry,
+    /// The copies that are removed (or archived).
+    pub extra: Vec<ChapterEntry>,
+}
+
+/// Returns how much the copy `chapter` should be kept (lower is better),
+/// see the [module docs](`self`).
+fn keep_rank(
+    chapter: &ChapterEntry,
+    groups: &Groups,
+) -> (bool, usize, bool, Reverse<DateTime<Utc>>) {
+    let blocked = preferred_name_rank(&chapter.groups, &groups.blocked).is_some();
+    let preferred = preferred_name_rank(&chapter.groups, &groups.preferred);
+
+    (
+        blocked,
+        preferred.unwrap_or(usize::MAX),
+        chapter.quality != ImageQuality::Lossless,
+        Reverse(chapter.downloaded_at),
+    )
+}
+
+/// Returns the chapters of `entry` that were downloaded more than once.
+#[must_use]
+pub fn find_duplicates(entry: &MangaEntry, groups: &Groups) -> Vec<Duplicates> {
+    let mut copies: BTreeMap<(&str, Option<&str>, &str), Vec<&ChapterEntry>> = BTreeMap::new();
+
+    for chapter in entry.sorted_chapters() {
+        if let Some(num) = chapter.chapter_number.as_deref() {
+            copies
+                .entry((chapter.language.as_str(), chapter.volume.as_deref(), num))
+                .or_default()
+                .push(chapter);
+        }
+    }
+
+    copies
+        .into_values()
+        .filter(|c| c.len() > 1)
+        .map(|mut c| {
+            c.sort_by_key(|chapter| keep_rank(chapter, groups));
+            let keep = c.remove(0).clone();
+
+            Duplicates {
+                manga: entry.uuid,
+                title: entry.title.clone(),
+                keep,
+                extra: c.into_iter().cloned().collect(),
+            }
+        })
+        .collect()
+}
+
+/// Copies the dir `from` (recursively) into `to`.
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to).into_diagnostic()?;
+
+    for entry in fs::read_dir(from).into_diagnostic()? {
+        let path = entry.into_diagnostic()?.path();
+        let dest = to.join(path.file_name().unwrap_or_default());
+
+        if path.is_dir() {
+            copy_dir(&path, &dest)?;
+        } else {
+            fs::copy(&path, &dest).into_diagnostic()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Moves the dir `from` to `to`, copying it if it can't be renamed (e.g. across disks).
+fn move_dir(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).into_diagnostic()?;
+    }
+
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    copy_dir(from, to)?;
+    fs::remove_dir_all(from).into_diagnostic()
+}
+
+/// Returns a chapter's label for listing, e.g. `Vol.2 Ch.12 [en] (Group A, Group B)`.
+fn copy_label(chapter: &ChapterEntry) -> String {
+    let volume = chapter
+        .volume
+        .as_deref()
+        .map(|v| format!("Vol.{v} "))
+        .unwrap_or_default();
+
+    format!(
+        "{volume}Ch.{} [{}] ({})",
+        chapter.chapter_number.as_deref().unwrap_or_default(),
+        chapter.language,
+        chapter.groups.join(", ")
+    )
+}
+
+/// Lists the chapters downloaded more than once (see the [module docs](`self`)) in manga
+/// whose title contains `manga_filter` (case-insensitive), then removes the extra copies if
+/// confirmed (or right away with `yes`). Nothing is removed in a dry run.
+///
+/// Extra copies are deleted, or moved into `archive` (in a dir per manga) if it's given.
+/// Either way, they're removed from the library index, which is reloaded after confirming
+/// so that nothing written to it in the meantime (e.g. by `watch`) is lost.
+///
+/// ## Errors
+///
+/// If the library index can't be loaded or saved, or prompting fails.
+/// Copies that can't be removed are logged instead.
+pub fn display_dedupe(
+    cfg: &Config,
+    manga_filter: Option<&str>,
+    archive: Option<&Path>,
+    yes: bool,
+) -> Result<()> {
+    let manga_filter = manga_filter.map(str::to_lowercase);
+    let index = LibraryIndex::load()?;
+    let duplicates: Vec<Duplicates> = index
+        .manga
+        .values()
+        .filter(|m| {
+            manga_filter
+                .as_ref()
+                .is_none_or(|f| m.title.to_lowercase().contains(f))
+        })
+        .flat_map(|m| find_duplicates(m, &cfg.groups))
+        .collect();
+
+    if duplicates.is_empty() {
+        print_line(style("No duplicate chapters found").green());
+        return Ok(());
+    }
+
+    let mut last_title = None;
+
+    for d in &duplicates {
+        if last_title != Some(&d.title) {
+            print_line(style(&d.title).bold());
+            last_title = Some(&d.title);
+        }
+
+        print_line(format!("  {} {}", style("✓").green(), copy_label(&d.keep)));
+
+        for extra in &d.extra {
+            print_line(format!(
+                "    {} {}",
+                style("✗").red(),
+                style(copy_label(extra)).dim()
+            ));
+        }
+    }
+
+    let extra: Vec<(Uuid, &ChapterEntry)> = duplicates
+        .iter()
+        .flat_map(|d| d.extra.iter().map(|c| (d.manga, c)))
+        .collect();
+
+    #[allow(clippy::cast_precision_loss)]
+    let mib = extra.iter().map(|(_, c)| c.size).sum::<u64>() as f64 / 1_048_576.0;
+    print_line(
+        style(format!(
+            "{} chapters have extra copies: {} copies ({mib:.1} MiB) to remove",
+            duplicates.len(),
+            extra.len()
+        ))
+        .yellow(),
+    );
+
+    let prompt = match archive {
+        Some(archive) => format!("Move them into {}?", archive.display()),
+        None => "Delete them?".to_string(),
+    };
+
+    if is_dry_run()
+        || !(yes
+            || Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt(prompt)
+                .default(false)
+                .interact()
+                .into_diagnostic()?)
+    {
+        return Ok(());
+    }
+
+    let library = manga_save_dir()?;
+    let mut removed: Vec<(Uuid, Uuid)> = Vec::with_capacity(extra.len());
+
+    for (manga, chapter) in extra {
+        let Some(entry) = index.manga.get(&manga) else {
+            continue;
+        };
+        let dir = entry.chapter_path(chapter)?;
+
+        let result = match archive {
+            Some(archive) => {
+                let dest: PathBuf = archive.join(&entry.dir).join(&chapter.dir);
+                move_dir(&dir, &dest)
+            }
+            None => fs::remove_dir_all(&dir).into_diagnostic(),
+        };
+
+        match result {
+            Ok(()) => removed.push((manga, chapter.uuid)),
+            Err(e) => error!(
+                "Failed to remove {}: {e}",
+                dir.strip_prefix(&library).unwrap_or(&dir).display()
+            ),
+        }
+    }
+
+    LibraryIndex::update(|index| {
+        for (manga, chapter) in &removed {
+            if let Some(entry) = index.manga.get_mut(manga) {
+                entry.chapters.remove(chapter);
+            }
+        }
+    })?;
+
+    if !removed.is_empty() {
+        prune_store_after_delete();
+    }
+
+    print_line(style(format!("Removed {} duplicate copies", removed.len())).green());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Spec {
+        id: u128,
+        volume: Option<&'static str>,
+        number: &'static str,
+        language: &'static str,
+        group: &'static str,
+        quality: ImageQuality,
+        downloaded_at: &'static str,
+    }
+
+    const SPEC: Spec = Spec {
+        id: 0,
+        volume: Some("1"),
+        number: "1",
+        language: "en",
+        group: "Alpha",
+        quality: ImageQuality::Lossless,
+        downloaded_at: "2024-01-01T00:00:00+00:00",
+    };
+
+    fn manga(specs: Vec<Spec>) -> MangaEntry {
+        let chapters = specs
+            .into_iter()
+            .map(|spec| {
+                let entry = ChapterEntry {
+                    uuid: Uuid::from_u128(spec.id),
+                    volume: spec.volume.map(ToString::to_string),
+                    chapter_number: Some(spec.number.to_string()),
+                    title: None,
+                    language: spec.language.to_string(),
+                    groups: vec![spec.group.to_string()],
+                    dir: PathBuf::new(),
+                    size: 0,
+                    pages: 20,
+                    checksum: String::new(),
+                    quality: spec.quality,
+                    downloaded_at: DateTime::parse_from_rfc3339(spec.downloaded_at)
+                        .unwrap()
+                        .to_utc(),
+                    version: None,
+                };
+                (entry.uuid, entry)
+            })
+            .collect();
+
+        MangaEntry {
+            uuid: Uuid::nil(),
+            title: "Manga".to_string(),
+            dir: PathBuf::new(),
+            updated_at: Utc::now(),
+            polled_at: None,
+            chapters,
+        }
+    }
+
+    fn kept_and_extra(duplicates: &Duplicates) -> (u128, Vec<u128>) {
+        let extra = duplicates.extra.iter().map(|c| c.uuid.as_u128()).collect();
+        (duplicates.keep.uuid.as_u128(), extra)
+    }
+
+    #[test]
+    fn groups_copies_by_language_volume_and_number() {
+        let entry = manga(vec![
+            Spec { id: 1, ..SPEC },
+            Spec { id: 2, ..SPEC },
+            Spec {
+                id: 3,
+                volume: Some("2"),
+                ..SPEC
+            },
+            Spec {
+                id: 4,
+                language: "fr",
+                ..SPEC
+            },
+            Spec {
+                id: 5,
+                number: "2",
+                ..SPEC
+            },
+        ]);
+        let duplicates = find_duplicates(&entry, &Groups::default());
+
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].extra.len(), 1);
+        assert!([1, 2].contains(&duplicates[0].keep.uuid.as_u128()));
+    }
+
+    #[test]
+    fn keeps_the_preferred_copy() {
+        let entry = manga(vec![
+            Spec {
+                id: 1,
+                group: "Alpha",
+                ..SPEC
+            },
+            Spec {
+                id: 2,
+                group: "Beta",
+                ..SPEC
+            },
+            Spec {
+                id: 3,
+                group: "Gamma",
+                ..SPEC
+            },
+        ]);
+        let groups = Groups {
+            preferred: vec!["beta".to_string()],
+            blocked: vec!["gamma".to_string()],
+        };
+        let duplicates = find_duplicates(&entry, &groups);
+
+        assert_eq!(kept_and_extra(&duplicates[0]), (2, vec![1, 3]));
+    }
+
+    #[test]
+    fn keeps_the_lossless_then_newest_copy() {
+        let entry = manga(vec![
+            Spec {
+                id: 1,
+                quality: ImageQuality::Lossy,
+                downloaded_at: "2024-06-01T00:00:00+00:00",
+                ..SPEC
+            },
+            Spec { id: 2, ..SPEC },
+            Spec {
+                id: 3,
+                downloaded_at: "2024-03-01T00:00:00+00:00",
+                ..SPEC
+            },
+        ]);
+        let duplicates = find_duplicates(&entry, &Groups::default());
+
+        assert_eq!(kept_and_extra(&duplicates[0]), (3, vec![2, 1]));
+    }
+
+    #[test]
+    fn labels_copies() {
+        let entry = manga(vec![Spec { id: 1, ..SPEC }]);
+        let chapter = &entry.chapters[&Uuid::from_u128(1)];
+
+        assert_eq!(copy_label(chapter), "Vol.1 Ch.1 [en] (Alpha)");
+    }
+}
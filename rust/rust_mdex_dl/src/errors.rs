@@ -1,110 +1,514 @@
 //! Contains user-defined errors.
 
-use miette::Diagnostic;
+use std::{fmt, ops::Range, time::Duration};
+
+use miette::{Diagnostic, NamedSource, SourceSpan};
 use reqwest::StatusCode;
 use thiserror::Error;
 
-use crate::api::endpoints::Endpoint;
+use crate::{
+    api::{
+        deprecation::ServerNotice, endpoints::Endpoint, request_id::RequestId,
+        response::ResponseHeaders,
+    },
+    deserializers::JsonPathError,
+    messages::message,
+};
+
+/// Where every error code is explained, see [`docs_url`].
+const ERRORS_DOC: &str =
+    "https://github.com/hachispin/learning-projects/blob/main/rust/rust_mdex_dl/ERRORS.md";
 
-/// Represents an error occuring with Manga-Dex's API.
+/// Returns the url explaining the error with `code`, which is a heading in `ERRORS.md`.
 ///
-/// This shouldn't be used for issues that aren't
-/// Manga-Dex's fault, per-se, like issues with `reqwest`.
-#[derive(Error, Debug, Diagnostic)]
-#[error("{error_text}")]
-#[diagnostic(help("{help}"))]
-pub struct ApiError {
-    error_text: String, // if you see a warning on this line, ignore it
-    help: String,
+/// GitHub's heading anchors drop the colons, so `mdex_dl::api::not_found`
+/// is at `#mdex_dlapinot_found`.
+#[must_use]
+pub fn docs_url(code: &str) -> String {
+    format!("{ERRORS_DOC}#{}", code.replace("::", ""))
 }
 
-impl ApiError {
-    /// Helper for [`ApiError::new()`] in constructing [`ApiError::help`]
-    fn get_status_code_help(status: StatusCode) -> String {
-        match status.as_u16() {
-            400 | 404 => "check if this link is actually valid",
-            401 => "authentication needed. (you shouldn't be seeing this!)",
-            403 => "you lack permission to access this. try something else",
-            429 => "you've been ratelimited and all rety attempts have failed. :(",
-            500 => "something went wrong with mangadex, consider retrying",
-            503 => "try again in a few minutes",
-            _ => status
-                .canonical_reason()
-                .unwrap_or("no reason found, sorry :("),
-        }
-        .to_string()
-    }
+/// The `errors` field of an error response from Manga-Dex, of which only the first is shown,
+/// along with what its headers said (see [`ServerNotice`]).
+#[derive(Debug, Clone, Default)]
+pub struct ErrorDetails {
+    /// The number of errors in the response, or `0` if it had no `errors` field.
+    pub count: usize,
+    pub title: Option<String>,
+    /// The first error's title as a [message catalog](`crate::messages`) key, lowercased
+    /// and with `_` for anything other than letters and digits (e.g. `not_found`).
+    pub code: Option<String>,
+    pub detail: Option<String>,
+    pub notice: ServerNotice,
+}
 
-    /// Helper for [`ApiError::new()`] if "errors" field in `r_json` doesn't exist
+impl ErrorDetails {
+    /// Parses the `errors` field of `r_json`, which may not exist
+    /// (e.g. if the response wasn't JSON to begin with).
     #[must_use]
-    pub fn blank(endpoint: &Endpoint, status: StatusCode) -> Self {
-        let status_code = status.as_u16();
+    pub fn parse(r_json: &serde_json::Value) -> Self {
+        let errors = r_json.get("errors").and_then(|e| e.as_array());
+
+        let Some(first_err) = errors.and_then(|e| e.first()) else {
+            return Self::default();
+        };
+
+        let field = |name: &str| {
+            first_err
+                .get(name)
+                .and_then(|s| s.as_str())
+                .map(str::to_string)
+        };
+
+        let title = field("title");
+        let code = title.as_deref().map(|t| {
+            t.trim()
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() {
+                        c.to_ascii_lowercase()
+                    } else {
+                        '_'
+                    }
+                })
+                .collect()
+        });
 
         Self {
-            error_text: format!(
-                "api error\n\n\
-                endpoint: {endpoint:?}\n\
-                status code: {status_code}\n\
-                (missing 'errors' field, couldn't gather more info)\n"
-            ),
-            help: Self::get_status_code_help(status),
+            count: errors.map_or(0, Vec::len),
+            code,
+            title,
+            detail: field("detail"),
+            notice: ServerNotice::default(),
         }
     }
+}
+
+impl fmt::Display for ErrorDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(id) = &self.notice.request_id {
+            writeln!(f, "manga-dex request id: {id}")?;
+        }
+
+        if self.notice.is_breaking() {
+            writeln!(f, "api change: {}", self.notice)?;
+        }
 
-    /// Helper for [`ApiError::new()`] in constructing [`ApiError::error`]
-    fn format_error_text(
-        number_of_errors: usize,
+        if self.count == 0 {
+            return writeln!(f, "(missing 'errors' field, couldn't gather more info)");
+        }
+
+        writeln!(
+            f,
+            "title: {}",
+            self.title.as_deref().unwrap_or("unknown title")
+        )?;
+        writeln!(
+            f,
+            "detail: {}",
+            self.detail.as_deref().unwrap_or("unknown detail")
+        )?;
+
+        if self.count > 1 {
+            writeln!(f, "(displaying 1 of {} errors)", self.count)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Represents an error occuring with Manga-Dex's API, by kind, so that callers can
+/// decide what to do about it (e.g. with [`ApiError::is_retryable`]).
+///
+/// Every variant has the [`RequestId`] of the failed request, shown in its message.
+#[derive(Error, Debug)]
+pub enum ApiError {
+    /// Every attempt was ratelimited (or the response was 429 without being retried).
+    #[error(
+        "api error\n\nrequest id: {id}\nendpoint: {endpoint:?}\n\
+        ratelimited (status code: 429){}\n",
+        retry_after.map(|d| format!(", last asked to retry after {}s", d.as_secs())).unwrap_or_default()
+    )]
+    RateLimited {
+        id: RequestId,
+        endpoint: Endpoint,
+        /// How long the last 429 response asked to wait, if it said.
+        retry_after: Option<Duration>,
+    },
+    #[error("api error\n\nrequest id: {id}\nendpoint: {endpoint:?}\nstatus code: 404\n{details}")]
+    NotFound {
+        id: RequestId,
+        endpoint: Endpoint,
+        details: ErrorDetails,
+    },
+    /// 401 or 403.
+    #[error(
+        "api error\n\nrequest id: {id}\nendpoint: {endpoint:?}\nstatus code: {}\n{details}",
+        status.as_u16()
+    )]
+    Forbidden {
+        id: RequestId,
+        endpoint: Endpoint,
+        status: StatusCode,
+        details: ErrorDetails,
+    },
+    /// Any 5xx status.
+    #[error(
+        "api error\n\nrequest id: {id}\nendpoint: {endpoint:?}\nstatus code: {}\n{details}",
+        status.as_u16()
+    )]
+    ServerError {
+        id: RequestId,
+        endpoint: Endpoint,
+        status: StatusCode,
+        details: ErrorDetails,
+    },
+    /// Any other error status (e.g. 400), or `"result": "error"` with a successful status.
+    #[error(
+        "api error\n\nrequest id: {id}\nendpoint: {endpoint:?}\nstatus code: {}\n{details}",
+        status.as_u16()
+    )]
+    Rejected {
+        id: RequestId,
+        endpoint: Endpoint,
+        status: StatusCode,
+        details: ErrorDetails,
+    },
+    /// The response was successful, but couldn't be parsed into what was expected.
+    #[error(
+        "api error\n\nrequest id: {id}\nendpoint: {endpoint:?}\n\
+        failed to parse response{}{}\n",
+        path.as_ref().map(|p| format!(" at `{p}`")).unwrap_or_default(),
+        value.as_ref().map(|v| format!("\nvalue: {v}")).unwrap_or_default()
+    )]
+    DeserializeFailed {
+        id: RequestId,
+        endpoint: Endpoint,
+        /// Where in the response parsing failed (e.g. `data[3].attributes.title`),
+        /// or `None` if it wasn't JSON at all.
+        path: Option<String>,
+        /// The (shortened) value at [`Self::DeserializeFailed::path`], if there is one.
+        value: Option<String>,
+        #[source]
+        source: serde_json::Error,
+    },
+    /// The response body was larger than `client.max_response_mib`.
+    #[error(
+        "api error\n\nrequest id: {id}\nendpoint: {endpoint:?}\n\
+        response body too large (limit: {limit} bytes, size: {})\n",
+        size.map_or_else(|| "unknown".to_string(), |s| format!("{s} bytes"))
+    )]
+    BodyTooLarge {
+        id: RequestId,
+        endpoint: Endpoint,
+        limit: usize,
+        /// The body's size in bytes if it's known (e.g, from `Content-Length`).
+        size: Option<u64>,
+    },
+    /// The request couldn't be sent, or its response couldn't be read.
+    #[error("network error during request {id}")]
+    Network {
+        id: RequestId,
+        #[source]
+        source: reqwest::Error,
+    },
+}
+
+impl ApiError {
+    /// Classifies an error response of `endpoint` by its `status`, with the details
+    /// from `r_json` (which also works if they're missing) and its `headers`.
+    #[must_use]
+    pub fn from_response(
+        id: RequestId,
         endpoint: &Endpoint,
+        r_json: &serde_json::Value,
         status: StatusCode,
-        title: &str,
-        detail: &str,
-    ) -> String {
-        let status = status.as_str();
-
-        format!(
-            "api error; displaying 1 of {number_of_errors}\n\n\
-            endpoint: {endpoint:?}\n\
-            status code: {status}\n\
-            title: {title}\n\
-            detail: {detail}\n"
+        headers: &ResponseHeaders,
+    ) -> Self {
+        error!("[{id}] `ApiError` encountered! Faulty JSON: {r_json:#?}");
+
+        let endpoint = endpoint.clone();
+        let details = ErrorDetails {
+            notice: headers.notice.clone(),
+            ..ErrorDetails::parse(r_json)
+        };
+
+        match status {
+            StatusCode::TOO_MANY_REQUESTS => Self::RateLimited {
+                id,
+                endpoint,
+                retry_after: headers.retry_after,
+            },
+            StatusCode::NOT_FOUND => Self::NotFound {
+                id,
+                endpoint,
+                details,
+            },
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Self::Forbidden {
+                id,
+                endpoint,
+                status,
+                details,
+            },
+            s if s.is_server_error() => Self::ServerError {
+                id,
+                endpoint,
+                status,
+                details,
+            },
+            _ => Self::Rejected {
+                id,
+                endpoint,
+                status,
+                details,
+            },
+        }
+    }
+
+    /// Used when a successful response of `endpoint` can't be parsed, see [`JsonPathError`].
+    #[must_use]
+    pub fn deserialize_failed(id: RequestId, endpoint: &Endpoint, e: JsonPathError) -> Self {
+        Self::DeserializeFailed {
+            id,
+            endpoint: endpoint.clone(),
+            path: Some(e.path),
+            value: e.value,
+            source: e.source,
+        }
+    }
+
+    /// Returns the status code of the response which caused this, if there was one.
+    #[must_use]
+    pub const fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::RateLimited { .. } => Some(StatusCode::TOO_MANY_REQUESTS),
+            Self::NotFound { .. } => Some(StatusCode::NOT_FOUND),
+            Self::Forbidden { status, .. }
+            | Self::ServerError { status, .. }
+            | Self::Rejected { status, .. } => Some(*status),
+            Self::DeserializeFailed { .. } | Self::BodyTooLarge { .. } | Self::Network { .. } => {
+                None
+            }
+        }
+    }
+
+    /// Returns the details of the error response which caused this, if it had any.
+    #[must_use]
+    pub const fn details(&self) -> Option<&ErrorDetails> {
+        match self {
+            Self::NotFound { details, .. }
+            | Self::Forbidden { details, .. }
+            | Self::ServerError { details, .. }
+            | Self::Rejected { details, .. } => Some(details),
+            Self::RateLimited { .. }
+            | Self::DeserializeFailed { .. }
+            | Self::BodyTooLarge { .. }
+            | Self::Network { .. } => None,
+        }
+    }
+
+    /// Returns true if trying again later could succeed, i.e. the error was
+    /// a ratelimit, Manga-Dex's fault or a network issue.
+    #[must_use]
+    pub const fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::RateLimited { .. } | Self::ServerError { .. } | Self::Network { .. }
         )
     }
 
-    /// Parses the provided JSON and parses fields to include extra error info.
-    ///
-    /// This also works if these fields are for some reason non-existent, which
-    /// means that this method would also work on actual, valid responses.
+    /// Returns the error's code, see [`Diagnostic::code`].
     #[must_use]
-    pub fn new(endpoint: &Endpoint, r_json: &serde_json::Value, status: StatusCode) -> Self {
-        error!("`ApiError` encountered! Faulty JSON: {r_json:#?}");
+    pub const fn code_str(&self) -> &'static str {
+        match self {
+            Self::RateLimited { .. } => "mdex_dl::api::rate_limited",
+            Self::NotFound { .. } => "mdex_dl::api::not_found",
+            Self::Forbidden { .. } => "mdex_dl::api::forbidden",
+            Self::ServerError { .. } => "mdex_dl::api::server_error",
+            Self::Rejected { .. } => "mdex_dl::api::rejected",
+            Self::DeserializeFailed { .. } => "mdex_dl::api::deserialize_failed",
+            Self::BodyTooLarge { .. } => "mdex_dl::api::body_too_large",
+            Self::Network { .. } => "mdex_dl::api::network",
+        }
+    }
 
-        let errors = r_json.get("errors").and_then(|e| e.as_array());
+    /// Returns the help text for an error response with `status` from the
+    /// [message catalog](`crate::messages`), preferring the one for its API error `code`
+    /// (see [`ErrorDetails::code`]) if there is one.
+    fn status_help(status: StatusCode, code: Option<&str>) -> String {
+        code.and_then(|code| message(&format!("error.{code}")))
+            .or_else(|| message(&format!("status.{}", status.as_u16())))
+            .or_else(|| status.canonical_reason().map(str::to_string))
+            .or_else(|| message("status.unknown"))
+            .unwrap_or_default()
+    }
+}
 
-        let Some(errors) = errors else {
-            return Self::blank(endpoint, status);
-        };
+impl Diagnostic for ApiError {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(self.code_str()))
+    }
 
-        let number_of_errors = errors.len();
-        let first_err = errors.first();
+    fn url<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(docs_url(self.code_str())))
+    }
 
-        let Some(first_err) = first_err else {
-            return Self::blank(endpoint, status);
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        let help = match self {
+            Self::DeserializeFailed { .. } => message("api.deserialize_failed"),
+            Self::BodyTooLarge { .. } => message("api.body_too_large"),
+            Self::Network { .. } => message("api.network"),
+            _ => self.status().map(|status| {
+                let code = self.details().and_then(|d| d.code.as_deref());
+                Self::status_help(status, code)
+            }),
         };
 
-        let title = first_err
-            .get("title")
-            .and_then(|s| s.as_str())
-            .unwrap_or("unknown title");
-        let detail = first_err
-            .get("detail")
-            .and_then(|s| s.as_str())
-            .unwrap_or("unknown detail");
+        help.map(|h| Box::new(h) as Box<dyn fmt::Display>)
+    }
+}
+
+/// Represents an invalid option in the [config](`crate::paths::config_toml`),
+/// pointing at the exact key in the file.
+#[derive(Error, Debug, Diagnostic)]
+pub enum ConfigError {
+    /// The config isn't valid TOML, or an option has the wrong type or value.
+    #[error("{error}")]
+    #[diagnostic(
+        code(mdex_dl::config::invalid_toml),
+        url("{}", docs_url("mdex_dl::config::invalid_toml")),
+        help("{help}")
+    )]
+    InvalidToml {
+        error: String,
+        #[source_code]
+        src: NamedSource<String>,
+        #[label("here!")]
+        pos: SourceSpan,
+        help: String,
+    },
+    /// An option failed extra validation, such as a permit being zero.
+    #[error("{error}")]
+    #[diagnostic(
+        code(mdex_dl::config::invalid_option),
+        url("{}", docs_url("mdex_dl::config::invalid_option")),
+        help("{help}")
+    )]
+    InvalidOption {
+        error: String,
+        #[source_code]
+        src: NamedSource<String>,
+        #[label("here!")]
+        pos: SourceSpan,
+        help: String,
+    },
+}
 
-        let error_text = Self::format_error_text(number_of_errors, endpoint, status, title, detail);
+/// Helper functions for presets
+impl ConfigError {
+    /// Used when the config isn't valid TOML, or an option has the wrong type or value.
+    ///
+    /// `name` is the config's path, `src` its contents and `path` the dotted path of the
+    /// offending option (e.g. `images.quality`, or `.` if it's not known).
+    #[must_use]
+    pub fn from_toml(name: &str, src: &str, path: &str, e: &toml::de::Error) -> Self {
+        let at = if path == "." {
+            String::new()
+        } else {
+            format!(" at `{path}`")
+        };
 
-        Self {
-            error_text,
-            help: Self::get_status_code_help(status),
+        Self::InvalidToml {
+            error: format!("invalid config{at}: {}", e.message().trim_end()),
+            src: NamedSource::new(name, src.to_string()),
+            pos: e.span().unwrap_or_default().into(),
+            help: "check this against the default config (which is created if you delete yours)"
+                .to_string(),
         }
     }
+
+    /// Used when an option fails extra validation, such as a permit being zero.
+    ///
+    /// `pos` is the span of the option's value, see [`crate::config::value_span`].
+    #[must_use]
+    pub fn invalid_option(
+        name: &str,
+        src: &str,
+        pos: Range<usize>,
+        error: String,
+        help: String,
+    ) -> Self {
+        Self::InvalidOption {
+            error,
+            src: NamedSource::new(name, src.to_string()),
+            pos: pos.into(),
+            help,
+        }
+    }
+}
+
+/// Manga-Dex seems to be down, found by the [preflight](`crate::api::preflight`)
+/// check before downloading.
+#[derive(Error, Debug)]
+pub enum PreflightError {
+    /// The ping couldn't be sent, or every attempt was ratelimited.
+    #[error("Manga-Dex's API can't be reached, so the download wasn't started\nreason: {reason}")]
+    Unreachable { reason: String },
+    /// The ping got an error status, or something other than `pong`.
+    #[error(
+        "Manga-Dex's API seems to be down (ping returned status {}), so the download wasn't started",
+        status.as_u16()
+    )]
+    Down { status: StatusCode },
+    /// The status page reports a major outage.
+    #[error("Manga-Dex's status page reports a {indicator} outage: {description}")]
+    Outage {
+        indicator: String,
+        description: String,
+    },
+}
+
+impl Diagnostic for PreflightError {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new("mdex_dl::api::unavailable"))
+    }
+
+    fn url<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(docs_url("mdex_dl::api::unavailable")))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        message("api.unavailable").map(|h| Box::new(h) as Box<dyn fmt::Display>)
+    }
+}
+
+/// The run's [budget](`crate::budget`) was exceeded, so it was aborted.
+#[derive(Error, Debug, Clone)]
+pub enum BudgetExhausted {
+    #[error("Retried {retries} requests, more than `budget.max_retries` ({max}) allows")]
+    Retries { retries: u64, max: u64 },
+    #[error(
+        "{failures} of {requests} requests failed ({percent}%), more than \
+        `budget.max_failure_percent` ({max}%) allows"
+    )]
+    Failures {
+        failures: u64,
+        requests: u64,
+        percent: u64,
+        max: u64,
+    },
+}
+
+impl Diagnostic for BudgetExhausted {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new("mdex_dl::api::budget_exhausted"))
+    }
+
+    fn url<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(docs_url("mdex_dl::api::budget_exhausted")))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        message("api.budget_exhausted").map(|h| Box::new(h) as Box<dyn fmt::Display>)
+    }
 }
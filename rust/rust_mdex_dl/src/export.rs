@@ -0,0 +1,559 @@
+//! Exports the [library](`crate::library::LibraryIndex`) in layouts that other
+//! readers can open directly, see [`ExportLayout`].
+//!
+//! Both layouts also save the manga's cover as `cover.jpg` in its dir, which Komga and
+//! Kavita pick up as the series cover too.
+//!
+//! Exports are incremental: chapter archives that are newer than the chapter's
+//! download are left alone, so exporting into the same dir again is cheap.
+
+use crate::{
+    api::{
+        client::ApiClient,
+        models::{Manga, Status},
+    },
+    config::{ArchiveCompression, Config},
+    library::{ChapterEntry, LibraryIndex, MangaEntry},
+    manifest::ChapterManifest,
+    offline::{fetch_manga, is_offline},
+    paths::manga_save_dir,
+    progress::print_line,
+};
+
+use std::{
+    fmt::Write as _,
+    fs::{self, File},
+    io::{self, BufWriter, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use console::style;
+use image::ImageFormat;
+use isolang::Language;
+use miette::{IntoDiagnostic, Result, miette};
+use reqwest::Client;
+use sanitise_file_name::sanitise;
+use serde::{Deserialize, Serialize};
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+/// The layouts the library can be exported in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportLayout {
+    /// [Mihon's local source](https://mihon.app/docs/guides/local-source/): a dir per manga
+    /// with a `details.json`, `cover.jpg` and a CBZ per chapter.
+    #[default]
+    Mihon,
+    /// [Komga](https://komga.org/docs/guides/scan-analysis-refresh) and Kavita's series
+    /// folders: `Series Name/Series Name - Vol.X Ch.Y.cbz`, with a `ComicInfo.xml` in each CBZ.
+    Komga,
+}
+
+/// The `details.json` read by Mihon's local source.
+///
+/// ## References
+///
+/// - [Mihon docs](https://mihon.app/docs/guides/local-source/advanced)
+#[derive(Serialize, Debug, Clone)]
+pub struct MihonDetails {
+    pub title: String,
+    pub author: String,
+    pub artist: String,
+    pub description: String,
+    pub genre: Vec<String>,
+    /// `"0"` (unknown), `"1"` (ongoing), `"2"` (completed), `"5"` (cancelled) or `"6"` (hiatus).
+    pub status: String,
+}
+
+impl MihonDetails {
+    /// Builds the details of `manga`, or only its title (from `entry`) if it couldn't be fetched.
+    #[must_use]
+    pub fn new(entry: &MangaEntry, manga: Option<&Manga>, language: Language) -> Self {
+        let Some(manga) = manga else {
+            return Self {
+                title: entry.title.clone(),
+                author: String::new(),
+                artist: String::new(),
+                description: String::new(),
+                genre: Vec::new(),
+                status: "0".to_string(),
+            };
+        };
+
+        let status = match manga.data.attributes.status {
+            Status::Ongoing => "1",
+            Status::Completed => "2",
+            Status::Cancelled => "5",
+            Status::Hiatus => "6",
+            Status::Unknown(_) => "0",
+        };
+
+        Self {
+            title: entry.title.clone(),
+            author: manga.people("author").join(", "),
+            artist: manga.people("artist").join(", "),
+            description: manga.description(language),
+            genre: manga.tag_names(language),
+            status: status.to_string(),
+        }
+    }
+}
+
+/// Returns the name of a chapter's archive (without an extension), in a form that
+/// readers parse the chapter number from, e.g. `Vol.2 Ch.11 - I broke through`.
+#[must_use]
+pub fn chapter_archive_name(chapter: &ChapterEntry) -> String {
+    let volume = chapter.volume.as_ref().map(|v| format!("Vol.{v} "));
+    let num = chapter
+        .chapter_number
+        .as_ref()
+        .map_or_else(|| "Oneshot".to_string(), |n| format!("Ch.{n}"));
+    let title = chapter
+        .title
+        .as_deref()
+        .filter(|t| !t.is_empty())
+        .map(|t| format!(" - {t}"));
+
+    // the uuid prevents naming conflicts between versions of the same chapter
+    sanitise(&format!(
+        "{}{num}{} ({})",
+        volume.unwrap_or_default(),
+        title.unwrap_or_default(),
+        &chapter.uuid.to_string()[..8]
+    ))
+}
+
+/// Returns the name of a chapter's archive in a Komga series folder (without an extension),
+/// e.g. `Series Name - Vol.2 Ch.11`.
+#[must_use]
+pub fn komga_archive_name(series: &str, chapter: &ChapterEntry) -> String {
+    let volume = chapter.volume.as_ref().map(|v| format!("Vol.{v} "));
+    let num = chapter
+        .chapter_number
+        .as_ref()
+        .map_or_else(|| "Oneshot".to_string(), |n| format!("Ch.{n}"));
+
+    sanitise(&format!("{series} - {}{num}", volume.unwrap_or_default()))
+}
+
+/// Escapes `text` for use in XML.
+#[must_use]
+pub fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Builds the `ComicInfo.xml` of `chapter`, which Komga and Kavita read metadata from.
+///
+/// ## References
+///
+/// - [ComicInfo schema](https://anansi-project.github.io/docs/comicinfo/schemas/v2.0)
+#[must_use]
+pub fn comic_info(
+    series: &str,
+    chapter: &ChapterEntry,
+    manga: Option<&Manga>,
+    language: Language,
+) -> String {
+    let mut fields = vec![
+        ("Title", chapter.title.clone().unwrap_or_default()),
+        ("Series", series.to_string()),
+        ("Number", chapter.chapter_number.clone().unwrap_or_default()),
+        ("Volume", chapter.volume.clone().unwrap_or_default()),
+    ];
+
+    if let Some(manga) = manga {
+        fields.extend([
+            ("Summary", manga.description(language)),
+            (
+                "Year",
+                manga
+                    .data
+                    .attributes
+                    .year
+                    .map(|y| y.to_string())
+                    .unwrap_or_default(),
+            ),
+            ("Writer", manga.people("author").join(", ")),
+            ("Penciller", manga.people("artist").join(", ")),
+        ]);
+    }
+
+    fields.push(("Translator", chapter.groups.join(", ")));
+
+    if let Some(manga) = manga {
+        fields.push(("Genre", manga.tag_names(language).join(", ")));
+    }
+
+    fields.extend([
+        (
+            "Web",
+            format!("https://mangadex.org/chapter/{}", chapter.uuid),
+        ),
+        ("PageCount", chapter.pages.to_string()),
+        ("LanguageISO", chapter.language.clone()),
+    ]);
+
+    // only japanese manga are read right to left, manhwa and manhua aren't,
+    // and the reading direction isn't known without the manga
+    if let Some(manga) = manga {
+        let manga_value = match manga.data.attributes.original_language.language {
+            Language::Jpn => "YesAndRightToLeft",
+            Language::Kor | Language::Zho => "Yes",
+            _ => "No",
+        };
+
+        fields.push(("Manga", manga_value.to_string()));
+    }
+
+    let mut elements = String::new();
+
+    for (key, value) in fields.into_iter().filter(|(_, value)| !value.is_empty()) {
+        let _ = writeln!(elements, "  <{key}>{}</{key}>", xml_escape(&value));
+    }
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+        <ComicInfo xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+        xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\">\n{elements}</ComicInfo>\n"
+    )
+}
+
+/// Returns the filenames of the chapter's pages in `chapter_dir`, in manifest order.
+///
+/// Older chapters have no manifest, so their files are sorted by name instead.
+///
+/// ## Errors
+///
+/// If the manifest exists but can't be read, or the dir can't be listed.
+pub fn chapter_pages(chapter_dir: &Path) -> Result<Vec<String>> {
+    if let Some(manifest) = ChapterManifest::read(chapter_dir)? {
+        return Ok(manifest.pages.into_iter().map(|p| p.file).collect());
+    }
+
+    let mut files = Vec::new();
+
+    for entry in fs::read_dir(chapter_dir).into_diagnostic()? {
+        let path = entry.into_diagnostic()?.path();
+
+        if path.is_file()
+            && let Some(name) = path.file_name()
+        {
+            files.push(name.to_string_lossy().to_string());
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Returns the options pages are zipped with for `compression`.
+#[must_use]
+pub fn archive_options(compression: ArchiveCompression) -> SimpleFileOptions {
+    let options = SimpleFileOptions::default();
+
+    match compression {
+        ArchiveCompression::Store => options.compression_method(CompressionMethod::Stored),
+        ArchiveCompression::Fast => options
+            .compression_method(CompressionMethod::Deflated)
+            .compression_level(Some(1)),
+        ArchiveCompression::Best => options
+            .compression_method(CompressionMethod::Deflated)
+            .compression_level(Some(9)),
+    }
+}
+
+/// Zips `pages` (each a name in the archive and the file to read it from) as a CBZ
+/// into `writer` with `compression` (see [`archive_options`]), returning it once the
+/// archive is finished. If given, `comic_info` is added as `ComicInfo.xml`.
+///
+/// Pages are streamed from their files into `writer`, so only the archive's index is
+/// kept in memory, however large the chapter is.
+///
+/// ## Errors
+///
+/// If a page can't be read, or the archive can't be written.
+pub fn zip_pages<W: Write + Seek>(
+    pages: &[(String, PathBuf)],
+    writer: W,
+    comic_info: Option<&str>,
+    compression: ArchiveCompression,
+) -> Result<W> {
+    let mut zip = ZipWriter::new(writer);
+    let options = archive_options(compression);
+
+    for (name, path) in pages {
+        zip.start_file(name.as_str(), options).into_diagnostic()?;
+        io::copy(&mut File::open(path).into_diagnostic()?, &mut zip).into_diagnostic()?;
+    }
+
+    if let Some(comic_info) = comic_info {
+        zip.start_file("ComicInfo.xml", options).into_diagnostic()?;
+        zip.write_all(comic_info.as_bytes()).into_diagnostic()?;
+    }
+
+    zip.finish().into_diagnostic()
+}
+
+/// Zips the pages of the chapter in `chapter_dir` (see [`chapter_pages`]) as a CBZ into
+/// `writer` with [`zip_pages`], returning it once the archive is finished.
+///
+/// ## Errors
+///
+/// If propagated from [`chapter_pages`] or [`zip_pages`].
+pub fn zip_chapter<W: Write + Seek>(
+    chapter_dir: &Path,
+    writer: W,
+    comic_info: Option<&str>,
+    compression: ArchiveCompression,
+) -> Result<W> {
+    let pages: Vec<(String, PathBuf)> = chapter_pages(chapter_dir)?
+        .into_iter()
+        .map(|page| {
+            let path = chapter_dir.join(&page);
+            (page, path)
+        })
+        .collect();
+
+    let writer = zip_pages(&pages, writer, comic_info, compression)?;
+    debug!(
+        "Zipped {} pages from {}",
+        pages.len(),
+        chapter_dir.display()
+    );
+    Ok(writer)
+}
+
+/// Zips the chapter in `chapter_dir` into a CBZ at `dest`, see [`zip_chapter`].
+///
+/// ## Errors
+///
+/// If propagated from [`zip_chapter`], or the archive can't be created.
+pub fn write_cbz(
+    chapter_dir: &Path,
+    dest: &Path,
+    comic_info: Option<&str>,
+    compression: ArchiveCompression,
+) -> Result<()> {
+    let partial = dest.with_extension("cbz.partial");
+
+    zip_chapter(
+        chapter_dir,
+        BufWriter::new(File::create(&partial).into_diagnostic()?),
+        comic_info,
+        compression,
+    )?
+    .flush()
+    .into_diagnostic()?;
+    fs::rename(&partial, dest).into_diagnostic()?;
+
+    debug!("Wrote {}", dest.display());
+    Ok(())
+}
+
+/// Returns true if `path` exists and was modified after `since`.
+#[must_use]
+pub fn is_up_to_date(path: &Path, since: DateTime<Utc>) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .is_ok_and(|modified| DateTime::<Utc>::from(modified) >= since)
+}
+
+/// Downloads the cover of `manga` to `dest` as a JPEG, re-encoding it if needed.
+async fn save_cover(client: &Client, manga: &Manga, dest: &Path) -> Result<()> {
+    let url = manga
+        .cover_url()
+        .ok_or_else(|| miette!("manga {} has no cover", manga.uuid()))?;
+
+    let bytes = client
+        .get(url)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .into_diagnostic()?
+        .bytes()
+        .await
+        .into_diagnostic()?;
+
+    if image::guess_format(&bytes).ok() == Some(ImageFormat::Jpeg) {
+        fs::write(dest, &bytes).into_diagnostic()?;
+    } else {
+        let cover = image::load_from_memory(&bytes).into_diagnostic()?;
+        cover
+            .to_rgb8()
+            .save_with_format(dest, ImageFormat::Jpeg)
+            .into_diagnostic()?;
+    }
+
+    Ok(())
+}
+
+/// The counts printed by [`export_library`].
+#[derive(Debug, Default)]
+struct ExportCounts {
+    manga: usize,
+    written: usize,
+    skipped: usize,
+    failed: usize,
+}
+
+/// Exports a single manga into `dest` for [`export_library`].
+async fn export_manga(
+    api: &ApiClient,
+    client: &Client,
+    entry: &MangaEntry,
+    dest: &Path,
+    layout: ExportLayout,
+    cfg: &Config,
+    counts: &mut ExportCounts,
+) -> Result<()> {
+    let (language, policy) = (cfg.client.language, &cfg.naming.policy);
+    let series = policy.dir_name(&entry.title, Some(entry.uuid));
+    let manga_dest = match layout {
+        ExportLayout::Mihon => dest.join(&entry.dir),
+        ExportLayout::Komga => dest.join(&series),
+    };
+    fs::create_dir_all(&manga_dest).into_diagnostic()?;
+
+    // the metadata is only used for the cover and details, so carry on without it
+    let manga = fetch_manga(api, entry.uuid)
+        .await
+        .inspect_err(|e| warn!("Failed to fetch manga {:?}: {e}", entry.title))
+        .ok();
+
+    if layout == ExportLayout::Mihon {
+        let details = MihonDetails::new(entry, manga.as_ref(), language);
+        fs::write(
+            manga_dest.join("details.json"),
+            serde_json::to_string_pretty(&details).into_diagnostic()?,
+        )
+        .into_diagnostic()?;
+    }
+
+    let cover = manga_dest.join("cover.jpg");
+
+    if let Some(manga) = &manga
+        && !cover.exists()
+        && !is_offline()
+        && let Err(e) = save_cover(client, manga, &cover).await
+    {
+        warn!("Failed to save the cover of {:?}: {e}", entry.title);
+    }
+
+    let manga_dir = manga_save_dir()?.join(&entry.dir);
+    let mut names: Vec<String> = Vec::with_capacity(entry.chapters.len());
+
+    for chapter in entry.chapters.values() {
+        let (name, comic_info) = match layout {
+            ExportLayout::Mihon => (chapter_archive_name(chapter), None),
+            ExportLayout::Komga => {
+                let mut name = komga_archive_name(&series, chapter);
+
+                // several versions (e.g. from different groups) of the same chapter
+                if names.contains(&name) {
+                    name = format!("{name} ({})", &chapter.uuid.to_string()[..8]);
+                }
+
+                let comic_info = comic_info(&entry.title, chapter, manga.as_ref(), language);
+                (name, Some(comic_info))
+            }
+        };
+
+        let name = policy.file_stem(&name, Some(chapter.uuid));
+        let archive = manga_dest.join(format!("{name}.cbz"));
+        names.push(name);
+
+        if is_up_to_date(&archive, chapter.downloaded_at) {
+            counts.skipped += 1;
+            continue;
+        }
+
+        match write_cbz(
+            &manga_dir.join(&chapter.dir),
+            &archive,
+            comic_info.as_deref(),
+            cfg.images.archive_compression,
+        ) {
+            Ok(()) => counts.written += 1,
+            Err(e) => {
+                error!("Failed to export {}: {e}", archive.display());
+                counts.failed += 1;
+            }
+        }
+    }
+
+    counts.manga += 1;
+    Ok(())
+}
+
+/// Exports every manga in the library (whose title contains `manga_filter`,
+/// case-insensitive) into `dest`, in `layout` (or `export.library_layout` if not given).
+///
+/// Manga metadata (e.g. authors and covers) is fetched from Manga-Dex, and is
+/// left out (with a warning) if that fails. When [offline](`crate::offline`), it's read
+/// from the search cache instead, and covers are only kept if they were exported before.
+///
+/// ## Errors
+///
+/// If the library index can't be loaded, or a manga's dir can't be written.
+/// Chapters that can't be exported are logged and counted as failed instead.
+pub async fn export_library(
+    cfg: &Config,
+    dest: &Path,
+    layout: Option<ExportLayout>,
+    manga_filter: Option<&str>,
+) -> Result<()> {
+    let layout = layout.unwrap_or(cfg.export.library_layout);
+    let manga_filter = manga_filter.map(str::to_lowercase);
+    let index = LibraryIndex::load()?;
+    let manga: Vec<&MangaEntry> = index
+        .manga
+        .values()
+        .filter(|m| {
+            manga_filter
+                .as_ref()
+                .is_none_or(|f| m.title.to_lowercase().contains(f))
+        })
+        .collect();
+
+    if manga.is_empty() {
+        print_line(style("No downloaded manga found").yellow().italic());
+        return Ok(());
+    }
+
+    let api = ApiClient::new(&cfg.client)?;
+    let client = Client::builder()
+        .user_agent(&cfg.client.user_agent)
+        .build()
+        .into_diagnostic()?;
+
+    let mut counts = ExportCounts::default();
+    info!(
+        "Exporting {} manga to {} ({layout:?})",
+        manga.len(),
+        dest.display()
+    );
+
+    for entry in manga {
+        export_manga(&api, &client, entry, dest, layout, cfg, &mut counts).await?;
+    }
+
+    print_line(
+        style(format!(
+            "Exported {} manga to {}: {} chapters written, {} already up to date, {} failed",
+            counts.manga,
+            dest.display(),
+            counts.written,
+            counts.skipped,
+            counts.failed
+        ))
+        .green(),
+    );
+
+    Ok(())
+}
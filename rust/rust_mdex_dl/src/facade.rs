@@ -0,0 +1,146 @@
+//! Contains [`MdexDl`], which bundles the clients needed to search and download,
+//! so that the crate can be used as a library without wiring them up by hand.
+//!
+//! ```no_run
+//! # async fn example() -> miette::Result<()> {
+//! use rust_mdex_dl::MdexDl;
+//!
+//! let mdex = MdexDl::from_config()?;
+//! let results = mdex.search("yotsuba", 0).await?;
+//! let manga = results.data[0].clone().into();
+//! let chapters = mdex.chapters(&manga).await?;
+//! let summary = mdex.download(manga, chapters).await?;
+//! summary.print();
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use crate::{
+    api::{
+        cache::SearchCache,
+        client::ApiClient,
+        download::{DownloadClient, DownloadSummary},
+        groups::apply_group_preferences,
+        models::{Chapter, Manga, set_title_preference},
+        search::{SearchClient, SearchResults},
+    },
+    config::{Config, load_config},
+};
+
+use miette::Result;
+use uuid::Uuid;
+
+/// The entry point for using the crate as a library, see the [module docs](`self`).
+#[derive(Debug, Clone)]
+pub struct MdexDl {
+    cfg: Config,
+    api: ApiClient,
+    searcher: SearchClient,
+    downloader: DownloadClient,
+}
+
+impl MdexDl {
+    /// Creates the clients for `cfg`, the same way the CLI does,
+    /// and applies its `client.title_preference`.
+    ///
+    /// ## Errors
+    ///
+    /// If propagated from [`ApiClient::new`] or [`DownloadClient::new`].
+    pub fn new(cfg: Config) -> Result<Self> {
+        set_title_preference(cfg.client.title_preference.clone());
+        let api = ApiClient::new(&cfg.client)?;
+        let searcher = SearchClient::new(api.clone(), cfg.client.language)
+            .with_extra_languages(cfg.client.extra_languages.clone())
+            .with_statistics(cfg.search.statistics)
+            .with_rerank(cfg.search.rerank)
+            .with_page_size(cfg.search.page_size)
+            .with_cache(SearchCache::new(
+                Duration::from_mins(cfg.search.cache_minutes),
+                cfg.search.cache_on_disk,
+            ));
+        let downloader = DownloadClient::new(&cfg)?;
+
+        Ok(Self {
+            cfg,
+            api,
+            searcher,
+            downloader,
+        })
+    }
+
+    /// Loads the user's config (without a profile) with [`load_config`],
+    /// then creates the clients for it with [`Self::new`].
+    ///
+    /// ## Errors
+    ///
+    /// If the config is invalid, or propagated from [`Self::new`].
+    pub fn from_config() -> Result<Self> {
+        Self::new(load_config(None, &[])?)
+    }
+
+    /// Returns the config this was created with.
+    #[must_use]
+    pub const fn config(&self) -> &Config {
+        &self.cfg
+    }
+
+    /// Returns the [`ApiClient`], for endpoints this doesn't wrap.
+    #[must_use]
+    pub const fn api(&self) -> &ApiClient {
+        &self.api
+    }
+
+    /// Returns the [`SearchClient`], for searches this doesn't wrap.
+    #[must_use]
+    pub const fn searcher(&self) -> &SearchClient {
+        &self.searcher
+    }
+
+    /// Returns the [`DownloadClient`], for downloads this doesn't wrap.
+    #[must_use]
+    pub const fn downloader(&self) -> &DownloadClient {
+        &self.downloader
+    }
+
+    /// Searches for manga matching `query`, returning the `page`th page of results.
+    ///
+    /// ## Errors
+    ///
+    /// If propagated from [`SearchClient::search`].
+    pub async fn search(&self, query: &str, page: u32) -> Result<SearchResults> {
+        self.searcher.search(query, page).await
+    }
+
+    /// Fetches the manga with `uuid`.
+    ///
+    /// ## Errors
+    ///
+    /// If propagated from [`Manga::new`].
+    pub async fn manga(&self, uuid: Uuid) -> Result<Manga> {
+        Manga::new(&self.api, uuid).await
+    }
+
+    /// Fetches every chapter of `manga` in the configured language,
+    /// with the configured group preferences applied.
+    ///
+    /// ## Errors
+    ///
+    /// If propagated from [`SearchClient::fetch_all_chapters`].
+    pub async fn chapters(&self, manga: &Manga) -> Result<Vec<Chapter>> {
+        let chapters = self.searcher.fetch_all_chapters(manga).await?;
+        Ok(apply_group_preferences(chapters, &self.cfg.groups))
+    }
+
+    /// Downloads `chapters` of `manga` into the library.
+    ///
+    /// ## Errors
+    ///
+    /// If propagated from [`DownloadClient::download_chapters`].
+    pub async fn download(&self, manga: Manga, chapters: Vec<Chapter>) -> Result<DownloadSummary> {
+        self.downloader
+            .download_chapters(&self.api, chapters, manga, &self.cfg.images)
+            .await
+    }
+}
@@ -0,0 +1,244 @@
+//! Contains [`RunRecord`], which describes a single download run, along with
+//! helpers for reading and appending to the [history file](`crate::paths::history_file`).
+//!
+//! The history file is stored as [JSON lines](https://jsonlines.org/),
+//! so appending a run never requires rewriting older records.
+
+use crate::{
+    api::{
+        download::{DownloadSummary, is_dry_run},
+        models::Chapter,
+    },
+    paths::history_file,
+    progress::print_line,
+};
+
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+};
+
+use chrono::{DateTime, Local, Utc};
+use console::style;
+use miette::{IntoDiagnostic, Result};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A chapter as stored in a [`RunRecord`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChapterRecord {
+    pub uuid: Uuid,
+    pub volume: Option<String>,
+    pub chapter_number: Option<String>,
+}
+
+impl From<&Chapter> for ChapterRecord {
+    fn from(chapter: &Chapter) -> Self {
+        let attrs = &chapter.data.attributes;
+
+        Self {
+            uuid: chapter.uuid(),
+            volume: attrs.volume.clone(),
+            chapter_number: attrs.chapter_number.clone(),
+        }
+    }
+}
+
+/// A failed chapter as stored in a [`RunRecord`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FailureRecord {
+    pub chapter: ChapterRecord,
+    pub reason: String,
+}
+
+/// Records a single download run of a manga.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RunRecord {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub manga_uuid: Uuid,
+    pub manga_title: String,
+    pub downloaded: Vec<ChapterRecord>,
+    pub failed: Vec<FailureRecord>,
+    pub total_bytes: usize,
+}
+
+impl RunRecord {
+    /// Constructs a [`RunRecord`] which finished now.
+    #[must_use]
+    pub fn new(
+        started_at: DateTime<Utc>,
+        manga_uuid: Uuid,
+        manga_title: String,
+        summary: &DownloadSummary,
+    ) -> Self {
+        let downloaded = summary.downloaded.iter().map(Into::into).collect();
+        let failed = summary
+            .failed
+            .iter()
+            .map(|(c, reason)| FailureRecord {
+                chapter: c.into(),
+                reason: reason.clone(),
+            })
+            .collect();
+
+        Self {
+            started_at,
+            finished_at: Utc::now(),
+            manga_uuid,
+            manga_title,
+            downloaded,
+            failed,
+            total_bytes: summary.total_bytes,
+        }
+    }
+
+    /// Returns the distinct volumes downloaded in this run, in the order they first appear.
+    ///
+    /// Chapters without a volume are ignored.
+    #[must_use]
+    pub fn volumes(&self) -> Vec<&str> {
+        let mut volumes: Vec<&str> = Vec::new();
+
+        for v in self.downloaded.iter().filter_map(|c| c.volume.as_deref()) {
+            if !volumes.contains(&v) {
+                volumes.push(v);
+            }
+        }
+
+        volumes
+    }
+
+    /// Formats this record as a single (styled) line for [`display_history`].
+    #[allow(clippy::cast_precision_loss)]
+    fn display(&self) -> String {
+        let when = self
+            .started_at
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M");
+
+        let volumes = self.volumes();
+        let volumes = if volumes.is_empty() {
+            String::new()
+        } else {
+            format!(" (vol. {})", volumes.join(", "))
+        };
+
+        let failed = if self.failed.is_empty() {
+            style("0 failed".to_string()).green()
+        } else {
+            style(format!("{} failed", self.failed.len())).red()
+        };
+
+        format!(
+            "{}  {}  {} chapters{}  {:.1} MiB  {}",
+            style(when).dim(),
+            style(&self.manga_title).bold(),
+            self.downloaded.len(),
+            volumes,
+            self.total_bytes as f64 / 1_048_576.0,
+            failed,
+        )
+    }
+}
+
+/// Appends `record` to the [history file](`crate::paths::history_file`),
+/// unless this is a [dry run](`is_dry_run`).
+///
+/// ## Errors
+///
+/// If the history file can't be opened or written to.
+pub fn append_record(record: &RunRecord) -> Result<()> {
+    if is_dry_run() {
+        return Ok(());
+    }
+
+    let path = history_file()?;
+    let line = serde_json::to_string(record).into_diagnostic()?;
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .into_diagnostic()?;
+
+    writeln!(file, "{line}").into_diagnostic()?;
+    debug!("Appended run record to {}", path.display());
+
+    Ok(())
+}
+
+/// Reads all records from the [history file](`crate::paths::history_file`), oldest first.
+///
+/// Lines which can't be parsed are logged and skipped.
+///
+/// ## Errors
+///
+/// If the history file exists but can't be read.
+pub fn read_records() -> Result<Vec<RunRecord>> {
+    let path = history_file()?;
+
+    if !path.try_exists().into_diagnostic()? {
+        return Ok(Vec::new());
+    }
+
+    let raw = fs::read_to_string(&path).into_diagnostic()?;
+    let mut records = Vec::new();
+
+    for (i, line) in raw
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+    {
+        match serde_json::from_str::<RunRecord>(line) {
+            Ok(r) => records.push(r),
+            Err(e) => warn!("Skipping malformed history record on line {}: {e}", i + 1),
+        }
+    }
+
+    Ok(records)
+}
+
+/// Prints past runs, newest first.
+///
+/// Only runs whose manga title contains `manga_filter` (case-insensitive) are
+/// shown, and at most `limit` runs are shown if it's provided.
+///
+/// ## Errors
+///
+/// If propagated from [`read_records`].
+pub fn display_history(manga_filter: Option<&str>, limit: Option<usize>) -> Result<()> {
+    let manga_filter = manga_filter.map(str::to_lowercase);
+
+    let records: Vec<RunRecord> = read_records()?
+        .into_iter()
+        .rev()
+        .filter(|r| {
+            manga_filter
+                .as_ref()
+                .is_none_or(|f| r.manga_title.to_lowercase().contains(f))
+        })
+        .take(limit.unwrap_or(usize::MAX))
+        .collect();
+
+    if records.is_empty() {
+        print_line(style("No download history found").yellow().italic());
+        return Ok(());
+    }
+
+    for r in &records {
+        print_line(r.display());
+
+        for f in &r.failed {
+            print_line(format!(
+                "    {} chapter {} ({}): {}",
+                style("failed").red(),
+                f.chapter.chapter_number.as_deref().unwrap_or("---"),
+                f.chapter.uuid,
+                f.reason
+            ));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,302 @@
+//! Contains utilities for handling downloaded images (pages),
+//! such as detecting their real format with [`ImageFormat`].
+
+use crate::config::{ConvertFormat, Images, SpreadMode, SpreadOrder};
+
+use std::{io::Cursor, path::Path};
+
+use image::{
+    DynamicImage, ImageReader,
+    codecs::{jpeg::JpegEncoder, png::PngEncoder, webp::WebPEncoder},
+    imageops::FilterType,
+};
+use miette::{IntoDiagnostic, Result, bail, miette};
+
+/// The image formats that pages may be served as.
+///
+/// Manga-Dex only accepts JPEG, PNG and GIF uploads, but WEBP is
+/// also recognised since some CDN nodes may re-encode pages.
+///
+/// ## References
+///
+/// - <https://api.mangadex.org/docs/04-chapter/upload/#requirements-and-limitations>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Gif,
+    Webp,
+}
+
+impl ImageFormat {
+    /// Every variant, in no particular order.
+    pub const ALL: [Self; 4] = [Self::Png, Self::Jpeg, Self::Gif, Self::Webp];
+
+    /// Returns the filename extension **without the leading dot**.
+    #[must_use]
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::Gif => "gif",
+            Self::Webp => "webp",
+        }
+    }
+
+    /// Returns the MIME type, e.g. `image/png`.
+    #[must_use]
+    pub const fn mime(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::Gif => "image/gif",
+            Self::Webp => "image/webp",
+        }
+    }
+
+    /// Detects the format from the leading "magic" bytes of `data`.
+    ///
+    /// ## References
+    ///
+    /// - <https://en.wikipedia.org/wiki/List_of_file_signatures>
+    #[must_use]
+    pub fn from_magic(data: &[u8]) -> Option<Self> {
+        match data {
+            [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some(Self::Png),
+            [0xFF, 0xD8, 0xFF, ..] => Some(Self::Jpeg),
+            [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(Self::Gif),
+            [b'R', b'I', b'F', b'F', _, _, _, _, rest @ ..] if rest.starts_with(b"WEBP") => {
+                Some(Self::Webp)
+            }
+            _ => None,
+        }
+    }
+
+    /// Parses a `Content-Type` header value, ignoring parameters such as `charset`.
+    #[must_use]
+    pub fn from_mime(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next()?.trim().to_ascii_lowercase();
+
+        match mime.as_str() {
+            "image/png" => Some(Self::Png),
+            "image/jpeg" | "image/jpg" => Some(Self::Jpeg),
+            "image/gif" => Some(Self::Gif),
+            "image/webp" => Some(Self::Webp),
+            _ => None,
+        }
+    }
+
+    /// Parses a filename extension (without the leading dot), case-insensitively.
+    #[must_use]
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_ascii_lowercase().as_str() {
+            "png" => Some(Self::Png),
+            "jpg" | "jpeg" => Some(Self::Jpeg),
+            "gif" => Some(Self::Gif),
+            "webp" => Some(Self::Webp),
+            _ => None,
+        }
+    }
+}
+
+/// Returns true if `data` looks like an HTML (or XML) document rather than an image.
+///
+/// This happens when a CDN node serves an error page with a `200 OK` status.
+#[must_use]
+pub fn looks_like_markup(data: &[u8]) -> bool {
+    let start = data
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(data.len());
+
+    let head: Vec<u8> = data[start..]
+        .iter()
+        .take(15)
+        .map(u8::to_ascii_lowercase)
+        .collect();
+
+    [&b"<!doctype"[..], b"<html", b"<?xml", b"<head", b"<body"]
+        .iter()
+        .any(|tag| head.starts_with(tag))
+}
+
+/// Checks that the image at `path` can be fully decoded and has non-zero dimensions.
+///
+/// This is CPU-bound, so it should be run with [`tokio::task::spawn_blocking`].
+///
+/// ## Errors
+///
+/// If the image can't be read or decoded, or either of its dimensions are zero.
+pub fn verify_image(path: &Path) -> Result<()> {
+    let image = ImageReader::open(path)
+        .into_diagnostic()?
+        .with_guessed_format()
+        .into_diagnostic()?
+        .decode()
+        .map_err(|e| miette!("failed to decode {}: {e}", path.display()))?;
+
+    if image.width() == 0 || image.height() == 0 {
+        bail!(
+            "image {} has zero dimensions ({}x{})",
+            path.display(),
+            image.width(),
+            image.height()
+        );
+    }
+
+    Ok(())
+}
+
+/// The output of [`postprocess`].
+#[derive(Debug, Clone)]
+pub struct Postprocessed {
+    /// The resulting images in reading order, or `None` if the original image is unchanged.
+    pub images: Option<Vec<(Vec<u8>, ImageFormat)>>,
+    /// Whether the original image is a (landscape) double-page spread.
+    pub is_spread: bool,
+}
+
+/// Returns the two halves of a double-page `spread` in reading order.
+fn split_spread(spread: &DynamicImage, order: SpreadOrder) -> Vec<DynamicImage> {
+    let (width, height) = (spread.width(), spread.height());
+    let left = spread.crop_imm(0, 0, width / 2, height);
+    let right = spread.crop_imm(width / 2, 0, width - width / 2, height);
+
+    match order {
+        SpreadOrder::Rtl => vec![right, left],
+        SpreadOrder::Ltr => vec![left, right],
+    }
+}
+
+/// Downscales `image` to fit within `max_width` x `max_height`, keeping its aspect ratio.
+///
+/// A limit of zero means no limit. Returns `None` if the image already fits.
+fn downscale(image: &DynamicImage, max_width: u32, max_height: u32) -> Option<DynamicImage> {
+    let max_width = if max_width == 0 { u32::MAX } else { max_width };
+    let max_height = if max_height == 0 {
+        u32::MAX
+    } else {
+        max_height
+    };
+
+    if image.width() <= max_width && image.height() <= max_height {
+        return None;
+    }
+
+    Some(image.resize(max_width, max_height, FilterType::Lanczos3))
+}
+
+/// Encodes `image` as `format`. `quality` is only used for JPEG.
+///
+/// ## Errors
+///
+/// If encoding fails.
+pub fn encode(image: &DynamicImage, format: ImageFormat, quality: u8) -> Result<Vec<u8>> {
+    let mut out = Cursor::new(Vec::new());
+
+    let encoded = match format {
+        ImageFormat::Png => image.write_with_encoder(PngEncoder::new(&mut out)),
+        // jpeg has no alpha channel
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut out, quality)),
+        ImageFormat::Webp => DynamicImage::ImageRgba8(image.to_rgba8())
+            .write_with_encoder(WebPEncoder::new_lossless(&mut out)),
+        ImageFormat::Gif => image.write_to(&mut out, image::ImageFormat::Gif),
+    };
+
+    encoded.map_err(|e| miette!("failed to encode image as {format:?}: {e}"))?;
+    Ok(out.into_inner())
+}
+
+/// Applies the post-processing steps in `images_cfg` to `data` (an image in `format`):
+///
+/// 1. double-page spreads are detected, then split or rotated (see [`SpreadMode`])
+/// 2. oversized images are downscaled to [`Images::max_width`] and [`Images::max_height`]
+/// 3. the result is re-encoded as [`Images::convert_to`], if it's set
+///
+/// The image is only decoded if a step needs it, and GIFs are never
+/// processed to avoid losing animations.
+///
+/// This is CPU-bound, so it should be run with [`tokio::task::spawn_blocking`].
+///
+/// ## Errors
+///
+/// If `data` can't be decoded, or re-encoding fails.
+pub fn postprocess(data: &[u8], format: ImageFormat, images_cfg: &Images) -> Result<Postprocessed> {
+    let convert_to = match images_cfg.convert_to {
+        ConvertFormat::None => None,
+        ConvertFormat::Png => Some(ImageFormat::Png),
+        ConvertFormat::Jpeg => Some(ImageFormat::Jpeg),
+        ConvertFormat::Webp => Some(ImageFormat::Webp),
+    };
+
+    let needs_conversion = convert_to.is_some_and(|f| f != format);
+    let needs_decoding = needs_conversion
+        || images_cfg.spreads != SpreadMode::Keep
+        || images_cfg.max_width != 0
+        || images_cfg.max_height != 0;
+
+    if format == ImageFormat::Gif || !needs_decoding {
+        return Ok(Postprocessed {
+            images: None,
+            is_spread: false,
+        });
+    }
+
+    let image = image::load_from_memory(data)
+        .map_err(|e| miette!("failed to decode image for post-processing: {e}"))?;
+
+    let is_spread = image.width() > image.height();
+    let order = images_cfg.spread_order;
+
+    let mut transformed = match images_cfg.spreads {
+        SpreadMode::Split if is_spread => Some(split_spread(&image, order)),
+        // rotate so the page that's read first ends up on top
+        SpreadMode::Rotate if is_spread => Some(vec![match order {
+            SpreadOrder::Rtl => image.rotate270(),
+            SpreadOrder::Ltr => image.rotate90(),
+        }]),
+        _ => None,
+    };
+
+    let (max_width, max_height) = (images_cfg.max_width, images_cfg.max_height);
+    let current = transformed
+        .as_deref()
+        .unwrap_or(std::slice::from_ref(&image));
+
+    let downscaled: Vec<_> = current
+        .iter()
+        .map(|i| downscale(i, max_width, max_height))
+        .collect();
+
+    if downscaled.iter().any(Option::is_some) {
+        transformed = Some(
+            current
+                .iter()
+                .zip(downscaled)
+                .map(|(i, d)| d.unwrap_or_else(|| i.clone()))
+                .collect(),
+        );
+    }
+
+    if transformed.is_none() && !needs_conversion {
+        return Ok(Postprocessed {
+            images: None,
+            is_spread,
+        });
+    }
+
+    let target = convert_to.unwrap_or(format);
+    let quality = images_cfg.convert_quality;
+
+    let images = transformed
+        .unwrap_or_else(|| vec![image])
+        .iter()
+        .map(|i| encode(i, target, quality).map(|data| (data, target)))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Postprocessed {
+        images: Some(images),
+        is_spread,
+    })
+}
@@ -0,0 +1,374 @@
+//! Imports manga from a Tachiyomi/Mihon backup (`.tachibk` or `.proto.gz`) into the
+//! [`DownloadQueue`], so a mobile library can be mirrored with `queue resume`.
+//!
+//! Backups are gzipped [protobuf](https://protobuf.dev/programming-guides/encoding/), which
+//! is decoded by hand here since only a handful of fields are needed:
+//!
+//! - `Backup`: `backupManga` (1) and `backupSources` (101)
+//! - `BackupManga`: `source` (1), `url` (2), `title` (3) and `chapters` (16)
+//! - `BackupChapter`: `url` (1) and `read` (4)
+//! - `BackupSource`: `name` (1) and `sourceId` (2)
+//!
+//! ## References
+//!
+//! - [Mihon's backup models](https://github.com/mihonapp/mihon/tree/main/app/src/main/java/eu/kanade/tachiyomi/data/backup/models)
+
+use crate::{
+    api::{
+        client::ApiClient, download::is_dry_run, groups::apply_group_preferences,
+        legacy::LegacyIds, models::Manga, search::SearchClient,
+    },
+    config::Config,
+    library::LibraryIndex,
+    progress::{print_line, print_status},
+    queue::DownloadQueue,
+};
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::Read,
+    path::Path,
+};
+
+use console::style;
+use flate2::read::GzDecoder;
+use miette::{IntoDiagnostic, Result, bail, miette};
+use uuid::Uuid;
+
+/// A single field of a protobuf message.
+#[derive(Debug, Clone, Copy)]
+enum WireValue<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    /// Fixed-size (32 or 64 bit) values, which none of the fields used here are.
+    Fixed,
+}
+
+/// Iterates over the fields of an encoded protobuf message as `(field number, value)`.
+struct ProtoReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ProtoReader<'a> {
+    const fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+
+        for shift in (0..64).step_by(7) {
+            let Some(&byte) = self.buf.get(self.pos) else {
+                bail!("truncated varint at byte {}", self.pos);
+            };
+
+            self.pos += 1;
+            value |= u64::from(byte & 0x7f) << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        bail!("varint too long at byte {}", self.pos)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.buf.len())
+            .ok_or_else(|| miette!("field of {len} bytes at byte {} is truncated", self.pos))?;
+
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// Reads the next field, returning `None` at the end of the message.
+    #[allow(clippy::cast_possible_truncation)]
+    fn next_field(&mut self) -> Result<Option<(u64, WireValue<'a>)>> {
+        if self.pos >= self.buf.len() {
+            return Ok(None);
+        }
+
+        let key = self.varint()?;
+
+        let value = match key & 0b111 {
+            0 => WireValue::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                WireValue::Fixed
+            }
+            2 => {
+                let len = self.varint()? as usize;
+                WireValue::Bytes(self.take(len)?)
+            }
+            5 => {
+                self.take(4)?;
+                WireValue::Fixed
+            }
+            wire_type => bail!("unsupported wire type {wire_type} at byte {}", self.pos),
+        };
+
+        Ok(Some((key >> 3, value)))
+    }
+}
+
+/// A manga read from a backup.
+#[derive(Debug, Clone, Default)]
+pub struct BackupManga {
+    pub source: u64,
+    pub url: String,
+    pub title: String,
+    /// The urls of the chapters marked as read.
+    pub read_chapters: Vec<String>,
+}
+
+/// Returns the first uuid in the path segments of `url` (e.g. `/manga/{uuid}`).
+#[must_use]
+pub fn uuid_from_url(url: &str) -> Option<Uuid> {
+    url.split(['/', '?', '#'])
+        .find_map(|segment| Uuid::parse_str(segment).ok())
+}
+
+fn parse_string(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+fn parse_chapter(bytes: &[u8]) -> Result<(String, bool)> {
+    let mut reader = ProtoReader::new(bytes);
+    let (mut url, mut read) = (String::new(), false);
+
+    while let Some(field) = reader.next_field()? {
+        match field {
+            (1, WireValue::Bytes(b)) => url = parse_string(b),
+            (4, WireValue::Varint(v)) => read = v != 0,
+            _ => {}
+        }
+    }
+
+    Ok((url, read))
+}
+
+fn parse_manga(bytes: &[u8]) -> Result<BackupManga> {
+    let mut reader = ProtoReader::new(bytes);
+    let mut manga = BackupManga::default();
+
+    while let Some(field) = reader.next_field()? {
+        match field {
+            (1, WireValue::Varint(v)) => manga.source = v,
+            (2, WireValue::Bytes(b)) => manga.url = parse_string(b),
+            (3, WireValue::Bytes(b)) => manga.title = parse_string(b),
+            (16, WireValue::Bytes(b)) => {
+                let (url, read) = parse_chapter(b)?;
+
+                if read {
+                    manga.read_chapters.push(url);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(manga)
+}
+
+/// Parses the (possibly gzipped) backup `raw`, returning its manga
+/// and the names of its sources by id.
+///
+/// ## Errors
+///
+/// If the backup can't be decompressed or isn't valid protobuf.
+pub fn parse_backup(raw: &[u8]) -> Result<(Vec<BackupManga>, HashMap<u64, String>)> {
+    let decompressed;
+
+    let raw = if raw.starts_with(&[0x1f, 0x8b]) {
+        let mut buf = Vec::new();
+        GzDecoder::new(raw)
+            .read_to_end(&mut buf)
+            .map_err(|e| miette!("failed to decompress backup: {e}"))?;
+        decompressed = buf;
+        &decompressed[..]
+    } else {
+        raw
+    };
+
+    let mut reader = ProtoReader::new(raw);
+    let mut manga = Vec::new();
+    let mut sources = HashMap::new();
+
+    while let Some(field) = reader.next_field()? {
+        match field {
+            (1, WireValue::Bytes(b)) => manga.push(parse_manga(b)?),
+            (101, WireValue::Bytes(b)) => {
+                let mut source = ProtoReader::new(b);
+                let (mut name, mut id) = (String::new(), 0);
+
+                while let Some(field) = source.next_field()? {
+                    match field {
+                        (1, WireValue::Bytes(b)) => name = parse_string(b),
+                        (2, WireValue::Varint(v)) => id = v,
+                        _ => {}
+                    }
+                }
+
+                sources.insert(id, name);
+            }
+            _ => {}
+        }
+    }
+
+    Ok((manga, sources))
+}
+
+/// Returns true if `manga` is from a Manga-Dex source. If the backup doesn't list its
+/// sources, any manga with a uuid in its url is assumed to be.
+fn is_mangadex(manga: &BackupManga, sources: &HashMap<u64, String>) -> bool {
+    match sources.get(&manga.source) {
+        Some(name) => name.to_lowercase().contains("mangadex"),
+        None => sources.is_empty() && uuid_from_url(&manga.url).is_some(),
+    }
+}
+
+/// Pairs each of `manga` with the uuid in its url (mapping legacy ids with `legacy`),
+/// logging and leaving out the ones without one.
+fn with_uuids<'a>(manga: &'a [BackupManga], legacy: &LegacyIds) -> Vec<(&'a BackupManga, Uuid)> {
+    manga
+        .iter()
+        .filter_map(|entry| {
+            let uuid = uuid_from_url(&entry.url).or_else(|| legacy.uuid(&entry.url));
+
+            if uuid.is_none() {
+                warn!(
+                    "No uuid in url {:?} of {:?}, skipping",
+                    entry.url, entry.title
+                );
+            }
+
+            Some((entry, uuid?))
+        })
+        .collect()
+}
+
+/// Imports the Manga-Dex manga in the backup at `path`, queueing every chapter that hasn't
+/// been downloaded (and, if `skip_read`, isn't marked as read in the backup).
+///
+/// Chapters are filtered by language and group like any other download. Nothing is
+/// queued in a dry run, only counted.
+///
+/// ## Errors
+///
+/// If the backup can't be read or parsed, or the queue can't be saved. Manga that
+/// can't be fetched are logged and skipped.
+pub async fn import_backup(cfg: &Config, path: &Path, skip_read: bool) -> Result<()> {
+    let raw = fs::read(path).into_diagnostic()?;
+    let (manga, sources) = parse_backup(&raw)
+        .map_err(|e| miette!("failed to parse backup {}: {e}", path.display()))?;
+
+    let total = manga.len();
+    let manga: Vec<BackupManga> = manga
+        .into_iter()
+        .filter(|m| is_mangadex(m, &sources))
+        .collect();
+
+    print_status(format!(
+        "Found {} Manga-Dex manga in the backup ({} from other sources skipped)",
+        manga.len(),
+        total - manga.len()
+    ));
+
+    let api = ApiClient::new(&cfg.client)?;
+    let searcher = SearchClient::new(api.clone(), cfg.client.language)
+        .with_extra_languages(cfg.client.extra_languages.clone());
+    let index = LibraryIndex::load()?;
+    let (mut queued_manga, mut queued_chapters) = (0, 0);
+
+    // older backups have legacy numeric ids instead of uuids
+    let urls = manga.iter().flat_map(|m| {
+        std::iter::once(m.url.as_str()).chain(m.read_chapters.iter().map(String::as_str))
+    });
+    let legacy = LegacyIds::resolve(&api, urls).await.unwrap_or_else(|e| {
+        warn!("Failed to map legacy ids, manga with them will be skipped: {e}");
+        LegacyIds::default()
+    });
+
+    let resolved = with_uuids(&manga, &legacy);
+    let uuids: Vec<Uuid> = resolved.iter().map(|(_, uuid)| *uuid).collect();
+    let mut fetched = searcher.fetch_manga_bulk(&uuids).await.unwrap_or_else(|e| {
+        warn!("Failed to fetch manga in bulk, fetching them one at a time: {e}");
+        HashMap::new()
+    });
+
+    for (entry, uuid) in resolved {
+        let read: HashSet<Uuid> = entry
+            .read_chapters
+            .iter()
+            .filter_map(|url| uuid_from_url(url).or_else(|| legacy.uuid(url)))
+            .collect();
+
+        let prefetched = fetched.remove(&uuid);
+        let manga_and_chapters = async {
+            let manga = match prefetched {
+                Some(v) => v,
+                None => Manga::new(&api, uuid).await?,
+            };
+            let chapters = searcher.fetch_all_chapters(&manga).await?;
+            Ok::<_, miette::Report>((manga, chapters))
+        }
+        .await;
+
+        let (manga, chapters) = match manga_and_chapters {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed to import manga {:?}: {e}", entry.title);
+                continue;
+            }
+        };
+
+        let chapters: Vec<_> = apply_group_preferences(chapters, &cfg.groups)
+            .into_iter()
+            .filter(|c| index.chapter(c.uuid()).is_none())
+            .filter(|c| !(skip_read && read.contains(&c.uuid())))
+            .collect();
+
+        let title = manga.title(cfg.client.language);
+        info!("Importing {} chapters of {title:?}", chapters.len());
+
+        if chapters.is_empty() {
+            continue;
+        }
+
+        if !is_dry_run() {
+            DownloadQueue::update(|queue| queue.enqueue(&manga, &title, &chapters))?;
+        }
+
+        print_line(format!(
+            "{} {} ({} chapters)",
+            style("Queued").green(),
+            style(&title).bold(),
+            chapters.len()
+        ));
+
+        queued_manga += 1;
+        queued_chapters += chapters.len();
+    }
+
+    let verb = if is_dry_run() {
+        "Would queue"
+    } else {
+        "Queued"
+    };
+
+    print_line(
+        style(format!(
+            "{verb} {queued_chapters} chapters of {queued_manga} manga, \
+            run `queue resume` to download them"
+        ))
+        .green(),
+    );
+
+    Ok(())
+}
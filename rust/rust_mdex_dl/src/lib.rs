@@ -0,0 +1,49 @@
+#![doc = include_str!("../README.md")]
+#![warn(clippy::pedantic)]
+
+pub mod api;
+pub mod budget;
+pub mod clean;
+pub mod cli;
+pub mod config;
+pub mod covers;
+pub mod deserializers;
+pub mod disk;
+pub mod duplicates;
+pub mod errors;
+pub mod export;
+pub mod facade;
+pub mod history;
+pub mod images;
+pub mod import;
+pub mod library;
+pub mod logging;
+pub mod manifest;
+pub mod messages;
+pub mod metadata;
+pub mod metrics;
+pub mod naming;
+pub mod notify;
+pub mod offline;
+pub mod order;
+pub mod path_policy;
+pub mod paths;
+pub mod progress;
+pub mod queue;
+pub mod redact;
+pub mod relations;
+pub mod repackage;
+pub mod selection;
+pub mod serve;
+pub mod stats;
+pub mod store;
+pub mod trace_bundle;
+pub mod tracking;
+pub mod update;
+pub mod upgrade;
+pub mod wizard;
+
+#[macro_use]
+extern crate tracing;
+
+pub use facade::MdexDl;
@@ -0,0 +1,367 @@
+//! Contains the [`LibraryIndex`], which records every downloaded manga and chapter.
+//!
+//! The index is stored as JSON in the [library](`crate::paths::manga_save_dir`) itself
+//! (see [`crate::paths::library_index`]), so that it moves along with it.
+
+use crate::{
+    api::models::{Chapter, ChapterNumber, Manga},
+    config::ImageQuality,
+    manifest::{ChapterManifest, sha256_hex},
+    paths::{library_index, manga_save_dir},
+    progress::print_line,
+    store::prune_store_after_delete,
+};
+
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use chrono::{DateTime, Local, Utc};
+use clap::ValueEnum;
+use console::style;
+use miette::{IntoDiagnostic, Result, miette};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A downloaded chapter in a [`MangaEntry`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChapterEntry {
+    pub uuid: Uuid,
+    pub volume: Option<String>,
+    pub chapter_number: Option<String>,
+    pub title: Option<String>,
+    /// The ISO 639-1 code of the chapter's translated language.
+    pub language: String,
+    /// The names of the chapter's scanlation groups.
+    pub groups: Vec<String>,
+    /// The chapter's dir, relative to its [manga's dir](`MangaEntry::dir`).
+    pub dir: PathBuf,
+    /// The total size of the chapter's pages in bytes.
+    pub size: u64,
+    pub pages: usize,
+    /// The SHA-256 hash of the chapter's page hashes (in manifest order),
+    /// which changes if any page is changed, added or removed.
+    pub checksum: String,
+    pub quality: ImageQuality,
+    pub downloaded_at: DateTime<Utc>,
+    /// The chapter's [version](`crate::api::models::ChapterAttributes::version`) when
+    /// it was downloaded, or `None` if it was downloaded before versions were recorded.
+    #[serde(default)]
+    pub version: Option<u32>,
+}
+
+impl ChapterEntry {
+    /// Constructs a new [`ChapterEntry`] from a chapter that was just saved in `dir`.
+    #[must_use]
+    pub fn new(chapter: &Chapter, manifest: &ChapterManifest, dir: PathBuf) -> Self {
+        let attrs = &chapter.data.attributes;
+        let page_hashes: Vec<&str> = manifest.pages.iter().map(|p| p.sha256.as_str()).collect();
+
+        Self {
+            uuid: chapter.uuid(),
+            volume: attrs.volume.clone(),
+            chapter_number: attrs.chapter_number.clone(),
+            title: attrs.title.clone(),
+            language: attrs.translated_language.code().unwrap_or_default(),
+            groups: chapter.group_names(),
+            dir,
+            size: manifest.pages.iter().map(|p| p.size).sum(),
+            pages: manifest.pages.len(),
+            checksum: sha256_hex(page_hashes.join("\n").as_bytes()),
+            quality: manifest.quality.clone(),
+            downloaded_at: manifest.downloaded_at,
+            version: Some(attrs.version),
+        }
+    }
+}
+
+/// A downloaded manga in the [`LibraryIndex`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MangaEntry {
+    pub uuid: Uuid,
+    pub title: String,
+    /// The manga's dir, relative to the library.
+    pub dir: PathBuf,
+    /// When a chapter of this manga was last downloaded.
+    pub updated_at: DateTime<Utc>,
+    /// When this manga was last checked for new chapters (and they were all downloaded),
+    /// so that `watch` only has to fetch chapters newer than this.
+    #[serde(default)]
+    pub polled_at: Option<DateTime<Utc>>,
+    pub chapters: BTreeMap<Uuid, ChapterEntry>,
+}
+
+impl MangaEntry {
+    /// Returns the total size of this manga's chapters in bytes.
+    #[must_use]
+    pub fn size(&self) -> u64 {
+        self.chapters.values().map(|c| c.size).sum()
+    }
+
+    /// Returns the path of `chapter`'s dir (which should be one of this manga's chapters).
+    ///
+    /// ## Errors
+    ///
+    /// If propagated from [`manga_save_dir`].
+    pub fn chapter_path(&self, chapter: &ChapterEntry) -> Result<PathBuf> {
+        Ok(manga_save_dir()?.join(&self.dir).join(&chapter.dir))
+    }
+
+    /// Returns the (sorted, deduped) languages of this manga's chapters.
+    #[must_use]
+    pub fn languages(&self) -> Vec<&str> {
+        let mut languages: Vec<&str> = self
+            .chapters
+            .values()
+            .map(|c| c.language.as_str())
+            .collect();
+
+        languages.sort_unstable();
+        languages.dedup();
+        languages
+    }
+
+    /// Returns this manga's chapters in reading order (by number, then language).
+    #[must_use]
+    pub fn sorted_chapters(&self) -> Vec<&ChapterEntry> {
+        let mut chapters: Vec<&ChapterEntry> = self.chapters.values().collect();
+        chapters.sort_by_key(|c| {
+            (
+                c.chapter_number.as_deref().and_then(ChapterNumber::parse),
+                c.language.clone(),
+            )
+        });
+        chapters
+    }
+}
+
+/// How manga are sorted by [`display_library`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LibrarySort {
+    /// Alphabetically, by title.
+    #[default]
+    Title,
+    /// Most recently updated first.
+    Updated,
+    /// Largest first.
+    Size,
+    /// Most chapters first.
+    Chapters,
+}
+
+/// Records every downloaded manga and chapter, keyed by uuid.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct LibraryIndex {
+    pub manga: BTreeMap<Uuid, MangaEntry>,
+}
+
+impl LibraryIndex {
+    /// Loads the index, returning an empty one if it doesn't exist yet.
+    ///
+    /// ## Errors
+    ///
+    /// If the index exists but can't be read or parsed.
+    pub fn load() -> Result<Self> {
+        let path = library_index()?;
+
+        if !path.try_exists().into_diagnostic()? {
+            return Ok(Self::default());
+        }
+
+        let raw = fs::read_to_string(&path).into_diagnostic()?;
+        serde_json::from_str(&raw)
+            .map_err(|e| miette!("failed to parse library index {}: {e}", path.display()))
+    }
+
+    /// Saves the index, replacing the old one atomically.
+    ///
+    /// ## Errors
+    ///
+    /// If the index can't be serialized or written.
+    pub fn save(&self) -> Result<()> {
+        let path = library_index()?;
+        let partial = path.with_extension("json.partial");
+
+        fs::write(
+            &partial,
+            serde_json::to_string_pretty(self).into_diagnostic()?,
+        )
+        .into_diagnostic()?;
+        fs::rename(&partial, &path).into_diagnostic()?;
+
+        debug!("Saved library index to {}", path.display());
+        Ok(())
+    }
+
+    /// Loads the index, applies `f` to it and saves it.
+    ///
+    /// ## Errors
+    ///
+    /// If propagated from [`Self::load`] or [`Self::save`].
+    pub fn update(f: impl FnOnce(&mut Self)) -> Result<()> {
+        let mut index = Self::load()?;
+        f(&mut index);
+        index.save()
+    }
+
+    /// Records `chapter` as downloaded into `manga_dir` (relative to the library),
+    /// replacing any previous entry for it.
+    pub fn record_chapter(
+        &mut self,
+        manga: &Manga,
+        manga_title: &str,
+        manga_dir: &str,
+        chapter: ChapterEntry,
+    ) {
+        let entry = self
+            .manga
+            .entry(manga.uuid())
+            .or_insert_with(|| MangaEntry {
+                uuid: manga.uuid(),
+                title: manga_title.to_string(),
+                dir: PathBuf::from(manga_dir),
+                updated_at: chapter.downloaded_at,
+                polled_at: None,
+                chapters: BTreeMap::new(),
+            });
+
+        entry.title = manga_title.to_string();
+        entry.dir = PathBuf::from(manga_dir);
+        entry.updated_at = entry.updated_at.max(chapter.downloaded_at);
+        entry.chapters.insert(chapter.uuid, chapter);
+    }
+
+    /// Records that the manga with `uuid` was checked for new chapters at `at`
+    /// (see [`MangaEntry::polled_at`]), if it's in the index.
+    pub fn record_poll(&mut self, uuid: Uuid, at: DateTime<Utc>) {
+        if let Some(entry) = self.manga.get_mut(&uuid) {
+            entry.polled_at = Some(at);
+        }
+    }
+
+    /// Returns the entry for the chapter with `uuid`, if it's been downloaded.
+    #[must_use]
+    pub fn chapter(&self, uuid: Uuid) -> Option<(&MangaEntry, &ChapterEntry)> {
+        self.manga
+            .values()
+            .find_map(|m| m.chapters.get(&uuid).map(|c| (m, c)))
+    }
+}
+
+/// Removes the old copies (at `old_dirs`) of the `downloaded` chapters which were saved
+/// under a different name this time, e.g. because their title or the naming changed.
+///
+/// ## Errors
+///
+/// If the index can't be loaded, or an old copy can't be removed.
+pub fn remove_old_copies(old_dirs: &BTreeMap<Uuid, PathBuf>, downloaded: &[Chapter]) -> Result<()> {
+    let index = LibraryIndex::load()?;
+    let mut removed = false;
+
+    for chapter in downloaded {
+        let (Some(old_dir), Some((manga, entry))) =
+            (old_dirs.get(&chapter.uuid()), index.chapter(chapter.uuid()))
+        else {
+            continue;
+        };
+
+        if *old_dir != manga.chapter_path(entry)? && old_dir.try_exists().into_diagnostic()? {
+            info!(
+                "Removing the old copy of chapter {} at {}",
+                chapter.uuid(),
+                old_dir.display()
+            );
+            fs::remove_dir_all(old_dir).into_diagnostic()?;
+            removed = true;
+        }
+    }
+
+    if removed {
+        prune_store_after_delete();
+    }
+
+    Ok(())
+}
+
+/// Lists the manga in the [`LibraryIndex`] with their chapter counts, languages,
+/// sizes and when they were last updated.
+///
+/// Only manga whose title contains `manga_filter` (case-insensitive) are listed, and if
+/// `language` (an ISO 639-1 code) is given, only chapters in that language are counted.
+///
+/// ## Errors
+///
+/// If propagated from [`LibraryIndex::load`].
+pub fn display_library(
+    manga_filter: Option<&str>,
+    language: Option<&str>,
+    sort: LibrarySort,
+    reverse: bool,
+) -> Result<()> {
+    let manga_filter = manga_filter.map(str::to_lowercase);
+    let index = LibraryIndex::load()?;
+
+    let mut manga: Vec<MangaEntry> = index
+        .manga
+        .into_values()
+        .filter(|m| {
+            manga_filter
+                .as_ref()
+                .is_none_or(|f| m.title.to_lowercase().contains(f))
+        })
+        .map(|mut m| {
+            if let Some(language) = language {
+                m.chapters
+                    .retain(|_, c| c.language.eq_ignore_ascii_case(language));
+            }
+            m
+        })
+        .filter(|m| !m.chapters.is_empty())
+        .collect();
+
+    if manga.is_empty() {
+        print_line(style("No downloaded manga found").yellow().italic());
+        return Ok(());
+    }
+
+    match sort {
+        LibrarySort::Title => manga.sort_by_key(|m| m.title.to_lowercase()),
+        LibrarySort::Updated => manga.sort_by_key(|m| std::cmp::Reverse(m.updated_at)),
+        LibrarySort::Size => manga.sort_by_key(|m| std::cmp::Reverse(m.size())),
+        LibrarySort::Chapters => manga.sort_by_key(|m| std::cmp::Reverse(m.chapters.len())),
+    }
+
+    if reverse {
+        manga.reverse();
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let to_mib = |bytes: u64| bytes as f64 / 1_048_576.0;
+
+    for m in &manga {
+        print_line(format!(
+            "{}  {} chapters  ({})  {:.1} MiB  {}",
+            style(&m.title).bold(),
+            m.chapters.len(),
+            m.languages().join(", "),
+            to_mib(m.size()),
+            style(format!(
+                "updated {}",
+                m.updated_at.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+            ))
+            .dim()
+        ));
+    }
+
+    let chapters: usize = manga.iter().map(|m| m.chapters.len()).sum();
+    let size: u64 = manga.iter().map(MangaEntry::size).sum();
+
+    print_line(
+        style(format!(
+            "{} manga, {chapters} chapters, {:.1} MiB",
+            manga.len(),
+            to_mib(size)
+        ))
+        .green(),
+    );
+
+    Ok(())
+}
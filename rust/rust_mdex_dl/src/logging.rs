@@ -1,32 +1,128 @@
 //! Contains the function [`init_logging`], which is self-explanatory.
 
-use crate::{config::Logging, paths::log_save_dir};
+use crate::{
+    config::Logging,
+    paths::log_save_dir,
+    progress::{is_quiet, multi_progress},
+    redact::RedactingWriter,
+};
 
-use std::fs::File;
+use std::{
+    env,
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
 
 use chrono::Utc;
-use simplelog::{ConfigBuilder, WriteLogger};
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{EnvFilter, Layer, fmt, layer::SubscriberExt, util::SubscriberInitExt};
 
-/// Initialises logging and creates a log file to write all messages to.
-/// This should only be called once.
+/// The log file of this run, set by [`init_logging`].
+static LOG_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Returns the log file of this run, or `None` if logging isn't enabled.
+pub fn log_file_path() -> Option<&'static Path> {
+    LOG_FILE.get().map(PathBuf::as_path)
+}
+
+/// Writes to stderr, hiding the [progress bars](`multi_progress`) while doing so,
+/// so that log lines are printed above them instead of through them.
+struct ProgressAwareStderr;
+
+impl Write for ProgressAwareStderr {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        multi_progress().suspend(|| io::stderr().write_all(buf))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+/// Directives added to every filter, to hide noisy dependencies.
+const IGNORED: &str = "rustyline=off,reqwest::connect=off";
+
+/// Returns an [`EnvFilter`] for `directives`, also hiding the [`IGNORED`] targets.
+fn env_filter(directives: &str) -> EnvFilter {
+    EnvFilter::builder().parse_lossy(format!("{directives},{IGNORED}"))
+}
+
+/// Returns `directives` with every level they set (without a target) raised to at least
+/// `DEBUG`, adding it if there's none. Levels that are already more verbose (i.e. `TRACE`)
+/// are kept, as are directives for specific targets.
+fn raise_to_debug(directives: &str) -> String {
+    let mut raised = false;
+    let mut directives: Vec<String> = directives
+        .split(',')
+        .filter(|d| !d.trim().is_empty())
+        .map(|d| match d.trim().parse::<LevelFilter>() {
+            Ok(level) => {
+                raised = true;
+                level.max(LevelFilter::DEBUG).to_string()
+            }
+            Err(_) => d.to_string(),
+        })
+        .collect();
+
+    if !raised {
+        directives.insert(0, LevelFilter::DEBUG.to_string());
+    }
+
+    directives.join(",")
+}
+
+/// Initialises logging, creating a log file to write events to (filtered by `logging.filter`,
+/// or `RUST_LOG` if it's set) and printing events allowed by `logging.console_filter`
+/// to stderr, above the progress bars.
+///
+/// If `verbose` is set, at least debug events are printed to stderr (see [`raise_to_debug`]),
+/// while [quiet](`is_quiet`) only prints errors. Events are prefixed with the spans they're
+/// in (such as the manga, chapter and page being downloaded). This should only be called once.
 ///
 /// ## Panics
 ///
-/// This function may panic with [`log::SetLoggerError`]
+/// This function may panic with [`tracing_subscriber::util::TryInitError`]
 /// or [`std::io::Error`], which is intentional.
-pub fn init_logging(logging_cfg: &Logging) {
-    if !logging_cfg.enabled {
-        return;
-    }
+pub fn init_logging(logging_cfg: &Logging, verbose: bool) {
+    let file_layer = logging_cfg.enabled.then(|| {
+        let now = Utc::now().format("%Y-%m-%d_%H-%M-%S");
+        let log_file = log_save_dir().unwrap().join(format!("{now}.log"));
+        let writer = Mutex::new(RedactingWriter(File::create(&log_file).unwrap()));
+        let directives = env::var("RUST_LOG").unwrap_or_else(|_| logging_cfg.filter.clone());
+
+        LOG_FILE.set(log_file).unwrap();
+        fmt::layer()
+            .with_writer(writer)
+            .with_ansi(false)
+            .with_target(false)
+            .with_thread_names(true)
+            .with_filter(env_filter(&directives))
+    });
+
+    let console_directives = if verbose {
+        raise_to_debug(&logging_cfg.console_filter)
+    } else if is_quiet() {
+        "ERROR".to_string()
+    } else {
+        logging_cfg.console_filter.clone()
+    };
+
+    // the time and thread are only useful in the log file
+    let console_layer = fmt::layer()
+        .with_writer(|| RedactingWriter(ProgressAwareStderr))
+        .with_ansi(console::colors_enabled_stderr())
+        .with_target(false)
+        .without_time()
+        .with_filter(env_filter(&console_directives));
 
-    let now = Utc::now().format("%Y-%m-%d_%H-%M-%S");
-    let log_file = log_save_dir().unwrap().join(format!("{now}.log"));
-    let config = ConfigBuilder::new()
-        .add_filter_ignore_str("rustyline")
-        .add_filter_ignore_str("reqwest::connect")
-        .set_target_level(log::LevelFilter::Off)
-        .build();
+    tracing_subscriber::registry()
+        .with(file_layer)
+        .with(console_layer)
+        .try_init()
+        .unwrap();
 
-    WriteLogger::init(logging_cfg.filter, config, File::create(log_file).unwrap()).unwrap();
     info!("Hello, world!");
 }
@@ -1,31 +1,62 @@
-#![doc = include_str!("../README.md")]
 #![warn(clippy::pedantic)]
 
-pub mod api;
-pub mod config;
-pub mod deserializers;
-pub mod errors;
-pub mod logging;
-pub mod paths;
-
 #[macro_use]
-extern crate log;
+extern crate tracing;
 
-use crate::{
+use rust_mdex_dl::{
+    MdexDl,
     api::{
         client::ApiClient,
-        download::DownloadClient,
-        models::Manga,
+        download::{is_dry_run, set_dry_run},
+        gaps::report_gaps,
+        groups::apply_group_preferences,
+        legacy::MdLink,
+        models::{Chapter, Manga},
         search::{SearchClient, SearchResults},
     },
-    config::load_config,
+    clean::display_clean,
+    cli::{Cli, Command, ConfigAction, LibraryAction, QueueAction},
+    config::{Chapters, Config, ConfigOverride, available_profiles, load_config},
+    covers::display_covers,
+    duplicates::display_dedupe,
+    export::export_library,
+    history::{RunRecord, append_record, display_history},
+    import::import_backup,
+    library::display_library,
     logging::init_logging,
+    manifest::display_verify,
+    messages::init_messages,
+    metadata::export_metadata,
+    metrics::report_metrics,
+    offline::{ensure_online, is_offline, run_offline, set_offline},
+    order::latest_chapters,
+    paths::{config_dir, config_toml, is_portable, manga_save_dir},
+    progress::{
+        ProgressEvent, SearchResult, emit, print_line, print_status, set_progress_mode, set_quiet,
+    },
+    queue::{DownloadQueue, clear_queue, display_queue, resume_queue},
+    redact::add_config_secrets,
+    relations::related_menu,
+    repackage::repackage_library,
+    selection::{group_by_volume, preview_chapters, select_chapters},
+    serve::serve,
+    stats::display_stats,
+    trace_bundle::{enable_recording, write_bundle},
+    tracking::StatusTracker,
+    update::{display_update, watch},
+    upgrade::display_upgrade,
+    wizard::run_config_wizard,
 };
 
+use std::collections::{HashMap, hash_map::Entry};
+
+use chrono::Utc;
+use clap::Parser;
 use console::{Term, style};
 use dialoguer::{Confirm, Input, Select, theme::ColorfulTheme};
 use isolang::Language;
 use miette::{IntoDiagnostic, Result};
+use tokio::task::JoinHandle;
 
 macro_rules! Input {
     () => {
@@ -45,47 +76,116 @@ macro_rules! Confirm {
     };
 }
 
-#[derive(PartialEq)]
-enum PagePosition {
-    Start,
-    Middle,
-    End,
-    All,
+/// What choosing an option of [`manga_search_menu`] does.
+enum PageAction {
+    Previous,
+    Next,
+    Jump,
+    PageSize,
+    /// Chooses the manga at this index of the page.
+    Choose(usize),
 }
 
-impl PagePosition {
-    fn new(start: u32, end: u32, page: u32) -> Self {
-        if start == end {
-            return Self::All;
-        }
+/// Returns the options for a page of search results (`rows`), which is `page` of
+/// `total_pages`, along with what choosing each of them does.
+fn page_options(
+    rows: Vec<String>,
+    page: u32,
+    total_pages: u32,
+    page_size: u32,
+) -> (Vec<String>, Vec<PageAction>) {
+    let mut options = Vec::new();
+    let mut actions = Vec::new();
+
+    if page > 0 {
+        options.push(style("Previous page").yellow().to_string());
+        actions.push(PageAction::Previous);
+    }
 
-        if page == start && page < end {
-            Self::Start
-        } else if page > start && page < end {
-            Self::Middle
-        } else {
-            Self::End
-        }
+    actions.extend((0..rows.len()).map(PageAction::Choose));
+    options.extend(rows);
+
+    if page + 1 < total_pages {
+        options.push(style("Next page").yellow().to_string());
+        actions.push(PageAction::Next);
+    }
+
+    if total_pages > 1 {
+        options.push(style("Jump to page...").yellow().to_string());
+        actions.push(PageAction::Jump);
     }
+
+    options.push(
+        style(format!("Results per page ({page_size})..."))
+            .yellow()
+            .to_string(),
+    );
+    actions.push(PageAction::PageSize);
+
+    (options, actions)
 }
 
-enum PageAction {
-    Last,
-    Next,
-    Choose,
+/// Asks for a number from 1 to `max`, using `default` if nothing is entered.
+fn ask_number(prompt: &str, default: u32, max: u32) -> Result<u32> {
+    Input!()
+        .with_prompt(prompt)
+        .default(default)
+        .validate_with(|n: &u32| {
+            if (1..=max).contains(n) {
+                Ok(())
+            } else {
+                Err(format!("must be from 1 to {max}"))
+            }
+        })
+        .interact_text()
+        .into_diagnostic()
+}
+
+/// Emits a page of search results as a [`ProgressEvent`] (if in JSON mode).
+fn emit_search_results(searcher: &SearchClient, query: &str, page: u32, results: &SearchResults) {
+    let language = searcher
+        .languages()
+        .first()
+        .copied()
+        .unwrap_or(Language::Eng);
+
+    emit(&ProgressEvent::SearchResults {
+        query: query.to_string(),
+        page,
+        total: results.total,
+        manga: results
+            .data
+            .iter()
+            .map(|md| {
+                let manga: Manga = md.clone().into();
+                SearchResult {
+                    uuid: manga.uuid(),
+                    title: manga.title(language),
+                }
+            })
+            .collect(),
+    });
 }
 
-impl PageAction {
-    fn new(page_pos: PagePosition, next_page_index: usize, chosen_index: usize) -> Self {
-        let next = next_page_index;
-        let last = 0usize;
-
-        match (page_pos, chosen_index) {
-            (PagePosition::Start, i) if i == next => Self::Next,
-            (PagePosition::Middle, i) if i == last => Self::Last,
-            (PagePosition::Middle, i) if i == next => Self::Next,
-            (PagePosition::End, i) if i == last => Self::Last,
-            _ => Self::Choose,
+/// Fetches the manga that `link` refers to, which may use a legacy id (see [`MdLink`]).
+///
+/// Returns `None` (after saying why) if it can't be found.
+async fn manga_from_link(api: &ApiClient, link: MdLink, out: &Term) -> Result<Option<Manga>> {
+    let fetched = async { Manga::new(api, link.manga_uuid(api).await?).await }.await;
+
+    match fetched {
+        Ok(manga) => Ok(Some(manga)),
+        Err(e) => {
+            warn!("Failed to fetch the manga of {link:?}: {e}");
+            out.write_line(
+                &style(format!("Couldn't find that manga: {e}"))
+                    .yellow()
+                    .italic()
+                    .to_string(),
+            )
+            .into_diagnostic()?;
+
+            Ok(None)
         }
     }
 }
@@ -96,12 +196,13 @@ impl PageAction {
 /// Returns the selected `Manga`, or `None` if there's no results/user exits.
 async fn manga_search_menu(
     searcher: &SearchClient,
-    language: Language,
     query: &str,
     out: &Term,
 ) -> Result<Option<Manga>> {
+    // owned, since the page size can be changed from the menu
+    let mut searcher = searcher.clone();
     let mut page = 0u32;
-    let mut pages: Vec<SearchResults> = Vec::new();
+    let mut pages: HashMap<u32, SearchResults> = HashMap::new();
 
     let results = searcher.search(query, page).await?;
 
@@ -112,84 +213,188 @@ async fn manga_search_menu(
         return Ok(None);
     }
 
-    let total_pages = results.total.div_ceil(SearchClient::MAX_MANGA_PAGINATION);
-    pages.reserve(total_pages as usize);
-    pages.push(results);
+    let total = results.total;
+    let mut total_pages = searcher.page_count(total);
+    pages.insert(page, results);
 
-    loop {
-        let results_maybe = pages.get(page as usize);
-
-        let results = match results_maybe {
-            Some(v) => v,
-            None => &searcher.search(query, page).await?,
-        };
+    // the next page, fetched in the background while the current one is shown
+    let mut prefetch: Option<(u32, JoinHandle<Result<SearchResults>>)> = None;
 
-        let mut options = results.display(language);
-        let prompt = format!("Page {}/{}", page + 1, total_pages);
+    loop {
+        if let Entry::Vacant(entry) = pages.entry(page) {
+            let results = match prefetch.take() {
+                Some((prefetched, handle)) if prefetched == page => {
+                    match handle.await.into_diagnostic()? {
+                        Ok(v) => v,
+                        Err(e) => {
+                            warn!("Prefetching page {page} failed, retrying: {e}");
+                            searcher.search(query, page).await?
+                        }
+                    }
+                }
+                other => {
+                    // jumped elsewhere, so the prefetched page isn't needed yet
+                    if let Some((_, handle)) = other {
+                        handle.abort();
+                    }
+                    searcher.search(query, page).await?
+                }
+            };
+
+            entry.insert(results);
+        }
 
-        let page_pos = PagePosition::new(0, total_pages - 1, page);
-        let mut offset = 0usize; // for when "last page" is inserted at index 0
+        let next = page + 1;
 
-        match page_pos {
-            PagePosition::Start => {
-                options.push(style("Next page").yellow().to_string());
-            }
-            PagePosition::Middle => {
-                options.insert(0, style("Last page").yellow().to_string());
-                options.push(style("Next page").yellow().to_string());
-                offset = 1;
-            }
-            PagePosition::End => {
-                options.insert(0, style("Last page").yellow().to_string());
-                offset = 1;
-            }
-            PagePosition::All => {}
+        if next < total_pages && !pages.contains_key(&next) && prefetch.is_none() {
+            let (searcher, query) = (searcher.clone(), query.to_string());
+            debug!("Prefetching page {next} of results for {query:?}");
+            prefetch = Some((
+                next,
+                tokio::spawn(async move { searcher.search(&query, next).await }),
+            ));
         }
 
-        // logically, this wouldn't be the index of the "next page" option if
-        // it wasn't inserted, however, this is "handled" in `PageAction::new()`
-        let next_page = options.len() - 1;
-
-        let chosen_index = Select!()
-            .with_prompt(prompt)
-            .items(options)
-            .interact_opt() // user can exit with 'Esc'/'q'
-            .into_diagnostic()?;
+        let results = &pages[&page];
+        emit_search_results(&searcher, query, page, results);
+
+        let offset = page * searcher.page_size();
+        let first = offset as usize + 1;
+        let prompt = format!(
+            "Page {}/{total_pages} (results {first}-{} of {total})",
+            page + 1,
+            first + results.data.len().saturating_sub(1)
+        );
+        let (options, actions) = page_options(
+            results.display(first, &searcher.languages()),
+            page,
+            total_pages,
+            searcher.page_size(),
+        );
+
+        // the prompt blocks, so let the prefetch keep running on other threads
+        let chosen_index = tokio::task::block_in_place(|| {
+            Select!()
+                .with_prompt(prompt)
+                .items(options)
+                .interact_opt() // user can exit with 'Esc'/'q'
+                .into_diagnostic()
+        })?;
 
         let Some(chosen_index) = chosen_index else {
             return Ok(None);
         };
 
-        let page_action = PageAction::new(page_pos, next_page, chosen_index);
-
-        match page_action {
-            PageAction::Last => page -= 1,
+        match actions[chosen_index] {
+            PageAction::Previous => page -= 1,
             PageAction::Next => page += 1,
-            PageAction::Choose => {
-                return Ok(Some(results.data[chosen_index - offset].clone().into()));
+            PageAction::Jump => {
+                let jump = tokio::task::block_in_place(|| {
+                    ask_number("Jump to page", page + 1, total_pages)
+                })?;
+                page = jump - 1;
             }
+            PageAction::PageSize => {
+                let page_size = tokio::task::block_in_place(|| {
+                    ask_number(
+                        "Results per page",
+                        searcher.page_size(),
+                        SearchClient::MAX_MANGA_PAGINATION,
+                    )
+                })?;
+
+                // stay on the page with the first result shown
+                page = offset / page_size;
+                searcher = searcher.with_page_size(page_size);
+                total_pages = searcher.page_count(total);
+                pages.clear();
+
+                if let Some((_, handle)) = prefetch.take() {
+                    handle.abort();
+                }
+            }
+            PageAction::Choose(i) => return Ok(Some(results.data[i].clone().into())),
         }
     }
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let cfg = load_config()?;
-    info!("Config: {cfg:?}");
-    init_logging(&cfg.logging);
+/// Asks which of `chapters` (of `manga`) to download, grouped by volume,
+/// after previewing them if `preview` (see [`preview_chapters`]).
+///
+/// Returns `None` if the menu is exited or the preview is declined.
+async fn chapter_menu(
+    searcher: &SearchClient,
+    manga: &Manga,
+    chapters: Vec<Chapter>,
+    chapters_cfg: &Chapters,
+) -> Result<Option<Vec<Chapter>>> {
+    if chapters.is_empty() {
+        return Ok(Some(chapters));
+    }
+
+    let aggregate = match searcher.fetch_aggregate(manga).await {
+        Ok(v) => Some(v),
+        Err(e) => {
+            warn!("Failed to fetch volumes, using each chapter's own instead: {e}");
+            None
+        }
+    };
 
+    let groups = group_by_volume(chapters, aggregate.as_ref());
+
+    let language = searcher
+        .languages()
+        .first()
+        .copied()
+        .unwrap_or(Language::Eng);
+
+    if chapters_cfg.preview {
+        // the thread is only a link in the preview, so carry on without it
+        let thread_url = match searcher.fetch_statistics(vec![manga.uuid()]).await {
+            Ok(statistics) => statistics
+                .get(&manga.uuid())
+                .and_then(|s| s.comments)
+                .map(|c| c.thread_url()),
+            Err(e) => {
+                debug!("Failed to fetch the statistics of {}: {e}", manga.uuid());
+                None
+            }
+        };
+
+        if !preview_chapters(&manga.title(language), thread_url.as_deref(), &groups)? {
+            return Ok(None);
+        }
+    }
+
+    select_chapters(&groups, chapters_cfg.attempt_unavailable)
+}
+
+/// Runs the interactive search menu and downloads the chosen manga.
+async fn run_interactive(cfg: &Config) -> Result<()> {
     let out = Term::stdout();
-    let api = ApiClient::new(&cfg.client)?;
-    let searcher = SearchClient::new(api.clone(), cfg.client.language);
-    let downloader = DownloadClient::new(&cfg)?;
+    let queued = DownloadQueue::load()?.pending_count();
+
+    if queued > 0 {
+        print_status(
+            style(format!(
+                "{queued} chapters are left in the download queue, run `queue resume` to download them"
+            ))
+            .yellow(),
+        );
+    }
+    let mdex = MdexDl::new(cfg.clone())?;
+    let searcher = mdex.searcher();
 
     let chosen_manga = loop {
         let query: String = Input!()
-            .with_prompt("Enter a manga")
+            .with_prompt("Enter a manga (or a link to one)")
             .interact_text()
             .into_diagnostic()?;
 
-        let chosen = manga_search_menu(&searcher, cfg.client.language, &query, &out).await?;
+        let chosen = match MdLink::parse(&query) {
+            Some(link) => manga_from_link(mdex.api(), link, &out).await?,
+            None => manga_search_menu(searcher, &query, &out).await?,
+        };
 
         if let Some(v) = chosen {
             break v;
@@ -205,12 +410,244 @@ async fn main() -> Result<()> {
     };
 
     let chapters = searcher.fetch_all_chapters(&chosen_manga).await?;
+    // the report is only advice, so failing to make it shouldn't stop the download
+    if let Err(e) = report_gaps(
+        searcher,
+        &chosen_manga,
+        &chapters,
+        cfg.client.language,
+        cfg.chapters.suggest_languages,
+    )
+    .await
+    {
+        warn!("Failed to check for missing chapters: {e}");
+    }
+    let chapters = apply_group_preferences(chapters, &cfg.groups);
+
+    if cfg.chapters.related {
+        related_menu(cfg, searcher, &chosen_manga).await?;
+    }
 
-    downloader
-        .download_chapters(&api, chapters, chosen_manga, &cfg.images)
-        .await?;
+    let chapters = if cfg.download.latest > 0 {
+        let chapters = latest_chapters(chapters, cfg.download.latest);
+        print_status(
+            style(format!(
+                "Downloading the latest {} chapters",
+                cfg.download.latest
+            ))
+            .green(),
+        );
+        chapters
+    } else {
+        let Some(chapters) = chapter_menu(searcher, &chosen_manga, chapters, &cfg.chapters).await?
+        else {
+            return Ok(());
+        };
+        chapters
+    };
+
+    let started_at = Utc::now();
+    let manga_uuid = chosen_manga.uuid();
+    let manga_title = chosen_manga.title(cfg.client.language);
+
+    let tracker = StatusTracker::new(cfg, mdex.api())?;
+    let summary = mdex.download(chosen_manga.clone(), chapters).await?;
+
+    if let Some(tracker) = &tracker {
+        tracker.sync(&chosen_manga, &summary.downloaded).await;
+    }
 
-    println!();
+    append_record(&RunRecord::new(
+        started_at,
+        manga_uuid,
+        manga_title,
+        &summary,
+    ))?;
+
+    print_status("");
+
+    if !is_dry_run() {
+        summary.print();
+    }
 
     Ok(())
 }
+
+/// Asks which profile to use if the config has any, returning `None` for no profile.
+fn profile_menu() -> Result<Option<String>> {
+    let profiles = available_profiles()?;
+
+    if profiles.is_empty() {
+        return Ok(None);
+    }
+
+    let mut options = vec![style("(no profile)").dim().to_string()];
+    options.extend(profiles.iter().cloned());
+
+    let chosen = Select!()
+        .with_prompt("Choose a profile")
+        .items(options)
+        .interact()
+        .into_diagnostic()?;
+
+    Ok(chosen.checked_sub(1).map(|i| profiles[i].clone()))
+}
+
+/// Runs `command` (or the interactive menu if there's none) with the loaded `cfg`.
+async fn run_command(cfg: &Config, command: Option<Command>) -> Result<()> {
+    match command {
+        None if is_offline() => run_offline(cfg),
+        None => run_interactive(cfg).await,
+        Some(Command::History { manga, limit }) => display_history(manga.as_deref(), limit),
+        Some(Command::Verify { manga }) => display_verify(manga.as_deref()),
+        Some(Command::Clean {
+            manga,
+            yes,
+            orphans,
+        }) => display_clean(manga.as_deref(), yes, orphans),
+        Some(Command::Dedupe {
+            manga,
+            archive,
+            yes,
+        }) => display_dedupe(cfg, manga.as_deref(), archive.as_deref(), yes),
+        Some(Command::Update { manga, check }) => {
+            display_update(cfg, manga.as_deref(), check).await
+        }
+        Some(Command::Upgrade {
+            manga,
+            check,
+            export,
+        }) => display_upgrade(cfg, manga.as_deref(), check, export.as_deref()).await,
+        Some(Command::Covers { manga, locale, all }) => {
+            display_covers(cfg, &manga, &locale, all).await
+        }
+        Some(Command::Watch { interval, manga }) => watch(cfg, manga.as_deref(), interval).await,
+        Some(Command::Queue { action }) => match action {
+            QueueAction::List => display_queue(),
+            QueueAction::Resume => resume_queue(cfg).await,
+            QueueAction::Clear => clear_queue(),
+        },
+        Some(Command::Library {
+            action:
+                LibraryAction::List {
+                    manga,
+                    language,
+                    sort,
+                    reverse,
+                },
+        }) => display_library(manga.as_deref(), language.as_deref(), sort, reverse),
+        Some(Command::Stats { top }) => display_stats(top),
+        Some(Command::Export {
+            dest,
+            layout,
+            manga,
+        }) => export_library(cfg, &dest, layout, manga.as_deref()).await,
+        Some(Command::ExportMetadata {
+            dest,
+            format,
+            manga,
+        }) => export_metadata(cfg, dest.as_deref(), format, manga.as_deref()).await,
+        Some(Command::Repackage {
+            dest,
+            format,
+            by,
+            manga,
+        }) => repackage_library(cfg, &dest, format, by, manga.as_deref()),
+        Some(Command::Serve { bind }) => serve(bind).await,
+        Some(Command::Import { backup, skip_read }) => import_backup(cfg, &backup, skip_read).await,
+        Some(Command::Config { .. }) => unreachable!("handled before loading the config"),
+    }
+}
+
+/// Loads the config (with `profile` and `overrides`), sets up logging and runs the given command.
+async fn run(
+    command: Option<Command>,
+    mut profile: Option<String>,
+    overrides: &[ConfigOverride],
+    verbose: bool,
+) -> Result<()> {
+    if let Some(Command::Config {
+        action: ConfigAction::Init,
+    }) = command
+    {
+        run_config_wizard()?;
+        return Ok(());
+    }
+
+    // offer to set up the config on the first run, rather than silently using the defaults
+    if command.is_none()
+        && Term::stdout().is_term()
+        && !config_toml()?.try_exists().into_diagnostic()?
+        && Confirm!()
+            .with_prompt("No config found, set one up now? (otherwise, the defaults are used)")
+            .default(true)
+            .interact()
+            .into_diagnostic()?
+    {
+        run_config_wizard()?;
+    }
+
+    if command.is_none() && profile.is_none() && Term::stdout().is_term() {
+        profile = profile_menu()?;
+    }
+
+    let cfg = load_config(profile.as_deref(), overrides)?;
+    add_config_secrets(&cfg);
+    init_logging(&cfg.logging, verbose);
+
+    if let Some(profile) = &profile {
+        info!("Using profile {profile:?}");
+    }
+
+    for o in ConfigOverride::from_env().iter().chain(overrides) {
+        info!("Overriding config option {o}");
+    }
+
+    info!(
+        "Using config dir {}, library {} (portable={})",
+        config_dir()?.display(),
+        manga_save_dir()?.display(),
+        is_portable()?
+    );
+    info!("Config: {cfg:?}");
+    init_messages(cfg.client.language)?;
+
+    if let Some(name) = command.as_ref().and_then(Command::needs_network) {
+        ensure_online(name)?;
+    }
+
+    let result = run_command(&cfg, command).await;
+
+    report_metrics(&cfg.metrics);
+    result
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    if cli.trace_bundle.is_some() {
+        enable_recording();
+    }
+
+    set_progress_mode(cli.progress);
+    set_quiet(cli.quiet);
+    set_dry_run(cli.dry_run);
+    set_offline(cli.offline);
+
+    let overrides = cli.config_overrides();
+    let result = run(cli.command, cli.profile.clone(), &overrides, cli.verbose).await;
+
+    if let Err(e) = &result {
+        emit(&ProgressEvent::Error {
+            message: e.to_string(),
+        });
+    }
+
+    if let Some(path) = &cli.trace_bundle {
+        write_bundle(path, result.as_ref().err())?;
+        print_line(format!("Wrote trace bundle to {}", path.display()));
+    }
+
+    result
+}
@@ -0,0 +1,327 @@
+//! Contains [`ChapterManifest`], which is saved as `manifest.json` in every chapter dir.
+//!
+//! Manifests record the SHA-256 hash, size and source url of each saved page, so that
+//! archives can be checked for corruption later on with [`verify_library`].
+//!
+//! They also record which page each of the chapter's files on the CDN was saved as. This
+//! is written [before any page is saved](`ChapterManifest::write_pending`), so that a
+//! chapter downloaded again (after failing) keeps its numbering even if the CDN changed.
+
+use crate::{config::ImageQuality, paths::manga_save_dir, progress::print_line};
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use console::style;
+use miette::{IntoDiagnostic, Result, miette};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// A single saved page in a [`ChapterManifest`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ManifestPage {
+    /// The zero-padded page number (without an extension).
+    pub page: String,
+    /// The filename, relative to the chapter dir.
+    pub file: String,
+    /// The size of the file in bytes.
+    pub size: u64,
+    /// The lowercase hex SHA-256 hash of the file.
+    pub sha256: String,
+    /// The url the page was downloaded from.
+    ///
+    /// Note that split spreads have two pages with the same url.
+    pub source_url: Url,
+    /// Whether this page is (or was split from) a double-page spread.
+    #[serde(default)]
+    pub spread: bool,
+}
+
+/// Describes a downloaded chapter and its pages.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChapterManifest {
+    pub chapter_uuid: Uuid,
+    pub manga_uuid: Uuid,
+    /// The `hash` field of the chapter's CDN info.
+    pub cdn_hash: String,
+    /// The quality the pages were actually downloaded in.
+    pub quality: ImageQuality,
+    /// The configured quality, if the chapter had no images in it and
+    /// [`Self::quality`] was used as a fallback instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested_quality: Option<ImageQuality>,
+    pub downloaded_at: DateTime<Utc>,
+    pub pages: Vec<ManifestPage>,
+    /// Maps the filename of each of the chapter's images on the CDN
+    /// to the page it's saved as (e.g. `"x1-abc.png"` to `"01"`).
+    #[serde(default)]
+    pub cdn_pages: BTreeMap<String, String>,
+}
+
+impl ChapterManifest {
+    /// The filename manifests are saved as in each chapter dir.
+    pub const FILENAME: &str = "manifest.json";
+
+    /// The filename [pending](`Self::write_pending`) manifests are saved as.
+    pub const PENDING_FILENAME: &str = "manifest.pending.json";
+
+    /// Reads the manifest from `chapter_dir`, returning `None` if it doesn't exist.
+    ///
+    /// ## Errors
+    ///
+    /// If the manifest exists but can't be read or parsed.
+    pub fn read(chapter_dir: &Path) -> Result<Option<Self>> {
+        Self::read_file(&chapter_dir.join(Self::FILENAME))
+    }
+
+    /// Reads the [pending](`Self::write_pending`) manifest from `chapter_dir`, falling back
+    /// to the finished one, returning `None` if neither exists.
+    ///
+    /// ## Errors
+    ///
+    /// If a manifest exists but can't be read or parsed.
+    pub fn read_latest(chapter_dir: &Path) -> Result<Option<Self>> {
+        match Self::read_file(&chapter_dir.join(Self::PENDING_FILENAME))? {
+            Some(pending) => Ok(Some(pending)),
+            None => Self::read(chapter_dir),
+        }
+    }
+
+    /// Reads the manifest at `path`, returning `None` if it doesn't exist.
+    fn read_file(path: &Path) -> Result<Option<Self>> {
+        if !path.try_exists().into_diagnostic()? {
+            return Ok(None);
+        }
+
+        let raw = fs::read_to_string(path).into_diagnostic()?;
+        let manifest = serde_json::from_str(&raw)
+            .map_err(|e| miette!("failed to parse manifest {}: {e}", path.display()))?;
+
+        Ok(Some(manifest))
+    }
+
+    /// Writes this manifest into `chapter_dir`, replacing any existing
+    /// one and removing the [pending](`Self::write_pending`) one.
+    ///
+    /// ## Errors
+    ///
+    /// If the manifest can't be serialized or written.
+    pub async fn write(&self, chapter_dir: &Path) -> Result<()> {
+        self.write_file(chapter_dir, Self::FILENAME).await?;
+
+        let pending = chapter_dir.join(Self::PENDING_FILENAME);
+        if tokio::fs::try_exists(&pending).await.into_diagnostic()? {
+            tokio::fs::remove_file(&pending).await.into_diagnostic()?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes this manifest into `chapter_dir` as [`Self::PENDING_FILENAME`], which records
+    /// [`Self::cdn_pages`] while the chapter is downloading (before any page is saved).
+    ///
+    /// ## Errors
+    ///
+    /// If the manifest can't be serialized or written.
+    pub async fn write_pending(&self, chapter_dir: &Path) -> Result<()> {
+        self.write_file(chapter_dir, Self::PENDING_FILENAME).await
+    }
+
+    /// Writes this manifest into `chapter_dir` as `filename`, through a `.partial` file.
+    async fn write_file(&self, chapter_dir: &Path, filename: &str) -> Result<()> {
+        let path = chapter_dir.join(filename);
+        let partial = chapter_dir.join(format!("{filename}.partial"));
+        let raw = serde_json::to_string_pretty(self).into_diagnostic()?;
+
+        tokio::fs::write(&partial, raw).await.into_diagnostic()?;
+        tokio::fs::rename(&partial, &path).await.into_diagnostic()?;
+
+        debug!("Wrote manifest to {}", path.display());
+        Ok(())
+    }
+}
+
+/// Returns the lowercase hex SHA-256 hash of `data`.
+#[must_use]
+pub fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// A problem found with a page by [`verify_library`].
+#[derive(Debug, Clone)]
+pub enum PageProblem {
+    Missing,
+    SizeMismatch { expected: u64, actual: u64 },
+    HashMismatch,
+}
+
+/// The result of verifying a single chapter dir.
+#[derive(Debug, Clone)]
+pub struct ChapterReport {
+    pub chapter_dir: PathBuf,
+    pub problems: Vec<(ManifestPage, PageProblem)>,
+}
+
+/// The result of [`verify_library`].
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    /// Chapters with at least one problem.
+    pub broken: Vec<ChapterReport>,
+    /// The number of chapters that were checked.
+    pub checked_chapters: usize,
+    /// The number of pages that were checked.
+    pub checked_pages: usize,
+    /// Chapter dirs without a manifest, which can't be verified.
+    pub unverifiable: Vec<PathBuf>,
+}
+
+/// Re-hashes every page listed in the manifest in `chapter_dir`.
+///
+/// ## Errors
+///
+/// If a page exists but can't be read.
+pub fn verify_chapter(chapter_dir: &Path, manifest: &ChapterManifest) -> Result<ChapterReport> {
+    let mut problems = Vec::new();
+
+    for page in &manifest.pages {
+        let path = chapter_dir.join(&page.file);
+
+        if !path.try_exists().into_diagnostic()? {
+            problems.push((page.clone(), PageProblem::Missing));
+            continue;
+        }
+
+        let data = fs::read(&path).into_diagnostic()?;
+        let actual = data.len() as u64;
+
+        if actual != page.size {
+            problems.push((
+                page.clone(),
+                PageProblem::SizeMismatch {
+                    expected: page.size,
+                    actual,
+                },
+            ));
+        } else if sha256_hex(&data) != page.sha256 {
+            problems.push((page.clone(), PageProblem::HashMismatch));
+        }
+    }
+
+    Ok(ChapterReport {
+        chapter_dir: chapter_dir.to_path_buf(),
+        problems,
+    })
+}
+
+/// Returns the sorted subdirectories of `dir`.
+fn subdirs(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+
+    for entry in fs::read_dir(dir).into_diagnostic()? {
+        let path = entry.into_diagnostic()?.path();
+
+        if path.is_dir() {
+            dirs.push(path);
+        }
+    }
+
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// Verifies every chapter in the [library](`crate::paths::manga_save_dir`) against its manifest.
+///
+/// Only manga dirs whose name contains `manga_filter` (case-insensitive) are checked.
+///
+/// ## Errors
+///
+/// If the library can't be walked, or a manifest or page can't be read.
+pub fn verify_library(manga_filter: Option<&str>) -> Result<VerifyReport> {
+    let manga_filter = manga_filter.map(str::to_lowercase);
+    let mut report = VerifyReport::default();
+
+    for manga_dir in subdirs(&manga_save_dir()?)? {
+        let name = manga_dir
+            .file_name()
+            .map(|n| n.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        if manga_filter.as_ref().is_some_and(|f| !name.contains(f)) {
+            continue;
+        }
+
+        for chapter_dir in subdirs(&manga_dir)? {
+            let Some(manifest) = ChapterManifest::read(&chapter_dir)? else {
+                report.unverifiable.push(chapter_dir);
+                continue;
+            };
+
+            let chapter_report = verify_chapter(&chapter_dir, &manifest)?;
+            report.checked_chapters += 1;
+            report.checked_pages += manifest.pages.len();
+
+            if !chapter_report.problems.is_empty() {
+                report.broken.push(chapter_report);
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Runs [`verify_library`] and prints the report.
+///
+/// ## Errors
+///
+/// If propagated from [`verify_library`].
+pub fn display_verify(manga_filter: Option<&str>) -> Result<()> {
+    let report = verify_library(manga_filter)?;
+
+    for chapter in &report.broken {
+        print_line(style(chapter.chapter_dir.display()).bold());
+
+        for (page, problem) in &chapter.problems {
+            let problem = match problem {
+                PageProblem::Missing => "missing".to_string(),
+                PageProblem::SizeMismatch { expected, actual } => {
+                    format!("size mismatch (expected {expected} bytes, got {actual} bytes)")
+                }
+                PageProblem::HashMismatch => "hash mismatch (corrupted)".to_string(),
+            };
+
+            print_line(format!("    {} {}: {problem}", style("✗").red(), page.file));
+        }
+    }
+
+    for dir in &report.unverifiable {
+        print_line(format!(
+            "{} {} (no manifest)",
+            style("?").yellow(),
+            style(dir.display()).dim()
+        ));
+    }
+
+    let broken_pages: usize = report.broken.iter().map(|c| c.problems.len()).sum();
+    let summary = format!(
+        "Checked {} pages in {} chapters: {} broken pages in {} chapters, {} chapters without a manifest",
+        report.checked_pages,
+        report.checked_chapters,
+        broken_pages,
+        report.broken.len(),
+        report.unverifiable.len(),
+    );
+
+    if report.broken.is_empty() {
+        print_line(style(summary).green());
+    } else {
+        print_line(style(summary).red());
+    }
+
+    Ok(())
+}
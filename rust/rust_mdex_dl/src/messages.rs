@@ -0,0 +1,185 @@
+//! Contains the [`MessageCatalog`], which stores user-facing help text for errors.
+//!
+//! Built-in messages are in English, but any of them can be overridden (or translated)
+//! by placing a [messages file](`crate::paths::messages_toml`) next to the config, e.g:
+//!
+//! ```toml
+//! [status]
+//! 429 = "ratelimited by mangadex, try again later"
+//!
+//! [error]
+//! captcha_required = "solve the captcha on the website first"
+//!
+//! [api]
+//! body_too_large = "the response was too big"
+//! ```
+//!
+//! Nested tables are flattened into dotted keys, so the above sets `status.429`,
+//! `error.captcha_required` and `api.body_too_large`. Unknown keys are ignored
+//! (with a warning), except under `error`.
+//!
+//! Error responses are explained by their API error code first (`error.{code}`,
+//! see [`ErrorDetails::code`](`crate::errors::ErrorDetails::code`)), falling back
+//! to their status (`status.{status}`).
+
+use crate::paths::messages_toml;
+
+use std::{collections::HashMap, fs, sync::OnceLock};
+
+use isolang::Language;
+use miette::{IntoDiagnostic, Result, miette};
+
+/// The catalog used by [`message`], set once with [`init_messages`].
+static CATALOG: OnceLock<MessageCatalog> = OnceLock::new();
+
+/// The built-in (English) messages, keyed by `"{category}.{code}"`.
+///
+/// API error codes (`error.{code}`) aren't here, since Manga-Dex doesn't document them;
+/// they can only be set in the messages file.
+const DEFAULT_MESSAGES: [(&str, &str); 13] = [
+    ("status.400", "check if this link is actually valid"),
+    (
+        "status.401",
+        "authentication needed. (you shouldn't be seeing this!)",
+    ),
+    (
+        "status.403",
+        "you lack permission to access this. try something else",
+    ),
+    ("status.404", "check if this link is actually valid"),
+    (
+        "status.429",
+        "you've been ratelimited and all retry attempts have failed. wait a while before retrying",
+    ),
+    (
+        "status.500",
+        "something went wrong with mangadex, consider retrying",
+    ),
+    ("status.503", "try again in a few minutes"),
+    ("status.unknown", "no reason found, sorry :("),
+    (
+        "api.body_too_large",
+        "raise `client.max_response_mib` in your config if this is expected",
+    ),
+    (
+        "api.deserialize_failed",
+        "mangadex may have changed its api, please report this along with the log",
+    ),
+    (
+        "api.network",
+        "check your internet connection and try again",
+    ),
+    (
+        "api.budget_exhausted",
+        "mangadex (or your connection) is probably having trouble. try again later, \
+         or raise the limits in the `[budget]` section of your config",
+    ),
+    (
+        "api.unavailable",
+        "nothing was downloaded. check https://status.mangadex.org and try again later \
+         (or set `preflight.enabled = false` to skip this check)",
+    ),
+];
+
+/// Stores user-facing messages keyed by `"{category}.{code}"`.
+#[derive(Debug, Clone)]
+pub struct MessageCatalog {
+    messages: HashMap<String, String>,
+}
+
+impl Default for MessageCatalog {
+    fn default() -> Self {
+        let messages = DEFAULT_MESSAGES
+            .iter()
+            .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+            .collect();
+
+        Self { messages }
+    }
+}
+
+impl MessageCatalog {
+    /// Loads the built-in messages, then applies overrides from the
+    /// [messages file](`crate::paths::messages_toml`) for `language` if it exists.
+    ///
+    /// ## Errors
+    ///
+    /// If the messages file exists but can't be read or parsed as TOML.
+    pub fn load(language: Language) -> Result<Self> {
+        let mut catalog = Self::default();
+        let path = messages_toml(language)?;
+
+        if !path.try_exists().into_diagnostic()? {
+            return Ok(catalog);
+        }
+
+        let raw = fs::read_to_string(&path).into_diagnostic()?;
+        let table: toml::Table =
+            toml::from_str(&raw).map_err(|e| miette!("failed to parse {}: {e}", path.display()))?;
+
+        let mut overrides = Vec::new();
+        Self::flatten("", &toml::Value::Table(table), &mut overrides);
+
+        for (key, value) in overrides {
+            if !key.starts_with("error.") && !catalog.messages.contains_key(&key) {
+                warn!("Unknown message key {key:?} in {}", path.display());
+                continue;
+            }
+
+            catalog.messages.insert(key, value);
+        }
+
+        info!("Loaded message overrides from {}", path.display());
+        Ok(catalog)
+    }
+
+    /// Flattens nested TOML tables into `(dotted.key, text)` pairs.
+    fn flatten(prefix: &str, value: &toml::Value, out: &mut Vec<(String, String)>) {
+        match value {
+            toml::Value::Table(table) => {
+                for (k, v) in table {
+                    let key = if prefix.is_empty() {
+                        k.clone()
+                    } else {
+                        format!("{prefix}.{k}")
+                    };
+
+                    Self::flatten(&key, v, out);
+                }
+            }
+            toml::Value::String(s) => out.push((prefix.to_string(), s.clone())),
+            other => warn!("Ignoring non-string message {prefix:?}={other}"),
+        }
+    }
+
+    /// Returns the message stored under `key`, if any.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.messages.get(key).map(String::as_str)
+    }
+}
+
+/// Sets the global catalog used by [`message`]. This should only be called once.
+///
+/// ## Errors
+///
+/// If propagated from [`MessageCatalog::load`].
+pub fn init_messages(language: Language) -> Result<()> {
+    let catalog = MessageCatalog::load(language)?;
+
+    if CATALOG.set(catalog).is_err() {
+        warn!("`init_messages()` called more than once, ignoring");
+    }
+
+    Ok(())
+}
+
+/// Looks up `key` in the global catalog, falling back
+/// to the built-in messages if it hasn't been initialised.
+#[must_use]
+pub fn message(key: &str) -> Option<String> {
+    CATALOG
+        .get_or_init(MessageCatalog::default)
+        .get(key)
+        .map(str::to_string)
+}
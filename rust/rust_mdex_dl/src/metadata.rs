@@ -0,0 +1,364 @@
+//! Writes [`SeriesMetadata`] for each manga in the library as `series.json` and/or
+//! `tvshow.nfo` (the Kodi/Jellyfin format), for media managers to read.
+//!
+//! Also contains [`ChapterMetadata`], which is written as `chapter.json` into each
+//! chapter dir as it's downloaded (if `storage.chapter_json` is set).
+
+use crate::{
+    api::{
+        client::ApiClient,
+        language::MdLanguage,
+        models::{
+            Chapter, ChapterAttributes, ContentRating, MAX_STATISTICS_IDS, Manga, MangaStatistics,
+            PublicationDemographic, Status,
+        },
+    },
+    config::Config,
+    export::xml_escape,
+    library::{LibraryIndex, MangaEntry},
+    offline::{fetch_manga, is_offline},
+    paths::manga_save_dir,
+    progress::print_line,
+};
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    fs,
+    path::Path,
+};
+
+use clap::ValueEnum;
+use console::style;
+use isolang::Language;
+use miette::{IntoDiagnostic, Result};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Which metadata files `export-metadata` writes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum MetadataFormat {
+    /// `series.json`
+    Json,
+    /// `tvshow.nfo`
+    Nfo,
+    /// Both of the above.
+    #[default]
+    Both,
+}
+
+/// A scanlation group of a chapter, see [`ChapterMetadata::groups`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChapterGroup {
+    pub uuid: Uuid,
+    pub name: Option<String>,
+}
+
+/// The details of a downloaded chapter, as written to `chapter.json`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChapterMetadata {
+    pub chapter_uuid: Uuid,
+    pub manga_uuid: Uuid,
+    pub groups: Vec<ChapterGroup>,
+    pub uploader: Option<String>,
+    pub language: MdLanguage,
+    /// The `hash` field of the chapter's CDN info, which changes if its pages do.
+    pub cdn_hash: String,
+    /// The chapter's attributes, as returned by Manga-Dex.
+    pub attributes: ChapterAttributes,
+    /// The url of the chapter's forum thread, if it had one when it was downloaded.
+    #[serde(default)]
+    pub thread_url: Option<String>,
+}
+
+impl ChapterMetadata {
+    /// The filename chapter metadata is saved as in each chapter dir.
+    pub const FILENAME: &str = "chapter.json";
+
+    /// Builds the metadata of `chapter`, whose pages came from the CDN with `cdn_hash`
+    /// and whose forum thread is at `thread_url`.
+    #[must_use]
+    pub fn new(chapter: &Chapter, cdn_hash: &str, thread_url: Option<String>) -> Self {
+        Self {
+            chapter_uuid: chapter.uuid(),
+            manga_uuid: chapter.parent_uuid(),
+            groups: chapter
+                .groups()
+                .map(|(uuid, name)| ChapterGroup {
+                    uuid,
+                    name: name.map(str::to_string),
+                })
+                .collect(),
+            uploader: chapter.uploader().map(str::to_string),
+            language: chapter.data.attributes.translated_language,
+            cdn_hash: cdn_hash.to_string(),
+            attributes: chapter.data.attributes.clone(),
+            thread_url,
+        }
+    }
+
+    /// Writes this into `chapter_dir` as [`Self::FILENAME`] through a `.partial` file,
+    /// replacing any existing file.
+    ///
+    /// ## Errors
+    ///
+    /// If this can't be serialized or written.
+    pub async fn write(&self, chapter_dir: &Path) -> Result<()> {
+        let path = chapter_dir.join(Self::FILENAME);
+        let partial = chapter_dir.join(format!("{}.partial", Self::FILENAME));
+        let raw = serde_json::to_string_pretty(self).into_diagnostic()?;
+
+        tokio::fs::write(&partial, raw).await.into_diagnostic()?;
+        tokio::fs::rename(&partial, &path).await.into_diagnostic()?;
+        debug!("Wrote chapter metadata to {}", path.display());
+        Ok(())
+    }
+}
+
+/// A title in another language, see [`SeriesMetadata::alt_titles`].
+#[derive(Serialize, Debug, Clone)]
+pub struct AltTitle {
+    /// The ISO 639-1 code of the title's language (with `-ro` if it's romanized), if it has one.
+    pub language: Option<String>,
+    pub title: String,
+}
+
+/// The metadata of a manga, as written to `series.json`.
+#[derive(Serialize, Debug, Clone)]
+pub struct SeriesMetadata {
+    pub uuid: Uuid,
+    pub title: String,
+    pub alt_titles: Vec<AltTitle>,
+    pub description: String,
+    pub authors: Vec<String>,
+    pub artists: Vec<String>,
+    pub tags: Vec<String>,
+    pub status: Status,
+    pub year: Option<u32>,
+    pub original_language: Option<String>,
+    pub demographic: Option<PublicationDemographic>,
+    pub content_rating: ContentRating,
+    pub last_volume: Option<String>,
+    pub last_chapter: Option<String>,
+    /// External links by Manga-Dex's keys (e.g. `"mal"`, `"al"`), along with `"md"`.
+    ///
+    /// ## References
+    ///
+    /// - [MangaDex docs](https://api.mangadex.org/docs/3-enumerations/#manga-links-data)
+    pub links: BTreeMap<String, String>,
+    /// The url of the manga's forum thread, if it has one.
+    pub thread_url: Option<String>,
+}
+
+impl SeriesMetadata {
+    /// Builds the metadata of `manga`, with its title, description and tags in `language`,
+    /// and its forum thread from `statistics` if given.
+    #[must_use]
+    pub fn new(manga: &Manga, language: Language, statistics: Option<&MangaStatistics>) -> Self {
+        let attrs = &manga.data.attributes;
+
+        let alt_titles = attrs
+            .alt_titles
+            .iter()
+            .flatten()
+            .map(|(lang, title)| AltTitle {
+                language: lang.code(),
+                title: title.clone(),
+            })
+            .collect();
+
+        let mut links: BTreeMap<String, String> = attrs
+            .links
+            .clone()
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        links.insert(
+            "md".to_string(),
+            format!("https://mangadex.org/title/{}", manga.uuid()),
+        );
+
+        Self {
+            uuid: manga.uuid(),
+            title: manga.title(language),
+            alt_titles,
+            description: manga.description(language),
+            authors: manga.people("author"),
+            artists: manga.people("artist"),
+            tags: manga.tag_names(language),
+            status: attrs.status.clone(),
+            year: attrs.year,
+            original_language: attrs.original_language.code(),
+            demographic: attrs.publication_demographic.clone(),
+            content_rating: attrs.content_rating.clone(),
+            last_volume: attrs.last_volume.clone(),
+            last_chapter: attrs.last_chapter.clone(),
+            links,
+            thread_url: statistics.and_then(|s| s.comments).map(|c| c.thread_url()),
+        }
+    }
+
+    /// Renders this as a Kodi-style `tvshow.nfo`.
+    ///
+    /// ## References
+    ///
+    /// - [Kodi wiki](https://kodi.wiki/view/NFO_files/TV_shows)
+    #[must_use]
+    pub fn to_nfo(&self) -> String {
+        let mut nfo = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<tvshow>\n",
+        );
+
+        let mut element = |key: &str, value: &str| {
+            if !value.is_empty() {
+                let _ = writeln!(nfo, "  <{key}>{}</{key}>", xml_escape(value));
+            }
+        };
+
+        element("title", &self.title);
+
+        for alt in &self.alt_titles {
+            element("originaltitle", &alt.title);
+        }
+
+        element("plot", &self.description);
+        element(
+            "status",
+            match self.status {
+                Status::Ongoing | Status::Hiatus => "Continuing",
+                Status::Completed | Status::Cancelled => "Ended",
+                Status::Unknown(_) => "",
+            },
+        );
+        element(
+            "year",
+            &self.year.map(|y| y.to_string()).unwrap_or_default(),
+        );
+
+        for tag in &self.tags {
+            element("genre", tag);
+        }
+
+        for person in self.authors.iter().chain(&self.artists) {
+            element("credits", person);
+        }
+
+        let _ = writeln!(
+            nfo,
+            "  <uniqueid type=\"mangadex\" default=\"true\">{}</uniqueid>",
+            self.uuid
+        );
+        nfo.push_str("</tvshow>\n");
+        nfo
+    }
+}
+
+/// Fetches the statistics (for their forum threads) of `manga`, in batches.
+///
+/// Statistics are only a nicety, so batches that fail are logged and left out.
+async fn fetch_statistics(
+    api: &ApiClient,
+    manga: &[&MangaEntry],
+) -> HashMap<Uuid, MangaStatistics> {
+    let mut statistics = HashMap::with_capacity(manga.len());
+
+    for batch in manga.chunks(MAX_STATISTICS_IDS) {
+        match MangaStatistics::fetch(api, batch.iter().map(|m| m.uuid).collect()).await {
+            Ok(fetched) => statistics.extend(fetched),
+            Err(e) => warn!(
+                "Failed to fetch the statistics of {} manga: {e}",
+                batch.len()
+            ),
+        }
+    }
+
+    statistics
+}
+
+/// Fetches the metadata of every manga in the library (whose title contains `manga_filter`,
+/// case-insensitive) and writes it into each manga's dir, under `dest` if given or the
+/// library otherwise.
+///
+/// When [offline](`crate::offline`), the metadata is read from the search cache instead
+/// (without forum threads), skipping manga that aren't in it.
+///
+/// ## Errors
+///
+/// If the library index can't be loaded, or a file can't be written. Manga that
+/// can't be fetched are logged and skipped.
+pub async fn export_metadata(
+    cfg: &Config,
+    dest: Option<&Path>,
+    format: MetadataFormat,
+    manga_filter: Option<&str>,
+) -> Result<()> {
+    let manga_filter = manga_filter.map(str::to_lowercase);
+    let index = LibraryIndex::load()?;
+    let manga: Vec<&MangaEntry> = index
+        .manga
+        .values()
+        .filter(|m| {
+            manga_filter
+                .as_ref()
+                .is_none_or(|f| m.title.to_lowercase().contains(f))
+        })
+        .collect();
+
+    if manga.is_empty() {
+        print_line(style("No downloaded manga found").yellow().italic());
+        return Ok(());
+    }
+
+    let api = ApiClient::new(&cfg.client)?;
+    let root = match dest {
+        Some(dest) => dest.to_path_buf(),
+        None => manga_save_dir()?,
+    };
+    let statistics = if is_offline() {
+        HashMap::new()
+    } else {
+        fetch_statistics(&api, &manga).await
+    };
+    let (mut written, mut failed) = (0, 0);
+
+    for entry in manga {
+        let metadata = match fetch_manga(&api, entry.uuid).await {
+            Ok(manga) => {
+                SeriesMetadata::new(&manga, cfg.client.language, statistics.get(&entry.uuid))
+            }
+            Err(e) => {
+                error!("Failed to fetch manga {:?}: {e}", entry.title);
+                failed += 1;
+                continue;
+            }
+        };
+
+        let dir = root.join(&entry.dir);
+        fs::create_dir_all(&dir).into_diagnostic()?;
+
+        if matches!(format, MetadataFormat::Json | MetadataFormat::Both) {
+            fs::write(
+                dir.join("series.json"),
+                serde_json::to_string_pretty(&metadata).into_diagnostic()?,
+            )
+            .into_diagnostic()?;
+        }
+
+        if matches!(format, MetadataFormat::Nfo | MetadataFormat::Both) {
+            fs::write(dir.join("tvshow.nfo"), metadata.to_nfo()).into_diagnostic()?;
+        }
+
+        debug!("Wrote metadata of {:?} to {}", entry.title, dir.display());
+        written += 1;
+    }
+
+    print_line(
+        style(format!(
+            "Wrote metadata for {written} manga to {} ({failed} failed)",
+            root.display()
+        ))
+        .green(),
+    );
+
+    Ok(())
+}
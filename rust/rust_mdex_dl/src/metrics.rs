@@ -0,0 +1,378 @@
+//! Contains the run's metrics: counters and histograms of API calls, retries,
+//! ratelimits, image downloads and chapter durations, aggregated in memory.
+//!
+//! Metrics are always collected (it's only a few atomics), then printed at the end
+//! of the run with [`Snapshot::print`] if `metrics.summary` is set, and written as
+//! JSON to `metrics.json_path` if it's set. In `watch` mode, the JSON is rewritten
+//! after every update, so the file always has the totals so far.
+
+use std::{
+    fs,
+    path::Path,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use crate::{
+    budget::{BudgetState, budget_state, record_budget_retry},
+    config,
+    progress::{json_progress, print_line},
+};
+
+use chrono::{DateTime, Utc};
+use console::style;
+use miette::{IntoDiagnostic, Result};
+use serde::Serialize;
+
+/// The upper bounds (in milliseconds) of the buckets of a [`Histogram`].
+const BUCKETS_MS: [u64; 10] = [
+    50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 30_000, 60_000,
+];
+
+static METRICS: Metrics = Metrics::new();
+
+/// A histogram of durations, bucketed by [`BUCKETS_MS`].
+#[derive(Debug, Clone, Serialize)]
+pub struct Histogram {
+    pub count: u64,
+    pub sum_ms: u64,
+    pub min_ms: Option<u64>,
+    pub max_ms: Option<u64>,
+    /// The number of durations up to each of [`BUCKETS_MS`] (and above the last).
+    pub buckets: [u64; BUCKETS_MS.len() + 1],
+}
+
+impl Histogram {
+    const fn new() -> Self {
+        Self {
+            count: 0,
+            sum_ms: 0,
+            min_ms: None,
+            max_ms: None,
+            buckets: [0; BUCKETS_MS.len() + 1],
+        }
+    }
+
+    fn record(&mut self, duration: Duration) {
+        let ms = u64::try_from(duration.as_millis()).unwrap_or(u64::MAX);
+        let bucket = BUCKETS_MS
+            .iter()
+            .position(|b| ms <= *b)
+            .unwrap_or(BUCKETS_MS.len());
+
+        self.count += 1;
+        self.sum_ms = self.sum_ms.saturating_add(ms);
+        self.min_ms = Some(self.min_ms.map_or(ms, |m| m.min(ms)));
+        self.max_ms = Some(self.max_ms.map_or(ms, |m| m.max(ms)));
+        self.buckets[bucket] += 1;
+    }
+
+    /// Returns the mean duration in milliseconds, if anything was recorded.
+    #[must_use]
+    pub fn mean_ms(&self) -> Option<u64> {
+        self.sum_ms.checked_div(self.count)
+    }
+
+    /// Estimates the `q`th quantile (e.g. `0.95`) in milliseconds from the
+    /// buckets, returning the upper bound of the bucket it falls in.
+    #[must_use]
+    pub fn quantile_ms(&self, q: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+
+        #[allow(
+            clippy::cast_precision_loss,
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss
+        )]
+        let target = ((self.count as f64 * q).ceil() as u64).max(1);
+        let mut seen = 0;
+
+        for (i, n) in self.buckets.iter().enumerate() {
+            seen += n;
+
+            if seen >= target {
+                return BUCKETS_MS.get(i).copied().or(self.max_ms);
+            }
+        }
+
+        self.max_ms
+    }
+
+    /// Returns a line such as `mean 120ms, p95 ≤250ms, max 800ms`.
+    fn describe(&self) -> String {
+        match (self.mean_ms(), self.quantile_ms(0.95), self.max_ms) {
+            (Some(mean), Some(p95), Some(max)) => {
+                format!("mean {mean}ms, p95 ≤{p95}ms, max {max}ms")
+            }
+            _ => "n/a".to_string(),
+        }
+    }
+}
+
+/// The run's metrics, see [`snapshot`].
+struct Metrics {
+    api_calls: AtomicU64,
+    api_failures: AtomicU64,
+    retries: AtomicU64,
+    ratelimited: AtomicU64,
+    images: AtomicU64,
+    image_bytes: AtomicU64,
+    chapters: AtomicU64,
+    deduped_pages: AtomicU64,
+    deduped_bytes: AtomicU64,
+    api_latency: Mutex<Histogram>,
+    image_latency: Mutex<Histogram>,
+    chapter_durations: Mutex<Histogram>,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            api_calls: AtomicU64::new(0),
+            api_failures: AtomicU64::new(0),
+            retries: AtomicU64::new(0),
+            ratelimited: AtomicU64::new(0),
+            images: AtomicU64::new(0),
+            image_bytes: AtomicU64::new(0),
+            chapters: AtomicU64::new(0),
+            deduped_pages: AtomicU64::new(0),
+            deduped_bytes: AtomicU64::new(0),
+            api_latency: Mutex::new(Histogram::new()),
+            image_latency: Mutex::new(Histogram::new()),
+            chapter_durations: Mutex::new(Histogram::new()),
+        }
+    }
+}
+
+/// Records an attempt at an API call which took `elapsed`, and whether it got a response.
+///
+/// ## Panics
+///
+/// If another thread panicked while recording metrics.
+pub fn record_api_call(elapsed: Duration, responded: bool) {
+    METRICS.api_calls.fetch_add(1, Ordering::Relaxed);
+
+    if !responded {
+        METRICS.api_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    METRICS
+        .api_latency
+        .lock()
+        .expect("metrics lock poisoned")
+        .record(elapsed);
+}
+
+/// Records an API call being ratelimited (receiving 429).
+pub fn record_ratelimited() {
+    METRICS.ratelimited.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Records a request being retried, such as after being ratelimited or for a corrupt image.
+///
+/// This also spends the run's [budget](`crate::budget`).
+pub fn record_retry() {
+    METRICS.retries.fetch_add(1, Ordering::Relaxed);
+    record_budget_retry();
+}
+
+/// Records an image of `bytes` bytes which took `elapsed` to download.
+///
+/// ## Panics
+///
+/// If another thread panicked while recording metrics.
+pub fn record_image(bytes: usize, elapsed: Duration) {
+    METRICS.images.fetch_add(1, Ordering::Relaxed);
+    METRICS
+        .image_bytes
+        .fetch_add(bytes as u64, Ordering::Relaxed);
+    METRICS
+        .image_latency
+        .lock()
+        .expect("metrics lock poisoned")
+        .record(elapsed);
+}
+
+/// Records a chapter which took `elapsed` to download.
+///
+/// ## Panics
+///
+/// If another thread panicked while recording metrics.
+pub fn record_chapter(elapsed: Duration) {
+    METRICS.chapters.fetch_add(1, Ordering::Relaxed);
+    METRICS
+        .chapter_durations
+        .lock()
+        .expect("metrics lock poisoned")
+        .record(elapsed);
+}
+
+/// Records a page of `bytes` bytes being linked from the [page store](`crate::store`)
+/// instead of being stored again.
+pub fn record_dedupe(bytes: u64) {
+    METRICS.deduped_pages.fetch_add(1, Ordering::Relaxed);
+    METRICS.deduped_bytes.fetch_add(bytes, Ordering::Relaxed);
+}
+
+/// The metrics at some point in the run, see [`snapshot`].
+#[derive(Debug, Clone, Serialize)]
+pub struct Snapshot {
+    pub taken_at: DateTime<Utc>,
+    pub api_calls: u64,
+    /// API calls which got no response at all (e.g. timeouts).
+    pub api_failures: u64,
+    pub retries: u64,
+    /// Responses with status 429 (Too Many Requests).
+    pub ratelimited: u64,
+    pub images: u64,
+    pub image_bytes: u64,
+    pub chapters: u64,
+    /// Pages linked from the [page store](`crate::store`), and the space that saved.
+    pub deduped_pages: u64,
+    pub deduped_bytes: u64,
+    pub api_latency: Histogram,
+    pub image_latency: Histogram,
+    pub chapter_durations: Histogram,
+    pub budget: BudgetState,
+}
+
+/// Returns the metrics so far.
+///
+/// ## Panics
+///
+/// If another thread panicked while recording metrics.
+#[must_use]
+pub fn snapshot() -> Snapshot {
+    let histogram = |h: &Mutex<Histogram>| h.lock().expect("metrics lock poisoned").clone();
+
+    Snapshot {
+        taken_at: Utc::now(),
+        api_calls: METRICS.api_calls.load(Ordering::Relaxed),
+        api_failures: METRICS.api_failures.load(Ordering::Relaxed),
+        retries: METRICS.retries.load(Ordering::Relaxed),
+        ratelimited: METRICS.ratelimited.load(Ordering::Relaxed),
+        images: METRICS.images.load(Ordering::Relaxed),
+        image_bytes: METRICS.image_bytes.load(Ordering::Relaxed),
+        chapters: METRICS.chapters.load(Ordering::Relaxed),
+        deduped_pages: METRICS.deduped_pages.load(Ordering::Relaxed),
+        deduped_bytes: METRICS.deduped_bytes.load(Ordering::Relaxed),
+        api_latency: histogram(&METRICS.api_latency),
+        image_latency: histogram(&METRICS.image_latency),
+        chapter_durations: histogram(&METRICS.chapter_durations),
+        budget: budget_state(),
+    }
+}
+
+impl Snapshot {
+    /// Returns true if nothing was recorded, i.e. the run never touched the network.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.api_calls == 0 && self.images == 0
+    }
+
+    /// Prints the metrics as a block of stats.
+    pub fn print(&self) {
+        #[allow(clippy::cast_precision_loss)]
+        let mib = self.image_bytes as f64 / 1_048_576.0;
+        #[allow(clippy::cast_precision_loss)]
+        let deduped_mib = self.deduped_bytes as f64 / 1_048_576.0;
+        let mut rows = vec![
+            (
+                "API calls",
+                format!(
+                    "{} ({} failed), {}",
+                    self.api_calls,
+                    self.api_failures,
+                    self.api_latency.describe()
+                ),
+            ),
+            (
+                "Retries",
+                format!("{} ({} ratelimited)", self.retries, self.ratelimited),
+            ),
+            (
+                "Images",
+                format!(
+                    "{} ({mib:.2} MiB), {}",
+                    self.images,
+                    self.image_latency.describe()
+                ),
+            ),
+            (
+                "Chapters",
+                format!("{}, {}", self.chapters, self.chapter_durations.describe()),
+            ),
+        ];
+
+        if self.deduped_pages > 0 {
+            rows.push((
+                "Deduped",
+                format!("{} pages ({deduped_mib:.2} MiB saved)", self.deduped_pages),
+            ));
+        }
+
+        // the budget is only worth mentioning if anything was spent
+        if self.budget.requests > 0 || self.budget.exhausted.is_some() {
+            rows.push(("Budget", self.budget.describe()));
+        }
+
+        if let Some(exhausted) = &self.budget.exhausted {
+            rows.push((
+                "",
+                style(format!("exhausted: {exhausted}")).red().to_string(),
+            ));
+        }
+
+        print_line(style("Stats").bold());
+
+        for (name, value) in rows {
+            print_line(format!("  {} {value}", style(format!("{name:<10}")).dim()));
+        }
+    }
+
+    /// Writes the metrics to `path` as JSON, replacing it.
+    ///
+    /// ## Errors
+    ///
+    /// If writing fails.
+    pub fn write_json(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_vec_pretty(self).into_diagnostic()?;
+        let partial = path.with_extension("json.partial");
+
+        fs::write(&partial, json).into_diagnostic()?;
+        fs::rename(&partial, path).into_diagnostic()
+    }
+}
+
+/// Writes the metrics so far to `metrics.json_path`, if it's set.
+///
+/// Failing to write is only logged, since metrics shouldn't fail the run.
+pub fn export_metrics(cfg: &config::Metrics) {
+    let Some(path) = &cfg.json_path else {
+        return;
+    };
+
+    match snapshot().write_json(path) {
+        Ok(()) => debug!("Wrote metrics to {}", path.display()),
+        Err(e) => warn!("Failed to write metrics to {}: {e}", path.display()),
+    }
+}
+
+/// Prints the metrics if `metrics.summary` is set (and the run did anything),
+/// then [exports](`export_metrics`) them. Called at the end of the run.
+pub fn report_metrics(cfg: &config::Metrics) {
+    let snapshot = snapshot();
+    info!("Metrics: {snapshot:?}");
+
+    if cfg.summary && !snapshot.is_empty() && !json_progress() {
+        print_line("");
+        snapshot.print();
+    }
+
+    export_metrics(cfg);
+}
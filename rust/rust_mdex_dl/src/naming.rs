@@ -0,0 +1,333 @@
+//! Contains [`Template`], a small template engine used for naming
+//! manga dirs, chapter dirs and pages (see the `[naming]` config section).
+//!
+//! Templates are plain text with `{field}` placeholders, which may have a
+//! format spec similar to Rust's [`format!`], e.g. `{num:0>4}` pads `num` to
+//! four characters with zeroes. Literal braces are written as `{{` and `}}`.
+//!
+//! Rendered names have runs of whitespace collapsed (so that empty fields don't
+//! leave gaps behind) and are sanitised to be valid filenames on every platform.
+
+use crate::api::models::{Aggregate, Chapter, Manga};
+
+use std::{collections::HashMap, fmt};
+
+use isolang::Language;
+use miette::{Result, bail};
+use sanitise_file_name::sanitise;
+use serde::Deserialize;
+
+/// The fields available to the `naming.manga` template.
+pub const MANGA_FIELDS: [&str; 3] = ["title", "uuid", "year"];
+
+/// The fields available to the `naming.chapter` template.
+pub const CHAPTER_FIELDS: [&str; 8] = [
+    "num", "volume", "title", "group", "uploader", "lang", "uuid", "uuid8",
+];
+
+/// The fields available to the `naming.page` template.
+pub const PAGE_FIELDS: [&str; 9] = [
+    "page", "num", "volume", "title", "group", "uploader", "lang", "uuid", "uuid8",
+];
+
+/// Returns the values of [`MANGA_FIELDS`] for `manga`, using its title in `language`.
+#[must_use]
+pub fn manga_values(manga: &Manga, language: Language) -> HashMap<&'static str, String> {
+    HashMap::from([
+        ("title", manga.title(language)),
+        ("uuid", manga.uuid().to_string()),
+        (
+            "year",
+            manga
+                .data
+                .attributes
+                .year
+                .map(|y| y.to_string())
+                .unwrap_or_default(),
+        ),
+    ])
+}
+
+/// The fewest digits chapter numbers are padded to, see [`num_width`].
+pub const MIN_NUM_WIDTH: usize = 3;
+
+/// Returns how many digits the integer parts of chapter numbers should be padded to so
+/// that they sort correctly, which is the most digits in `manga`'s last chapter or any
+/// of `chapters` (but at least [`MIN_NUM_WIDTH`]).
+#[must_use]
+pub fn num_width(manga: &Manga, chapters: &[Chapter]) -> usize {
+    let last = manga.data.attributes.last_chapter.as_deref();
+    let numbers = chapters
+        .iter()
+        .filter_map(|c| c.data.attributes.chapter_number.as_deref());
+
+    last.into_iter()
+        .chain(numbers)
+        .map(|num| integer_part(num).len())
+        .fold(MIN_NUM_WIDTH, usize::max)
+}
+
+/// Like [`num_width`], but also counting every chapter in `aggregate` (in any language),
+/// so that the width comes from the whole series rather than only the chapters being
+/// downloaded, and names don't change between runs.
+#[must_use]
+pub fn series_num_width(manga: &Manga, chapters: &[Chapter], aggregate: &Aggregate) -> usize {
+    aggregate
+        .volumes
+        .values()
+        .flat_map(|v| v.chapters.keys())
+        .map(|num| integer_part(num).len())
+        .fold(num_width(manga, chapters), usize::max)
+}
+
+/// Returns the digits before the decimal point in `num`, e.g. `"12"` for `"12.5"`.
+fn integer_part(num: &str) -> &str {
+    let end = num.find(|c: char| !c.is_ascii_digit()).unwrap_or(num.len());
+    &num[..end]
+}
+
+/// Pads the integer part of `num` with zeroes to `width` digits, e.g. `"0012.5"` for
+/// `"12.5"` and a width of 4. Numbers that aren't numeric (e.g. `"---"`) are kept as is.
+#[must_use]
+pub fn pad_chapter_number(num: &str, width: usize) -> String {
+    let digits = integer_part(num).len();
+
+    if digits == 0 {
+        return num.to_string();
+    }
+
+    format!("{}{num}", "0".repeat(width.saturating_sub(digits)))
+}
+
+/// Returns the values of [`CHAPTER_FIELDS`] for `chapter`, with
+/// `num` padded to `num_width` digits (see [`num_width`]).
+///
+/// Chapters without a number (e.g. oneshots) use `"---"` instead.
+#[must_use]
+pub fn chapter_values(chapter: &Chapter, num_width: usize) -> HashMap<&'static str, String> {
+    let attrs = &chapter.data.attributes;
+    let uuid = chapter.uuid().to_string();
+
+    HashMap::from([
+        (
+            "num",
+            attrs.chapter_number.as_deref().map_or_else(
+                || "---".to_string(),
+                |num| pad_chapter_number(num, num_width),
+            ),
+        ),
+        ("volume", attrs.volume.clone().unwrap_or_default()),
+        ("title", attrs.title.clone().unwrap_or_default()),
+        ("group", chapter.group_names().join(", ")),
+        (
+            "uploader",
+            chapter.uploader().unwrap_or_default().to_string(),
+        ),
+        ("lang", attrs.translated_language.code().unwrap_or_default()),
+        ("uuid8", uuid[..8].to_string()),
+        ("uuid", uuid),
+    ])
+}
+
+/// How a field is aligned within its width.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+/// A parsed part of a [`Template`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Field {
+        name: String,
+        fill: char,
+        align: Align,
+        width: usize,
+    },
+}
+
+/// A parsed naming template, such as `"[{num:0>4}] {title}"`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Template {
+    raw: String,
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    /// Parses `raw` into a [`Template`].
+    ///
+    /// ## Errors
+    ///
+    /// If a brace is unmatched, a field name is empty or a format spec is invalid.
+    pub fn parse(raw: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = raw.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '}' => bail!("unmatched `}}` in naming template {raw:?}"),
+                '{' => {
+                    let mut field = String::new();
+
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => field.push(c),
+                            None => bail!("unmatched `{{` in naming template {raw:?}"),
+                        }
+                    }
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+
+                    segments.push(Self::parse_field(&field, raw)?);
+                }
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self {
+            raw: raw.to_string(),
+            segments,
+        })
+    }
+
+    /// Parses the inside of a placeholder, e.g. `num:0>4`.
+    fn parse_field(field: &str, raw: &str) -> Result<Segment> {
+        let (name, spec) = field.split_once(':').unwrap_or((field, ""));
+        let name = name.trim();
+
+        if name.is_empty() {
+            bail!("empty field name in naming template {raw:?}");
+        }
+
+        // `[[fill]align]width`, where fill is only allowed if align is given
+        let spec: Vec<char> = spec.chars().collect();
+        let to_align = |c: &char| match c {
+            '<' => Some(Align::Left),
+            '>' => Some(Align::Right),
+            '^' => Some(Align::Center),
+            _ => None,
+        };
+
+        let (fill, align, width) = match spec.as_slice() {
+            [fill, align, width @ ..] if to_align(align).is_some() => {
+                (*fill, to_align(align), width)
+            }
+            [align, width @ ..] if to_align(align).is_some() => (' ', to_align(align), width),
+            width => (' ', None, width),
+        };
+
+        let width: String = width.iter().collect();
+        let width = if width.is_empty() {
+            0
+        } else {
+            match width.parse() {
+                Ok(w) => w,
+                Err(_) => bail!("invalid width {width:?} for field `{name}` in {raw:?}"),
+            }
+        };
+
+        Ok(Segment::Field {
+            name: name.to_string(),
+            fill,
+            align: align.unwrap_or(Align::Left),
+            width,
+        })
+    }
+
+    /// Returns the names of every field used in this template.
+    pub fn fields(&self) -> impl Iterator<Item = &str> {
+        self.segments.iter().filter_map(|s| match s {
+            Segment::Field { name, .. } => Some(name.as_str()),
+            Segment::Literal(_) => None,
+        })
+    }
+
+    /// Checks that this template only uses fields in `allowed`.
+    ///
+    /// ## Errors
+    ///
+    /// If an unknown field is used, listing the allowed ones.
+    pub fn check_fields(&self, option: &str, allowed: &[&str]) -> Result<()> {
+        if let Some(unknown) = self.fields().find(|f| !allowed.contains(f)) {
+            bail!(
+                "Unknown field `{{{unknown}}}` in option `{option}`, expected one of: {}",
+                allowed
+                    .iter()
+                    .map(|f| format!("{{{f}}}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Renders this template with `values`, then collapses whitespace and sanitises the result.
+    ///
+    /// Fields missing from `values` are rendered as empty strings.
+    #[must_use]
+    pub fn render(&self, values: &HashMap<&str, String>) -> String {
+        let mut out = String::new();
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(s) => out.push_str(s),
+                Segment::Field {
+                    name,
+                    fill,
+                    align,
+                    width,
+                } => {
+                    let value = values.get(name.as_str()).map_or("", String::as_str);
+                    let padding = width.saturating_sub(value.chars().count());
+                    let (left, right) = match align {
+                        Align::Left => (0, padding),
+                        Align::Right => (padding, 0),
+                        Align::Center => (padding / 2, padding - padding / 2),
+                    };
+
+                    out.extend(std::iter::repeat_n(*fill, left));
+                    out.push_str(value);
+                    out.extend(std::iter::repeat_n(*fill, right));
+                }
+            }
+        }
+
+        let collapsed = out.split_whitespace().collect::<Vec<_>>().join(" ");
+        sanitise(&collapsed)
+    }
+}
+
+impl TryFrom<String> for Template {
+    type Error = String;
+
+    fn try_from(raw: String) -> std::result::Result<Self, Self::Error> {
+        Self::parse(&raw).map_err(|e| e.to_string())
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
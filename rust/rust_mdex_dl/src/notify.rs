@@ -0,0 +1,251 @@
+//! Contains [`Notifier`], which sends notifications when downloads finish or fail
+//! (see the `[notifications]` config section).
+//!
+//! Notifications are best-effort: if sending one fails, a warning is logged
+//! and the download carries on.
+
+use crate::{
+    api::{download::is_dry_run, models::Chapter},
+    config::{Notifications, NotifyEvent},
+};
+
+use std::time::Duration;
+
+use miette::{IntoDiagnostic, Result, bail};
+use reqwest::{Client, Url, header::CONTENT_TYPE};
+use serde::Serialize;
+use tokio::process::Command;
+
+/// Whether the thing a [`Notification`] is about finished or failed.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyStatus {
+    Finished,
+    Failed,
+}
+
+/// A single notification, which is sent as JSON to webhooks.
+#[derive(Serialize, Debug, Clone)]
+pub struct Notification {
+    pub event: NotifyEvent,
+    pub status: NotifyStatus,
+    /// The manga's title (or titles, for updates).
+    pub title: String,
+    /// The chapter numbers involved.
+    pub chapters: Vec<String>,
+    /// The total size of the saved pages in bytes.
+    pub size: u64,
+    pub error: Option<String>,
+}
+
+impl Notification {
+    /// Constructs a finished [`Notification`].
+    #[must_use]
+    pub fn finished(event: NotifyEvent, title: &str, chapters: Vec<String>, size: u64) -> Self {
+        Self {
+            event,
+            status: NotifyStatus::Finished,
+            title: title.to_string(),
+            chapters,
+            size,
+            error: None,
+        }
+    }
+
+    /// Constructs a failed [`Notification`].
+    #[must_use]
+    pub fn failed(event: NotifyEvent, title: &str, chapters: Vec<String>, error: String) -> Self {
+        Self {
+            event,
+            status: NotifyStatus::Failed,
+            title: title.to_string(),
+            chapters,
+            size: 0,
+            error: Some(error),
+        }
+    }
+
+    /// Returns the chapter's number, or its title for chapters without one (e.g. oneshots).
+    #[must_use]
+    pub fn chapter_label(chapter: &Chapter) -> String {
+        let attrs = &chapter.data.attributes;
+
+        attrs
+            .chapter_number
+            .clone()
+            .or_else(|| attrs.title.clone())
+            .unwrap_or_else(|| "oneshot".to_string())
+    }
+
+    /// Returns a human-readable summary, e.g. `Downloaded "Title" chapters 1, 2 (3.4 MiB)`.
+    #[must_use]
+    pub fn message(&self) -> String {
+        let what = match self.event {
+            NotifyEvent::Chapter => "chapter",
+            NotifyEvent::Manga => "chapters",
+            NotifyEvent::Update => "new chapters of",
+        };
+
+        let chapters = if self.chapters.is_empty() || self.event == NotifyEvent::Update {
+            String::new()
+        } else {
+            format!(" {what} {}", self.chapters.join(", "))
+        };
+
+        #[allow(clippy::cast_precision_loss)]
+        let mib = self.size as f64 / 1_048_576.0;
+
+        match (self.status, self.event) {
+            (NotifyStatus::Finished, NotifyEvent::Update) if self.chapters.is_empty() => {
+                "Update finished, no new chapters".to_string()
+            }
+            (NotifyStatus::Finished, NotifyEvent::Update) => format!(
+                "Downloaded {} {what} {} ({mib:.1} MiB)",
+                self.chapters.len(),
+                self.title
+            ),
+            (NotifyStatus::Finished, _) => {
+                format!("Downloaded {:?}{chapters} ({mib:.1} MiB)", self.title)
+            }
+            (NotifyStatus::Failed, NotifyEvent::Update) => format!(
+                "Update failed: {}",
+                self.error.as_deref().unwrap_or("unknown error")
+            ),
+            (NotifyStatus::Failed, _) => format!(
+                "Failed to download {:?}{chapters}: {}",
+                self.title,
+                self.error.as_deref().unwrap_or("unknown error")
+            ),
+        }
+    }
+}
+
+/// The JSON payload sent to webhooks, which sets both Discord's `content`
+/// and Slack's `text` to the [message](`Notification::message`).
+#[derive(Serialize, Debug)]
+struct WebhookPayload<'a> {
+    content: &'a str,
+    text: &'a str,
+    #[serde(flatten)]
+    notification: &'a Notification,
+}
+
+/// Sends [`Notification`]s to the configured command and webhook.
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    cfg: Notifications,
+    client: Client,
+}
+
+impl Notifier {
+    /// How long to wait for a webhook to respond.
+    const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Constructs a new [`Notifier`].
+    ///
+    /// ## Errors
+    ///
+    /// If the HTTP client can't be constructed.
+    pub fn new(cfg: &Notifications, user_agent: &str) -> Result<Self> {
+        let client = Client::builder()
+            .user_agent(user_agent)
+            .timeout(Self::WEBHOOK_TIMEOUT)
+            .build()
+            .into_diagnostic()?;
+
+        Ok(Self {
+            cfg: cfg.clone(),
+            client,
+        })
+    }
+
+    /// Returns true if `event` is enabled and there's somewhere to send it.
+    #[must_use]
+    pub fn wants(&self, event: NotifyEvent) -> bool {
+        (self.cfg.command.is_some() || self.cfg.webhook_url.is_some())
+            && self.cfg.events.contains(&event)
+    }
+
+    /// Sends `notification` if its event is enabled (and this isn't a dry run), logging (but otherwise ignoring) failures.
+    pub async fn send(&self, notification: &Notification) {
+        if !self.wants(notification.event) || is_dry_run() {
+            return;
+        }
+
+        debug!("Sending notification: {notification:?}");
+
+        if let Some(command) = &self.cfg.command
+            && let Err(e) = Self::run_command(command, notification).await
+        {
+            warn!("Notification command failed: {e}");
+        }
+
+        if let Some(url) = &self.cfg.webhook_url
+            && let Err(e) = self.post_webhook(url.clone(), notification).await
+        {
+            warn!("Notification webhook failed: {e}");
+        }
+    }
+
+    /// Runs `command` through the shell, passing `notification` as environment variables.
+    async fn run_command(command: &str, notification: &Notification) -> Result<()> {
+        let mut cmd = if cfg!(windows) {
+            let mut cmd = Command::new("cmd");
+            cmd.arg("/C").arg(command);
+            cmd
+        } else {
+            let mut cmd = Command::new("sh");
+            cmd.arg("-c").arg(command);
+            cmd
+        };
+
+        let event = serde_json::to_value(notification.event).into_diagnostic()?;
+        let status = serde_json::to_value(notification.status).into_diagnostic()?;
+
+        let status = cmd
+            .env("MDEX_NOTIFY_EVENT", event.as_str().unwrap_or_default())
+            .env("MDEX_NOTIFY_STATUS", status.as_str().unwrap_or_default())
+            .env("MDEX_NOTIFY_TITLE", &notification.title)
+            .env("MDEX_NOTIFY_CHAPTERS", notification.chapters.join(", "))
+            .env("MDEX_NOTIFY_SIZE", notification.size.to_string())
+            .env(
+                "MDEX_NOTIFY_ERROR",
+                notification.error.as_deref().unwrap_or_default(),
+            )
+            .env("MDEX_NOTIFY_MESSAGE", notification.message())
+            .status()
+            .await
+            .into_diagnostic()?;
+
+        if !status.success() {
+            bail!("`{command}` exited with {status}");
+        }
+
+        Ok(())
+    }
+
+    /// Posts `notification` to `url` as a [`WebhookPayload`].
+    async fn post_webhook(&self, url: Url, notification: &Notification) -> Result<()> {
+        let message = notification.message();
+        let payload = WebhookPayload {
+            content: &message,
+            text: &message,
+            notification,
+        };
+
+        let response = self
+            .client
+            .post(url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&payload).into_diagnostic()?)
+            .send()
+            .await
+            .into_diagnostic()?;
+
+        if !response.status().is_success() {
+            bail!("webhook responded with {}", response.status());
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,323 @@
+//! Contains offline mode (`--offline`), where nothing is fetched from Manga-Dex.
+//!
+//! Searches only look through the [library](`LibraryIndex`), matching alt titles too if the
+//! manga is in the [search cache](`crate::api::cache::cached_manga`) on disk. "Downloading"
+//! chapters verifies them against their manifests instead, and can repackage them as CBZs
+//! (see [`run_offline`]). Commands that can't work without the network refuse to run.
+//!
+//! Every [`ApiClient`] also gets the [`RefuseRequests`] middleware, so that nothing
+//! slips through to the network by accident.
+
+use crate::{
+    api::{
+        cache::cached_manga, client::ApiClient, download::is_dry_run, middleware::Middleware,
+        models::Manga,
+    },
+    config::Config,
+    export::chapter_archive_name,
+    library::{ChapterEntry, LibraryIndex, MangaEntry},
+    manifest::{ChapterManifest, verify_chapter},
+    progress::{print_line, print_status},
+    repackage::{ChapterPackaging, PackageFormat, PackageUnit, RepackageCounts, repackage_manga},
+};
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        LazyLock,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use console::style;
+use dialoguer::{Confirm, Input, MultiSelect, Select, theme::ColorfulTheme};
+use futures::future::BoxFuture;
+use miette::{IntoDiagnostic, Result, bail, miette};
+use uuid::Uuid;
+
+/// Whether nothing is fetched from Manga-Dex, set by [`set_offline`].
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// The manga in the search cache on disk, read when first needed.
+static CACHED_MANGA: LazyLock<HashMap<Uuid, Manga>> = LazyLock::new(cached_manga);
+
+/// Turns offline mode (`--offline`) on or off, see the [module docs](`self`).
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+/// Returns true if nothing is fetched from Manga-Dex, see [`set_offline`].
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// Returns an error naming `command` if offline, for commands that need the network.
+///
+/// ## Errors
+///
+/// If offline mode is on.
+pub fn ensure_online(command: &str) -> Result<()> {
+    if is_offline() {
+        bail!("`{command}` needs to reach Manga-Dex, so it can't be used with --offline");
+    }
+
+    Ok(())
+}
+
+/// Fetches the manga with `uuid`, or reads it from the search cache if offline.
+///
+/// ## Errors
+///
+/// If propagated from [`Manga::new`], or if offline and the manga isn't cached.
+pub async fn fetch_manga(api: &ApiClient, uuid: Uuid) -> Result<Manga> {
+    if !is_offline() {
+        return Manga::new(api, uuid).await;
+    }
+
+    CACHED_MANGA
+        .get(&uuid)
+        .cloned()
+        .ok_or_else(|| miette!("manga {uuid} isn't in the search cache, and this is offline"))
+}
+
+/// Fails every request, since nothing should be sent while offline.
+#[derive(Debug, Clone, Copy)]
+pub struct RefuseRequests;
+
+impl Middleware for RefuseRequests {
+    fn before<'a>(&'a self, request: &'a mut reqwest::Request) -> BoxFuture<'a, Result<()>> {
+        let url = request.url().clone();
+        Box::pin(async move { Err(miette!("not requesting {url}, since this is offline")) })
+    }
+}
+
+/// Returns the manga in `index` whose title (or any cached alt title)
+/// contains `query` (case-insensitive), sorted by title.
+#[must_use]
+pub fn search_library<'a>(index: &'a LibraryIndex, query: &str) -> Vec<&'a MangaEntry> {
+    let query = query.trim().to_lowercase();
+    let mut matches: Vec<&MangaEntry> = index
+        .manga
+        .values()
+        .filter(|m| {
+            m.title.to_lowercase().contains(&query)
+                || CACHED_MANGA.get(&m.uuid).is_some_and(|manga| {
+                    let attrs = &manga.data.attributes;
+                    attrs
+                        .title
+                        .values()
+                        .chain(attrs.alt_titles.iter().flatten().map(|(_, t)| t))
+                        .any(|t| t.to_lowercase().contains(&query))
+                })
+        })
+        .collect();
+
+    matches.sort_by_key(|m| m.title.to_lowercase());
+    matches
+}
+
+/// How the chosen chapters fared in [`verify_chosen`].
+#[derive(Debug, Default)]
+struct OfflineCounts {
+    verified: usize,
+    broken: usize,
+    unverifiable: usize,
+}
+
+/// Verifies each of `chapters` against its manifest, printing the ones that aren't intact
+/// and returning the ones that can be repackaged (the intact and unverifiable ones).
+fn verify_chosen<'a>(
+    entry: &MangaEntry,
+    chapters: &[&'a ChapterEntry],
+    counts: &mut OfflineCounts,
+) -> Result<Vec<(&'a ChapterEntry, PathBuf)>> {
+    let mut intact = Vec::with_capacity(chapters.len());
+
+    for &chapter in chapters {
+        let dir = entry.chapter_path(chapter)?;
+        let name = chapter_archive_name(chapter);
+
+        let Some(manifest) = ChapterManifest::read(&dir)? else {
+            print_line(format!(
+                "  {} {name}: no manifest, can't verify",
+                style("?").yellow()
+            ));
+            counts.unverifiable += 1;
+            intact.push((chapter, dir));
+            continue;
+        };
+
+        let report = verify_chapter(&dir, &manifest)?;
+
+        if report.problems.is_empty() {
+            counts.verified += 1;
+            intact.push((chapter, dir));
+        } else {
+            print_line(format!(
+                "  {} {name}: {} of {} pages missing or corrupted",
+                style("✗").red(),
+                report.problems.len(),
+                manifest.pages.len()
+            ));
+            counts.broken += 1;
+        }
+    }
+
+    Ok(intact)
+}
+
+/// Asks for a downloaded manga matching a query, see [`search_library`].
+fn library_search_menu(index: &LibraryIndex) -> Result<Option<&MangaEntry>> {
+    loop {
+        let query: String = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Enter a downloaded manga")
+            .interact_text()
+            .into_diagnostic()?;
+        let matches = search_library(index, &query);
+
+        if matches.is_empty() {
+            print_line(
+                style(format!("No downloaded manga matches {query:?}"))
+                    .yellow()
+                    .italic(),
+            );
+        } else {
+            let rows: Vec<String> = matches
+                .iter()
+                .map(|m| {
+                    let languages = m.languages().join(", ");
+                    let details = format!("({} chapters, {languages})", m.chapters.len());
+                    format!("{}  {}", m.title, style(details).dim())
+                })
+                .collect();
+
+            if let Some(i) = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("Choose a manga (esc to search again)")
+                .items(&rows)
+                .default(0)
+                .max_length(15)
+                .interact_opt()
+                .into_diagnostic()?
+            {
+                return Ok(Some(matches[i]));
+            }
+        }
+
+        if !Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Search again?")
+            .interact()
+            .into_diagnostic()?
+        {
+            return Ok(None);
+        }
+    }
+}
+
+/// The interactive menu in offline mode: searches the library, then verifies the chosen
+/// chapters and offers to repackage them as CBZs (see the [module docs](`self`)).
+///
+/// ## Errors
+///
+/// If the library index can't be loaded, prompting fails, or a chapter can't be read.
+pub fn run_offline(cfg: &Config) -> Result<()> {
+    let index = LibraryIndex::load()?;
+
+    if index.manga.is_empty() {
+        print_line(style("No downloaded manga found").yellow().italic());
+        return Ok(());
+    }
+
+    print_status(style("Offline: searching the library, nothing is fetched from Manga-Dex").dim());
+
+    let Some(entry) = library_search_menu(&index)? else {
+        return Ok(());
+    };
+
+    let chapters = entry.sorted_chapters();
+    let rows: Vec<String> = chapters
+        .iter()
+        .map(|c| {
+            let details = format!("[{}] {}", c.language, c.groups.join(", "));
+            format!("{}  {}", chapter_archive_name(c), style(details).dim())
+        })
+        .collect();
+
+    let Some(chosen) = MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Choose chapters (space toggles, enter confirms)")
+        .items(&rows)
+        .defaults(&vec![true; rows.len()])
+        .max_length(20)
+        .interact_opt()
+        .into_diagnostic()?
+    else {
+        return Ok(());
+    };
+
+    let chosen: Vec<&ChapterEntry> = chosen.into_iter().map(|i| chapters[i]).collect();
+    let mut counts = OfflineCounts::default();
+    let intact = verify_chosen(entry, &chosen, &mut counts)?;
+
+    print_line(
+        style(format!(
+            "{} chapters intact, {} broken, {} without a manifest",
+            counts.verified, counts.broken, counts.unverifiable
+        ))
+        .green(),
+    );
+
+    if counts.broken > 0 {
+        print_line(style("Broken chapters can only be downloaded again once online").italic());
+    }
+
+    if intact.is_empty() {
+        return Ok(());
+    }
+
+    let dest: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("Repackage them as CBZs into this dir? (empty skips)")
+        .allow_empty(true)
+        .interact_text()
+        .into_diagnostic()?;
+
+    if dest.trim().is_empty() {
+        return Ok(());
+    }
+
+    let dest = Path::new(dest.trim());
+
+    if is_dry_run() {
+        let message = format!(
+            "Would repackage {} chapters into {}",
+            intact.len(),
+            dest.display()
+        );
+        print_line(style(message).green());
+        return Ok(());
+    }
+
+    let packaging = ChapterPackaging::new(cfg, PackageFormat::Cbz, dest.to_path_buf());
+    let only: Vec<Uuid> = intact.iter().map(|(c, _)| c.uuid).collect();
+    let mut counts = RepackageCounts::default();
+    repackage_manga(
+        &packaging,
+        entry,
+        PackageUnit::Chapter,
+        CACHED_MANGA.get(&entry.uuid),
+        Some(&only),
+        &mut counts,
+    )?;
+
+    print_line(
+        style(format!(
+            "Repackaged {} chapters into {} ({} already up to date, {} failed)",
+            counts.written,
+            dest.join(&entry.dir).display(),
+            counts.skipped,
+            counts.failed
+        ))
+        .green(),
+    );
+
+    Ok(())
+}
@@ -0,0 +1,86 @@
+//! Contains [`sort_chapters`], which orders chapters for downloading by `download.order`,
+//! and [`latest_chapters`], which keeps only the newest few (`download.latest`, or `--latest`).
+//!
+//! Chapters without a number (e.g. oneshots or extras) always go last, since there's
+//! no telling where they belong.
+
+use crate::{
+    api::models::{Chapter, ChapterNumber},
+    config::DownloadOrder,
+};
+
+use std::{cmp::Ordering, collections::BTreeSet};
+
+/// Compares optional numbers with `cmp`, putting `None` last.
+fn cmp_numbers(
+    a: Option<ChapterNumber>,
+    b: Option<ChapterNumber>,
+    cmp: impl Fn(&ChapterNumber, &ChapterNumber) -> Ordering,
+) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => cmp(&a, &b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Returns the volume of `chapter` as a number, if it has a numeric one.
+fn volume(chapter: &Chapter) -> Option<ChapterNumber> {
+    ChapterNumber::parse(chapter.data.attributes.volume.as_deref()?)
+}
+
+/// Sorts `chapters` by `order`, keeping the order of chapters that compare
+/// equal (e.g. versions of the same chapter from different groups).
+pub fn sort_chapters(chapters: &mut [Chapter], order: DownloadOrder) {
+    match order {
+        DownloadOrder::Feed => {}
+        DownloadOrder::Ascending => {
+            chapters.sort_by(|a, b| cmp_numbers(a.number(), b.number(), Ord::cmp));
+        }
+        DownloadOrder::Descending => {
+            chapters.sort_by(|a, b| cmp_numbers(a.number(), b.number(), |a, b| b.cmp(a)));
+        }
+        DownloadOrder::Volume => chapters.sort_by(|a, b| {
+            cmp_numbers(volume(a), volume(b), Ord::cmp)
+                .then_with(|| cmp_numbers(a.number(), b.number(), Ord::cmp))
+        }),
+    }
+}
+
+/// Returns the chapters of `chapters` with the `latest` highest chapter numbers (every
+/// version of each), or every chapter if `latest` is `0`. The order is kept.
+///
+/// If none of them have a number, the `latest` most recently readable are kept instead.
+#[must_use]
+pub fn latest_chapters(chapters: Vec<Chapter>, latest: usize) -> Vec<Chapter> {
+    if latest == 0 {
+        return chapters;
+    }
+
+    let numbers: BTreeSet<ChapterNumber> = chapters.iter().filter_map(Chapter::number).collect();
+
+    if numbers.is_empty() {
+        let mut readable: Vec<_> = chapters
+            .iter()
+            .map(|c| c.data.attributes.readable_at)
+            .collect();
+        readable.sort_unstable_by(|a, b| b.cmp(a));
+
+        let Some(&oldest) = readable.get(latest - 1).or(readable.last()) else {
+            return chapters;
+        };
+
+        return chapters
+            .into_iter()
+            .filter(|c| c.data.attributes.readable_at >= oldest)
+            .collect();
+    }
+
+    let kept: BTreeSet<ChapterNumber> = numbers.into_iter().rev().take(latest).collect();
+
+    chapters
+        .into_iter()
+        .filter(|c| c.number().is_some_and(|n| kept.contains(&n)))
+        .collect()
+}
@@ -0,0 +1,214 @@
+//! Contains [`PathPolicy`], which turns rendered names (see [`crate::naming`]) into
+//! file and dir names that are safe to use, and to extract from archives, on every platform.
+//!
+//! [`sanitise`] already removes characters that aren't allowed (and appends an underscore to
+//! names reserved on Windows, e.g. `CON`), but leaves long names alone. Long titles in
+//! Japanese and the like easily go past Windows' path limit of 260 characters once a manga
+//! and chapter dir are joined, so names are also shortened to [`PathPolicy::max_length`].
+
+use deunicode::deunicode;
+use sanitise_file_name::sanitise;
+use serde::Deserialize;
+use uuid::Uuid;
+
+/// The most bytes allowed in a single name by most file systems.
+const MAX_BYTES: usize = 255;
+
+/// Room left in file names for an extension, e.g. `.jpeg.partial`.
+const EXTENSION_RESERVE: usize = 16;
+
+/// How names longer than [`PathPolicy::max_length`] are shortened.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Truncation {
+    /// The end is cut off, and the first 8 characters of the uuid are appended,
+    /// so that names which only differ at the end stay unique.
+    #[default]
+    Uuid,
+    /// The end is cut off.
+    Cut,
+}
+
+/// How rendered names are made into file and dir names, see the [module docs](`self`).
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PathPolicy {
+    /// The most characters in a single file or dir name, or 0 for no limit.
+    /// Defaults to [`Self::DEFAULT_MAX_LENGTH`].
+    ///
+    /// Names are limited to 255 bytes regardless, since longer ones can't be created.
+    pub max_length: usize,
+    /// Whether names are transliterated to ASCII, e.g. `Pokémon` to `Pokemon`.
+    pub ascii: bool,
+    pub truncation: Truncation,
+}
+
+impl Default for PathPolicy {
+    fn default() -> Self {
+        Self {
+            max_length: Self::DEFAULT_MAX_LENGTH,
+            ascii: false,
+            truncation: Truncation::default(),
+        }
+    }
+}
+
+impl PathPolicy {
+    /// The shortest [`Self::max_length`] allowed (other than 0).
+    pub const MIN_MAX_LENGTH: usize = 32;
+
+    /// The default [`Self::max_length`], which only limits names on Windows (where long
+    /// paths break), so that existing dirs elsewhere keep their names.
+    pub const DEFAULT_MAX_LENGTH: usize = if cfg!(windows) { 100 } else { 0 };
+
+    /// Returns `name` as a dir name, shortened (with `uuid`, if given) if it's too long.
+    #[must_use]
+    pub fn dir_name(&self, name: &str, uuid: Option<Uuid>) -> String {
+        self.apply(name, uuid, 0)
+    }
+
+    /// Like [`Self::dir_name`], but leaves room for an extension to be appended.
+    #[must_use]
+    pub fn file_stem(&self, name: &str, uuid: Option<Uuid>) -> String {
+        self.apply(name, uuid, EXTENSION_RESERVE)
+    }
+
+    /// Transliterates (if enabled), sanitises and shortens `name`,
+    /// leaving room for `reserve` more characters.
+    fn apply(&self, name: &str, uuid: Option<Uuid>, reserve: usize) -> String {
+        let name = if self.ascii {
+            sanitise(&deunicode(name))
+        } else {
+            sanitise(name)
+        };
+
+        let max_chars = match self.max_length {
+            0 => usize::MAX,
+            n => n.saturating_sub(reserve),
+        };
+        let max_bytes = MAX_BYTES - reserve;
+
+        if name.chars().count() <= max_chars && name.len() <= max_bytes {
+            return name;
+        }
+
+        let suffix = match (self.truncation, uuid) {
+            (Truncation::Uuid, Some(uuid)) => format!(" ({})", &uuid.to_string()[..8]),
+            _ => String::new(),
+        };
+
+        let kept = truncate(
+            &name,
+            max_chars.saturating_sub(suffix.len()),
+            max_bytes - suffix.len(),
+        );
+        // names can't end with a space or dot on Windows
+        let shortened = format!("{}{suffix}", kept.trim_end_matches([' ', '.']));
+
+        debug!("Shortened name {name:?} to {shortened:?}");
+        sanitise(&shortened)
+    }
+}
+
+/// Returns the longest start of `name` with at most `max_chars` characters and `max_bytes` bytes.
+fn truncate(name: &str, max_chars: usize, max_bytes: usize) -> &str {
+    let end = name
+        .char_indices()
+        .map(|(i, c)| i + c.len_utf8())
+        .take(max_chars)
+        .take_while(|&end| end <= max_bytes)
+        .last()
+        .unwrap_or(0);
+
+    &name[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UUID: Uuid = Uuid::from_u128(0x0123_4567_89ab_cdef_0123_4567_89ab_cdef);
+
+    fn policy(max_length: usize, truncation: Truncation) -> PathPolicy {
+        PathPolicy {
+            max_length,
+            ascii: false,
+            truncation,
+        }
+    }
+
+    #[test]
+    fn keeps_short_names() {
+        let policy = policy(40, Truncation::Uuid);
+        assert_eq!(policy.dir_name("Short title", Some(UUID)), "Short title");
+    }
+
+    #[test]
+    fn appends_uuid_when_shortening() {
+        let name = "A very long title that goes on for quite a while";
+        let dir = policy(40, Truncation::Uuid).dir_name(name, Some(UUID));
+
+        assert_eq!(dir, "A very long title that goes o (01234567)");
+        assert_eq!(dir.chars().count(), 40);
+    }
+
+    #[test]
+    fn cuts_without_uuid() {
+        let name = "A very long title that goes on for quite a while";
+        let cut = policy(40, Truncation::Cut);
+
+        assert_eq!(
+            cut.dir_name(name, Some(UUID)),
+            "A very long title that goes on for quite"
+        );
+        assert_eq!(
+            policy(40, Truncation::Uuid).dir_name(name, None),
+            "A very long title that goes on for quite"
+        );
+    }
+
+    #[test]
+    fn trims_trailing_spaces_and_dots() {
+        let name = "Title ending in lots of dots... and more";
+        assert_eq!(
+            policy(32, Truncation::Cut).dir_name(name, None),
+            "Title ending in lots of dots"
+        );
+    }
+
+    #[test]
+    fn leaves_room_for_extensions() {
+        let name = "A very long title that goes on for quite a while";
+        let stem = policy(40, Truncation::Cut).file_stem(name, None);
+
+        assert_eq!(stem.chars().count(), 40 - EXTENSION_RESERVE);
+    }
+
+    #[test]
+    fn limits_bytes_without_max_length() {
+        let name = "あ".repeat(100);
+        let dir = policy(0, Truncation::Cut).dir_name(&name, None);
+
+        assert_eq!(dir, "あ".repeat(MAX_BYTES / 3));
+        assert_eq!(
+            policy(0, Truncation::Cut).dir_name("あいう", None),
+            "あいう"
+        );
+    }
+
+    #[test]
+    fn truncates_on_char_boundaries() {
+        assert_eq!(truncate("Pokémon", 4, 255), "Poké");
+        assert_eq!(truncate("Pokémon", 10, 4), "Pok");
+        assert_eq!(truncate("Pokémon", 0, 255), "");
+    }
+
+    #[test]
+    fn transliterates_to_ascii() {
+        let policy = PathPolicy {
+            ascii: true,
+            ..policy(0, Truncation::Cut)
+        };
+        assert_eq!(policy.dir_name("Pokémon", None), "Pokemon");
+    }
+}
@@ -1,21 +1,189 @@
 //! Contains file locations and other file-related utilities.
+//!
+//! Files are stored in the platform's standard locations (see [`ProjectDirs`]), e.g. on Linux:
+//!
+//! - the config (and messages files) in `~/.config/rust_mdex_dl`
+//! - the manga library, history and download queue in `~/.local/share/rust_mdex_dl`
+//! - logs in `~/.local/share/rust_mdex_dl/logs`
+//!
+//! If a legacy `config_rust_mdex_dl.toml` exists in the current dir, everything is
+//! stored in the current dir instead ("portable mode"), as in older versions.
+//! This is also used as a fallback if no home directory can be found.
+//!
+//! The manga library can also be moved with the `storage.library_dir` option.
 
 #![allow(clippy::missing_errors_doc)]
 
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use directories::ProjectDirs;
+use isolang::Language;
 use miette::{IntoDiagnostic, Result};
-use std::path::PathBuf;
+
+/// The config filename used in portable mode.
+const PORTABLE_CONFIG: &str = "config_rust_mdex_dl.toml";
+
+/// The resolved locations, set once by [`dirs`].
+static DIRS: OnceLock<Dirs> = OnceLock::new();
+
+/// Overrides [`manga_save_dir`], set by [`set_library_dir`].
+static LIBRARY_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Where files are stored, see the [module docs](`self`).
+#[derive(Debug, Clone)]
+struct Dirs {
+    /// Stores the config and messages files.
+    config: PathBuf,
+    /// Stores the manga library, history and logs.
+    data: PathBuf,
+    /// Whether everything is stored in the current dir.
+    portable: bool,
+}
+
+impl Dirs {
+    fn resolve() -> Result<Self> {
+        let cwd = std::env::current_dir().into_diagnostic()?;
+        let portable = Self {
+            config: cwd.clone(),
+            data: cwd.clone(),
+            portable: true,
+        };
+
+        if cwd.join(PORTABLE_CONFIG).try_exists().into_diagnostic()? {
+            return Ok(portable);
+        }
+
+        let Some(project) = ProjectDirs::from("", "", env!("CARGO_PKG_NAME")) else {
+            return Ok(portable);
+        };
+
+        Ok(Self {
+            config: project.config_dir().to_path_buf(),
+            data: project.data_dir().to_path_buf(),
+            portable: false,
+        })
+    }
+}
+
+/// Returns the resolved [`Dirs`], resolving them on the first call.
+fn dirs() -> Result<&'static Dirs> {
+    if let Some(dirs) = DIRS.get() {
+        return Ok(dirs);
+    }
+
+    let dirs = Dirs::resolve()?;
+    Ok(DIRS.get_or_init(|| dirs))
+}
+
+/// Returns true if files are stored in the current dir, see the [module docs](`self`).
+pub fn is_portable() -> Result<bool> {
+    Ok(dirs()?.portable)
+}
+
+/// The dir that [`config_toml`] and [`messages_toml`] are stored in.
+pub fn config_dir() -> Result<&'static Path> {
+    Ok(&dirs()?.config)
+}
+
+/// Sets the dir returned by [`manga_save_dir`] (from the `storage.library_dir` option).
+/// This should only be called once.
+pub fn set_library_dir(path: PathBuf) {
+    if LIBRARY_DIR.set(path).is_err() {
+        warn!("`set_library_dir()` called more than once, ignoring");
+    }
+}
 
 pub fn manga_save_dir() -> Result<PathBuf> {
-    Ok(std::env::current_dir().into_diagnostic()?.join("manga"))
+    if let Some(library_dir) = LIBRARY_DIR.get() {
+        return Ok(library_dir.clone());
+    }
+
+    Ok(dirs()?.data.join("manga"))
+}
+
+/// The [library index](`crate::library::LibraryIndex`), which is stored in the library itself.
+pub fn library_index() -> Result<PathBuf> {
+    Ok(manga_save_dir()?.join("library.json"))
+}
+
+/// The [page store](`crate::store`), which is stored in the library itself.
+pub fn page_store_dir() -> Result<PathBuf> {
+    Ok(manga_save_dir()?.join(".pages"))
+}
+
+/// Where `clean --orphans` moves orphaned dirs, which is stored in the library itself.
+pub fn trash_dir() -> Result<PathBuf> {
+    Ok(manga_save_dir()?.join(".trash"))
 }
 
 pub fn log_save_dir() -> Result<PathBuf> {
-    Ok(std::env::current_dir().into_diagnostic()?.join("logs"))
+    Ok(dirs()?.data.join("logs"))
 }
 
 pub fn config_toml() -> Result<PathBuf> {
-    // maybe use ~/.config?
-    Ok(std::env::current_dir()
-        .into_diagnostic()?
-        .join("config_rust_mdex_dl.toml"))
+    let dirs = dirs()?;
+
+    if dirs.portable {
+        Ok(dirs.config.join(PORTABLE_CONFIG))
+    } else {
+        Ok(dirs.config.join("config.toml"))
+    }
+}
+
+pub fn history_file() -> Result<PathBuf> {
+    let dirs = dirs()?;
+
+    if dirs.portable {
+        Ok(dirs.data.join("history_rust_mdex_dl.jsonl"))
+    } else {
+        Ok(dirs.data.join("history.jsonl"))
+    }
+}
+
+/// Where search results are cached, see [`crate::api::cache::SearchCache`].
+pub fn search_cache_dir() -> Result<PathBuf> {
+    Ok(dirs()?.data.join("cache").join("search"))
+}
+
+/// The [download queue](`crate::queue::DownloadQueue`).
+pub fn queue_file() -> Result<PathBuf> {
+    let dirs = dirs()?;
+
+    if dirs.portable {
+        Ok(dirs.data.join("queue_rust_mdex_dl.json"))
+    } else {
+        Ok(dirs.data.join("queue.json"))
+    }
+}
+
+/// The file for overriding (or translating) messages in the given `language`.
+///
+/// See [`crate::messages`] for the format.
+pub fn messages_toml(language: Language) -> Result<PathBuf> {
+    let code = language.to_639_1().unwrap_or("en");
+
+    Ok(dirs()?.config.join(format!("messages_{code}.toml")))
+}
+
+/// Returns `path` made absolute, with symlinks resolved as far as it exists.
+fn resolve(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+
+    for ancestor in absolute.ancestors() {
+        if let Ok(resolved) = ancestor.canonicalize() {
+            let rest = absolute.strip_prefix(ancestor).unwrap_or(Path::new(""));
+            return resolved.join(rest);
+        }
+    }
+
+    absolute
+}
+
+/// Returns true if `path` is `dir` or inside it. Neither has to exist yet.
+#[must_use]
+pub fn is_within(path: &Path, dir: &Path) -> bool {
+    resolve(path).starts_with(resolve(dir))
 }
@@ -0,0 +1,377 @@
+//! Contains [`JobProgress`], the overall progress bar shown above the per-chapter ones,
+//! and [`ProgressEvent`]s, which replace the progress bars with `--progress json`.
+//!
+//! In JSON mode, every event is written to stdout as a single line of JSON, with an
+//! `event` field saying what kind it is (e.g. `"chapter_finished"`). Other output
+//! (prompts, summaries) never starts with `{`, so it can be told apart.
+//!
+//! Besides indicatif's own [template keys](indicatif#templates), the template (see the
+//! `progress.template` option) can use:
+//!
+//! - `{mib}`: the MiB saved so far
+//! - `{speed}`: the current download speed in MiB/s, over roughly the last second
+//! - `{avg_speed}`: the average download speed in MiB/s
+
+use std::{
+    fmt,
+    io::Write,
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use clap::ValueEnum;
+use indicatif::{
+    MultiProgress, ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle,
+    style::ProgressTracker,
+};
+use miette::{IntoDiagnostic, Result};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// The progress mode, set once by [`set_progress_mode`].
+static MODE: OnceLock<ProgressMode> = OnceLock::new();
+
+/// Whether progress bars and status messages are hidden, set by [`set_quiet`].
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// The [`MultiProgress`] every progress bar is drawn with, see [`multi_progress`].
+static MULTI: OnceLock<MultiProgress> = OnceLock::new();
+
+/// How download progress is shown, chosen with `--progress`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ProgressMode {
+    /// Progress bars, for people.
+    #[default]
+    Bars,
+    /// [`ProgressEvent`]s as JSON lines on stdout, for scripts and GUIs.
+    Json,
+}
+
+/// Sets the [`ProgressMode`]. This should only be called once.
+pub fn set_progress_mode(mode: ProgressMode) {
+    if MODE.set(mode).is_err() {
+        warn!("`set_progress_mode()` called more than once, ignoring");
+    }
+}
+
+/// Returns true if progress is shown as [`ProgressEvent`]s instead of bars.
+pub fn json_progress() -> bool {
+    MODE.get().copied().unwrap_or_default() == ProgressMode::Json
+}
+
+/// Hides progress bars and status messages (`--quiet`), leaving only summaries and errors.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// Returns true if only summaries and errors should be printed.
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Returns the [`MultiProgress`] that every progress bar is drawn with,
+/// which is hidden in JSON mode or when [quiet](`is_quiet`).
+///
+/// This is shared so that logs printed to stderr (with `--verbose`) can be drawn
+/// above the bars instead of through them, see [`crate::logging`].
+pub fn multi_progress() -> MultiProgress {
+    MULTI
+        .get_or_init(|| {
+            if json_progress() || is_quiet() {
+                MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+            } else {
+                MultiProgress::new()
+            }
+        })
+        .clone()
+}
+
+/// Prints `line` to stdout above the progress bars, so that it isn't drawn through them.
+///
+/// This is for summaries and prompts, which are printed even when [quiet](`is_quiet`).
+pub fn print_line(line: impl fmt::Display) {
+    multi_progress().suspend(|| println!("{line}"));
+}
+
+/// Prints `line` like [`print_line`], unless [quiet](`is_quiet`).
+///
+/// This is for status messages, such as what's about to be downloaded.
+pub fn print_status(line: impl fmt::Display) {
+    if !is_quiet() {
+        print_line(line);
+    }
+}
+
+/// A manga in a [`ProgressEvent::SearchResults`].
+#[derive(Serialize, Debug, Clone)]
+pub struct SearchResult {
+    pub uuid: Uuid,
+    pub title: String,
+}
+
+/// A structured progress event, emitted with [`emit`] in JSON mode.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    SearchResults {
+        query: String,
+        /// The zero-indexed page of results.
+        page: u32,
+        /// The total number of results, across every page.
+        total: u32,
+        manga: Vec<SearchResult>,
+    },
+    MangaStarted {
+        manga_uuid: Uuid,
+        title: String,
+        chapters: usize,
+    },
+    MangaFinished {
+        manga_uuid: Uuid,
+        downloaded: usize,
+        failed: usize,
+        bytes: usize,
+    },
+    ChapterStarted {
+        manga_uuid: Uuid,
+        chapter_uuid: Uuid,
+        chapter_number: Option<String>,
+        pages: usize,
+    },
+    PageDownloaded {
+        chapter_uuid: Uuid,
+        page: String,
+        bytes: usize,
+    },
+    ChapterFinished {
+        chapter_uuid: Uuid,
+        pages: usize,
+        bytes: u64,
+    },
+    ChapterFailed {
+        chapter_uuid: Uuid,
+        error: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// Writes `event` to stdout as a line of JSON, if in JSON mode.
+pub fn emit(event: &ProgressEvent) {
+    if !json_progress() {
+        return;
+    }
+
+    match serde_json::to_string(event) {
+        Ok(line) => {
+            // locked so that concurrent events don't interleave
+            let mut stdout = std::io::stdout().lock();
+            let _ = writeln!(stdout, "{line}");
+            let _ = stdout.flush();
+        }
+        Err(e) => warn!("Failed to serialize progress event {event:?}: {e}"),
+    }
+}
+
+/// The default `progress.template`.
+pub const DEFAULT_TEMPLATE: &str = "{spinner:.green} [{elapsed_precise}] [{bar:40.green/white}] \
+    {pos}/{len} chapters, {mib} MiB at {speed} MiB/s (avg {avg_speed} MiB/s), eta {eta}";
+
+#[allow(clippy::cast_precision_loss)]
+fn to_mib(bytes: usize) -> f64 {
+    bytes as f64 / 1_048_576.0
+}
+
+/// Tracks the current download speed, which is resampled every [`Self::INTERVAL`].
+#[derive(Debug, Clone)]
+struct Speed {
+    bytes: Arc<AtomicUsize>,
+    /// When the last sample was taken, and the bytes saved at that point.
+    last: (Instant, usize),
+    mib_per_sec: f64,
+}
+
+impl Speed {
+    const INTERVAL: Duration = Duration::from_secs(1);
+}
+
+impl ProgressTracker for Speed {
+    fn clone_box(&self) -> Box<dyn ProgressTracker> {
+        Box::new(self.clone())
+    }
+
+    fn tick(&mut self, _: &ProgressState, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last.0);
+
+        if elapsed >= Self::INTERVAL {
+            let bytes = self.bytes.load(Ordering::Relaxed);
+            self.mib_per_sec = to_mib(bytes.saturating_sub(self.last.1)) / elapsed.as_secs_f64();
+            self.last = (now, bytes);
+        }
+    }
+
+    fn reset(&mut self, _: &ProgressState, now: Instant) {
+        self.last = (now, self.bytes.load(Ordering::Relaxed));
+        self.mib_per_sec = 0.0;
+    }
+
+    fn write(&self, _: &ProgressState, w: &mut dyn fmt::Write) {
+        let _ = write!(w, "{:.2}", self.mib_per_sec);
+    }
+}
+
+/// Builds the [`ProgressStyle`] for `template`, with the extra keys in the [module docs](`self`).
+///
+/// ## Errors
+///
+/// If `template` is invalid.
+pub fn job_style(template: &str, bytes: &Arc<AtomicUsize>) -> Result<ProgressStyle> {
+    let mib_bytes = bytes.clone();
+    let avg_bytes = bytes.clone();
+    let start = Instant::now();
+
+    let style = ProgressStyle::with_template(template)
+        .into_diagnostic()?
+        .progress_chars("=>-")
+        .with_key("mib", move |_: &ProgressState, w: &mut dyn fmt::Write| {
+            let _ = write!(w, "{:.1}", to_mib(mib_bytes.load(Ordering::Relaxed)));
+        })
+        .with_key(
+            "avg_speed",
+            move |_: &ProgressState, w: &mut dyn fmt::Write| {
+                let secs = start.elapsed().as_secs_f64().max(f64::EPSILON);
+                let _ = write!(w, "{:.2}", to_mib(avg_bytes.load(Ordering::Relaxed)) / secs);
+            },
+        )
+        .with_key(
+            "speed",
+            Speed {
+                bytes: bytes.clone(),
+                last: (Instant::now(), 0),
+                mib_per_sec: 0.0,
+            },
+        );
+
+    Ok(style)
+}
+
+/// The overall progress bar for a download job, which may span several manga
+/// (see [`DownloadClient::download_many`](`crate::api::download::DownloadClient::download_many`)).
+///
+/// The bar is created when the first manga starts, and finished once every
+/// manga that started has ended.
+#[derive(Debug)]
+pub struct JobProgress {
+    template: String,
+    state: Mutex<JobState>,
+}
+
+#[derive(Debug, Default)]
+struct JobState {
+    bar: Option<ProgressBar>,
+    /// The total bytes saved during this job, shared with the bar's style.
+    bytes: Arc<AtomicUsize>,
+    /// How many manga are currently being downloaded.
+    active: usize,
+}
+
+impl JobProgress {
+    /// How often the bar is redrawn, even if nothing has changed (for the speed and spinner).
+    const TICK: Duration = Duration::from_millis(250);
+
+    #[must_use]
+    pub fn new(template: &str) -> Self {
+        Self {
+            template: template.to_string(),
+            state: Mutex::new(JobState::default()),
+        }
+    }
+
+    /// Adds a manga with `chapters` chapters to the job, adding the bar to
+    /// `pb_multi` if this starts a new job.
+    ///
+    /// ## Panics
+    ///
+    /// If the lock is poisoned.
+    pub fn start(&self, pb_multi: &MultiProgress, chapters: usize) {
+        let mut state = self.state.lock().expect("job progress lock poisoned");
+
+        if state.active == 0 {
+            let bytes = Arc::new(AtomicUsize::new(0));
+            let style = job_style(&self.template, &bytes).unwrap_or_else(|e| {
+                // validated with the config, so this shouldn't happen
+                warn!("Invalid progress template, using the default: {e}");
+                job_style(DEFAULT_TEMPLATE, &bytes).expect("default template is valid")
+            });
+
+            let bar = pb_multi.insert(0, ProgressBar::new(0).with_style(style));
+            bar.enable_steady_tick(Self::TICK);
+
+            state.bar = Some(bar);
+            state.bytes = bytes;
+        }
+
+        state.active += 1;
+
+        if let Some(bar) = &state.bar {
+            bar.inc_length(chapters as u64);
+        }
+    }
+
+    /// Ends a manga started with [`Self::start`], finishing the bar if it was the last one.
+    ///
+    /// ## Panics
+    ///
+    /// If the lock is poisoned.
+    pub fn end(&self) {
+        let mut state = self.state.lock().expect("job progress lock poisoned");
+        state.active = state.active.saturating_sub(1);
+
+        if state.active == 0
+            && let Some(bar) = state.bar.take()
+        {
+            bar.finish();
+        }
+    }
+
+    /// Counts `bytes` as saved.
+    ///
+    /// ## Panics
+    ///
+    /// If the lock is poisoned.
+    pub fn add_bytes(&self, bytes: usize) {
+        let state = self.state.lock().expect("job progress lock poisoned");
+        state.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Removes `chapters` that won't be downloaded (e.g. because their cdns couldn't be fetched)
+    /// from the total.
+    ///
+    /// ## Panics
+    ///
+    /// If the lock is poisoned.
+    pub fn chapters_skipped(&self, chapters: usize) {
+        let state = self.state.lock().expect("job progress lock poisoned");
+
+        if let Some(bar) = &state.bar {
+            bar.dec_length(chapters as u64);
+        }
+    }
+
+    /// Counts a chapter as completed.
+    ///
+    /// ## Panics
+    ///
+    /// If the lock is poisoned.
+    pub fn chapter_done(&self) {
+        let state = self.state.lock().expect("job progress lock poisoned");
+
+        if let Some(bar) = &state.bar {
+            bar.inc(1);
+        }
+    }
+}
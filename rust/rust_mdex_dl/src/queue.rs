@@ -0,0 +1,314 @@
+//! Contains the [`DownloadQueue`], which persists chosen chapters across runs.
+//!
+//! Chapters are queued (see [`crate::paths::queue_file`]) before they're downloaded and
+//! marked done as each batch finishes, so an interrupted or crashed run can be finished
+//! later with `queue resume`, without searching for and choosing the chapters again.
+
+use crate::{
+    api::{
+        client::ApiClient,
+        download::DownloadClient,
+        models::{Chapter, Manga},
+    },
+    config::Config,
+    errors::ApiError,
+    history::{RunRecord, append_record},
+    paths::queue_file,
+    progress::{print_line, print_status},
+    tracking::StatusTracker,
+};
+
+use std::fs;
+
+use chrono::{DateTime, Local, Utc};
+use console::style;
+use miette::{IntoDiagnostic, Result, miette};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A chapter in a [`QueueEntry`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QueuedChapter {
+    pub uuid: Uuid,
+    pub chapter_number: Option<String>,
+    pub done: bool,
+}
+
+/// The chapters of a single manga in the [`DownloadQueue`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QueueEntry {
+    pub manga_uuid: Uuid,
+    pub manga_title: String,
+    pub added_at: DateTime<Utc>,
+    pub chapters: Vec<QueuedChapter>,
+}
+
+impl QueueEntry {
+    /// Returns the chapters that haven't been downloaded yet.
+    pub fn pending(&self) -> impl Iterator<Item = &QueuedChapter> {
+        self.chapters.iter().filter(|c| !c.done)
+    }
+}
+
+/// Chapters that were chosen for download, grouped by manga in the order they were queued.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DownloadQueue {
+    pub entries: Vec<QueueEntry>,
+}
+
+impl DownloadQueue {
+    /// Loads the queue, returning an empty one if it doesn't exist yet.
+    ///
+    /// ## Errors
+    ///
+    /// If the queue exists but can't be read or parsed.
+    pub fn load() -> Result<Self> {
+        let path = queue_file()?;
+
+        if !path.try_exists().into_diagnostic()? {
+            return Ok(Self::default());
+        }
+
+        let raw = fs::read_to_string(&path).into_diagnostic()?;
+        serde_json::from_str(&raw)
+            .map_err(|e| miette!("failed to parse download queue {}: {e}", path.display()))
+    }
+
+    /// Saves the queue, replacing the old one atomically.
+    ///
+    /// ## Errors
+    ///
+    /// If the queue can't be serialized or written.
+    pub fn save(&self) -> Result<()> {
+        let path = queue_file()?;
+        let partial = path.with_extension("json.partial");
+
+        fs::write(
+            &partial,
+            serde_json::to_string_pretty(self).into_diagnostic()?,
+        )
+        .into_diagnostic()?;
+        fs::rename(&partial, &path).into_diagnostic()?;
+
+        debug!("Saved download queue to {}", path.display());
+        Ok(())
+    }
+
+    /// Loads the queue, applies `f` to it and saves it.
+    ///
+    /// ## Errors
+    ///
+    /// If propagated from [`Self::load`] or [`Self::save`].
+    pub fn update(f: impl FnOnce(&mut Self)) -> Result<()> {
+        let mut queue = Self::load()?;
+        f(&mut queue);
+        queue.save()
+    }
+
+    /// Queues `chapters` of `manga`, skipping any that are already queued.
+    pub fn enqueue(&mut self, manga: &Manga, manga_title: &str, chapters: &[Chapter]) {
+        let index = if let Some(i) = self
+            .entries
+            .iter()
+            .position(|e| e.manga_uuid == manga.uuid())
+        {
+            i
+        } else {
+            self.entries.push(QueueEntry {
+                manga_uuid: manga.uuid(),
+                manga_title: manga_title.to_string(),
+                added_at: Utc::now(),
+                chapters: Vec::new(),
+            });
+            self.entries.len() - 1
+        };
+
+        let entry = &mut self.entries[index];
+
+        for chapter in chapters {
+            let uuid = chapter.uuid();
+
+            if let Some(queued) = entry.chapters.iter_mut().find(|c| c.uuid == uuid) {
+                queued.done = false;
+            } else {
+                entry.chapters.push(QueuedChapter {
+                    uuid,
+                    chapter_number: chapter.data.attributes.chapter_number.clone(),
+                    done: false,
+                });
+            }
+        }
+    }
+
+    /// Marks `chapters` of the manga with `manga_uuid` as done,
+    /// removing the manga once every one of its chapters is.
+    pub fn mark_done(&mut self, manga_uuid: Uuid, chapters: &[Uuid]) {
+        for entry in self
+            .entries
+            .iter_mut()
+            .filter(|e| e.manga_uuid == manga_uuid)
+        {
+            for queued in &mut entry.chapters {
+                if chapters.contains(&queued.uuid) {
+                    queued.done = true;
+                }
+            }
+        }
+
+        self.entries.retain(|e| e.pending().next().is_some());
+    }
+
+    /// Returns the total number of chapters that haven't been downloaded yet.
+    #[must_use]
+    pub fn pending_count(&self) -> usize {
+        self.entries.iter().map(|e| e.pending().count()).sum()
+    }
+}
+
+/// Downloads every pending chapter in the [`DownloadQueue`], several manga at once
+/// (see [`DownloadClient::download_many`]).
+///
+/// Chapters that can no longer be fetched (e.g. because they were removed) are
+/// logged and left in the queue, see [`clear_queue`].
+///
+/// ## Errors
+///
+/// If the queue can't be loaded, a client can't be constructed or the history can't be written.
+pub async fn resume_queue(cfg: &Config) -> Result<()> {
+    let queue = DownloadQueue::load()?;
+
+    if queue.entries.is_empty() {
+        print_line(style("The download queue is empty").yellow().italic());
+        return Ok(());
+    }
+
+    let api = ApiClient::new(&cfg.client)?;
+    let downloader = DownloadClient::new(cfg)?;
+    let tracker = StatusTracker::new(cfg, &api)?;
+    let mut jobs = Vec::new();
+
+    info!(
+        "Resuming {} queued chapters of {} manga",
+        queue.pending_count(),
+        queue.entries.len()
+    );
+
+    for entry in &queue.entries {
+        match fetch_entry(&api, entry).await {
+            Ok(job) if !job.1.is_empty() => jobs.push(job),
+            Ok(_) => {}
+            Err(e) => error!("Failed to resume manga {:?}: {e}", entry.manga_title),
+        }
+    }
+
+    let started_at = Utc::now();
+    let manga: Vec<Manga> = jobs.iter().map(|(manga, _)| manga.clone()).collect();
+
+    for m in &manga {
+        print_status(format!(
+            "{} {}",
+            style("Resuming").green(),
+            style(m.title(cfg.client.language)).bold()
+        ));
+    }
+
+    let results = downloader.download_many(&api, jobs, &cfg.images).await?;
+
+    for (manga, result) in manga.into_iter().zip(results) {
+        let manga_title = manga.title(cfg.client.language);
+
+        match result {
+            Ok(summary) => {
+                if let Some(tracker) = &tracker {
+                    tracker.sync(&manga, &summary.downloaded).await;
+                }
+
+                append_record(&RunRecord::new(
+                    started_at,
+                    manga.uuid(),
+                    manga_title,
+                    &summary,
+                ))?;
+            }
+            Err(e) => error!("Failed to resume manga {manga_title:?}: {e}"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Helper for [`resume_queue`], which fetches a queued manga and its pending chapters.
+async fn fetch_entry(api: &ApiClient, entry: &QueueEntry) -> Result<(Manga, Vec<Chapter>)> {
+    let manga = Manga::new(api, entry.manga_uuid).await?;
+    let mut chapters = Vec::new();
+
+    for queued in entry.pending() {
+        match Chapter::new(api, queued.uuid).await {
+            Ok(chapter) => chapters.push(chapter),
+            Err(e) if matches!(e.downcast_ref(), Some(ApiError::NotFound { .. })) => {
+                warn!(
+                    "Queued chapter {} no longer exists, skipping it",
+                    queued.uuid
+                );
+            }
+            Err(e) => error!("Failed to fetch queued chapter {}: {e}", queued.uuid),
+        }
+    }
+
+    Ok((manga, chapters))
+}
+
+/// Lists the manga in the [`DownloadQueue`] with how many of their chapters are left.
+///
+/// ## Errors
+///
+/// If propagated from [`DownloadQueue::load`].
+pub fn display_queue() -> Result<()> {
+    let queue = DownloadQueue::load()?;
+
+    if queue.entries.is_empty() {
+        print_line(style("The download queue is empty").yellow().italic());
+        return Ok(());
+    }
+
+    for entry in &queue.entries {
+        let pending: Vec<&str> = entry
+            .pending()
+            .map(|c| c.chapter_number.as_deref().unwrap_or("---"))
+            .collect();
+
+        print_line(format!(
+            "{}  {}/{} chapters left ({})  {}",
+            style(&entry.manga_title).bold(),
+            pending.len(),
+            entry.chapters.len(),
+            pending.join(", "),
+            style(format!(
+                "queued {}",
+                entry
+                    .added_at
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M")
+            ))
+            .dim()
+        ));
+    }
+
+    print_line(style("Run `queue resume` to download them, or `queue clear` to forget them").dim());
+
+    Ok(())
+}
+
+/// Removes every chapter from the [`DownloadQueue`].
+///
+/// ## Errors
+///
+/// If propagated from [`DownloadQueue::save`].
+pub fn clear_queue() -> Result<()> {
+    let count = DownloadQueue::load()?.pending_count();
+    DownloadQueue::default().save()?;
+
+    print_line(style(format!("Removed {count} chapters from the download queue")).green());
+
+    Ok(())
+}
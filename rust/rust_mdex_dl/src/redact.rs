@@ -0,0 +1,171 @@
+//! Contains [`redact`], which masks credentials before they're written anywhere,
+//! so that log files and trace bundles can be shared in issue reports.
+//!
+//! Three kinds of values are masked:
+//!
+//! - bearer and basic credentials (e.g. `Authorization: Bearer ...`)
+//! - values of [sensitive keys](`is_sensitive_key`) in JSON, TOML, query strings,
+//!   headers and `Debug` output (e.g. `"session": "..."` or `?token=...`)
+//! - secrets from the config (or elsewhere) registered with [`add_secret`]
+
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+    sync::{LazyLock, RwLock},
+};
+
+use regex::Regex;
+
+use crate::config::Config;
+
+/// What every masked value is replaced with.
+pub const REDACTED: &str = "<redacted>";
+
+/// Keys containing any of these have their values masked.
+pub const SENSITIVE_KEYS: [&str; 7] = [
+    "token", "secret", "password", "session", "cookie", "api_key", "webhook",
+];
+
+/// Keys containing any of these as a whole word (split by `_` or `-`) have their values
+/// masked too, so that e.g. `x-auth-key` is masked, but `author` isn't.
+pub const SENSITIVE_WORDS: [&str; 2] = ["auth", "authorization"];
+
+/// Returns true if the values of `key` should be masked, see [`SENSITIVE_KEYS`]
+/// and [`SENSITIVE_WORDS`].
+#[must_use]
+pub fn is_sensitive_key(key: &str) -> bool {
+    let key = key.to_lowercase();
+
+    SENSITIVE_KEYS.iter().any(|k| key.contains(k))
+        || key
+            .split(['_', '-'])
+            .any(|word| SENSITIVE_WORDS.contains(&word))
+}
+
+/// Secrets that are masked wherever they appear, see [`add_secret`].
+static SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Matches credentials after a scheme, e.g. `Bearer abc.def`.
+static CREDENTIALS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b(bearer|basic)\s+[A-Za-z0-9._~+/=-]+").expect("credentials regex is valid")
+});
+
+/// Matches a sensitive key followed by its value, e.g. `"token": "abc"` or `token=abc`.
+static KEY_VALUE: LazyLock<Regex> = LazyLock::new(|| {
+    let keys = SENSITIVE_KEYS.join("|");
+    let words = SENSITIVE_WORDS.join("|");
+    let pattern = format!(
+        r#"(?i)((?:[\w-]*(?:{keys})[\w-]*|(?:\b[\w-]*[_-]|\b)(?:{words})(?:[_-][\w-]*)?)["']?\s*[:=]\s*(?:Some\()?["']?)(?:(?:bearer|basic)\s+)?[^"'\s,;&)}}]+"#
+    );
+
+    Regex::new(&pattern).expect("key-value regex is valid")
+});
+
+/// Registers `secret` to be masked wherever it appears. Very short values
+/// (under 4 characters) are ignored, since masking them would mangle the logs.
+///
+/// ## Panics
+///
+/// If another thread panicked while registering a secret.
+pub fn add_secret(secret: &str) {
+    if secret.len() < 4 {
+        return;
+    }
+
+    let mut secrets = SECRETS.write().expect("secrets lock poisoned");
+
+    if !secrets.iter().any(|s| s == secret) {
+        secrets.push(secret.to_string());
+    }
+}
+
+/// Registers the secrets in `cfg`, which are the [sensitive](`is_sensitive_key`) options.
+///
+/// For urls, the path and query are registered too, since `Debug` prints them separately.
+pub fn add_config_secrets(cfg: &Config) {
+    if let Some(url) = &cfg.notifications.webhook_url {
+        add_secret(url.as_str());
+        add_secret(url.path());
+
+        if let Some(query) = url.query() {
+            add_secret(query);
+        }
+    }
+
+    for secret in [&cfg.auth.password, &cfg.auth.client_secret]
+        .into_iter()
+        .flatten()
+    {
+        add_secret(secret);
+    }
+}
+
+/// Returns `text` with every credential, sensitive value and registered secret masked.
+///
+/// ## Panics
+///
+/// If another thread panicked while registering a secret.
+pub fn redact(text: &str) -> Cow<'_, str> {
+    let mut text = Cow::Borrowed(text);
+
+    for secret in SECRETS.read().expect("secrets lock poisoned").iter() {
+        if text.contains(secret.as_str()) {
+            text = Cow::Owned(text.replace(secret.as_str(), REDACTED));
+        }
+    }
+
+    if let Cow::Owned(s) = CREDENTIALS.replace_all(&text, format!("$1 {REDACTED}")) {
+        text = Cow::Owned(s);
+    }
+
+    if let Cow::Owned(s) = KEY_VALUE.replace_all(&text, format!("${{1}}{REDACTED}")) {
+        text = Cow::Owned(s);
+    }
+
+    text
+}
+
+/// Wraps a writer, [redacting](`redact`) everything written to it.
+///
+/// Each write is expected to be a whole log line, which is how log events are written.
+pub struct RedactingWriter<W: Write>(pub W);
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        self.0.write_all(redact(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masks_sensitive_keys() {
+        assert_eq!(redact("token=abc123"), format!("token={REDACTED}"));
+        assert_eq!(
+            redact(r#""x-auth-key": "abc""#),
+            format!(r#""x-auth-key": "{REDACTED}""#)
+        );
+        assert_eq!(redact("auth: abc"), format!("auth: {REDACTED}"));
+        assert_eq!(
+            redact("Authorization: Bearer abc.def"),
+            format!("Authorization: {REDACTED}")
+        );
+    }
+
+    #[test]
+    fn keeps_author_names() {
+        let line = r#"authors: ["Oda"], author="Oda Eiichiro", co-author=Someone"#;
+        assert_eq!(redact(line), line);
+        assert!(!is_sensitive_key("author"));
+        assert!(is_sensitive_key("x-auth-key"));
+        assert!(is_sensitive_key("client_secret"));
+    }
+}
@@ -0,0 +1,232 @@
+//! Contains [`related_works`], which follows a manga's relations (sequels, prequels,
+//! spin-offs and so on, see [`MangaRelation`]), and [`related_menu`], the "related works"
+//! section shown before choosing chapters, which offers to queue them for download.
+//!
+//! Sequels are followed to their own sequels (and prequels to their prequels), up to
+//! [`MAX_RELATED_DEPTH`] away, so that a whole series shows up. Other relations are only
+//! listed if they're direct, since e.g. a spin-off's spin-offs are rarely wanted.
+
+use crate::{
+    api::{
+        download::is_dry_run,
+        groups::apply_group_preferences,
+        models::{Manga, MangaRelation},
+        search::SearchClient,
+    },
+    config::Config,
+    library::LibraryIndex,
+    progress::print_line,
+    queue::DownloadQueue,
+};
+
+use std::collections::{HashMap, HashSet};
+
+use console::style;
+use dialoguer::{MultiSelect, theme::ColorfulTheme};
+use isolang::Language;
+use miette::{IntoDiagnostic, Result};
+use uuid::Uuid;
+
+/// How many relations away sequels (and prequels) are followed.
+pub const MAX_RELATED_DEPTH: usize = 3;
+
+/// A manga related to another, see [`related_works`].
+#[derive(Debug, Clone)]
+pub struct RelatedWork {
+    pub manga: Manga,
+    /// How this relates to the manga that [`related_works`] started from.
+    pub relation: MangaRelation,
+    /// How many relations away this is, starting at 1 for direct ones.
+    pub depth: usize,
+}
+
+impl RelatedWork {
+    /// Returns how this relates to the manga it was found from, e.g. `sequel of sequel`.
+    #[must_use]
+    pub fn label(&self) -> String {
+        vec![self.relation.to_string(); self.depth].join(" of ")
+    }
+}
+
+/// Returns the manga related to `manga` (see the [module docs](`self`)), with prequels
+/// first (furthest first), then sequels (nearest first), then every other relation.
+///
+/// ## Errors
+///
+/// If propagated from [`SearchClient::fetch_manga_bulk`].
+pub async fn related_works(
+    searcher: &SearchClient,
+    manga: &Manga,
+    max_depth: usize,
+) -> Result<Vec<RelatedWork>> {
+    let mut seen = HashSet::from([manga.uuid()]);
+    let mut works = Vec::new();
+    let mut next: Vec<(Uuid, MangaRelation)> =
+        manga.related().map(|(uuid, r)| (uuid, r.clone())).collect();
+    let mut depth = 1;
+
+    while !next.is_empty() && depth <= max_depth {
+        let relations: HashMap<Uuid, MangaRelation> = next
+            .drain(..)
+            .filter(|(uuid, _)| seen.insert(*uuid))
+            .collect();
+        let uuids: Vec<Uuid> = relations.keys().copied().collect();
+
+        for related in searcher.fetch_manga_bulk(&uuids).await?.into_values() {
+            let Some(relation) = relations.get(&related.uuid()) else {
+                continue;
+            };
+
+            if relation.is_continuation() {
+                next.extend(
+                    related
+                        .related()
+                        .filter(|(_, r)| *r == relation)
+                        .map(|(uuid, r)| (uuid, r.clone())),
+                );
+            }
+
+            works.push(RelatedWork {
+                manga: related,
+                relation: relation.clone(),
+                depth,
+            });
+        }
+
+        depth += 1;
+    }
+
+    let rank = |w: &RelatedWork| match w.relation {
+        MangaRelation::Prequel => 0,
+        MangaRelation::Sequel => 1,
+        _ => 2,
+    };
+
+    works.sort_by(|a, b| {
+        rank(a)
+            .cmp(&rank(b))
+            .then_with(|| match a.relation {
+                MangaRelation::Prequel => b.depth.cmp(&a.depth),
+                _ => a.depth.cmp(&b.depth),
+            })
+            .then_with(|| a.relation.as_str().cmp(b.relation.as_str()))
+            .then_with(|| a.manga.uuid().cmp(&b.manga.uuid()))
+    });
+
+    Ok(works)
+}
+
+/// Returns a line for each of `works`, with its relation, title, status and year,
+/// pointing out the ones already in `index`.
+fn related_rows(works: &[RelatedWork], index: &LibraryIndex, language: Language) -> Vec<String> {
+    let width = works
+        .iter()
+        .map(|w| w.label().chars().count())
+        .max()
+        .unwrap_or_default();
+
+    works
+        .iter()
+        .map(|w| {
+            let attrs = &w.manga.data.attributes;
+            let year = attrs.year.map(|y| format!(", {y}")).unwrap_or_default();
+            let downloaded = if index.manga.contains_key(&w.manga.uuid()) {
+                style("  (downloaded)").green().to_string()
+            } else {
+                String::new()
+            };
+
+            format!(
+                "{:<width$}  {}  {}{downloaded}",
+                w.label(),
+                w.manga.title(language),
+                style(format!("({}{year})", attrs.status)).dim()
+            )
+        })
+        .collect()
+}
+
+/// Lists the works related to `manga` (see [`related_works`]) and asks which of them to
+/// queue for download, queueing every chapter of theirs that isn't downloaded yet.
+///
+/// Related works are only a nicety, so failing to fetch them is logged and skipped.
+///
+/// ## Errors
+///
+/// If the library index can't be loaded, prompting fails or the queue can't be saved.
+pub async fn related_menu(cfg: &Config, searcher: &SearchClient, manga: &Manga) -> Result<()> {
+    if manga.related().next().is_none() {
+        return Ok(());
+    }
+
+    let works = match related_works(searcher, manga, MAX_RELATED_DEPTH).await {
+        Ok(v) => v,
+        Err(e) => {
+            warn!("Failed to fetch related works: {e}");
+            return Ok(());
+        }
+    };
+
+    if works.is_empty() {
+        return Ok(());
+    }
+
+    let index = LibraryIndex::load()?;
+    let rows = related_rows(&works, &index, cfg.client.language);
+
+    print_line(style("Related works").bold());
+
+    let Some(chosen) = MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Queue any for download? (space toggles, enter confirms, esc skips)")
+        .items(&rows)
+        .max_length(15)
+        .interact_opt()
+        .into_diagnostic()?
+    else {
+        return Ok(());
+    };
+
+    let mut queued = 0;
+
+    for work in chosen.into_iter().map(|i| &works[i]) {
+        let title = work.manga.title(cfg.client.language);
+        let chapters = match searcher.fetch_all_chapters(&work.manga).await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed to fetch the chapters of {title:?}: {e}");
+                continue;
+            }
+        };
+
+        let chapters: Vec<_> = apply_group_preferences(chapters, &cfg.groups)
+            .into_iter()
+            .filter(|c| index.chapter(c.uuid()).is_none())
+            .collect();
+
+        if chapters.is_empty() {
+            print_line(format!(
+                "{} has no chapters left to download",
+                style(&title).bold()
+            ));
+            continue;
+        }
+
+        if !is_dry_run() {
+            DownloadQueue::update(|queue| queue.enqueue(&work.manga, &title, &chapters))?;
+        }
+
+        print_line(format!(
+            "{} {} ({} chapters)",
+            style("Queued").green(),
+            style(&title).bold(),
+            chapters.len()
+        ));
+        queued += 1;
+    }
+
+    if queued > 0 {
+        print_line(style("Run `queue resume` to download the queued works").italic());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,857 @@
+//! Contains [`repackage_library`], which writes the downloaded library out in another
+//! [format](`PackageFormat`), as a file (or dir) per chapter or per volume, without
+//! downloading anything.
+//!
+//! Pages are read in manifest order, and chapters are verified against their manifests
+//! before being packaged, so corrupted pages aren't carried over (chapters without a
+//! manifest are packaged as they are). Like [exports](`crate::export`), packages that
+//! are newer than their chapters' downloads are left alone.
+//!
+//! Chapters can also be packaged one by one as they're downloaded with [`package_chapter`]
+//! (`[package]` in the config).
+
+use crate::{
+    api::{download::is_dry_run, models::Manga},
+    config::{ArchiveCompression, Config, SpreadOrder},
+    export::{
+        archive_options, chapter_archive_name, chapter_pages, comic_info, is_up_to_date,
+        xml_escape, zip_pages,
+    },
+    images::{ImageFormat, encode},
+    library::{ChapterEntry, LibraryIndex, MangaEntry},
+    manifest::{ChapterManifest, verify_chapter},
+    path_policy::PathPolicy,
+    paths::{is_within, manga_save_dir},
+    progress::print_line,
+};
+
+use std::{
+    fmt::Write as _,
+    fs::{self, File},
+    io::{self, Cursor, Read, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::Utc;
+use clap::ValueEnum;
+use console::style;
+use image::{ColorType, ImageDecoder, ImageReader, codecs::jpeg::JpegDecoder};
+use isolang::Language;
+use miette::{IntoDiagnostic, Result, bail, miette};
+use serde::Deserialize;
+use uuid::Uuid;
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+/// The formats the library can be repackaged into.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PackageFormat {
+    /// A dir of pages, as saved in the library.
+    Raw,
+    /// A comic book zip, with a `ComicInfo.xml` when packaging chapters.
+    #[default]
+    Cbz,
+    /// A fixed-layout EPUB 3 with a page per image, for e-readers.
+    Epub,
+    /// A PDF with a page per image. Pages that aren't JPEGs are re-encoded
+    /// as JPEGs (in `images.convert_quality`).
+    Pdf,
+}
+
+impl PackageFormat {
+    /// Returns the filename extension **without the leading dot**, or `None` for dirs.
+    #[must_use]
+    pub const fn extension(self) -> Option<&'static str> {
+        match self {
+            Self::Raw => None,
+            Self::Cbz => Some("cbz"),
+            Self::Epub => Some("epub"),
+            Self::Pdf => Some("pdf"),
+        }
+    }
+}
+
+/// What each package holds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum PackageUnit {
+    /// A package per chapter, named like exported archives.
+    #[default]
+    Chapter,
+    /// A package per volume (and language), e.g. `Vol.2`.
+    Volume,
+}
+
+/// A file (or dir) written by [`repackage_manga`].
+#[derive(Debug)]
+struct Package<'a> {
+    /// The filename, without an extension.
+    name: String,
+    /// The title shown by readers.
+    title: String,
+    /// The `dc:identifier` of EPUBs.
+    identifier: String,
+    /// The ISO 639-1 code of the chapters' language.
+    language: String,
+    /// The chapters in reading order, with their dirs.
+    chapters: Vec<(&'a ChapterEntry, PathBuf)>,
+}
+
+/// What [`repackage_manga`] did, as printed by [`repackage_library`].
+#[derive(Debug, Default)]
+pub struct RepackageCounts {
+    pub manga: usize,
+    pub written: usize,
+    /// Packages which were already up to date.
+    pub skipped: usize,
+    /// Chapters which weren't packaged since they don't match their manifest.
+    pub broken: usize,
+    pub failed: usize,
+}
+
+/// Returns the package of a single `chapter` (in `dir`) of the manga titled `manga_title`.
+fn chapter_package<'a>(manga_title: &str, chapter: &'a ChapterEntry, dir: PathBuf) -> Package<'a> {
+    let name = chapter_archive_name(chapter);
+
+    Package {
+        title: format!("{manga_title} - {name}"),
+        name,
+        identifier: format!("urn:uuid:{}", chapter.uuid),
+        language: chapter.language.clone(),
+        chapters: vec![(chapter, dir)],
+    }
+}
+
+/// Returns where `package` is written in `manga_dest` as `format`, named by `policy`.
+///
+/// `uuid` is the package's chapter, if it holds a single one.
+fn package_path(
+    policy: &PathPolicy,
+    manga_dest: &Path,
+    package: &Package,
+    uuid: Option<Uuid>,
+    format: PackageFormat,
+) -> PathBuf {
+    let name = policy.file_stem(&package.name, uuid);
+
+    match format.extension() {
+        Some(extension) => manga_dest.join(format!("{name}.{extension}")),
+        None => manga_dest.join(&name),
+    }
+}
+
+/// Groups the chapters of `entry` into packages (in reading order) by `unit`.
+fn group_packages(entry: &MangaEntry, unit: PackageUnit) -> Result<Vec<Package<'_>>> {
+    let multilingual = entry.languages().len() > 1;
+    let mut packages: Vec<Package> = Vec::new();
+
+    for chapter in entry.sorted_chapters() {
+        let dir = entry.chapter_path(chapter)?;
+
+        if unit == PackageUnit::Chapter {
+            packages.push(chapter_package(&entry.title, chapter, dir));
+            continue;
+        }
+
+        let mut name = chapter
+            .volume
+            .as_ref()
+            .map_or_else(|| "No volume".to_string(), |v| format!("Vol.{v}"));
+
+        if multilingual {
+            name = format!("{name} [{}]", chapter.language);
+        }
+
+        if let Some(package) = packages.iter_mut().find(|p| p.name == name) {
+            package.chapters.push((chapter, dir));
+        } else {
+            packages.push(Package {
+                title: format!("{} - {name}", entry.title),
+                identifier: format!("urn:uuid:{}#{name}", entry.uuid),
+                name,
+                language: chapter.language.clone(),
+                chapters: vec![(chapter, dir)],
+            });
+        }
+    }
+
+    Ok(packages)
+}
+
+/// Returns true if the chapter in `dir` matches its manifest, or has none to check against.
+fn is_intact(dir: &Path) -> Result<bool> {
+    let Some(manifest) = ChapterManifest::read(dir)? else {
+        return Ok(true);
+    };
+
+    Ok(verify_chapter(dir, &manifest)?.problems.is_empty())
+}
+
+/// Returns the pages of `chapters`, each a name in the package and the file to read it from.
+///
+/// When there are several chapters, page names are prefixed with the chapter's
+/// position so that they stay in reading order (and don't clash).
+fn package_pages(chapters: &[(&ChapterEntry, PathBuf)]) -> Result<Vec<(String, PathBuf)>> {
+    let mut pages = Vec::new();
+
+    for (i, (_, dir)) in chapters.iter().enumerate() {
+        for page in chapter_pages(dir)? {
+            let name = if chapters.len() == 1 {
+                page.clone()
+            } else {
+                format!("{i:03}-{page}")
+            };
+
+            pages.push((name, dir.join(page)));
+        }
+    }
+
+    Ok(pages)
+}
+
+/// Writes a file to `dest` through a `.partial` file, using `write`.
+/// The `.partial` file is removed if anything fails.
+fn write_atomically(dest: &Path, write: impl FnOnce(File) -> Result<()>) -> Result<()> {
+    let mut partial = dest.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+
+    let result = File::create(&partial)
+        .into_diagnostic()
+        .and_then(write)
+        .and_then(|()| fs::rename(&partial, dest).into_diagnostic());
+
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+
+    result
+}
+
+/// Copies `pages` into the dir `dest` (through a `.partial` dir, removed if anything
+/// fails), replacing it if it exists.
+fn write_raw(pages: &[(String, PathBuf)], dest: &Path) -> Result<()> {
+    let mut partial = dest.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+
+    if partial.try_exists().into_diagnostic()? {
+        fs::remove_dir_all(&partial).into_diagnostic()?;
+    }
+
+    let write = || -> Result<()> {
+        fs::create_dir_all(&partial).into_diagnostic()?;
+
+        for (name, path) in pages {
+            fs::copy(path, partial.join(name)).into_diagnostic()?;
+        }
+
+        if dest.try_exists().into_diagnostic()? {
+            fs::remove_dir_all(dest).into_diagnostic()?;
+        }
+
+        fs::rename(&partial, dest).into_diagnostic()
+    };
+
+    let result = write();
+
+    if result.is_err() {
+        let _ = fs::remove_dir_all(&partial);
+    }
+
+    result
+}
+
+/// Opens the page at `path`, returning it (rewound) with its format and dimensions.
+///
+/// Only the start of the page is read, so that the rest can be copied into an
+/// archive without holding the whole page in memory.
+fn open_page(path: &Path) -> Result<(File, ImageFormat, (u32, u32))> {
+    let mut file = File::open(path).into_diagnostic()?;
+    let mut magic = Vec::with_capacity(16);
+    (&mut file)
+        .take(16)
+        .read_to_end(&mut magic)
+        .into_diagnostic()?;
+    file.rewind().into_diagnostic()?;
+
+    let format = ImageFormat::from_magic(&magic)
+        .ok_or_else(|| miette!("{} isn't a supported image", path.display()))?;
+    let dimensions = ImageReader::open(path)
+        .into_diagnostic()?
+        .with_guessed_format()
+        .into_diagnostic()?
+        .into_dimensions()
+        .map_err(|e| miette!("failed to read the dimensions of {}: {e}", path.display()))?;
+
+    Ok((file, format, dimensions))
+}
+
+/// Zips `pages` as a fixed-layout EPUB 3 (a page per image, read in `order`)
+/// into `writer`, returning it once the archive is finished.
+///
+/// ## References
+///
+/// - [EPUB 3.3](https://www.w3.org/TR/epub-33/)
+/// - [Fixed layouts](https://www.w3.org/TR/epub-33/#sec-fixed-layouts)
+fn zip_epub<W: Write + Seek>(
+    package: &Package,
+    pages: &[(String, PathBuf)],
+    compression: ArchiveCompression,
+    order: SpreadOrder,
+    writer: W,
+) -> Result<W> {
+    let mut zip = ZipWriter::new(writer);
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let page_options = archive_options(compression);
+
+    // readers expect the mimetype first, uncompressed
+    zip.start_file("mimetype", stored).into_diagnostic()?;
+    zip.write_all(b"application/epub+zip").into_diagnostic()?;
+
+    zip.start_file("META-INF/container.xml", deflated)
+        .into_diagnostic()?;
+    zip.write_all(
+        b"<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+        <container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">\n\
+        <rootfiles>\n\
+        <rootfile full-path=\"OEBPS/content.opf\" media-type=\"application/oebps-package+xml\"/>\n\
+        </rootfiles>\n\
+        </container>\n",
+    )
+    .into_diagnostic()?;
+
+    let title = xml_escape(&package.title);
+    let (mut items, mut spine) = (String::new(), String::new());
+
+    for (i, (_, path)) in pages.iter().enumerate() {
+        let (mut file, format, (width, height)) = open_page(path)?;
+
+        let image = format!("images/{i:04}.{}", format.extension());
+        zip.start_file(format!("OEBPS/{image}"), page_options)
+            .into_diagnostic()?;
+        io::copy(&mut file, &mut zip).into_diagnostic()?;
+
+        let page = format!("pages/{i:04}.xhtml");
+        zip.start_file(format!("OEBPS/{page}"), deflated)
+            .into_diagnostic()?;
+        write!(
+            zip,
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+            <html xmlns=\"http://www.w3.org/1999/xhtml\">\n\
+            <head><title>{title}</title>\
+            <meta name=\"viewport\" content=\"width={width}, height={height}\"/></head>\n\
+            <body style=\"margin: 0\">\
+            <img src=\"../{image}\" alt=\"\" style=\"width: 100%; height: 100%\"/></body>\n\
+            </html>\n"
+        )
+        .into_diagnostic()?;
+
+        let properties = if i == 0 {
+            " properties=\"cover-image\""
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            items,
+            "<item id=\"img{i}\" href=\"{image}\" media-type=\"{}\"{properties}/>\n\
+            <item id=\"page{i}\" href=\"{page}\" media-type=\"application/xhtml+xml\"/>",
+            format.mime()
+        );
+        let _ = writeln!(spine, "<itemref idref=\"page{i}\"/>");
+    }
+
+    zip.start_file("OEBPS/nav.xhtml", deflated)
+        .into_diagnostic()?;
+    write!(
+        zip,
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+        <html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\">\n\
+        <head><title>{title}</title></head>\n\
+        <body><nav epub:type=\"toc\"><ol><li><a href=\"pages/0000.xhtml\">{title}</a></li></ol>\
+        </nav></body>\n\
+        </html>\n"
+    )
+    .into_diagnostic()?;
+
+    let direction = match order {
+        SpreadOrder::Rtl => "rtl",
+        SpreadOrder::Ltr => "ltr",
+    };
+
+    zip.start_file("OEBPS/content.opf", deflated)
+        .into_diagnostic()?;
+    write!(
+        zip,
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+        <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"id\">\n\
+        <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n\
+        <dc:identifier id=\"id\">{}</dc:identifier>\n\
+        <dc:title>{title}</dc:title>\n\
+        <dc:language>{}</dc:language>\n\
+        <meta property=\"dcterms:modified\">{}</meta>\n\
+        <meta property=\"rendition:layout\">pre-paginated</meta>\n\
+        <meta property=\"rendition:spread\">landscape</meta>\n\
+        </metadata>\n\
+        <manifest>\n\
+        <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n\
+        {items}</manifest>\n\
+        <spine page-progression-direction=\"{direction}\">\n{spine}</spine>\n\
+        </package>\n",
+        xml_escape(&package.identifier),
+        xml_escape(&package.language),
+        Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+    )
+    .into_diagnostic()?;
+
+    zip.finish().into_diagnostic()
+}
+
+/// Counts the bytes written through it, for the offsets in a PDF's cross-reference table.
+struct CountingWriter<W> {
+    inner: W,
+    written: usize,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reads the markers of the JPEG `data` up to its first scan, returning whether it's
+/// inverted (as Adobe's are) if it's CMYK, or `None` if it isn't.
+fn jpeg_cmyk(data: &[u8]) -> Option<bool> {
+    let (mut i, mut adobe, mut components) = (2, false, None);
+
+    while let [0xFF, marker, len_high, len_low, ..] = *data.get(i..)? {
+        // the length counts itself, but not the marker
+        let len = usize::from(u16::from_be_bytes([len_high, len_low]));
+        let segment = data.get(i + 4..i + 2 + len)?;
+
+        match marker {
+            // the start of the scan, after which there's only image data
+            0xDA => break,
+            0xEE => adobe |= segment.starts_with(b"Adobe"),
+            // the start of the frame (the other markers in this range aren't)
+            0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                components = segment.get(5).copied();
+            }
+            _ => {}
+        }
+
+        i += 2 + len;
+    }
+
+    (components? == 4).then_some(adobe)
+}
+
+/// Returns the page at `path` as JPEG data for a PDF, with its dimensions and color space.
+///
+/// JPEGs are embedded as they are, while other formats are re-encoded in `quality`.
+fn pdf_image(path: &Path, quality: u8) -> Result<(Vec<u8>, u32, u32, &'static str)> {
+    let data = fs::read(path).into_diagnostic()?;
+
+    if ImageFormat::from_magic(&data) == Some(ImageFormat::Jpeg) {
+        let decoder = JpegDecoder::new(Cursor::new(&data))
+            .map_err(|e| miette!("failed to read {}: {e}", path.display()))?;
+        let (width, height) = decoder.dimensions();
+        // the decoder converts CMYK to RGB, so its color type can't tell them apart
+        let color_space = match (jpeg_cmyk(&data), decoder.color_type()) {
+            // Adobe's CMYK JPEGs are stored inverted
+            (Some(true), _) => "/DeviceCMYK /Decode [1 0 1 0 1 0 1 0]",
+            (Some(false), _) => "/DeviceCMYK",
+            (None, ColorType::L8) => "/DeviceGray",
+            (None, _) => "/DeviceRGB",
+        };
+
+        return Ok((data, width, height, color_space));
+    }
+
+    let image = image::load_from_memory(&data)
+        .map_err(|e| miette!("failed to decode {}: {e}", path.display()))?;
+    let jpeg = encode(&image, ImageFormat::Jpeg, quality)?;
+
+    Ok((jpeg, image.width(), image.height(), "/DeviceRGB"))
+}
+
+/// Writes `pages` as a PDF into `writer`, with a page per image (sized to match it).
+///
+/// ## References
+///
+/// - [PDF 1.7](https://opensource.adobe.com/dc-acrobat-sdk-docs/pdfstandards/PDF32000_2008.pdf)
+fn write_pdf<W: Write>(pages: &[(String, PathBuf)], quality: u8, writer: W) -> Result<W> {
+    let mut out = CountingWriter {
+        inner: writer,
+        written: 0,
+    };
+    // the byte offset of each object, starting from object 1
+    let mut offsets = Vec::with_capacity(2 + 3 * pages.len());
+
+    out.write_all(b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n")
+        .into_diagnostic()?;
+
+    // objects 1 and 2 are the catalog and page tree, then each page takes three:
+    // the page itself, its contents and its image
+    let kids: Vec<String> = (0..pages.len())
+        .map(|i| format!("{} 0 R", 3 + 3 * i))
+        .collect();
+
+    offsets.push(out.written);
+    out.write_all(b"1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n")
+        .into_diagnostic()?;
+
+    offsets.push(out.written);
+    write!(
+        out,
+        "2 0 obj\n<< /Type /Pages /Kids [{}] /Count {} >>\nendobj\n",
+        kids.join(" "),
+        pages.len()
+    )
+    .into_diagnostic()?;
+
+    for (i, (_, path)) in pages.iter().enumerate() {
+        let (data, width, height, color_space) = pdf_image(path, quality)?;
+        let id = 3 + 3 * i;
+
+        offsets.push(out.written);
+        write!(
+            out,
+            "{id} 0 obj\n<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {width} {height}] \
+            /Resources << /XObject << /Im0 {} 0 R >> >> /Contents {} 0 R >>\nendobj\n",
+            id + 2,
+            id + 1
+        )
+        .into_diagnostic()?;
+
+        let contents = format!("q {width} 0 0 {height} 0 0 cm /Im0 Do Q");
+        offsets.push(out.written);
+        write!(
+            out,
+            "{} 0 obj\n<< /Length {} >>\nstream\n{contents}\nendstream\nendobj\n",
+            id + 1,
+            contents.len()
+        )
+        .into_diagnostic()?;
+
+        offsets.push(out.written);
+        write!(
+            out,
+            "{} 0 obj\n<< /Type /XObject /Subtype /Image /Width {width} /Height {height} \
+            /ColorSpace {color_space} /BitsPerComponent 8 /Filter /DCTDecode /Length {} >>\n\
+            stream\n",
+            id + 2,
+            data.len()
+        )
+        .into_diagnostic()?;
+        out.write_all(&data).into_diagnostic()?;
+        out.write_all(b"\nendstream\nendobj\n").into_diagnostic()?;
+    }
+
+    let xref = out.written;
+    write!(out, "xref\n0 {}\n0000000000 65535 f \n", offsets.len() + 1).into_diagnostic()?;
+
+    for offset in &offsets {
+        writeln!(out, "{offset:010} 00000 n ").into_diagnostic()?;
+    }
+
+    write!(
+        out,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+        offsets.len() + 1
+    )
+    .into_diagnostic()?;
+
+    Ok(out.inner)
+}
+
+/// Writes `package` (with `pages`) of the manga titled `manga_title` to `dest`,
+/// as set by `packaging`. The `manga` (if known) adds to the `ComicInfo.xml` of CBZs.
+fn write_package(
+    packaging: &ChapterPackaging,
+    manga_title: &str,
+    manga: Option<&Manga>,
+    package: &Package,
+    pages: &[(String, PathBuf)],
+    dest: &Path,
+) -> Result<()> {
+    match packaging.format {
+        PackageFormat::Raw => write_raw(pages, dest),
+        PackageFormat::Cbz => {
+            let info = match package.chapters.as_slice() {
+                [(chapter, _)] => Some(comic_info(manga_title, chapter, manga, packaging.language)),
+                _ => None,
+            };
+
+            write_atomically(dest, |file| {
+                let writer = std::io::BufWriter::new(file);
+                zip_pages(pages, writer, info.as_deref(), packaging.compression)?
+                    .flush()
+                    .into_diagnostic()
+            })
+        }
+        PackageFormat::Epub => write_atomically(dest, |file| {
+            zip_epub(
+                package,
+                pages,
+                packaging.compression,
+                packaging.spread_order,
+                file,
+            )
+            .map(|_| ())
+        }),
+        PackageFormat::Pdf => write_atomically(dest, |file| {
+            write_pdf(pages, packaging.quality, std::io::BufWriter::new(file))?
+                .flush()
+                .into_diagnostic()
+        }),
+    }
+}
+
+/// Repackages a single manga into its dir in [`ChapterPackaging::dest`], adding to
+/// `counts`. Only the chapters in `only` are packaged, if given.
+///
+/// This is what [`repackage_library`] does per manga, and offline mode
+/// (see [`crate::offline`]) uses for the chapters chosen there.
+///
+/// ## Errors
+///
+/// If the manga's dir can't be created or its chapters can't be read.
+/// Packages which fail to be written are only counted.
+pub fn repackage_manga(
+    packaging: &ChapterPackaging,
+    entry: &MangaEntry,
+    unit: PackageUnit,
+    manga: Option<&Manga>,
+    only: Option<&[Uuid]>,
+    counts: &mut RepackageCounts,
+) -> Result<()> {
+    let (policy, format) = (&packaging.policy, packaging.format);
+    let manga_dest = packaging.dest.join(&entry.dir);
+
+    if !is_dry_run() {
+        fs::create_dir_all(&manga_dest).into_diagnostic()?;
+    }
+
+    for mut package in group_packages(entry, unit)? {
+        if let Some(only) = only {
+            package.chapters.retain(|(c, _)| only.contains(&c.uuid));
+
+            if package.chapters.is_empty() {
+                continue;
+            }
+        }
+
+        let uuid = match package.chapters.as_slice() {
+            [(chapter, _)] if unit == PackageUnit::Chapter => Some(chapter.uuid),
+            _ => None,
+        };
+        let path = package_path(policy, &manga_dest, &package, uuid, format);
+        let newest = package.chapters.iter().map(|(c, _)| c.downloaded_at).max();
+
+        if newest.is_some_and(|newest| is_up_to_date(&path, newest)) {
+            counts.skipped += 1;
+            continue;
+        }
+
+        let mut intact = Vec::with_capacity(package.chapters.len());
+
+        for (chapter, dir) in package.chapters {
+            if is_intact(&dir)? {
+                intact.push((chapter, dir));
+            } else {
+                warn!(
+                    "Not packaging {}, which has missing or corrupted pages (see `verify`)",
+                    dir.display()
+                );
+                counts.broken += 1;
+            }
+        }
+
+        package.chapters = intact;
+
+        if package.chapters.is_empty() {
+            continue;
+        }
+
+        if is_dry_run() {
+            print_line(format!("Would write {}", path.display()));
+            counts.written += 1;
+            continue;
+        }
+
+        let result = package_pages(&package.chapters).and_then(|pages| {
+            write_package(packaging, &entry.title, manga, &package, &pages, &path)
+        });
+
+        match result {
+            Ok(()) => counts.written += 1,
+            Err(e) => {
+                error!("Failed to repackage {}: {e}", path.display());
+                counts.failed += 1;
+            }
+        }
+    }
+
+    counts.manga += 1;
+    Ok(())
+}
+
+/// Where and how chapters are packaged, by [`package_chapter`] (as they're
+/// downloaded) and [`repackage_library`].
+#[derive(Debug, Clone)]
+pub struct ChapterPackaging {
+    pub format: PackageFormat,
+    /// The dir packages are written to, in a dir per manga (named like the library's).
+    pub dest: PathBuf,
+    pub policy: PathPolicy,
+    /// The language of `ComicInfo.xml` in CBZs.
+    pub language: Language,
+    /// The JPEG quality of pages re-encoded for PDFs.
+    pub quality: u8,
+    /// How pages are compressed in CBZs and EPUBs.
+    pub compression: ArchiveCompression,
+    /// The reading order of EPUBs.
+    pub spread_order: SpreadOrder,
+}
+
+impl ChapterPackaging {
+    /// Packages as `format` into `dest`, with everything else from `cfg`.
+    #[must_use]
+    pub fn new(cfg: &Config, format: PackageFormat, dest: PathBuf) -> Self {
+        Self {
+            format,
+            dest,
+            policy: cfg.naming.policy.clone(),
+            language: cfg.client.language,
+            quality: cfg.images.convert_quality,
+            compression: cfg.images.archive_compression,
+            spread_order: cfg.images.spread_order,
+        }
+    }
+}
+
+/// Packages a single downloaded `chapter` of the manga titled `manga_title` (saved in
+/// `manga_dir`, relative to the library) as [`ChapterPackaging::format`], returning
+/// where the package was written.
+///
+/// This is what [`repackage_library`] does per chapter, for packaging chapters as soon as
+/// they're downloaded. Nothing is written if the package is already up to date.
+///
+/// ## Errors
+///
+/// If the chapter doesn't match its manifest, or its package can't be written.
+pub fn package_chapter(
+    packaging: &ChapterPackaging,
+    manga_title: &str,
+    manga_dir: &Path,
+    chapter: &ChapterEntry,
+) -> Result<PathBuf> {
+    let dir = manga_save_dir()?.join(manga_dir).join(&chapter.dir);
+    let manga_dest = packaging.dest.join(manga_dir);
+    let package = chapter_package(manga_title, chapter, dir);
+    let path = package_path(
+        &packaging.policy,
+        &manga_dest,
+        &package,
+        Some(chapter.uuid),
+        packaging.format,
+    );
+
+    if is_up_to_date(&path, chapter.downloaded_at) {
+        return Ok(path);
+    }
+
+    if !is_intact(&package.chapters[0].1)? {
+        bail!("the chapter has missing or corrupted pages (see `verify`)");
+    }
+
+    fs::create_dir_all(&manga_dest).into_diagnostic()?;
+    let pages = package_pages(&package.chapters)?;
+    write_package(packaging, manga_title, None, &package, &pages, &path)?;
+
+    Ok(path)
+}
+
+/// Repackages every manga in the library (whose title contains `manga_filter`,
+/// case-insensitive) into `dest` as `format`, a package per `unit`.
+///
+/// Nothing is fetched from Manga-Dex: pages come from the library, and chapters
+/// that don't match their manifests are skipped (with a warning).
+///
+/// ## Errors
+///
+/// If the library index can't be loaded, `dest` is the library itself, or a manga's
+/// dir can't be written. Packages that can't be written are logged and counted as
+/// failed instead.
+pub fn repackage_library(
+    cfg: &Config,
+    dest: &Path,
+    format: PackageFormat,
+    unit: PackageUnit,
+    manga_filter: Option<&str>,
+) -> Result<()> {
+    let library = manga_save_dir()?;
+
+    // raw chapters would be replaced by copies of themselves,
+    // and packages inside it would look like orphaned dirs
+    if is_within(dest, &library) {
+        bail!("can't repackage the library into itself, choose a dir outside of it");
+    }
+
+    let manga_filter = manga_filter.map(str::to_lowercase);
+    let index = LibraryIndex::load()?;
+    let manga: Vec<&MangaEntry> = index
+        .manga
+        .values()
+        .filter(|m| {
+            manga_filter
+                .as_ref()
+                .is_none_or(|f| m.title.to_lowercase().contains(f))
+        })
+        .collect();
+
+    if manga.is_empty() {
+        print_line(style("No downloaded manga found").yellow().italic());
+        return Ok(());
+    }
+
+    let packaging = ChapterPackaging::new(cfg, format, dest.to_path_buf());
+    let mut counts = RepackageCounts::default();
+    info!(
+        "Repackaging {} manga to {} ({format:?} per {unit:?})",
+        manga.len(),
+        dest.display()
+    );
+
+    for entry in manga {
+        repackage_manga(&packaging, entry, unit, None, None, &mut counts)?;
+    }
+
+    let verb = if is_dry_run() {
+        "would be written"
+    } else {
+        "written"
+    };
+    print_line(
+        style(format!(
+            "Repackaged {} manga to {}: {} packages {verb}, {} already up to date, \
+            {} broken chapters skipped, {} failed",
+            counts.manga,
+            dest.display(),
+            counts.written,
+            counts.skipped,
+            counts.broken,
+            counts.failed
+        ))
+        .green(),
+    );
+
+    Ok(())
+}
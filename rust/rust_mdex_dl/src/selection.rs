@@ -0,0 +1,510 @@
+//! Contains [`select_chapters`], the interactive menu for choosing which of a
+//! manga's chapters to download, with chapters grouped by volume.
+//!
+//! Besides toggling chapters and volumes, chapters can be selected all at once,
+//! inverted, selected by number ranges (see [`parse_ranges`]), or picked from a
+//! searchable list.
+
+use crate::{
+    api::models::{Aggregate, Chapter, ChapterNumber},
+    progress::print_line,
+    update::parse_number,
+};
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use console::{Alignment, measure_text_width, pad_str, style};
+use dialoguer::{Confirm, Input, MultiSelect, Select, theme::ColorfulTheme};
+use miette::{IntoDiagnostic, Result};
+
+/// The chapters of a volume, in the order they're listed in.
+#[derive(Debug, Clone)]
+pub struct VolumeGroup {
+    /// The volume number, or `None` for chapters without one.
+    pub volume: Option<String>,
+    pub chapters: Vec<Chapter>,
+}
+
+impl VolumeGroup {
+    /// Returns the volume's header, e.g. `Volume 3 (ch. 15–21)`.
+    fn label(&self) -> String {
+        let name = self
+            .volume
+            .as_ref()
+            .map_or_else(|| "No volume".to_string(), |v| format!("Volume {v}"));
+
+        let numbers: Vec<&str> = self
+            .chapters
+            .iter()
+            .filter_map(|c| c.data.attributes.chapter_number.as_deref())
+            .collect();
+
+        match (numbers.first(), numbers.last()) {
+            (Some(first), Some(last)) if first == last => format!("{name} (ch. {first})"),
+            (Some(first), Some(last)) => format!("{name} (ch. {first}–{last})"),
+            _ => name,
+        }
+    }
+}
+
+/// The most scanlation groups named per volume by [`preview_chapters`].
+const MAX_PREVIEW_GROUPS: usize = 3;
+
+impl VolumeGroup {
+    /// Returns the columns of the volume's line in [`preview_chapters`], unpadded.
+    fn preview_columns(&self) -> Vec<String> {
+        let languages: BTreeSet<String> = self
+            .chapters
+            .iter()
+            .map(|c| c.data.attributes.translated_language.to_string())
+            .collect();
+        let group_names: BTreeSet<String> = self
+            .chapters
+            .iter()
+            .flat_map(Chapter::group_names)
+            .collect();
+        let external = self.chapters.iter().filter(|c| c.is_external()).count();
+
+        let mut groups: Vec<String> = group_names
+            .iter()
+            .take(MAX_PREVIEW_GROUPS)
+            .cloned()
+            .collect();
+
+        if group_names.len() > MAX_PREVIEW_GROUPS {
+            groups.push(format!("+{}", group_names.len() - MAX_PREVIEW_GROUPS));
+        }
+
+        vec![
+            self.label(),
+            format!("{} chapters", self.chapters.len()),
+            languages.into_iter().collect::<Vec<_>>().join(", "),
+            groups.join(", "),
+            if external == 0 {
+                String::new()
+            } else {
+                style(format!("{external} external")).yellow().to_string()
+            },
+        ]
+    }
+}
+
+/// Prints a line per volume of `groups` (the chapters of the manga titled `title`) with
+/// how many chapters it has, their languages and groups, and how many are only hosted
+/// outside of Manga-Dex, then asks whether to carry on to choosing chapters.
+///
+/// The manga's forum thread is linked too, if `thread_url` is given.
+///
+/// This is so that problems (e.g. only a few chapters in the chosen language)
+/// are noticed before downloading any pages. The chapters have already been
+/// fetched by then, so this doesn't save any requests for the chapter list.
+///
+/// ## Errors
+///
+/// If prompting fails (e.g. there's no terminal).
+pub fn preview_chapters(
+    title: &str,
+    thread_url: Option<&str>,
+    groups: &[VolumeGroup],
+) -> Result<bool> {
+    let rows: Vec<Vec<String>> = groups.iter().map(VolumeGroup::preview_columns).collect();
+    let column_count = rows.first().map_or(0, Vec::len);
+    let widths: Vec<usize> = (0..column_count)
+        .map(|c| {
+            rows.iter()
+                .map(|r| measure_text_width(&r[c]))
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let chapters: usize = groups.iter().map(|g| g.chapters.len()).sum();
+    let external: usize = groups
+        .iter()
+        .flat_map(|g| &g.chapters)
+        .filter(|c| c.is_external())
+        .count();
+
+    print_line(
+        style(format!(
+            "{title}: {chapters} chapters in {} volumes",
+            groups.len()
+        ))
+        .bold(),
+    );
+
+    if let Some(url) = thread_url {
+        print_line(format!(
+            "  {} {}",
+            style("Discussion:").dim(),
+            style(url).underlined()
+        ));
+    }
+
+    for row in rows {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| pad_str(cell, *width, Alignment::Left, None).into_owned())
+            .collect();
+
+        print_line(format!("  {}", cells.join("  ").trim_end()));
+    }
+
+    if external > 0 {
+        let message = format!(
+            "{external} chapters are hosted outside of Manga-Dex, so they have no pages to download"
+        );
+        print_line(style(message).yellow().italic());
+    }
+
+    Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt("Continue to choosing chapters?")
+        .default(true)
+        .interact()
+        .into_diagnostic()
+}
+
+/// Groups `chapters` by volume, in reading order with chapters without a volume last.
+///
+/// Volumes are taken from `aggregate` if given, since chapters are sometimes missing
+/// their volume when another version of them has one, falling back to each chapter's own.
+#[must_use]
+pub fn group_by_volume(chapters: Vec<Chapter>, aggregate: Option<&Aggregate>) -> Vec<VolumeGroup> {
+    let volumes = aggregate
+        .map(Aggregate::volumes_by_chapter)
+        .unwrap_or_default();
+    let mut groups: BTreeMap<Option<String>, Vec<Chapter>> = BTreeMap::new();
+
+    for chapter in chapters {
+        let volume = volumes
+            .get(&chapter.uuid())
+            .cloned()
+            .or_else(|| chapter.data.attributes.volume.clone());
+
+        groups.entry(volume).or_default().push(chapter);
+    }
+
+    let sort_key = |number: Option<&str>| parse_number(number).unwrap_or(f64::MAX);
+
+    let mut groups: Vec<VolumeGroup> = groups
+        .into_iter()
+        .map(|(volume, mut chapters)| {
+            chapters.sort_by(|a, b| {
+                let key = |c: &Chapter| sort_key(c.data.attributes.chapter_number.as_deref());
+                key(a).total_cmp(&key(b))
+            });
+
+            VolumeGroup { volume, chapters }
+        })
+        .collect();
+
+    // `None` sorts first in the map, but volume-less chapters go last
+    groups.sort_by(|a, b| {
+        let key = |g: &VolumeGroup| {
+            g.volume
+                .as_deref()
+                .map_or(f64::INFINITY, |v| sort_key(Some(v)))
+        };
+        key(a).total_cmp(&key(b))
+    });
+
+    groups
+}
+
+/// Returns a line describing `chapter`, e.g.
+/// `Ch. 12: Title · Group · by uploader · 2024-05-01 · 24 pages`.
+///
+/// Unavailable chapters (see [`Chapter::is_unavailable`]) are dimmed and tagged
+/// `(unavailable)`, since they usually can't be downloaded.
+#[must_use]
+pub fn chapter_row(chapter: &Chapter) -> String {
+    let attrs = &chapter.data.attributes;
+    let number = attrs
+        .chapter_number
+        .as_deref()
+        .map_or_else(|| "Oneshot".to_string(), |n| format!("Ch. {n}"));
+
+    let mut parts = vec![match attrs.title.as_deref() {
+        Some(title) if !title.is_empty() => format!("{number}: {title}"),
+        _ => number,
+    }];
+
+    let groups = chapter.group_names();
+
+    if !groups.is_empty() {
+        parts.push(groups.join(", "));
+    }
+
+    if let Some(uploader) = chapter.uploader() {
+        parts.push(format!("by {uploader}"));
+    }
+
+    parts.push(attrs.publish_at.format("%Y-%m-%d").to_string());
+    parts.push(format!("{} pages", attrs.pages));
+    let row = parts.join(" · ");
+
+    if chapter.is_unavailable() {
+        style(format!("{row} (unavailable)")).dim().to_string()
+    } else {
+        row
+    }
+}
+
+/// Parses chapter number ranges such as `"1-10, 15, 20.5"` as inclusive `(start, end)` pairs.
+///
+/// ## Errors
+///
+/// If a part isn't a number or a range of two numbers, returning a message saying which.
+pub fn parse_ranges(input: &str) -> Result<Vec<(ChapterNumber, ChapterNumber)>, String> {
+    input
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let number = |n: &str| {
+                ChapterNumber::parse(n)
+                    .ok_or_else(|| format!("{:?} isn't a chapter number", n.trim()))
+            };
+
+            match part.split_once('-') {
+                Some((start, end)) => {
+                    let (start, end) = (number(start)?, number(end)?);
+
+                    if start > end {
+                        return Err(format!("{part:?} starts after it ends"));
+                    }
+
+                    Ok((start, end))
+                }
+                None => number(part).map(|n| (n, n)),
+            }
+        })
+        .collect()
+}
+
+/// A row of the menu in [`select_chapters`].
+#[derive(Clone, Copy)]
+enum Row {
+    Download,
+    SelectAll,
+    Invert,
+    /// Selects only the chapters in some number ranges, see [`parse_ranges`].
+    Range,
+    /// Picks chapters from a list, filtered by a search.
+    Search,
+    /// A volume's header, which expands or collapses it.
+    Volume(usize),
+    /// Selects (or deselects) every chapter of a volume.
+    WholeVolume(usize),
+    Chapter(usize, usize),
+}
+
+/// Builds the rows of the menu and their labels.
+fn rows(
+    groups: &[VolumeGroup],
+    selected: &[Vec<bool>],
+    expanded: &[bool],
+) -> (Vec<Row>, Vec<String>) {
+    let total: usize = selected.iter().flatten().filter(|s| **s).count();
+    let mut rows = vec![
+        Row::Download,
+        Row::SelectAll,
+        Row::Invert,
+        Row::Range,
+        Row::Search,
+    ];
+    let mut labels = vec![
+        style(format!("Download {total} selected chapters"))
+            .green()
+            .to_string(),
+        style("Select all").yellow().to_string(),
+        style("Invert selection").yellow().to_string(),
+        style("Select by chapter numbers (e.g. 1-10, 15)")
+            .yellow()
+            .to_string(),
+        style("Search and pick chapters").yellow().to_string(),
+    ];
+
+    for (v, group) in groups.iter().enumerate() {
+        let count = selected[v].iter().filter(|s| **s).count();
+        let marker = if expanded[v] { "▾" } else { "▸" };
+
+        rows.push(Row::Volume(v));
+        labels.push(format!(
+            "{marker} {} {}",
+            style(group.label()).bold(),
+            style(format!("[{count}/{}]", group.chapters.len())).dim()
+        ));
+
+        if !expanded[v] {
+            continue;
+        }
+
+        let whole = if count == group.chapters.len() {
+            "Deselect whole volume"
+        } else {
+            "Select whole volume"
+        };
+
+        rows.push(Row::WholeVolume(v));
+        labels.push(format!("    {}", style(whole).yellow()));
+
+        for (c, chapter) in group.chapters.iter().enumerate() {
+            let check = if selected[v][c] { "[x]" } else { "[ ]" };
+            rows.push(Row::Chapter(v, c));
+            labels.push(format!("    {check} {}", chapter_row(chapter)));
+        }
+    }
+
+    (rows, labels)
+}
+
+/// Asks for chapter number ranges, selecting only the chapters of `groups` in them.
+fn select_ranges(groups: &[VolumeGroup], selected: &mut [Vec<bool>]) -> Result<()> {
+    let input: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("Chapter numbers")
+        .validate_with(|input: &String| parse_ranges(input).map(|_| ()))
+        .interact_text()
+        .into_diagnostic()?;
+
+    // already validated
+    let ranges = parse_ranges(&input).unwrap_or_default();
+
+    for (group, selected) in groups.iter().zip(selected) {
+        for (chapter, selected) in group.chapters.iter().zip(selected) {
+            *selected = chapter.number().is_some_and(|n| {
+                ranges
+                    .iter()
+                    .any(|(start, end)| (*start..=*end).contains(&n))
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Asks for a search, then lets the chapters of `groups` matching it be picked from a list.
+fn search_chapters(groups: &[VolumeGroup], selected: &mut [Vec<bool>]) -> Result<()> {
+    let theme = ColorfulTheme::default();
+    let query: String = Input::with_theme(&theme)
+        .with_prompt("Search chapters (number, title, group or date, empty for all)")
+        .allow_empty(true)
+        .interact_text()
+        .into_diagnostic()?;
+    let query = query.trim().to_lowercase();
+
+    // (volume, chapter, row) of every match
+    let matches: Vec<(usize, usize, String)> = groups
+        .iter()
+        .enumerate()
+        .flat_map(|(v, g)| {
+            g.chapters
+                .iter()
+                .enumerate()
+                .map(move |(c, chapter)| (v, c, chapter_row(chapter)))
+        })
+        .filter(|(_, _, row)| row.to_lowercase().contains(&query))
+        .collect();
+
+    if matches.is_empty() {
+        print_line(style("No chapters match").yellow().italic());
+        return Ok(());
+    }
+
+    let defaults: Vec<bool> = matches.iter().map(|(v, c, _)| selected[*v][*c]).collect();
+
+    let Some(picked) = MultiSelect::with_theme(&theme)
+        .with_prompt("Pick chapters (space toggles, enter confirms)")
+        .items(matches.iter().map(|(_, _, row)| row))
+        .defaults(&defaults)
+        .max_length(20)
+        .interact_opt()
+        .into_diagnostic()?
+    else {
+        return Ok(());
+    };
+
+    for (i, (v, c, _)) in matches.iter().enumerate() {
+        selected[*v][*c] = picked.contains(&i);
+    }
+
+    Ok(())
+}
+
+/// Asks which chapters of `groups` to download, with every chapter selected to begin with,
+/// except for unavailable ones unless `attempt_unavailable` is set (which "Select all"
+/// also leaves out).
+///
+/// Volumes start collapsed. Choosing a volume expands it, showing its chapters (which
+/// can be toggled one by one) and a shortcut for selecting the whole volume. Above
+/// the volumes are shortcuts for selecting all, inverting, selecting by number ranges
+/// and picking chapters from a searchable list.
+///
+/// Returns the selected chapters in order, or `None` if the menu is exited.
+///
+/// ## Errors
+///
+/// If prompting fails (e.g. there's no terminal).
+pub fn select_chapters(
+    groups: &[VolumeGroup],
+    attempt_unavailable: bool,
+) -> Result<Option<Vec<Chapter>>> {
+    let defaults: Vec<Vec<bool>> = groups
+        .iter()
+        .map(|g| {
+            g.chapters
+                .iter()
+                .map(|c| attempt_unavailable || !c.is_unavailable())
+                .collect()
+        })
+        .collect();
+    let mut selected = defaults.clone();
+    let mut expanded = vec![false; groups.len()];
+    let mut cursor = 0;
+
+    loop {
+        let (rows, labels) = rows(groups, &selected, &expanded);
+
+        let Some(chosen) = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Choose chapters (enter expands a volume or toggles a chapter)")
+            .items(&labels)
+            .default(cursor.min(labels.len() - 1))
+            .max_length(20)
+            .interact_opt()
+            .into_diagnostic()?
+        else {
+            return Ok(None);
+        };
+
+        cursor = chosen;
+
+        match rows[chosen] {
+            Row::Download => {
+                let chapters: Vec<Chapter> = groups
+                    .iter()
+                    .zip(&selected)
+                    .flat_map(|(g, s)| g.chapters.iter().zip(s).filter(|(_, s)| **s))
+                    .map(|(c, _)| c.clone())
+                    .collect();
+
+                if chapters.is_empty() {
+                    print_line(style("No chapters selected").yellow().italic());
+                    continue;
+                }
+
+                return Ok(Some(chapters));
+            }
+            Row::SelectAll => selected.clone_from(&defaults),
+            Row::Invert => selected.iter_mut().flatten().for_each(|s| *s = !*s),
+            Row::Range => select_ranges(groups, &mut selected)?,
+            Row::Search => search_chapters(groups, &mut selected)?,
+            Row::Volume(v) => expanded[v] = !expanded[v],
+            Row::WholeVolume(v) => {
+                let all = selected[v].iter().all(|s| *s);
+                selected[v].fill(!all);
+            }
+            Row::Chapter(v, c) => selected[v][c] = !selected[v][c],
+        }
+    }
+}
@@ -0,0 +1,519 @@
+//! A minimal [OPDS 1.2](https://specs.opds.io/opds-1.2) catalog of the
+//! [library](`crate::library::LibraryIndex`), served with the `serve` command.
+//!
+//! This is a tiny HTTP/1.1 server which only answers `GET` (and `HEAD`) requests,
+//! closing the connection after each response. At most [`MAX_CONNECTIONS`] are handled at
+//! once, and clients get [`REQUEST_TIMEOUT`] to send their request. The catalog has three
+//! kinds of pages:
+//!
+//! - `/opds`: a navigation feed with an entry per manga
+//! - `/opds/manga/{uuid}`: an acquisition feed with an entry per chapter
+//! - `/cbz/{manga_uuid}/{chapter_uuid}`: the chapter as a CBZ, zipped on the fly into a
+//!   temporary file (so that large chapters aren't held in memory), except for `HEAD`
+//!   requests, which are answered without zipping it
+//!
+//! The index is reloaded for every request, so newly downloaded chapters show up
+//! without restarting the server.
+
+use crate::{
+    config::ArchiveCompression,
+    export::{chapter_archive_name, xml_escape, zip_chapter},
+    library::{ChapterEntry, LibraryIndex, MangaEntry},
+    paths::manga_save_dir,
+    progress::{print_line, print_status},
+    update::{parse_number, shutdown_signal},
+};
+
+use std::{
+    fs::{self, File},
+    io::ErrorKind,
+    net::SocketAddr,
+    path::PathBuf,
+    process,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use console::style;
+use miette::{IntoDiagnostic, Result};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::Semaphore,
+    time::{Instant, timeout_at},
+};
+use uuid::Uuid;
+
+const NAVIGATION: &str = "application/atom+xml;profile=opds-catalog;kind=navigation";
+const ACQUISITION: &str = "application/atom+xml;profile=opds-catalog;kind=acquisition";
+const CBZ: &str = "application/vnd.comicbook+zip";
+
+/// The max size of a request's head, anything larger is rejected.
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// How long a client has to send its request's head, before it's answered with a 408.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The most connections handled at once. Further ones wait to be accepted.
+pub const MAX_CONNECTIONS: usize = 32;
+
+/// Counts the temporary CBZs made, so that concurrent requests don't share one.
+static TEMP_CBZS: AtomicU64 = AtomicU64::new(0);
+
+/// A CBZ zipped into the temporary directory, which is removed when dropped.
+struct TempCbz(PathBuf);
+
+impl TempCbz {
+    fn new() -> Self {
+        let n = TEMP_CBZS.fetch_add(1, Ordering::Relaxed);
+        let name = format!("{}-{}-{n}.cbz", env!("CARGO_PKG_NAME"), process::id());
+
+        Self(std::env::temp_dir().join(name))
+    }
+}
+
+impl Drop for TempCbz {
+    fn drop(&mut self) {
+        // it isn't there if it couldn't be created
+        if let Err(e) = fs::remove_file(&self.0)
+            && e.kind() != ErrorKind::NotFound
+        {
+            warn!("Failed to remove the temporary CBZ {:?}: {e}", self.0);
+        }
+    }
+}
+
+/// The body of a [`Response`].
+enum Body {
+    Bytes(Vec<u8>),
+    /// Sent from the file, rather than read into memory first.
+    File(TempCbz),
+    /// No body and no `Content-Length`, for `HEAD` requests for CBZs, which
+    /// aren't zipped just to find out their size.
+    Unsized,
+}
+
+/// An HTTP response, written by [`Response::write`].
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Body,
+    /// Extra headers, such as `Content-Disposition`.
+    headers: Vec<String>,
+}
+
+impl Response {
+    fn ok(content_type: &'static str, body: Body) -> Self {
+        Self {
+            status: "200 OK",
+            content_type,
+            body,
+            headers: Vec::new(),
+        }
+    }
+
+    fn error(status: &'static str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: Body::Bytes(format!("{status}\n").into_bytes()),
+            headers: Vec::new(),
+        }
+    }
+
+    fn redirect(location: &str) -> Self {
+        Self {
+            headers: vec![format!("Location: {location}")],
+            ..Self::error("302 Found")
+        }
+    }
+
+    /// Writes the response to `stream`, leaving out the body if `head_only`.
+    async fn write(self, stream: &mut TcpStream, head_only: bool) -> std::io::Result<()> {
+        let len = match &self.body {
+            Body::Bytes(bytes) => Some(bytes.len() as u64),
+            Body::File(cbz) => Some(tokio::fs::metadata(&cbz.0).await?.len()),
+            Body::Unsized => None,
+        };
+
+        let content_length = len
+            .map(|len| format!("Content-Length: {len}\r\n"))
+            .unwrap_or_default();
+
+        let mut head = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\n{content_length}Connection: close\r\n",
+            self.status, self.content_type,
+        );
+
+        for header in &self.headers {
+            head.push_str(header);
+            head.push_str("\r\n");
+        }
+
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes()).await?;
+
+        if !head_only {
+            match &self.body {
+                Body::Bytes(bytes) => stream.write_all(bytes).await?,
+                Body::File(cbz) => {
+                    let mut file = tokio::fs::File::open(&cbz.0).await?;
+                    tokio::io::copy(&mut file, stream).await?;
+                }
+                Body::Unsized => {}
+            }
+        }
+
+        stream.flush().await
+    }
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Wraps `entries` in an Atom feed with the links every OPDS feed should have.
+fn feed(
+    id: &str,
+    title: &str,
+    updated: DateTime<Utc>,
+    path: &str,
+    kind: &str,
+    entries: &str,
+) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+        <feed xmlns=\"http://www.w3.org/2005/Atom\" xmlns:opds=\"http://opds-spec.org/2010/catalog\">\n\
+        <id>{id}</id>\n\
+        <title>{}</title>\n\
+        <updated>{}</updated>\n\
+        <author><name>{}</name></author>\n\
+        <link rel=\"self\" href=\"{path}\" type=\"{kind}\"/>\n\
+        <link rel=\"start\" href=\"/opds\" type=\"{NAVIGATION}\"/>\n\
+        {entries}</feed>\n",
+        xml_escape(title),
+        timestamp(updated),
+        env!("CARGO_PKG_NAME"),
+    )
+}
+
+/// The navigation feed, listing every manga in `index`.
+fn root_feed(index: &LibraryIndex) -> String {
+    let mut manga: Vec<&MangaEntry> = index.manga.values().collect();
+    manga.sort_by_key(|m| m.title.to_lowercase());
+
+    let entries: Vec<String> = manga
+        .iter()
+        .map(|m| {
+            format!(
+                "<entry>\n\
+                <title>{}</title>\n\
+                <id>urn:uuid:{}</id>\n\
+                <updated>{}</updated>\n\
+                <content type=\"text\">{} chapters</content>\n\
+                <link rel=\"subsection\" href=\"/opds/manga/{}\" type=\"{ACQUISITION}\"/>\n\
+                </entry>\n",
+                xml_escape(&m.title),
+                m.uuid,
+                timestamp(m.updated_at),
+                m.chapters.len(),
+                m.uuid,
+            )
+        })
+        .collect();
+
+    let updated = manga
+        .iter()
+        .map(|m| m.updated_at)
+        .max()
+        .unwrap_or_else(Utc::now);
+
+    feed(
+        "urn:rust_mdex_dl:library",
+        "Library",
+        updated,
+        "/opds",
+        NAVIGATION,
+        &entries.concat(),
+    )
+}
+
+/// Returns the chapters of `manga` in reading order (by volume, then chapter number).
+fn sorted_chapters(manga: &MangaEntry) -> Vec<&ChapterEntry> {
+    let mut chapters: Vec<&ChapterEntry> = manga.chapters.values().collect();
+
+    chapters.sort_by(|a, b| {
+        let key = |c: &ChapterEntry| {
+            (
+                parse_number(c.volume.as_deref()).unwrap_or(f64::MAX),
+                parse_number(c.chapter_number.as_deref()).unwrap_or(f64::MAX),
+            )
+        };
+
+        let (a, b) = (key(a), key(b));
+        a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1))
+    });
+
+    chapters
+}
+
+/// The acquisition feed of `manga`, listing its chapters with links to their CBZs.
+fn manga_feed(manga: &MangaEntry) -> String {
+    let entries: Vec<String> = sorted_chapters(manga)
+        .into_iter()
+        .map(|c| {
+            format!(
+                "<entry>\n\
+                <title>{}</title>\n\
+                <id>urn:uuid:{}</id>\n\
+                <updated>{}</updated>\n\
+                <dc:language xmlns:dc=\"http://purl.org/dc/terms/\">{}</dc:language>\n\
+                <content type=\"text\">{} pages, {}</content>\n\
+                <link rel=\"http://opds-spec.org/acquisition\" href=\"/cbz/{}/{}\" type=\"{CBZ}\"/>\n\
+                </entry>\n",
+                xml_escape(&chapter_archive_name(c)),
+                c.uuid,
+                timestamp(c.downloaded_at),
+                xml_escape(&c.language),
+                c.pages,
+                xml_escape(&c.groups.join(", ")),
+                manga.uuid,
+                c.uuid,
+            )
+        })
+        .collect();
+
+    feed(
+        &format!("urn:uuid:{}", manga.uuid),
+        &manga.title,
+        manga.updated_at,
+        &format!("/opds/manga/{}", manga.uuid),
+        ACQUISITION,
+        &entries.concat(),
+    )
+}
+
+/// Returns a `Content-Disposition` header for downloading a file named `filename`.
+///
+/// Titles can have any characters, so the plain `filename` only keeps printable ASCII
+/// (without quotes and backslashes, which would end it early), while the full name is
+/// given as an [RFC 5987](https://www.rfc-editor.org/rfc/rfc5987) `filename*`.
+fn content_disposition(filename: &str) -> String {
+    let ascii: String = filename
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => '_',
+            c if c.is_ascii_graphic() || c == ' ' => c,
+            _ => '_',
+        })
+        .collect();
+
+    let encoded: String = filename
+        .bytes()
+        .map(|b| {
+            // RFC 5987's attr-chars, which don't need escaping
+            if b.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&b) {
+                char::from(b).to_string()
+            } else {
+                format!("%{b:02X}")
+            }
+        })
+        .collect();
+
+    format!("Content-Disposition: attachment; filename=\"{ascii}\"; filename*=UTF-8''{encoded}")
+}
+
+/// Zips the chapter with `chapter_uuid` of the manga with `manga_uuid` into a [`TempCbz`],
+/// unless `head_only`, in which case only the headers are returned.
+fn chapter_cbz(
+    index: &LibraryIndex,
+    manga_uuid: Uuid,
+    chapter_uuid: Uuid,
+    head_only: bool,
+) -> Result<Response> {
+    let Some((manga, chapter)) = index
+        .manga
+        .get(&manga_uuid)
+        .and_then(|m| m.chapters.get(&chapter_uuid).map(|c| (m, c)))
+    else {
+        return Ok(Response::error("404 Not Found"));
+    };
+
+    let body = if head_only {
+        Body::Unsized
+    } else {
+        let chapter_dir = manga_save_dir()?.join(&manga.dir).join(&chapter.dir);
+        let cbz = TempCbz::new();
+        // stored, so that zipping doesn't hold up the response
+        zip_chapter(
+            &chapter_dir,
+            File::create(&cbz.0).into_diagnostic()?,
+            None,
+            ArchiveCompression::Store,
+        )?;
+
+        Body::File(cbz)
+    };
+
+    let mut response = Response::ok(CBZ, body);
+    let filename = format!("{} - {}.cbz", manga.title, chapter_archive_name(chapter));
+    response.headers.push(content_disposition(&filename));
+
+    Ok(response)
+}
+
+/// Routes a `GET` (or `HEAD`, if `head_only`) request for `path` to the page it's for.
+fn route(path: &str, head_only: bool) -> Result<Response> {
+    // query strings aren't used, so they're ignored
+    let path = path.split('?').next().unwrap_or_default();
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+    match segments.as_slice() {
+        [] => Ok(Response::redirect("/opds")),
+        ["opds"] => {
+            let index = LibraryIndex::load()?;
+            Ok(Response::ok(
+                NAVIGATION,
+                Body::Bytes(root_feed(&index).into_bytes()),
+            ))
+        }
+        ["opds", "manga", uuid] => {
+            let index = LibraryIndex::load()?;
+
+            match Uuid::parse_str(uuid).ok().and_then(|u| index.manga.get(&u)) {
+                Some(manga) => Ok(Response::ok(
+                    ACQUISITION,
+                    Body::Bytes(manga_feed(manga).into_bytes()),
+                )),
+                None => Ok(Response::error("404 Not Found")),
+            }
+        }
+        ["cbz", manga_uuid, chapter_uuid] => {
+            let (Ok(manga_uuid), Ok(chapter_uuid)) =
+                (Uuid::parse_str(manga_uuid), Uuid::parse_str(chapter_uuid))
+            else {
+                return Ok(Response::error("404 Not Found"));
+            };
+
+            chapter_cbz(&LibraryIndex::load()?, manga_uuid, chapter_uuid, head_only)
+        }
+        _ => Ok(Response::error("404 Not Found")),
+    }
+}
+
+/// Reads a request from `stream` and answers it, giving up on reading
+/// it after [`REQUEST_TIMEOUT`].
+async fn handle(mut stream: TcpStream, peer: SocketAddr) -> std::io::Result<()> {
+    let mut buf = vec![0u8; MAX_REQUEST_SIZE];
+    let mut len = 0;
+    let deadline = Instant::now() + REQUEST_TIMEOUT;
+
+    // only the request line is used, but the head is read fully before responding
+    while !buf[..len].windows(4).any(|w| w == b"\r\n\r\n") {
+        if len == buf.len() {
+            return Response::error("431 Request Header Fields Too Large")
+                .write(&mut stream, false)
+                .await;
+        }
+
+        let Ok(n) = timeout_at(deadline, stream.read(&mut buf[len..])).await else {
+            debug!("{peer} didn't send a request in time");
+            return Response::error("408 Request Timeout")
+                .write(&mut stream, false)
+                .await;
+        };
+        let n = n?;
+
+        if n == 0 {
+            return Ok(());
+        }
+
+        len += n;
+    }
+
+    let head = String::from_utf8_lossy(&buf[..len]);
+    let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+    let (method, path) = (
+        request_line.next().unwrap_or_default(),
+        request_line.next().unwrap_or_default(),
+    );
+
+    debug!("{peer} {method} {path}");
+
+    let response = match method {
+        "GET" | "HEAD" => {
+            let owned_path = path.to_string();
+            let head_only = method == "HEAD";
+
+            // zipping chapters blocks, so keep it off the async threads
+            tokio::task::spawn_blocking(move || route(&owned_path, head_only))
+                .await
+                .map_err(std::io::Error::other)?
+                .unwrap_or_else(|e| {
+                    error!("Failed to answer {method} {path}: {e}");
+                    Response::error("500 Internal Server Error")
+                })
+        }
+        _ => Response::error("405 Method Not Allowed"),
+    };
+
+    response.write(&mut stream, method == "HEAD").await
+}
+
+/// Serves the OPDS catalog on `addr` until the process is asked to stop.
+///
+/// ## Errors
+///
+/// If `addr` can't be bound.
+pub async fn serve(addr: SocketAddr) -> Result<()> {
+    let listener = TcpListener::bind(addr).await.into_diagnostic()?;
+    let addr = listener.local_addr().into_diagnostic()?;
+
+    info!("Serving the OPDS catalog on {addr}");
+    print_line(format!(
+        "{} {}",
+        style("Serving the library's OPDS catalog at").green(),
+        style(format!("http://{addr}/opds")).bold()
+    ));
+    print_status(style("Press ctrl-c to stop").dim());
+
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    let connections = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+
+    loop {
+        // once there are `MAX_CONNECTIONS`, waits for one to finish before accepting another
+        let permit = tokio::select! {
+            () = &mut shutdown => break,
+            permit = connections.clone().acquire_owned() => permit.into_diagnostic()?,
+        };
+
+        tokio::select! {
+            () = &mut shutdown => break,
+            accepted = listener.accept() => {
+                let (stream, peer) = match accepted {
+                    Ok(v) => v,
+                    Err(e) => {
+                        warn!("Failed to accept a connection: {e}");
+                        continue;
+                    }
+                };
+
+                tokio::spawn(async move {
+                    if let Err(e) = handle(stream, peer).await {
+                        debug!("Connection with {peer} failed: {e}");
+                    }
+
+                    drop(permit);
+                });
+            }
+        }
+    }
+
+    info!("Stopped serving the OPDS catalog");
+    Ok(())
+}
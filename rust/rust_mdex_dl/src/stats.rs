@@ -0,0 +1,278 @@
+//! Contains [`display_stats`], which shows how much disk space the library takes up: in
+//! total, per manga, per language and per format.
+//!
+//! Sizes come from walking the library rather than the [index](`LibraryIndex`) (which only
+//! knows the pages), so covers, metadata and leftovers count too. Hardlinked files, such as
+//! [deduped](`crate::store`) pages, are only counted once.
+
+use crate::{
+    images::ImageFormat,
+    library::LibraryIndex,
+    paths::{manga_save_dir, page_store_dir},
+    progress::print_line,
+};
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use console::style;
+use miette::{IntoDiagnostic, Result};
+use uuid::Uuid;
+
+/// The disk usage of a manga in [`LibraryStats`].
+#[derive(Debug, Clone)]
+pub struct MangaUsage {
+    pub uuid: Uuid,
+    pub title: String,
+    pub chapters: usize,
+    /// The size of everything in the manga's dir, in bytes.
+    pub bytes: u64,
+}
+
+/// The disk usage of the library, in bytes, gathered by [`library_stats`].
+#[derive(Debug, Clone, Default)]
+pub struct LibraryStats {
+    /// The size of every file in the library.
+    pub total: u64,
+    /// Every manga in the index, largest first.
+    pub manga: Vec<MangaUsage>,
+    /// The size of each language's chapters, by ISO 639-1 code.
+    pub languages: BTreeMap<String, u64>,
+    /// The size of each kind of file, by extension (e.g. `jpg`), with
+    /// anything that isn't an image as `other`.
+    pub formats: BTreeMap<String, u64>,
+    /// The size of files outside of every manga's dir (e.g. the index or the page store).
+    pub unowned: u64,
+    /// The space saved by hardlinked files, which are only counted once.
+    pub shared: u64,
+}
+
+/// Returns an identifier for the file with `meta`, shared by its hardlinks (or `None`
+/// on platforms where it isn't available, where hardlinks are counted every time).
+#[allow(clippy::unnecessary_wraps)] // always `Some` on unix
+fn file_id(meta: &fs::Metadata) -> Option<(u64, u64)> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        Some((meta.dev(), meta.ino()))
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = meta;
+        None
+    }
+}
+
+/// Returns the kind of file `path` is, see [`LibraryStats::formats`].
+fn file_format(path: &Path) -> &'static str {
+    path.extension()
+        .and_then(|ext| ImageFormat::from_extension(&ext.to_string_lossy()))
+        .map_or("other", ImageFormat::extension)
+}
+
+/// Tracks what [`walk`] has been through, so that nothing is counted twice.
+#[derive(Debug, Default)]
+struct Walked {
+    /// The dirs already walked, which are skipped.
+    dirs: HashSet<PathBuf>,
+    /// The [ids](`file_id`) of the files already counted.
+    files: HashSet<(u64, u64)>,
+    /// The size of the files found again through another hardlink.
+    shared: u64,
+    /// The [page store](`crate::store`), whose links aren't counted as [`Self::shared`],
+    /// since they're how pages are shared rather than another copy of them.
+    store: PathBuf,
+}
+
+/// Walks the dir at `path` (if it exists and wasn't walked already), calling `f` with
+/// every file and its size that hasn't been counted yet.
+fn walk(path: &Path, walked: &mut Walked, f: &mut impl FnMut(&Path, u64)) -> Result<()> {
+    if !path.is_dir() || !walked.dirs.insert(path.to_path_buf()) {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(path).into_diagnostic()? {
+        let entry = entry.into_diagnostic()?;
+        let meta = entry.metadata().into_diagnostic()?;
+
+        if meta.is_dir() {
+            walk(&entry.path(), walked, f)?;
+        } else if file_id(&meta).is_none_or(|id| walked.files.insert(id)) {
+            f(&entry.path(), meta.len());
+        } else if !path.starts_with(&walked.store) {
+            walked.shared += meta.len();
+        }
+    }
+
+    Ok(())
+}
+
+/// Walks the library, gathering its [`LibraryStats`].
+///
+/// Chapters are walked first, then the rest of each manga's dir, then the rest of the
+/// library, so that each file counts towards the first of those it's in.
+///
+/// ## Errors
+///
+/// If the library index can't be loaded, or the library can't be walked.
+pub fn library_stats() -> Result<LibraryStats> {
+    let index = LibraryIndex::load()?;
+    let library = manga_save_dir()?;
+    let mut stats = LibraryStats::default();
+    let mut walked = Walked {
+        store: page_store_dir()?,
+        ..Walked::default()
+    };
+
+    for entry in index.manga.values() {
+        let manga_dir = library.join(&entry.dir);
+        let mut bytes = 0;
+
+        for chapter in entry.chapters.values() {
+            let language = stats.languages.entry(chapter.language.clone()).or_default();
+
+            walk(
+                &manga_dir.join(&chapter.dir),
+                &mut walked,
+                &mut |path, size| {
+                    bytes += size;
+                    *language += size;
+                    *stats
+                        .formats
+                        .entry(file_format(path).to_string())
+                        .or_default() += size;
+                },
+            )?;
+        }
+
+        walk(&manga_dir, &mut walked, &mut |path, size| {
+            bytes += size;
+            *stats
+                .formats
+                .entry(file_format(path).to_string())
+                .or_default() += size;
+        })?;
+
+        stats.total += bytes;
+        stats.manga.push(MangaUsage {
+            uuid: entry.uuid,
+            title: entry.title.clone(),
+            chapters: entry.chapters.len(),
+            bytes,
+        });
+    }
+
+    walk(&library, &mut walked, &mut |path, size| {
+        stats.unowned += size;
+        *stats
+            .formats
+            .entry(file_format(path).to_string())
+            .or_default() += size;
+    })?;
+
+    stats.total += stats.unowned;
+    stats.shared = walked.shared;
+    stats.manga.sort_by_key(|m| std::cmp::Reverse(m.bytes));
+
+    Ok(stats)
+}
+
+/// Prints the [`LibraryStats`], with the `top` largest manga.
+///
+/// ## Errors
+///
+/// If propagated from [`library_stats`].
+pub fn display_stats(top: usize) -> Result<()> {
+    let stats = library_stats()?;
+
+    if stats.manga.is_empty() {
+        print_line(style("No downloaded manga found").yellow().italic());
+        return Ok(());
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let to_mib = |bytes: u64| bytes as f64 / 1_048_576.0;
+    #[allow(clippy::cast_precision_loss)]
+    let percent = |bytes: u64| bytes as f64 / stats.total.max(1) as f64 * 100.0;
+    let chapters: usize = stats.manga.iter().map(|m| m.chapters).sum();
+
+    print_line(
+        style(format!(
+            "{:.1} MiB in {} manga ({chapters} chapters)",
+            to_mib(stats.total),
+            stats.manga.len()
+        ))
+        .bold(),
+    );
+
+    print_line(format!(
+        "\n{}",
+        style(format!("Largest {top} manga")).bold()
+    ));
+    for (i, m) in stats.manga.iter().take(top).enumerate() {
+        print_line(format!(
+            "{:>4}. {}  {:.1} MiB  {}",
+            i + 1,
+            m.title,
+            to_mib(m.bytes),
+            style(format!(
+                "({} chapters, {:.1}%)",
+                m.chapters,
+                percent(m.bytes)
+            ))
+            .dim()
+        ));
+    }
+
+    let mut languages: Vec<(&String, &u64)> = stats.languages.iter().collect();
+    languages.sort_by_key(|(_, bytes)| std::cmp::Reverse(**bytes));
+
+    print_line(format!("\n{}", style("By language").bold()));
+    for (language, &bytes) in languages {
+        print_line(format!(
+            "  {language:<6}{:>10.1} MiB  {}",
+            to_mib(bytes),
+            style(format!("({:.1}%)", percent(bytes))).dim()
+        ));
+    }
+
+    let mut formats: Vec<(&String, &u64)> = stats.formats.iter().collect();
+    formats.sort_by_key(|(_, bytes)| std::cmp::Reverse(**bytes));
+
+    print_line(format!("\n{}", style("By format").bold()));
+    for (format, &bytes) in formats {
+        print_line(format!(
+            "  {format:<6}{:>10.1} MiB  {}",
+            to_mib(bytes),
+            style(format!("({:.1}%)", percent(bytes))).dim()
+        ));
+    }
+
+    print_line("");
+    if stats.unowned > 0 {
+        print_line(
+            style(format!(
+                "{:.1} MiB outside of every manga's dir (e.g. the page store, or leftovers \
+                that `clean` removes)",
+                to_mib(stats.unowned)
+            ))
+            .dim(),
+        );
+    }
+
+    if stats.shared > 0 {
+        print_line(
+            style(format!(
+                "{:.1} MiB saved by hardlinked (deduped) pages",
+                to_mib(stats.shared)
+            ))
+            .green(),
+        );
+    }
+
+    Ok(())
+}
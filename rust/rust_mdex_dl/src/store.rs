@@ -0,0 +1,176 @@
+//! Contains the page store used if `storage.dedupe` is enabled, where every saved page is
+//! hardlinked by its sha256 hash, so that identical pages (e.g. credit pages repeated in
+//! every chapter) are only stored once.
+//!
+//! The store is `.pages` in the library (see [`page_store_dir`]), since hardlinks can't cross
+//! file systems. Pages are always replaced by renaming a new file into place, so a linked page
+//! is never changed underneath the others. If linking fails, pages are simply stored as usual.
+//!
+//! Once every chapter linking to a stored page is deleted, only the store's own link is left,
+//! so [`prune_store`] is run after deleting chapters to free their space.
+
+use crate::{metrics::record_dedupe, paths::page_store_dir};
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use miette::{IntoDiagnostic, Result};
+
+/// Returns where the page with `sha256` (and extension `ext`) is in the store.
+///
+/// ## Errors
+///
+/// If propagated from [`page_store_dir`].
+pub fn stored_path(sha256: &str, ext: &str) -> Result<PathBuf> {
+    // split by the first byte, so that no single dir gets too large
+    Ok(page_store_dir()?
+        .join(&sha256[..2])
+        .join(format!("{sha256}.{ext}")))
+}
+
+/// Saves `save` as a hardlink to the stored page with `sha256`, going through `partial`.
+///
+/// Returns false (without touching `save`) if the page isn't stored or can't be linked,
+/// in which case it should be written as usual.
+///
+/// ## Errors
+///
+/// If renaming `partial` to `save` fails.
+pub async fn link_stored(sha256: &str, ext: &str, save: &Path, partial: &Path) -> Result<bool> {
+    let stored = stored_path(sha256, ext)?;
+
+    if !tokio::fs::try_exists(&stored).await.unwrap_or(false) {
+        return Ok(false);
+    }
+
+    let _ = tokio::fs::remove_file(partial).await;
+
+    if let Err(e) = tokio::fs::hard_link(&stored, partial).await {
+        debug!(
+            "Failed to link {:?} from the page store: {e}",
+            save.to_str()
+        );
+        return Ok(false);
+    }
+
+    tokio::fs::rename(partial, save).await.into_diagnostic()?;
+
+    let size = tokio::fs::metadata(save).await.map_or(0, |m| m.len());
+    record_dedupe(size);
+    trace!(
+        "Linked {:?} to identical page {}",
+        save.to_str(),
+        stored.display()
+    );
+
+    Ok(true)
+}
+
+/// Adds the page saved at `path` to the store, so that identical pages saved later
+/// can be linked to it. Does nothing if it's already stored.
+///
+/// Failing to is only logged, since the page itself is saved regardless.
+pub async fn add_to_store(path: &Path, sha256: &str, ext: &str) {
+    let stored = match stored_path(sha256, ext) {
+        Ok(v) => v,
+        Err(e) => {
+            debug!("Failed to find the page store: {e}");
+            return;
+        }
+    };
+
+    if tokio::fs::try_exists(&stored).await.unwrap_or(false) {
+        return;
+    }
+
+    if let Some(parent) = stored.parent()
+        && let Err(e) = tokio::fs::create_dir_all(parent).await
+    {
+        debug!("Failed to create {}: {e}", parent.display());
+        return;
+    }
+
+    // two identical pages may be saved at once, in which case one of these fails harmlessly
+    if let Err(e) = tokio::fs::hard_link(path, &stored).await {
+        debug!("Failed to add {:?} to the page store: {e}", path.to_str());
+    }
+}
+
+/// Returns how many hardlinks the file with `meta` has (or `None` on platforms where
+/// it isn't available, where the store is never pruned).
+#[allow(clippy::unnecessary_wraps)] // always `Some` on unix
+fn link_count(meta: &fs::Metadata) -> Option<u64> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        Some(meta.nlink())
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = meta;
+        None
+    }
+}
+
+/// Removes the pages in the store that no chapter links to anymore (i.e. the store's link
+/// is their only one), along with any dirs left empty, returning how many bytes were freed.
+///
+/// ## Errors
+///
+/// If the store can't be read. Pages that can't be removed are logged instead.
+pub fn prune_store() -> Result<u64> {
+    let store = page_store_dir()?;
+
+    if !store.try_exists().into_diagnostic()? {
+        return Ok(0);
+    }
+
+    let mut freed = 0;
+
+    for dir in fs::read_dir(&store).into_diagnostic()? {
+        let dir = dir.into_diagnostic()?.path();
+
+        if !dir.is_dir() {
+            continue;
+        }
+
+        for page in fs::read_dir(&dir).into_diagnostic()? {
+            let page = page.into_diagnostic()?.path();
+            let Ok(meta) = fs::metadata(&page) else {
+                continue;
+            };
+
+            if link_count(&meta) != Some(1) {
+                continue;
+            }
+
+            match fs::remove_file(&page) {
+                Ok(()) => freed += meta.len(),
+                Err(e) => warn!(
+                    "Failed to remove {} from the page store: {e}",
+                    page.display()
+                ),
+            }
+        }
+
+        // only succeeds if it's empty
+        let _ = fs::remove_dir(&dir);
+    }
+
+    if freed > 0 {
+        debug!("Pruned {freed} bytes of unlinked pages from the page store");
+    }
+
+    Ok(freed)
+}
+
+/// Runs [`prune_store`] after chapters were deleted, only logging if it fails
+/// (since the chapters were deleted regardless).
+pub fn prune_store_after_delete() {
+    if let Err(e) = prune_store() {
+        warn!("Failed to prune the page store: {e}");
+    }
+}
@@ -0,0 +1,166 @@
+//! Contains the recorder behind `--trace-bundle`, which collects everything needed
+//! to reproduce an issue into a single zip archive:
+//!
+//! - `config.toml`, with sensitive values masked (see [`crate::redact`])
+//! - `versions.txt`, with the crate version, OS and architecture, and any API changes
+//!   Manga-Dex signalled (see [`crate::api::deprecation`])
+//! - `requests.jsonl`, with every API request made (see [`RequestRecord`])
+//! - `error.txt`, with the final error (if any)
+//! - `run.log`, with the log file of this run (if logging is enabled)
+
+use crate::{
+    api::deprecation::deprecations,
+    logging::log_file_path,
+    paths::config_toml,
+    redact::{REDACTED, is_sensitive_key},
+};
+
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::Path,
+    sync::{Mutex, OnceLock},
+};
+
+use chrono::{DateTime, Utc};
+use miette::{IntoDiagnostic, Report, Result};
+use serde::Serialize;
+use zip::{ZipWriter, write::SimpleFileOptions};
+
+/// Every request recorded so far, or `None` if recording isn't enabled.
+static REQUESTS: OnceLock<Mutex<Vec<RequestRecord>>> = OnceLock::new();
+
+/// A single API request, as recorded for the trace bundle.
+#[derive(Serialize, Debug, Clone)]
+pub struct RequestRecord {
+    /// The [`RequestId`](`crate::api::request_id::RequestId`) shown in log lines.
+    pub request_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub method: String,
+    pub url: String,
+    /// The response status code, or `None` if no response was received.
+    pub status: Option<u16>,
+    /// Manga-Dex's own id for the request (its `X-Request-ID`), if it sent one.
+    pub server_request_id: Option<String>,
+    /// How many requests to the endpoint were left in the ratelimit window, if it said.
+    pub ratelimit_remaining: Option<u32>,
+    pub elapsed_ms: u128,
+}
+
+/// Starts recording requests with [`record_request`]. Before this
+/// is called, recording is a no-op so that it costs nothing.
+pub fn enable_recording() {
+    REQUESTS.get_or_init(|| Mutex::new(Vec::new()));
+}
+
+/// Records `record` if recording has been enabled with [`enable_recording`].
+pub fn record_request(record: RequestRecord) {
+    if let Some(requests) = REQUESTS.get() {
+        requests
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .push(record);
+    }
+}
+
+/// Masks the values of any [sensitive](`is_sensitive_key`) keys in `table`, recursively.
+fn sanitize_table(table: &mut toml::Table) {
+    for (key, value) in table.iter_mut() {
+        if let toml::Value::Table(inner) = value {
+            sanitize_table(inner);
+        } else if is_sensitive_key(key) {
+            *value = toml::Value::String(REDACTED.to_string());
+        }
+    }
+}
+
+/// Returns the contents of the config with sensitive values masked.
+///
+/// If the config can't be parsed, a note is returned instead of the (unmasked) raw contents.
+fn sanitized_config() -> String {
+    let raw = match config_toml().and_then(|p| fs::read_to_string(p).into_diagnostic()) {
+        Ok(v) => v,
+        Err(e) => return format!("# couldn't read config: {e}\n"),
+    };
+
+    match toml::from_str::<toml::Table>(&raw) {
+        Ok(mut table) => {
+            sanitize_table(&mut table);
+            toml::to_string_pretty(&table)
+                .unwrap_or_else(|e| format!("# couldn't serialize config: {e}\n"))
+        }
+        Err(e) => format!("# config isn't valid toml, so it was omitted: {e}\n"),
+    }
+}
+
+/// Formats `error` along with its causes and help text, without any styling.
+fn format_error(error: &Report) -> String {
+    let mut lines: Vec<String> = error
+        .chain()
+        .enumerate()
+        .map(|(i, cause)| format!("{}{cause}", "  ".repeat(i)))
+        .collect();
+
+    if let Some(help) = error.help() {
+        lines.push(format!("\nhelp: {help}"));
+    }
+
+    lines.join("\n") + "\n"
+}
+
+/// Writes the trace bundle to `path`, including `error` if the run failed.
+///
+/// ## Errors
+///
+/// If the archive can't be created or written to.
+pub fn write_bundle(path: &Path, error: Option<&Report>) -> Result<()> {
+    let mut zip = ZipWriter::new(File::create(path).into_diagnostic()?);
+    let options = SimpleFileOptions::default();
+
+    zip.start_file("config.toml", options).into_diagnostic()?;
+    zip.write_all(sanitized_config().as_bytes())
+        .into_diagnostic()?;
+
+    zip.start_file("versions.txt", options).into_diagnostic()?;
+    writeln!(
+        zip,
+        "{} {}\nos: {}\narch: {}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+    )
+    .into_diagnostic()?;
+
+    for (path, notice) in deprecations() {
+        writeln!(zip, "api change: {path}: {notice}").into_diagnostic()?;
+    }
+
+    zip.start_file("requests.jsonl", options)
+        .into_diagnostic()?;
+    if let Some(requests) = REQUESTS.get() {
+        let requests = requests
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+        for r in requests.iter() {
+            writeln!(zip, "{}", serde_json::to_string(r).into_diagnostic()?).into_diagnostic()?;
+        }
+    }
+
+    zip.start_file("error.txt", options).into_diagnostic()?;
+    match error {
+        Some(e) => zip.write_all(format_error(e).as_bytes()),
+        None => zip.write_all(b"no error, the run completed successfully\n"),
+    }
+    .into_diagnostic()?;
+
+    if let Some(log_file) = log_file_path() {
+        zip.start_file("run.log", options).into_diagnostic()?;
+        zip.write_all(&fs::read(log_file).into_diagnostic()?)
+            .into_diagnostic()?;
+    }
+
+    zip.finish().into_diagnostic()?;
+    Ok(())
+}
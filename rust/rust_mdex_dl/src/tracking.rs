@@ -0,0 +1,168 @@
+//! Contains [`StatusTracker`], which sets the reading status of downloaded manga in the
+//! user's Manga-Dex library (see the `[tracking]` config section).
+//!
+//! A manga is set to `tracking.completed` once the last chapter of a finished manga has been
+//! downloaded, and to `tracking.downloaded` otherwise. Statuses set to anything else by hand
+//! (e.g. `dropped`) are left alone, and so are manga that are already completed.
+//!
+//! Like [notifications](`crate::notify`), this is best-effort: failures are only logged.
+
+use crate::{
+    api::{
+        auth::{AuthSession, BearerAuth, Credentials},
+        client::ApiClient,
+        download::is_dry_run,
+        endpoints::Endpoint,
+        models::{Chapter, ChapterNumber, Manga, ReadingStatus, Status},
+    },
+    config::Config,
+    library::LibraryIndex,
+};
+
+use std::sync::Arc;
+
+use isolang::Language;
+use miette::{Result, miette};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Deserialize, Debug)]
+struct StatusResponse {
+    status: Option<ReadingStatus>,
+}
+
+#[derive(Serialize, Debug)]
+struct StatusRequest {
+    status: ReadingStatus,
+}
+
+/// Sets reading statuses after downloading, see the [module docs](`self`).
+#[derive(Debug, Clone)]
+pub struct StatusTracker {
+    /// Sends the access token of the `[auth]` session with every request.
+    api: ApiClient,
+    downloaded: Option<ReadingStatus>,
+    completed: Option<ReadingStatus>,
+    /// The language of titles in logs.
+    language: Language,
+}
+
+impl StatusTracker {
+    /// Constructs a new [`StatusTracker`] that shares `api`'s middleware,
+    /// or returns `None` if `tracking.sync_status` is off.
+    ///
+    /// This doesn't log in yet, which happens when a status is first set.
+    ///
+    /// ## Errors
+    ///
+    /// If an `[auth]` option isn't set, or propagated from [`AuthSession::new`].
+    pub fn new(cfg: &Config, api: &ApiClient) -> Result<Option<Self>> {
+        if !cfg.tracking.sync_status {
+            return Ok(None);
+        }
+
+        let credentials = Credentials::from_config(&cfg.auth)
+            .map_err(|key| miette!("`{key}` must be set to sync reading statuses"))?;
+        let session = AuthSession::new(credentials, &cfg.client.user_agent)?;
+
+        Ok(Some(Self {
+            api: api.clone().with_middleware(BearerAuth(Arc::new(session))),
+            downloaded: cfg.tracking.downloaded.0,
+            completed: cfg.tracking.completed.0,
+            language: cfg.client.language,
+        }))
+    }
+
+    /// Returns the status `manga` should be set to after downloading `downloaded`,
+    /// or `None` if it should be left as is.
+    #[must_use]
+    pub fn status_for(&self, manga: &Manga, downloaded: &[Chapter]) -> Option<ReadingStatus> {
+        if has_last_chapter(manga, downloaded) {
+            self.completed
+        } else {
+            self.downloaded
+        }
+    }
+
+    /// Sets the reading status of `manga` after downloading `downloaded` (see
+    /// [`Self::status_for`]), logging (but otherwise ignoring) failures.
+    ///
+    /// Nothing is set if no chapters were downloaded, or if this is a dry run.
+    pub async fn sync(&self, manga: &Manga, downloaded: &[Chapter]) {
+        if downloaded.is_empty() || is_dry_run() {
+            return;
+        }
+
+        let Some(status) = self.status_for(manga, downloaded) else {
+            return;
+        };
+
+        if let Err(e) = self.set_status(manga.uuid(), status).await {
+            warn!(
+                "Failed to set the reading status of {:?}: {e}",
+                manga.title(self.language)
+            );
+        }
+    }
+
+    /// Sets the reading status of the manga with `uuid` to `status`, unless
+    /// it's already that, completed, or set to something else by hand.
+    async fn set_status(&self, uuid: Uuid, status: ReadingStatus) -> Result<()> {
+        let current: StatusResponse = self
+            .api
+            .get_ok_parsed(Endpoint::MangaReadingStatus(uuid))
+            .await?;
+
+        if let Some(current) = current.status {
+            if current == status || Some(current) == self.completed {
+                debug!("Manga {uuid} is already {current}, leaving it");
+                return Ok(());
+            }
+
+            if Some(current) != self.downloaded {
+                info!(
+                    "Leaving the reading status of manga {uuid} at {current}, which was set by hand"
+                );
+                return Ok(());
+            }
+        }
+
+        let _: serde_json::Value = self
+            .api
+            .post_json(
+                Endpoint::MangaReadingStatus(uuid),
+                &StatusRequest { status },
+            )
+            .await?;
+        info!("Set the reading status of manga {uuid} to {status}");
+
+        Ok(())
+    }
+}
+
+/// Returns true if `manga` is finished and its last chapter has been downloaded,
+/// either just now (in `downloaded`) or before (see [`LibraryIndex`]).
+fn has_last_chapter(manga: &Manga, downloaded: &[Chapter]) -> bool {
+    let attrs = &manga.data.attributes;
+
+    let Some(last) = attrs.last_chapter.as_deref().and_then(ChapterNumber::parse) else {
+        return false;
+    };
+
+    if !matches!(attrs.status, Status::Completed) {
+        return false;
+    }
+
+    if downloaded.iter().any(|c| c.number() == Some(last)) {
+        return true;
+    }
+
+    LibraryIndex::load().is_ok_and(|index| {
+        index.manga.get(&manga.uuid()).is_some_and(|entry| {
+            entry
+                .chapters
+                .values()
+                .any(|c| c.chapter_number.as_deref().and_then(ChapterNumber::parse) == Some(last))
+        })
+    })
+}
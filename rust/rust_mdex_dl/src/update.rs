@@ -0,0 +1,834 @@
+//! Contains [`run_update`], which downloads new chapters for every manga in the
+//! [library index](`crate::library::LibraryIndex`), and [`watch`], which runs it periodically.
+
+use crate::{
+    api::{
+        client::ApiClient,
+        download::{DownloadClient, is_dry_run},
+        groups::apply_group_preferences,
+        models::{Chapter, ChapterNumber, Manga},
+        search::SearchClient,
+    },
+    budget::check_budget,
+    config::{Config, NotifyEvent},
+    errors::ApiError,
+    history::{RunRecord, append_record},
+    library::{LibraryIndex, MangaEntry, remove_old_copies},
+    metrics::export_metrics,
+    notify::{Notification, Notifier},
+    order::latest_chapters,
+    progress::{print_line, print_status},
+    tracking::StatusTracker,
+};
+
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local, TimeDelta, Utc};
+use console::style;
+use miette::{Result, bail, miette};
+use uuid::Uuid;
+
+/// Parses a chapter number such as `"10.5"`, returning `None` if it isn't numeric.
+#[must_use]
+pub fn parse_number(number: Option<&str>) -> Option<f64> {
+    ChapterNumber::parse(number?).map(ChapterNumber::value)
+}
+
+/// Returns true if the chapter at `(volume, number)` comes after the one at `other`.
+///
+/// Volumes are compared first when both chapters have one, since some manga
+/// restart their chapter numbers every volume.
+fn comes_after(chapter: (Option<f64>, f64), other: (Option<f64>, f64)) -> bool {
+    let by_volume = match (chapter.0, other.0) {
+        (Some(volume), Some(other_volume)) => volume.partial_cmp(&other_volume),
+        _ => None,
+    };
+
+    match by_volume {
+        Some(Ordering::Equal) | None => chapter.1 > other.1,
+        Some(ordering) => ordering == Ordering::Greater,
+    }
+}
+
+/// Returns the chapters in `chapters` which are newer than the ones in `local`.
+///
+/// A chapter is new if it hasn't been downloaded, and either it [comes after](`comes_after`)
+/// every numbered local chapter, or it became readable after `local` was last updated
+/// and can't be placed by its number. That's the case if it has no number, or has no
+/// volume while the manga's numbers reset each volume (`numbers_reset`). Older chapters
+/// which were never downloaded are deliberately skipped.
+#[must_use]
+pub fn new_chapters(
+    local: &MangaEntry,
+    chapters: Vec<Chapter>,
+    numbers_reset: bool,
+) -> Vec<Chapter> {
+    let positions: Vec<(Option<f64>, f64)> = local
+        .chapters
+        .values()
+        .filter_map(|c| {
+            let number = parse_number(c.chapter_number.as_deref())?;
+            Some((parse_number(c.volume.as_deref()), number))
+        })
+        .collect();
+
+    chapters
+        .into_iter()
+        .filter(|c| !local.chapters.contains_key(&c.uuid()))
+        .filter(|c| {
+            let attrs = &c.data.attributes;
+            let volume = parse_number(attrs.volume.as_deref());
+            let placeable = !positions.is_empty() && (volume.is_some() || !numbers_reset);
+
+            match parse_number(attrs.chapter_number.as_deref()) {
+                Some(number) if placeable => positions
+                    .iter()
+                    .all(|&other| comes_after((volume, number), other)),
+                _ => attrs.readable_at > local.updated_at,
+            }
+        })
+        .collect()
+}
+
+/// Returns the chapters in `chapters` which were downloaded (into `local`) at an older
+/// [version](`crate::api::models::ChapterAttributes::version`), i.e. which were edited
+/// since, such as when the group replaced or fixed their pages.
+///
+/// Chapters downloaded before versions were recorded are left out, see [`record_versions`].
+#[must_use]
+pub fn changed_chapters(local: &MangaEntry, chapters: &[Chapter]) -> Vec<Chapter> {
+    chapters
+        .iter()
+        .filter(|c| {
+            local
+                .chapters
+                .get(&c.uuid())
+                .and_then(|entry| entry.version)
+                .is_some_and(|version| c.data.attributes.version > version)
+        })
+        .cloned()
+        .collect()
+}
+
+/// Records the versions of `local`'s chapters which were downloaded before versions
+/// were recorded in the library index, taking them from `chapters`, so that edits
+/// from now on are picked up by [`changed_chapters`].
+///
+/// ## Errors
+///
+/// If propagated from [`LibraryIndex::update`].
+pub fn record_versions(local: &MangaEntry, chapters: &[Chapter]) -> Result<()> {
+    let missing: Vec<(Uuid, u32)> = chapters
+        .iter()
+        .filter(|c| {
+            local
+                .chapters
+                .get(&c.uuid())
+                .is_some_and(|entry| entry.version.is_none())
+        })
+        .map(|c| (c.uuid(), c.data.attributes.version))
+        .collect();
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    debug!(
+        "Recording the versions of {} chapters of {:?}",
+        missing.len(),
+        local.title
+    );
+
+    LibraryIndex::update(|index| {
+        let Some(manga) = index.manga.get_mut(&local.uuid) else {
+            return;
+        };
+
+        for (uuid, version) in missing {
+            if let Some(entry) = manga.chapters.get_mut(&uuid) {
+                entry.version.get_or_insert(version);
+            }
+        }
+    })
+}
+
+/// The outcome of updating a single manga with [`run_update`].
+#[derive(Debug, Clone)]
+pub struct MangaUpdate {
+    pub title: String,
+    /// The numbers of the new chapters that were found (and downloaded, unless only checking).
+    pub new_chapters: Vec<String>,
+    /// The numbers of the downloaded chapters that were edited since (see [`changed_chapters`]),
+    /// which were downloaded again (unless only checking).
+    pub changed_chapters: Vec<String>,
+    /// How many of the new chapters failed to download.
+    pub failed: usize,
+    /// The total size of the downloaded chapters in bytes.
+    pub size: u64,
+}
+
+/// How far before [`MangaEntry::polled_at`] incremental checks start fetching from,
+/// in case chapters became readable while the last check was running.
+pub const POLL_OVERLAP: TimeDelta = TimeDelta::minutes(10);
+
+/// How often [`watch`] checks every manga's whole feed, instead of only the chapters
+/// since it was last polled, so that edited chapters are still noticed.
+pub const FULL_CHECK_INTERVAL: Duration = Duration::from_hours(24);
+
+/// Checks every manga in the library index whose title contains `manga_filter`
+/// (case-insensitive) for new chapters (see [`new_chapters`]) and downloads them,
+/// several manga at once (see [`DownloadClient::download_many`]). Chapters that were
+/// edited since they were downloaded (see [`changed_chapters`]) are downloaded again,
+/// replacing their pages, and the old copies of any that are now named differently
+/// are removed (see [`remove_old_copies`]).
+///
+/// If `check_only` is set, new chapters are only reported, not downloaded.
+///
+/// If `incremental` is set, only chapters that became readable since each manga was last
+/// polled (see [`MangaEntry::polled_at`], less [`POLL_OVERLAP`]) are fetched, instead of its
+/// whole feed. Manga that were never polled are checked in full. Note that edited chapters
+/// are then only noticed if they're in that window.
+///
+/// A manga that fails to update is logged and skipped, so that one broken
+/// manga doesn't stop the rest of the library from updating. Afterwards, an
+/// [update notification](`NotifyEvent::Update`) is sent (unless only checking).
+///
+/// ## Errors
+///
+/// If the library index can't be loaded, or a client can't be constructed.
+pub async fn run_update(
+    cfg: &Config,
+    manga_filter: Option<&str>,
+    check_only: bool,
+    incremental: bool,
+) -> Result<Vec<MangaUpdate>> {
+    let api = ApiClient::new(&cfg.client)?;
+    let searcher = SearchClient::new(api.clone(), cfg.client.language);
+    let downloader = DownloadClient::new(cfg)?;
+    let notifier = Notifier::new(&cfg.notifications, &cfg.client.user_agent)?;
+    let status_tracker = StatusTracker::new(cfg, &api)?;
+    let mut updates = Vec::new();
+    let mut failed = Vec::new();
+    // the index into `updates` of each manga to download
+    let mut jobs = Vec::new();
+    let mut old_dirs = BTreeMap::new();
+    // when each manga was checked, recorded once its new chapters are all downloaded
+    // (never when only checking, since nothing is downloaded then)
+    let mut polled = BTreeMap::new();
+
+    let tracked = tracked_manga(manga_filter)?;
+    info!("Checking {} manga for new chapters", tracked.len());
+
+    let uuids: Vec<Uuid> = tracked.iter().map(|m| m.uuid).collect();
+    let mut fetched = searcher.fetch_manga_bulk(&uuids).await.unwrap_or_else(|e| {
+        warn!("Failed to fetch manga in bulk, fetching them one at a time: {e}");
+        HashMap::new()
+    });
+
+    for local in tracked {
+        let manga = fetched.remove(&local.uuid);
+        let polled_at = Utc::now();
+        let since = local
+            .polled_at
+            .filter(|_| incremental)
+            .map(|t| t - POLL_OVERLAP);
+
+        match check_manga(cfg, &api, &searcher, &local, manga, since, !check_only).await {
+            Ok((manga, chapters, changed)) => {
+                if !check_only {
+                    polled.insert(local.uuid, polled_at);
+                }
+
+                updates.push(MangaUpdate {
+                    title: local.title.clone(),
+                    new_chapters: chapters.iter().map(Notification::chapter_label).collect(),
+                    changed_chapters: changed.iter().map(Notification::chapter_label).collect(),
+                    failed: 0,
+                    size: 0,
+                });
+
+                for (uuid, entry) in changed
+                    .iter()
+                    .filter_map(|c| local.chapters.get_key_value(&c.uuid()))
+                {
+                    old_dirs.insert(*uuid, local.chapter_path(entry)?);
+                }
+
+                if !check_only && (!chapters.is_empty() || !changed.is_empty()) {
+                    jobs.push((updates.len() - 1, manga, [chapters, changed].concat()));
+                }
+            }
+            Err(e) => {
+                error!("Failed to update manga {:?}: {e}", local.title);
+                failed.push(local.title.clone());
+            }
+        }
+    }
+
+    if check_only {
+        return Ok(updates);
+    }
+
+    let started_at = Utc::now();
+    let indices: Vec<usize> = jobs.iter().map(|(i, _, _)| *i).collect();
+    let manga: Vec<Manga> = jobs.iter().map(|(_, manga, _)| manga.clone()).collect();
+
+    let jobs = jobs
+        .into_iter()
+        .map(|(_, manga, chapters)| (manga, chapters))
+        .collect();
+
+    let results = downloader.download_many(&api, jobs, &cfg.images).await?;
+
+    for ((i, manga), result) in indices.into_iter().zip(manga).zip(results) {
+        let update = &mut updates[i];
+
+        match result {
+            Ok(summary) => {
+                if !summary.failed.is_empty() {
+                    polled.remove(&manga.uuid());
+                }
+
+                update.failed = summary.failed.len();
+                update.size = summary.total_bytes as u64;
+                remove_old_copies(&old_dirs, &summary.downloaded)?;
+
+                if let Some(tracker) = &status_tracker {
+                    tracker.sync(&manga, &summary.downloaded).await;
+                }
+
+                append_record(&RunRecord::new(
+                    started_at,
+                    manga.uuid(),
+                    manga.title(cfg.client.language),
+                    &summary,
+                ))?;
+            }
+            Err(e) => {
+                error!("Failed to update manga {:?}: {e}", update.title);
+                polled.remove(&manga.uuid());
+                update.failed = update.new_chapters.len() + update.changed_chapters.len();
+                failed.push(update.title.clone());
+            }
+        }
+    }
+
+    record_polls(polled)?;
+
+    notifier.send(&update_notification(&updates, &failed)).await;
+
+    Ok(updates)
+}
+
+/// Records when each manga in `polled` was checked (see [`LibraryIndex::record_poll`]),
+/// unless this is a dry run.
+///
+/// ## Errors
+///
+/// If the library index can't be updated.
+fn record_polls(polled: BTreeMap<Uuid, DateTime<Utc>>) -> Result<()> {
+    if is_dry_run() {
+        return Ok(());
+    }
+
+    LibraryIndex::update(|index| {
+        for (uuid, at) in polled {
+            index.record_poll(uuid, at);
+        }
+    })
+}
+
+/// Returns the manga in the library index whose title contains `manga_filter`
+/// (case-insensitive), or every one if it isn't given.
+fn tracked_manga(manga_filter: Option<&str>) -> Result<Vec<MangaEntry>> {
+    let manga_filter = manga_filter.map(str::to_lowercase);
+
+    Ok(LibraryIndex::load()?
+        .manga
+        .into_values()
+        .filter(|m| {
+            manga_filter
+                .as_ref()
+                .is_none_or(|f| m.title.to_lowercase().contains(f))
+        })
+        .collect())
+}
+
+/// Returns the [`Notification`] for an update, which failed if any manga in `failed` did.
+fn update_notification(updates: &[MangaUpdate], failed: &[String]) -> Notification {
+    let with_new: Vec<&MangaUpdate> = updates
+        .iter()
+        .filter(|u| !(u.new_chapters.is_empty() && u.changed_chapters.is_empty()))
+        .collect();
+
+    let title = with_new
+        .iter()
+        .map(|u| u.title.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let chapters = with_new
+        .iter()
+        .flat_map(|u| {
+            let new = u.new_chapters.iter().map(|c| format!("{} {c}", u.title));
+            let changed = (u.changed_chapters.iter()).map(|c| format!("{} {c} (changed)", u.title));
+            new.chain(changed)
+        })
+        .collect();
+
+    if failed.is_empty() {
+        Notification::finished(
+            NotifyEvent::Update,
+            &title,
+            chapters,
+            updates.iter().map(|u| u.size).sum(),
+        )
+    } else {
+        Notification::failed(
+            NotifyEvent::Update,
+            &title,
+            chapters,
+            format!("failed to update {}", failed.join(", ")),
+        )
+    }
+}
+
+/// Helper for [`run_update`], which fetches the new and [changed](`changed_chapters`)
+/// chapters of a single manga, returning `(manga, new, changed)`.
+///
+/// The manga itself is only fetched if it wasn't already (as `manga`), and only chapters
+/// that became readable after `since` are fetched, if given.
+/// If `record` is set, missing versions are recorded with [`record_versions`].
+async fn check_manga(
+    cfg: &Config,
+    api: &ApiClient,
+    searcher: &SearchClient,
+    local: &MangaEntry,
+    manga: Option<Manga>,
+    since: Option<DateTime<Utc>>,
+    record: bool,
+) -> Result<(Manga, Vec<Chapter>, Vec<Chapter>)> {
+    let manga = match manga {
+        Some(v) => v,
+        None => Manga::new(api, local.uuid).await?,
+    };
+    let chapters = match since {
+        Some(since) => searcher.fetch_chapters_since(&manga, since).await?,
+        None => searcher.fetch_all_chapters(&manga).await?,
+    };
+
+    if record && !is_dry_run() {
+        record_versions(local, &chapters)?;
+    }
+
+    // chosen regardless of group preferences, since they were downloaded already
+    let changed = changed_chapters(local, &chapters);
+    let chapters = latest_chapters(
+        new_chapters(
+            local,
+            apply_group_preferences(chapters, &cfg.groups),
+            manga.data.attributes.chapter_numbers_reset_on_new_volume,
+        ),
+        cfg.download.latest,
+    );
+
+    info!(
+        "Found {} new and {} changed chapters for manga {:?}",
+        chapters.len(),
+        changed.len(),
+        local.title
+    );
+
+    Ok((manga, chapters, changed))
+}
+
+/// Prints which manga in `updates` had new chapters, and how many were found in total.
+fn print_updates(updates: &[MangaUpdate], check_only: bool) {
+    if updates.is_empty() {
+        print_line(style("No downloaded manga to update").yellow().italic());
+        return;
+    }
+
+    for u in updates
+        .iter()
+        .filter(|u| !(u.new_chapters.is_empty() && u.changed_chapters.is_empty()))
+    {
+        let failed = if u.failed == 0 {
+            String::new()
+        } else {
+            style(format!(" ({} failed)", u.failed)).red().to_string()
+        };
+
+        let changed = if u.changed_chapters.is_empty() {
+            String::new()
+        } else {
+            style(format!(" ({} changed)", u.changed_chapters.len()))
+                .yellow()
+                .to_string()
+        };
+
+        print_line(format!(
+            "{}  {} new chapters{changed}{failed}",
+            style(&u.title).bold(),
+            u.new_chapters.len()
+        ));
+    }
+
+    let total: usize = updates.iter().map(|u| u.new_chapters.len()).sum();
+    let changed: usize = updates.iter().map(|u| u.changed_chapters.len()).sum();
+    let verb = if check_only || is_dry_run() {
+        "found"
+    } else {
+        "downloaded"
+    };
+    let changed = if changed == 0 {
+        String::new()
+    } else {
+        format!(" and {changed} changed")
+    };
+
+    print_line(
+        style(format!(
+            "Checked {} manga, {verb} {total} new{changed} chapters",
+            updates.len()
+        ))
+        .green(),
+    );
+}
+
+/// Runs [`run_update`] and prints which manga had new chapters.
+///
+/// ## Errors
+///
+/// If propagated from [`run_update`].
+pub async fn display_update(
+    cfg: &Config,
+    manga_filter: Option<&str>,
+    check_only: bool,
+) -> Result<()> {
+    let updates = run_update(cfg, manga_filter, check_only, false).await?;
+    print_updates(&updates, check_only);
+
+    Ok(())
+}
+
+/// Completes when the process is asked to stop (ctrl-c, or `SIGTERM` on Unix).
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {},
+                    _ = sigterm.recv() => {},
+                }
+                return;
+            }
+            Err(e) => warn!("Failed to listen for SIGTERM: {e}"),
+        }
+    }
+
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        // never shut down, rather than immediately
+        error!("Failed to listen for ctrl-c: {e}");
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Runs [`run_update`] every `interval` until the process is asked to stop.
+///
+/// After the first update, only chapters since each manga was last polled are fetched
+/// (see `incremental` in [`run_update`]), except for a full check every
+/// [`FULL_CHECK_INTERVAL`].
+///
+/// Stopping while an update is running lets it finish first, so that no chapter is
+/// left half-downloaded. A failed update is logged and retried at the next interval.
+///
+/// ## Errors
+///
+/// This only returns an error if it can't start, i.e. the first update fails outright
+/// (with an error that retrying wouldn't fix, see [`ApiError::is_retryable`]).
+pub async fn watch(cfg: &Config, manga_filter: Option<&str>, interval: Duration) -> Result<()> {
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
+    let mut stopping = false;
+    let mut first = true;
+    let mut last_full_check: Option<Instant> = None;
+
+    print_status(
+        style(format!(
+            "Watching for new chapters every {}, press ctrl-c to stop",
+            format_interval(interval)
+        ))
+        .green(),
+    );
+
+    loop {
+        let incremental = last_full_check.is_some_and(|t| t.elapsed() < FULL_CHECK_INTERVAL);
+        let started_at = Instant::now();
+        let update = run_update(cfg, manga_filter, false, incremental);
+        tokio::pin!(update);
+
+        let result = tokio::select! {
+            result = &mut update => result,
+            () = &mut shutdown => {
+                stopping = true;
+                print_status(style("Stopping after the current update...").yellow());
+                update.await
+            }
+        };
+
+        match result {
+            Ok(updates) => {
+                if !incremental {
+                    last_full_check = Some(started_at);
+                }
+
+                print_line(style(format!("[{}]", Local::now().format("%Y-%m-%d %H:%M"))).dim());
+                print_updates(&updates, false);
+                export_metrics(&cfg.metrics);
+            }
+            // a network blip or ratelimit on the first update shouldn't stop `watch` for good
+            Err(e)
+                if first
+                    && !e
+                        .downcast_ref::<ApiError>()
+                        .is_some_and(ApiError::is_retryable) =>
+            {
+                return Err(e);
+            }
+            Err(e) => error!(
+                "Update failed, retrying in {}: {e}",
+                format_interval(interval)
+            ),
+        }
+
+        first = false;
+        // an outage shouldn't be retried every interval forever
+        check_budget()?;
+
+        if stopping {
+            break;
+        }
+
+        info!("Next update in {}", format_interval(interval));
+
+        tokio::select! {
+            () = tokio::time::sleep(interval) => {},
+            () = &mut shutdown => break,
+        }
+    }
+
+    print_status(style("Stopped watching").green());
+    Ok(())
+}
+
+/// The shortest interval allowed by [`parse_interval`], to avoid hammering the API.
+pub const MIN_INTERVAL: Duration = Duration::from_mins(10);
+
+/// Parses an interval such as `"6h"`, `"90m"` or `"1d12h"`.
+///
+/// Units are `s`, `m`, `h` and `d`, and the interval must be at least [`MIN_INTERVAL`].
+///
+/// ## Errors
+///
+/// If `raw` is malformed or too short.
+pub fn parse_interval(raw: &str) -> Result<Duration> {
+    let mut secs: u64 = 0;
+    let mut number = String::new();
+
+    for c in raw.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => bail!("unknown unit `{c}` in interval {raw:?}, expected one of s, m, h, d"),
+        };
+
+        let Ok(n) = number.parse::<u64>() else {
+            bail!("expected a number before `{c}` in interval {raw:?}");
+        };
+
+        secs = n
+            .checked_mul(unit)
+            .and_then(|n| secs.checked_add(n))
+            .ok_or_else(|| miette!("interval {raw:?} is too long"))?;
+        number.clear();
+    }
+
+    if !number.is_empty() {
+        bail!("missing unit after `{number}` in interval {raw:?}, e.g. \"{number}h\"");
+    }
+
+    let interval = Duration::from_secs(secs);
+
+    if interval < MIN_INTERVAL {
+        bail!(
+            "interval {raw:?} is too short, it must be at least {}",
+            format_interval(MIN_INTERVAL)
+        );
+    }
+
+    Ok(interval)
+}
+
+/// Formats `interval` like [`parse_interval`] accepts, e.g. `"1d12h"`.
+#[must_use]
+pub fn format_interval(interval: Duration) -> String {
+    let mut secs = interval.as_secs();
+    let mut out = String::new();
+
+    for (unit, size) in [("d", 86_400), ("h", 3_600), ("m", 60), ("s", 1)] {
+        if secs >= size {
+            out.push_str(&(secs / size).to_string());
+            out.push_str(unit);
+            secs %= size;
+        }
+    }
+
+    if out.is_empty() {
+        "0s".to_string()
+    } else {
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{config::ImageQuality, library::ChapterEntry};
+
+    use chrono::{DateTime, Utc};
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    const UPDATED_AT: &str = "2024-06-01T00:00:00+00:00";
+    const BEFORE: &str = "2024-01-01T00:00:00+00:00";
+    const AFTER: &str = "2024-12-01T00:00:00+00:00";
+
+    fn time(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().to_utc()
+    }
+
+    fn chapter(id: u128, volume: Option<&str>, number: Option<&str>, readable_at: &str) -> Chapter {
+        serde_json::from_value(json!({
+            "data": {
+                "id": Uuid::from_u128(id).to_string(),
+                "type": "chapter",
+                "attributes": {
+                    "volume": volume,
+                    "chapter": number,
+                    "title": null,
+                    "translatedLanguage": "en",
+                    "externalUrl": null,
+                    "isUnavailable": false,
+                    "publishAt": readable_at,
+                    "readableAt": readable_at,
+                    "createdAt": readable_at,
+                    "updatedAt": readable_at,
+                    "pages": 20,
+                    "version": 1,
+                },
+                "relationships": [],
+            }
+        }))
+        .unwrap()
+    }
+
+    fn local(chapters: &[(u128, Option<&str>, &str)]) -> MangaEntry {
+        let chapters = chapters
+            .iter()
+            .map(|&(id, volume, number)| {
+                let entry = ChapterEntry {
+                    uuid: Uuid::from_u128(id),
+                    volume: volume.map(ToString::to_string),
+                    chapter_number: Some(number.to_string()),
+                    title: None,
+                    language: "en".to_string(),
+                    groups: Vec::new(),
+                    dir: PathBuf::new(),
+                    size: 0,
+                    pages: 20,
+                    checksum: String::new(),
+                    quality: ImageQuality::Lossless,
+                    downloaded_at: time(BEFORE),
+                    version: Some(1),
+                };
+                (entry.uuid, entry)
+            })
+            .collect();
+
+        MangaEntry {
+            uuid: Uuid::nil(),
+            title: "Manga".to_string(),
+            dir: PathBuf::new(),
+            updated_at: time(UPDATED_AT),
+            polled_at: None,
+            chapters,
+        }
+    }
+
+    fn ids(chapters: &[Chapter]) -> Vec<u128> {
+        chapters.iter().map(|c| c.uuid().as_u128()).collect()
+    }
+
+    #[test]
+    fn compares_volumes_before_numbers() {
+        assert!(comes_after((Some(2.0), 1.0), (Some(1.0), 10.0)));
+        assert!(!comes_after((Some(1.0), 11.0), (Some(2.0), 1.0)));
+        assert!(comes_after((Some(1.0), 2.0), (Some(1.0), 1.0)));
+        assert!(comes_after((None, 11.0), (Some(1.0), 10.0)));
+        assert!(!comes_after((None, 10.0), (None, 10.0)));
+    }
+
+    #[test]
+    fn finds_chapters_after_the_local_ones() {
+        let local = local(&[(1, Some("1"), "1"), (2, Some("1"), "2")]);
+        let chapters = vec![
+            chapter(1, Some("1"), Some("1"), BEFORE),
+            chapter(3, Some("1"), Some("1.5"), AFTER),
+            chapter(4, Some("1"), Some("3"), BEFORE),
+        ];
+
+        // 1 is downloaded and 1.5 comes before 2, even though it's newer
+        assert_eq!(ids(&new_chapters(&local, chapters, false)), [4]);
+    }
+
+    #[test]
+    fn finds_chapters_in_later_volumes_when_numbers_reset() {
+        let local = local(&[(1, Some("1"), "1"), (2, Some("1"), "10")]);
+        let chapters = vec![
+            chapter(3, Some("2"), Some("1"), BEFORE),
+            chapter(4, None, Some("2"), AFTER),
+            chapter(5, None, Some("3"), BEFORE),
+        ];
+
+        // chapters without a volume can't be placed, so they're new if they're newer
+        assert_eq!(ids(&new_chapters(&local, chapters, true)), [3, 4]);
+    }
+
+    #[test]
+    fn falls_back_to_readable_at() {
+        let chapters = vec![
+            chapter(1, None, None, AFTER),
+            chapter(2, None, None, BEFORE),
+            chapter(3, Some("1"), Some("1"), AFTER),
+        ];
+
+        assert_eq!(ids(&new_chapters(&local(&[]), chapters, false)), [1, 3]);
+    }
+}
@@ -0,0 +1,299 @@
+//! Contains [`run_upgrade`], which downloads chapters that were saved in a lower quality
+//! than `images.quality` again, e.g. after switching from `lossy` to `lossless`.
+//!
+//! Pages are replaced in place, and a chapter's old copy is removed if it's saved
+//! under a different name now. Archives are repackaged by [exporting](`export_library`)
+//! again afterwards, which only rewrites the chapters that changed.
+
+use crate::{
+    api::{
+        client::ApiClient,
+        download::{DownloadClient, is_dry_run},
+        models::{Chapter, Manga},
+        search::SearchClient,
+    },
+    config::{Config, ImageQuality},
+    export::export_library,
+    history::{RunRecord, append_record},
+    library::{ChapterEntry, LibraryIndex, MangaEntry, remove_old_copies},
+    manifest::ChapterManifest,
+    paths::manga_save_dir,
+    progress::print_line,
+};
+
+use std::{collections::BTreeMap, path::Path};
+
+use chrono::Utc;
+use console::style;
+use miette::Result;
+use uuid::Uuid;
+
+/// The outcome of upgrading a single manga with [`run_upgrade`].
+#[derive(Debug, Clone)]
+pub struct MangaUpgrade {
+    pub title: String,
+    /// The numbers of the chapters found in a lower quality
+    /// (and downloaded again, unless only checking).
+    pub chapters: Vec<String>,
+    /// How many of the chapters failed to download.
+    pub failed: usize,
+}
+
+/// Returns a chapter's number (or title) for listing, like
+/// [`Notification::chapter_label`](`crate::notify::Notification::chapter_label`).
+fn entry_label(chapter: &ChapterEntry) -> String {
+    chapter
+        .chapter_number
+        .clone()
+        .or_else(|| chapter.title.clone())
+        .unwrap_or_else(|| "oneshot".to_string())
+}
+
+/// Returns the chapters of `entry` that were saved in a lower quality than `quality`.
+///
+/// Chapters that were only saved in a lower quality because they had no images in
+/// `quality` (see [`ChapterManifest::requested_quality`]) are left out, since
+/// downloading them again would just fall back again.
+///
+/// ## Errors
+///
+/// If propagated from [`manga_save_dir`].
+pub fn lower_quality_chapters<'a>(
+    entry: &'a MangaEntry,
+    quality: &ImageQuality,
+) -> Result<Vec<&'a ChapterEntry>> {
+    let manga_dir = manga_save_dir()?.join(&entry.dir);
+
+    Ok(entry
+        .chapters
+        .values()
+        .filter(|c| c.quality.is_lower_than(quality))
+        .filter(|c| {
+            let fell_back = ChapterManifest::read(&manga_dir.join(&c.dir))
+                .ok()
+                .flatten()
+                .is_some_and(|m| m.requested_quality.as_ref() == Some(quality));
+
+            if fell_back {
+                debug!(
+                    "Skipping chapter {}, which has no images in {quality:?} quality",
+                    c.uuid
+                );
+            }
+
+            !fell_back
+        })
+        .collect())
+}
+
+/// Fetches the chapters of `manga` with `uuids`, from its feed if they're in it
+/// and one by one otherwise (e.g. if they're in another language).
+///
+/// Chapters that can't be fetched (e.g. if they've been deleted) are logged and left out.
+async fn fetch_chapters(
+    api: &ApiClient,
+    searcher: &SearchClient,
+    manga: &Manga,
+    uuids: &[Uuid],
+) -> Result<Vec<Chapter>> {
+    let mut chapters: Vec<Chapter> = searcher
+        .fetch_all_chapters(manga)
+        .await?
+        .into_iter()
+        .filter(|c| uuids.contains(&c.uuid()))
+        .collect();
+
+    for &uuid in uuids {
+        if chapters.iter().any(|c| c.uuid() == uuid) {
+            continue;
+        }
+
+        match Chapter::new(api, uuid).await {
+            Ok(chapter) => chapters.push(chapter),
+            Err(e) => warn!("Skipping chapter {uuid}, which couldn't be fetched: {e}"),
+        }
+    }
+
+    Ok(chapters)
+}
+
+/// Finds the chapters of every manga in the library index whose title contains
+/// `manga_filter` (case-insensitive) that were saved in a lower quality than
+/// `images.quality` (see [`lower_quality_chapters`]), and downloads them again.
+///
+/// If `check_only` is set, these chapters are only reported, not downloaded.
+///
+/// ## Errors
+///
+/// If the library index can't be loaded, or a client can't be constructed.
+/// A manga that fails to upgrade is logged and counted as failed instead.
+pub async fn run_upgrade(
+    cfg: &Config,
+    manga_filter: Option<&str>,
+    check_only: bool,
+) -> Result<Vec<MangaUpgrade>> {
+    let manga_filter = manga_filter.map(str::to_lowercase);
+    let quality = &cfg.images.quality;
+    let api = ApiClient::new(&cfg.client)?;
+    let searcher = SearchClient::new(api.clone(), cfg.client.language);
+    let downloader = DownloadClient::new(cfg)?;
+    let mut upgrades = Vec::new();
+    let mut jobs = Vec::new();
+    let mut old_dirs = BTreeMap::new();
+
+    for local in LibraryIndex::load()?.manga.into_values().filter(|m| {
+        manga_filter
+            .as_ref()
+            .is_none_or(|f| m.title.to_lowercase().contains(f))
+    }) {
+        let lower = lower_quality_chapters(&local, quality)?;
+
+        if lower.is_empty() {
+            continue;
+        }
+
+        info!(
+            "Found {} chapters of {:?} below {quality:?} quality",
+            lower.len(),
+            local.title
+        );
+
+        let mut upgrade = MangaUpgrade {
+            title: local.title.clone(),
+            chapters: lower.iter().map(|c| entry_label(c)).collect(),
+            failed: 0,
+        };
+
+        if check_only {
+            upgrades.push(upgrade);
+            continue;
+        }
+
+        let uuids: Vec<Uuid> = lower.iter().map(|c| c.uuid).collect();
+        for c in &lower {
+            old_dirs.insert(c.uuid, local.chapter_path(c)?);
+        }
+
+        let fetched = match Manga::new(&api, local.uuid).await {
+            Ok(manga) => fetch_chapters(&api, &searcher, &manga, &uuids)
+                .await
+                .map(|chapters| (manga, chapters)),
+            Err(e) => Err(e),
+        };
+
+        match fetched {
+            Ok((manga, chapters)) => jobs.push((upgrades.len(), manga, chapters)),
+            Err(e) => {
+                error!("Failed to upgrade manga {:?}: {e}", local.title);
+                upgrade.failed = uuids.len();
+            }
+        }
+
+        upgrades.push(upgrade);
+    }
+
+    if check_only {
+        return Ok(upgrades);
+    }
+
+    let started_at = Utc::now();
+    let indices: Vec<usize> = jobs.iter().map(|(i, _, _)| *i).collect();
+    let records: Vec<(Uuid, String)> = jobs
+        .iter()
+        .map(|(_, manga, _)| (manga.uuid(), manga.title(cfg.client.language)))
+        .collect();
+    let jobs = jobs
+        .into_iter()
+        .map(|(_, manga, chapters)| (manga, chapters))
+        .collect();
+
+    let results = downloader.download_many(&api, jobs, &cfg.images).await?;
+
+    for ((i, (manga_uuid, manga_title)), result) in indices.into_iter().zip(records).zip(results) {
+        let upgrade = &mut upgrades[i];
+
+        match result {
+            Ok(summary) => {
+                upgrade.failed = upgrade.chapters.len() - summary.downloaded.len();
+                remove_old_copies(&old_dirs, &summary.downloaded)?;
+                append_record(&RunRecord::new(
+                    started_at,
+                    manga_uuid,
+                    manga_title,
+                    &summary,
+                ))?;
+            }
+            Err(e) => {
+                error!("Failed to upgrade manga {:?}: {e}", upgrade.title);
+                upgrade.failed = upgrade.chapters.len();
+            }
+        }
+    }
+
+    Ok(upgrades)
+}
+
+/// Prints which manga in `upgrades` had chapters in a lower quality than `quality`.
+fn print_upgrades(upgrades: &[MangaUpgrade], quality: &ImageQuality, check_only: bool) {
+    if upgrades.is_empty() {
+        print_line(
+            style(format!("No chapters saved below {quality:?} quality"))
+                .yellow()
+                .italic(),
+        );
+        return;
+    }
+
+    for u in upgrades {
+        let failed = if u.failed == 0 {
+            String::new()
+        } else {
+            style(format!(" ({} failed)", u.failed)).red().to_string()
+        };
+
+        print_line(format!(
+            "{}  {} chapters{failed}",
+            style(&u.title).bold(),
+            u.chapters.len()
+        ));
+    }
+
+    let total: usize = upgrades.iter().map(|u| u.chapters.len()).sum();
+    let failed: usize = upgrades.iter().map(|u| u.failed).sum();
+    let message = if check_only || is_dry_run() {
+        format!("Found {total} chapters to upgrade to {quality:?} quality")
+    } else {
+        format!(
+            "Upgraded {} chapters to {quality:?} quality",
+            total - failed
+        )
+    };
+
+    print_line(style(message).green());
+}
+
+/// Runs [`run_upgrade`] and prints which manga were upgraded, then exports them
+/// into `export` (if given) to repackage their archives.
+///
+/// ## Errors
+///
+/// If propagated from [`run_upgrade`] or [`export_library`].
+pub async fn display_upgrade(
+    cfg: &Config,
+    manga_filter: Option<&str>,
+    check_only: bool,
+    export: Option<&Path>,
+) -> Result<()> {
+    let upgrades = run_upgrade(cfg, manga_filter, check_only).await?;
+    print_upgrades(&upgrades, &cfg.images.quality, check_only);
+
+    if let Some(dest) = export
+        && !check_only
+        && !is_dry_run()
+        && !upgrades.is_empty()
+    {
+        export_library(cfg, dest, None, manga_filter).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,113 @@
+//! Contains [`run_config_wizard`], a guided setup flow for writing the
+//! [config](`crate::paths::config_toml`), used by `config init` and on the first run.
+
+use crate::{
+    config::{config_with_options, parse_config},
+    paths::{config_toml, manga_save_dir},
+    progress::print_line,
+};
+
+use std::fs;
+
+use console::style;
+use dialoguer::{Confirm, Input, Select, theme::ColorfulTheme};
+use isolang::Language;
+use miette::{IntoDiagnostic, Result, miette};
+
+/// Asks for a non-zero number, using `default` if nothing is entered.
+fn ask_permits(prompt: &str, default: usize) -> Result<usize> {
+    Input::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .default(default)
+        .validate_with(|n: &usize| {
+            if *n == 0 {
+                Err("must be at least 1")
+            } else {
+                Ok(())
+            }
+        })
+        .interact_text()
+        .into_diagnostic()
+}
+
+/// Asks for the main config options, then writes a commented config with them.
+///
+/// If the config already exists, this asks before overwriting it.
+///
+/// Returns true if the config was written.
+///
+/// ## Errors
+///
+/// If prompting fails (e.g. there's no terminal), or the config can't be written.
+pub fn run_config_wizard() -> Result<bool> {
+    let theme = ColorfulTheme::default();
+    let path = config_toml()?;
+
+    if path.try_exists().into_diagnostic()?
+        && !Confirm::with_theme(&theme)
+            .with_prompt(format!("{} already exists, overwrite it?", path.display()))
+            .default(false)
+            .interact()
+            .into_diagnostic()?
+    {
+        return Ok(false);
+    }
+
+    let language: String = Input::with_theme(&theme)
+        .with_prompt("Language (ISO 639-1 code)")
+        .default("en".to_string())
+        .validate_with(|code: &String| {
+            Language::from_639_1(code.trim())
+                .map(|_| ())
+                .ok_or("expected a two letter code, e.g. \"en\" or \"ja\"")
+        })
+        .interact_text()
+        .into_diagnostic()?;
+
+    let quality = ["lossless", "lossy"][Select::with_theme(&theme)
+        .with_prompt("Image quality (lossy is smaller and faster to download)")
+        .items(["lossless", "lossy"])
+        .default(0)
+        .interact()
+        .into_diagnostic()?];
+
+    let image_permits = ask_permits("Images downloaded at once", 10)?;
+    let chapter_permits = ask_permits("Chapters downloaded at once", 3)?;
+
+    let default_library = manga_save_dir()?.display().to_string();
+    let library: String = Input::with_theme(&theme)
+        .with_prompt("Save manga to")
+        .default(default_library.clone())
+        .interact_text()
+        .into_diagnostic()?;
+
+    let mut options = vec![
+        ("language", format!("{:?}", language.trim())),
+        ("quality", format!("{quality:?}")),
+        ("image_permits", image_permits.to_string()),
+        ("chapter_permits", chapter_permits.to_string()),
+    ];
+
+    if library.trim() != default_library {
+        options.push(("library_dir", toml::Value::from(library.trim()).to_string()));
+    }
+
+    let raw_cfg = config_with_options(&options);
+    parse_config(&raw_cfg, &path.display().to_string(), &[])
+        .map_err(|e| miette!("the generated config is invalid: {e}"))?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).into_diagnostic()?;
+    }
+
+    fs::write(&path, raw_cfg)
+        .map_err(|e| miette!("failed to write config to {}: {e}", path.display()))?;
+
+    print_line(format!(
+        "{} {}",
+        style("Wrote config to").green(),
+        style(path.display()).bold()
+    ));
+
+    Ok(true)
+}